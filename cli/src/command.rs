@@ -0,0 +1,353 @@
+//! Parser of the command line. Tokens are separated by spaces, quotes group tokens with spaces.
+use dbms_client::notifications;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Engine {
+    InMemory,
+    Cache,
+    OnDisk,
+    Lsm
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WriteAction {
+    Insert,
+    Set
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Exit,
+    Ping,
+    Tables,
+    Hierarchy,
+    Shards,
+    Migrate { from: u16, to: u16, node: u16 },
+    Rebalance { is_dry_run: bool },
+    AddNode { machines: Vec<String> },
+    RemoveNode { node: u16 },
+    AddMachine { node: u16, addr: String },
+    RemoveMachine { addr: String },
+    Info,
+    Slowlog { count: u16 },
+    SlowlogReset,
+    Shutdown { dump: Option<bool> },
+    ConfigGet { name: String },
+    ConfigSet { name: String, value: String },
+    Scheme { table: String },
+    Create {
+        engine: Engine,
+        name: String,
+        is_logging: bool,
+        cache_duration: u64,
+        /// The scheme JSON as is. Empty string is an empty scheme.
+        scheme: String
+    },
+    Get { table: String, key: String, fields: Vec<String> },
+    Write { action: WriteAction, table: String, key: String, value: String },
+    Delete { table: String, key: String },
+    Publish { channel: String, message: String },
+    Notifications { table: String, events: u8 },
+    Watch { table: String, key: String, seconds: u64 }
+}
+
+pub const HELP: &str = "\
+Commands:
+  ping                                            check the connection
+  tables                                          list tables with their numbers
+  scheme <table>                                  show the scheme of the table
+  create memory <name> [nolog] [scheme]           create an in memory table
+  create cache <name> <minutes> [nolog] [scheme]  create a cache table
+  create disk <name> [scheme]                     create an on disk table
+  create lsm <name> [scheme]                      create a table on the LSM tree
+  get <table> <key> [field...]                    get the value or some fields
+  set <table> <key> <value>                       insert or replace the value
+  insert <table> <key> <value>                    insert the value, if the key doesn't exist
+  delete <table> <key>                            delete the key
+  publish <channel> <message>                     send the message to subscribers of the channel
+  notifications <table> [event...]                publish set, insert, delete, expire or all events, none turns them off
+  watch <table> <key> [seconds]                   wait for the change of the key, forever by default
+  hierarchy                                       show machines of every node
+  shards                                          show nodes of shard ranges
+  migrate <from> <to> <node>                      move shards from..=to of the connected node to the node
+  rebalance [dry]                                 even out numbers of shards of nodes, dry only shows moves
+  node add <address...>                           add the node with the machines to the hierarchy
+  node remove <node>                              remove the node without shards from the hierarchy
+  machine add <node> <address>                    add the machine to the node
+  machine remove <address>                        remove the machine from the hierarchy
+  info                                            show uptime, persistence, tables and clients
+  slowlog [count]                                 show the slowest recent messages, 10 by default
+  slowlog reset                                   clear the slow log
+  shutdown [dump|nodump]                          shut the server down gracefully
+  config get [name]                               show all settings or one setting
+  config set <name> <value>                       change a reloadable setting at runtime
+  help                                            show this message
+  exit                                            exit
+
+<table> is a name or a number. The scheme is JSON: {\"sized_fields\": {...}, \"unsized_fields\": {...}},
+it may set \"compression\": \"lz4\" or \"zstd\".
+The value is a JSON object for tables with a scheme and a string for tables without it.";
+
+/// Returns the next token and the rest of the line.
+fn next_token(line: &str) -> Result<Option<(String, &str)>, String> {
+    let line = line.trim_start();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if !line.starts_with('"') {
+        let end = line.find(char::is_whitespace).unwrap_or(line.len());
+        return Ok(Some((line[..end].to_string(), &line[end..])));
+    }
+
+    let mut token = String::new();
+    let mut chars = line.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok(Some((token, &line[i + 1..]))),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => token.push('\n'),
+                Some((_, 't')) => token.push('\t'),
+                Some((_, c)) => token.push(c),
+                None => break
+            },
+            c => token.push(c)
+        }
+    }
+    Err("unclosed quote".to_string())
+}
+
+fn expect_token<'a>(line: &'a str, what: &str) -> Result<(String, &'a str), String> {
+    match next_token(line)? {
+        Some(token) => Ok(token),
+        None => Err(format!("{} is missing, see `help`", what))
+    }
+}
+
+fn expect_end(line: &str) -> Result<(), String> {
+    match next_token(line)? {
+        Some((token, _)) => Err(format!("unexpected argument {}, see `help`", token)),
+        None => Ok(())
+    }
+}
+
+/// Parses the line. Returns `None` for empty lines and comments.
+pub fn parse(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let (name, rest) = expect_token(line, "command")?;
+    let command = match name.to_lowercase().as_str() {
+        "help" | "?" => Command::Help,
+        "exit" | "quit" => Command::Exit,
+        "ping" => Command::Ping,
+        "tables" => Command::Tables,
+        "hierarchy" => Command::Hierarchy,
+        "shards" => Command::Shards,
+        "migrate" => {
+            let mut numbers = [0u16; 3];
+            let mut rest = rest;
+            for (number, what) in numbers.iter_mut().zip(["first shard", "last shard", "node"]) {
+                let (token, next) = expect_token(rest, what)?;
+                *number = token.parse().map_err(|_| format!("{} must be a number from 0 to 65535, not {}", what, token))?;
+                rest = next;
+            }
+            expect_end(rest)?;
+            Command::Migrate { from: numbers[0], to: numbers[1], node: numbers[2] }
+        }
+        "node" => {
+            let (subcommand, rest) = expect_token(rest, "add or remove")?;
+            match subcommand.to_lowercase().as_str() {
+                "add" => {
+                    let (addr, mut rest) = expect_token(rest, "address")?;
+                    let mut machines = vec![addr];
+                    while let Some((addr, next)) = next_token(rest)? {
+                        machines.push(addr);
+                        rest = next;
+                    }
+                    Command::AddNode { machines }
+                }
+                "remove" => {
+                    let (node, rest) = expect_token(rest, "node")?;
+                    expect_end(rest)?;
+                    Command::RemoveNode { node: parse_node(&node)? }
+                }
+                _ => return Err(format!("unknown subcommand node {}, see `help`", subcommand))
+            }
+        }
+        "machine" => {
+            let (subcommand, rest) = expect_token(rest, "add or remove")?;
+            match subcommand.to_lowercase().as_str() {
+                "add" => {
+                    let (node, rest) = expect_token(rest, "node")?;
+                    let (addr, rest) = expect_token(rest, "address")?;
+                    expect_end(rest)?;
+                    Command::AddMachine { node: parse_node(&node)?, addr }
+                }
+                "remove" => {
+                    let (addr, rest) = expect_token(rest, "address")?;
+                    expect_end(rest)?;
+                    Command::RemoveMachine { addr }
+                }
+                _ => return Err(format!("unknown subcommand machine {}, see `help`", subcommand))
+            }
+        }
+        "rebalance" => match next_token(rest)? {
+            None => Command::Rebalance { is_dry_run: false },
+            Some((argument, rest)) => {
+                expect_end(rest)?;
+                if !argument.eq_ignore_ascii_case("dry") {
+                    return Err(format!("unknown argument {}, use dry", argument));
+                }
+                Command::Rebalance { is_dry_run: true }
+            }
+        },
+        "info" => Command::Info,
+        "slowlog" => match next_token(rest)? {
+            None => Command::Slowlog { count: 10 },
+            Some((argument, rest)) => {
+                expect_end(rest)?;
+                if argument.eq_ignore_ascii_case("reset") {
+                    Command::SlowlogReset
+                } else {
+                    let count = argument.parse().map_err(|_| format!("wrong count {}, see `help`", argument))?;
+                    Command::Slowlog { count }
+                }
+            }
+        },
+        "shutdown" => match next_token(rest)? {
+            None => Command::Shutdown { dump: None },
+            Some((argument, rest)) => {
+                expect_end(rest)?;
+                match argument.to_lowercase().as_str() {
+                    "dump" => Command::Shutdown { dump: Some(true) },
+                    "nodump" => Command::Shutdown { dump: Some(false) },
+                    _ => return Err(format!("unknown argument {}, use dump or nodump", argument))
+                }
+            }
+        },
+        "config" => {
+            let (subcommand, rest) = expect_token(rest, "get or set")?;
+            match subcommand.to_lowercase().as_str() {
+                "get" => match next_token(rest)? {
+                    None => Command::ConfigGet { name: String::new() },
+                    Some((name, rest)) => {
+                        expect_end(rest)?;
+                        Command::ConfigGet { name }
+                    }
+                },
+                "set" => {
+                    let (name, rest) = expect_token(rest, "setting")?;
+                    let (value, rest) = expect_token(rest, "value")?;
+                    expect_end(rest)?;
+                    Command::ConfigSet { name, value }
+                }
+                _ => return Err(format!("unknown subcommand config {}, see `help`", subcommand))
+            }
+        }
+        "scheme" => {
+            let (table, rest) = expect_token(rest, "table")?;
+            expect_end(rest)?;
+            Command::Scheme { table }
+        }
+        "create" => parse_create(rest)?,
+        "get" => {
+            let (table, rest) = expect_token(rest, "table")?;
+            let (key, mut rest) = expect_token(rest, "key")?;
+            let mut fields = Vec::new();
+            while let Some((field, next)) = next_token(rest)? {
+                fields.push(field);
+                rest = next;
+            }
+            Command::Get { table, key, fields }
+        }
+        "set" | "insert" => {
+            let action = if name.eq_ignore_ascii_case("set") { WriteAction::Set } else { WriteAction::Insert };
+            let (table, rest) = expect_token(rest, "table")?;
+            let (key, rest) = expect_token(rest, "key")?;
+            let value = rest.trim().to_string();
+            if value.is_empty() {
+                return Err("value is missing, see `help`".to_string());
+            }
+            Command::Write { action, table, key, value }
+        }
+        "delete" | "del" => {
+            let (table, rest) = expect_token(rest, "table")?;
+            let (key, rest) = expect_token(rest, "key")?;
+            expect_end(rest)?;
+            Command::Delete { table, key }
+        }
+        "publish" => {
+            let (channel, rest) = expect_token(rest, "channel")?;
+            let message = rest.trim().to_string();
+            Command::Publish { channel, message }
+        }
+        "notifications" => {
+            let (table, mut rest) = expect_token(rest, "table")?;
+            let mut events = 0;
+            while let Some((event, next)) = next_token(rest)? {
+                events |= match event.to_lowercase().as_str() {
+                    "set" => notifications::SET,
+                    "insert" => notifications::INSERT,
+                    "delete" => notifications::DELETE,
+                    "expire" => notifications::EXPIRE,
+                    "all" => notifications::ALL,
+                    _ => return Err(format!("unknown event {}, see `help`", event))
+                };
+                rest = next;
+            }
+            Command::Notifications { table, events }
+        }
+        "watch" => {
+            let (table, rest) = expect_token(rest, "table")?;
+            let (key, rest) = expect_token(rest, "key")?;
+            let seconds = match next_token(rest)? {
+                Some((seconds, rest)) => {
+                    expect_end(rest)?;
+                    seconds.parse().map_err(|_| format!("seconds must be a number, not {}", seconds))?
+                }
+                None => 0
+            };
+            Command::Watch { table, key, seconds }
+        }
+        _ => return Err(format!("unknown command {}, see `help`", name))
+    };
+    Ok(Some(command))
+}
+
+fn parse_node(node: &str) -> Result<u16, String> {
+    node.parse().map_err(|_| format!("node must be a number from 0 to 65535, not {}", node))
+}
+
+fn parse_create(line: &str) -> Result<Command, String> {
+    let (engine, rest) = expect_token(line, "engine")?;
+    let engine = match engine.to_lowercase().as_str() {
+        "memory" | "in_memory" => Engine::InMemory,
+        "cache" => Engine::Cache,
+        "disk" | "on_disk" => Engine::OnDisk,
+        "lsm" => Engine::Lsm,
+        _ => return Err(format!("unknown engine {}, use memory, cache, disk or lsm", engine))
+    };
+    let (name, mut rest) = expect_token(rest, "name")?;
+    let mut cache_duration = 0;
+    if engine == Engine::Cache {
+        let (minutes, next) = expect_token(rest, "cache duration")?;
+        cache_duration = minutes.parse().map_err(|_| format!("cache duration must be a number of minutes, not {}", minutes))?;
+        rest = next;
+    }
+    let mut is_logging = true;
+    loop {
+        let trimmed = rest.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with('{') {
+            break;
+        }
+        let (option, next) = expect_token(rest, "option")?;
+        match option.as_str() {
+            "nolog" if engine == Engine::InMemory || engine == Engine::Cache => is_logging = false,
+            _ => return Err(format!("unknown option {}", option))
+        }
+        rest = next;
+    }
+    Ok(Command::Create { engine, name, is_logging, cache_duration, scheme: rest.trim().to_string() })
+}
\ No newline at end of file
@@ -0,0 +1,135 @@
+//! Conversion of values between JSON and the binary format of the table scheme.
+use serde_json::{Map, Number, Value};
+use dbms_client::{Field, FieldType, Scheme};
+
+pub fn field_to_json(field: &Field) -> Value {
+    match field {
+        Field::Byte(v) | Field::Uint8(v) => Value::from(*v),
+        Field::Bool(v) => Value::from(*v),
+        Field::Uint16(v) => Value::from(*v),
+        Field::Uint32(v) => Value::from(*v),
+        Field::Uint64(v) => Value::from(*v),
+        Field::Int8(v) => Value::from(*v),
+        Field::Int16(v) => Value::from(*v),
+        Field::Int32(v) => Value::from(*v),
+        Field::Int64(v) => Value::from(*v),
+        // JSON numbers can't keep 128 bits.
+        Field::Uint128(v) => Value::from(v.to_string()),
+        Field::Int128(v) => Value::from(v.to_string()),
+        Field::Float32(v) => Number::from_f64(*v as f64).map(Value::Number).unwrap_or(Value::Null),
+        Field::Float64(v) => Number::from_f64(*v).map(Value::Number).unwrap_or(Value::Null),
+        Field::String(v) => Value::from(v.as_str()),
+        Field::ByteSlice(v) => Value::from(to_hex(v))
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + bytes.len() * 2);
+    hex.push_str("0x");
+    for byte in bytes {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x")?;
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
+
+fn json_to_field(name: &str, field_type: FieldType, value: &Value) -> Result<Field, String> {
+    let wrong = || format!("field {} must be {}, but it is {}", name, field_type.name(), value);
+    macro_rules! int {
+        ($variant:ident, $t:ty, $get:ident) => {
+            value.$get().and_then(|v| <$t>::try_from(v).ok()).map(Field::$variant).ok_or_else(wrong)
+        };
+    }
+    macro_rules! big_int {
+        ($variant:ident, $t:ty) => {
+            match value {
+                Value::String(v) => v.parse::<$t>().ok(),
+                Value::Number(v) => v.to_string().parse::<$t>().ok(),
+                _ => None
+            }.map(Field::$variant).ok_or_else(wrong)
+        };
+    }
+    match field_type {
+        FieldType::Byte => int!(Byte, u8, as_u64),
+        FieldType::Uint8 => int!(Uint8, u8, as_u64),
+        FieldType::Uint16 => int!(Uint16, u16, as_u64),
+        FieldType::Uint32 => int!(Uint32, u32, as_u64),
+        FieldType::Uint64 => int!(Uint64, u64, as_u64),
+        FieldType::Int8 => int!(Int8, i8, as_i64),
+        FieldType::Int16 => int!(Int16, i16, as_i64),
+        FieldType::Int32 => int!(Int32, i32, as_i64),
+        FieldType::Int64 => int!(Int64, i64, as_i64),
+        FieldType::Uint128 => big_int!(Uint128, u128),
+        FieldType::Int128 => big_int!(Int128, i128),
+        FieldType::Bool => value.as_bool().map(Field::Bool).ok_or_else(wrong),
+        FieldType::Float32 => value.as_f64().map(|v| Field::Float32(v as f32)).ok_or_else(wrong),
+        FieldType::Float64 => value.as_f64().map(Field::Float64).ok_or_else(wrong),
+        FieldType::String => value.as_str().map(|v| Field::String(v.to_string())).ok_or_else(wrong),
+        FieldType::ByteSlice => match value {
+            Value::String(v) => from_hex(v).map(Field::ByteSlice).ok_or_else(wrong),
+            Value::Array(items) => items.iter()
+                .map(|item| item.as_u64().and_then(|v| u8::try_from(v).ok()))
+                .collect::<Option<Vec<u8>>>()
+                .map(Field::ByteSlice)
+                .ok_or_else(wrong),
+            _ => Err(wrong())
+        }
+    }
+}
+
+/// Encodes the user input. Tables without a scheme store the string as is.
+pub fn encode_value(scheme: &Scheme, input: &str) -> Result<Vec<u8>, String> {
+    if scheme.is_empty() {
+        // "quoted value" is a JSON string, everything else is stored as is.
+        if let Ok(Value::String(value)) = serde_json::from_str::<Value>(input) {
+            return Ok(value.into_bytes());
+        }
+        return Ok(input.as_bytes().to_vec());
+    }
+
+    let object = match serde_json::from_str::<Value>(input) {
+        Ok(Value::Object(object)) => object,
+        _ => return Err("the value must be a JSON object with all fields of the scheme".to_string())
+    };
+    let mut fields = Vec::with_capacity(object.len());
+    for (name, value) in object.iter() {
+        let field_type = match scheme.field_number(name) {
+            Some(number) => scheme.fields()[number as usize].1,
+            None => return Err(format!("field {} is not in the scheme", name))
+        };
+        fields.push((name.as_str(), json_to_field(name, field_type, value)?));
+    }
+    scheme.encode(&fields).map_err(|e| e.to_string())
+}
+
+/// Decodes the value for printing. Values of tables without a scheme are strings or hex, if they are not UTF-8.
+pub fn decode_value(scheme: &Scheme, value: &[u8]) -> Value {
+    if scheme.is_empty() {
+        return match std::str::from_utf8(value) {
+            Ok(value) => Value::from(value),
+            Err(_) => Value::from(to_hex(value))
+        };
+    }
+    match scheme.decode(value) {
+        Ok(fields) => {
+            let mut object = Map::new();
+            for (name, field) in fields.iter() {
+                object.insert(name.clone(), field_to_json(field));
+            }
+            Value::Object(object)
+        }
+        // The value was written by somebody, who doesn't know the scheme. Show it as is.
+        Err(_) => Value::from(to_hex(value))
+    }
+}
+
+pub fn scheme_to_json(scheme: &Scheme) -> Value {
+    serde_json::from_str(&scheme.to_json()).unwrap_or(Value::Null)
+}
\ No newline at end of file
@@ -0,0 +1,192 @@
+use std::{
+    env,
+    io::{self, BufRead, IsTerminal},
+    path::PathBuf,
+    process::ExitCode,
+    time::Duration
+};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use serde_json::{json, Value};
+use dbms_client::{Address, Client, PoolConfig};
+use crate::{
+    command::Command,
+    session::{Error, Session}
+};
+
+mod command;
+mod json;
+mod session;
+
+const USAGE: &str = "\
+Usage: dbms-cli [OPTIONS]
+
+Options:
+  --tcp <ADDR>         TCP address of the server (default: $TCP_ADDR or localhost:10000)
+  --unix <PATH>        Unix socket of the server
+  --password <PASS>    password of the server (default: $PASSWORD)
+  --timeout <SECONDS>  read and write timeout
+  --json               print every result as one JSON line
+  --script             read commands from stdin without the prompt (default, if stdin is not a terminal)
+  --help               show this message";
+
+struct Options {
+    address: Address,
+    password: String,
+    timeout: Option<Duration>,
+    is_json: bool,
+    is_script: bool
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        address: Address::tcp(env::var("TCP_ADDR").unwrap_or("localhost:10000".to_string())),
+        password: env::var("PASSWORD").unwrap_or_default(),
+        timeout: None,
+        is_json: false,
+        is_script: !io::stdin().is_terminal()
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--tcp" => options.address = Address::tcp(value("--tcp")?),
+            "--unix" => options.address = Address::unix(value("--unix")?),
+            "--password" => options.password = value("--password")?,
+            "--timeout" => {
+                let seconds = value("--timeout")?;
+                let seconds: u64 = seconds.parse().map_err(|_| format!("--timeout must be a number of seconds, not {}", seconds))?;
+                options.timeout = Some(Duration::from_secs(seconds));
+            }
+            "--json" => options.is_json = true,
+            "--script" => options.is_script = true,
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ => return Err(format!("unknown option {}\n\n{}", arg, USAGE))
+        }
+    }
+    Ok(options)
+}
+
+fn print_result(result: &Result<Value, Error>, is_json: bool) {
+    if is_json {
+        let line = match result {
+            Ok(value) => json!({ "ok": true, "result": value }),
+            Err(e) => json!({ "ok": false, "error": e.to_json() })
+        };
+        println!("{}", line);
+        return;
+    }
+    match result {
+        Ok(Value::Null) => println!("OK"),
+        Ok(Value::String(value)) => println!("{}", value),
+        Ok(value) => println!("{}", serde_json::to_string_pretty(value).unwrap_or_default()),
+        Err(e) => eprintln!("(error) {}", e)
+    }
+}
+
+/// Executes the line. Returns `None`, if the user wants to exit, otherwise returns true, if the command has succeeded.
+fn execute_line(session: &mut Session, line: &str, is_json: bool) -> Option<bool> {
+    let command = match command::parse(line) {
+        Ok(Some(Command::Exit)) => return None,
+        Ok(Some(command)) => command,
+        Ok(None) => return Some(true),
+        Err(message) => {
+            let result = Err(Error::Usage(message));
+            print_result(&result, is_json);
+            return Some(false);
+        }
+    };
+    let result = session.execute(command);
+    print_result(&result, is_json);
+    Some(result.is_ok())
+}
+
+/// Reads commands from stdin. Returns false, if any command has failed.
+fn run_script(session: &mut Session, is_json: bool) -> bool {
+    let mut is_ok = true;
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Can't read stdin: {}", e);
+                return false;
+            }
+        };
+        match execute_line(session, &line, is_json) {
+            Some(res) => is_ok &= res,
+            None => break
+        }
+    }
+    is_ok
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".dbms_cli_history"))
+}
+
+fn run_repl(session: &mut Session, is_json: bool) {
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            eprintln!("Can't open the terminal: {}", e);
+            return;
+        }
+    };
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+    println!("Type `help` to see commands.");
+    loop {
+        match editor.readline("dbms> ") {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    let _ = editor.add_history_entry(line.as_str());
+                }
+                if execute_line(session, &line, is_json).is_none() {
+                    break;
+                }
+            }
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Can't read the line: {}", e);
+                break;
+            }
+        }
+    }
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(2);
+        }
+    };
+    let config = PoolConfig {
+        max_size: 1,
+        password: options.password,
+        timeout: options.timeout
+    };
+    let client = match Client::connect(options.address.clone(), config) {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Can't connect to {:?}: {}", options.address, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut session = Session::new(client);
+    if options.is_script {
+        if !run_script(&mut session, options.is_json) {
+            return ExitCode::FAILURE;
+        }
+    } else {
+        run_repl(&mut session, options.is_json);
+    }
+    ExitCode::SUCCESS
+}
\ No newline at end of file
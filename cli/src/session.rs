@@ -0,0 +1,204 @@
+use std::{collections::HashMap, time::Duration};
+use serde_json::{json, Map, Value};
+use dbms_client::{notifications, Client, Scheme};
+use crate::{
+    command::{Command, Engine, WriteAction, HELP},
+    json::{decode_value, encode_value, scheme_to_json}
+};
+
+pub enum Error {
+    /// The command is wrong. Nothing was sent to the server.
+    Usage(String),
+    Client(dbms_client::Error)
+}
+
+impl From<dbms_client::Error> for Error {
+    fn from(e: dbms_client::Error) -> Self {
+        Error::Client(e)
+    }
+}
+
+impl Error {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Error::Usage(message) => json!({ "code": null, "message": message }),
+            Error::Client(e) => json!({ "code": e.code(), "message": e.to_string() })
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Client(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+/// Executes commands and caches table names and schemes. Results are JSON, so we can print them both ways.
+pub struct Session {
+    client: Client,
+    tables: Vec<String>,
+    schemes: HashMap<u16, Scheme>
+}
+
+impl Session {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            tables: Vec::new(),
+            schemes: HashMap::new()
+        }
+    }
+
+    /// Returns the number of the table by its name or number.
+    fn table(&mut self, table: &str) -> Result<u16, Error> {
+        for refresh in [false, true] {
+            if refresh {
+                self.tables = self.client.get_tables_names()?;
+            }
+            if let Some(number) = self.tables.iter().position(|name| name == table) {
+                return Ok(number as u16);
+            }
+        }
+        match table.parse::<u16>() {
+            Ok(number) if (number as usize) < self.tables.len() => Ok(number),
+            _ => Err(Error::Usage(format!("table {} is not found", table)))
+        }
+    }
+
+    fn scheme(&mut self, table: u16) -> Result<&Scheme, Error> {
+        if !self.schemes.contains_key(&table) {
+            let scheme = self.client.get_table_scheme(table)?;
+            self.schemes.insert(table, scheme);
+        }
+        Ok(&self.schemes[&table])
+    }
+
+    /// Returns `Value::Null` for commands without a result.
+    pub fn execute(&mut self, command: Command) -> Result<Value, Error> {
+        match command {
+            Command::Help => Ok(Value::from(HELP)),
+            Command::Exit => Ok(Value::Null),
+            Command::Ping => {
+                self.client.ping()?;
+                Ok(Value::from("PONG"))
+            }
+            Command::Tables => {
+                self.tables = self.client.get_tables_names()?;
+                Ok(Value::Array(self.tables.iter().enumerate()
+                    .map(|(number, name)| json!({ "number": number, "name": name }))
+                    .collect()))
+            }
+            Command::Hierarchy => Ok(json!(self.client.get_hierarchy()?)),
+            Command::Migrate { from, to, node } => Ok(self.client.migrate_shards(from, to, node)?),
+            Command::Rebalance { is_dry_run } => Ok(self.client.rebalance(is_dry_run)?),
+            Command::AddNode { machines } => {
+                let machines: Vec<&str> = machines.iter().map(|addr| addr.as_str()).collect();
+                Ok(self.client.add_node(&machines)?)
+            }
+            Command::RemoveNode { node } => Ok(self.client.remove_node(node)?),
+            Command::AddMachine { node, addr } => Ok(self.client.add_machine(node, &addr)?),
+            Command::RemoveMachine { addr } => Ok(self.client.remove_machine(&addr)?),
+            Command::Info => Ok(self.client.info()?),
+            Command::Slowlog { count } => Ok(self.client.slowlog_get(count)?),
+            Command::SlowlogReset => {
+                self.client.slowlog_reset()?;
+                Ok(Value::Null)
+            }
+            Command::Shutdown { dump } => {
+                self.client.shutdown(dump)?;
+                Ok(Value::Null)
+            }
+            Command::ConfigGet { name } => Ok(self.client.config_get(&name)?),
+            Command::ConfigSet { name, value } => {
+                self.client.config_set(&name, &value)?;
+                Ok(Value::Null)
+            }
+            Command::Shards => {
+                let shards = self.client.get_shard_metadata()?;
+                // 65536 numbers are unreadable, so we print ranges of shards with the same node.
+                let mut ranges = Vec::new();
+                let mut start = 0;
+                for i in 1..=shards.len() {
+                    if i == shards.len() || shards[i] != shards[start] {
+                        ranges.push(json!({ "from": start, "to": i - 1, "node": shards[start] }));
+                        start = i;
+                    }
+                }
+                Ok(Value::Array(ranges))
+            }
+            Command::Scheme { table } => {
+                let table = self.table(&table)?;
+                Ok(scheme_to_json(self.scheme(table)?))
+            }
+            Command::Create { engine, name, is_logging, cache_duration, scheme } => {
+                let scheme = Scheme::from_json(scheme.as_bytes())?;
+                let number = match engine {
+                    Engine::InMemory => self.client.create_table_in_memory(&name, &scheme, is_logging)?,
+                    Engine::Cache => self.client.create_table_cache(&name, &scheme, cache_duration, is_logging)?,
+                    Engine::OnDisk => self.client.create_table_on_disk(&name, &scheme)?,
+                    Engine::Lsm => self.client.create_table_lsm(&name, &scheme)?
+                };
+                self.schemes.insert(number, scheme);
+                Ok(json!({ "number": number, "name": name }))
+            }
+            Command::Get { table, key, fields } => {
+                let table = self.table(&table)?;
+                let scheme = self.scheme(table)?.clone();
+                if fields.is_empty() {
+                    return Ok(match self.client.get(table, key.as_bytes())? {
+                        Some(value) => decode_value(&scheme, &value),
+                        None => Value::Null
+                    });
+                }
+
+                let mut numbers = Vec::with_capacity(fields.len());
+                for field in fields.iter() {
+                    match scheme.field_number(field) {
+                        Some(number) => numbers.push(number),
+                        None => return Err(Error::Usage(format!("field {} is not in the scheme", field)))
+                    }
+                }
+                let values = match self.client.get_fields(table, key.as_bytes(), &numbers)? {
+                    Some(values) => values,
+                    None => return Ok(Value::Null)
+                };
+                let mut object = Map::new();
+                for ((name, number), value) in fields.iter().zip(numbers).zip(values) {
+                    object.insert(name.clone(), crate::json::field_to_json(&scheme.decode_field(number, &value)?));
+                }
+                Ok(Value::Object(object))
+            }
+            Command::Write { action, table, key, value } => {
+                let table = self.table(&table)?;
+                let value = encode_value(self.scheme(table)?, &value).map_err(Error::Usage)?;
+                match action {
+                    WriteAction::Insert => self.client.insert(table, key.as_bytes(), &value)?,
+                    WriteAction::Set => self.client.set(table, key.as_bytes(), &value)?
+                }
+                Ok(Value::Null)
+            }
+            Command::Delete { table, key } => {
+                let table = self.table(&table)?;
+                self.client.delete(table, key.as_bytes())?;
+                Ok(Value::Null)
+            }
+            Command::Publish { channel, message } => Ok(Value::from(self.client.publish(channel.as_bytes(), message.as_bytes())?)),
+            Command::Notifications { table, events } => {
+                let table = self.table(&table)?;
+                self.client.set_notifications(table, events)?;
+                Ok(Value::Null)
+            }
+            Command::Watch { table, key, seconds } => {
+                let table = self.table(&table)?;
+                let timeout = if seconds == 0 { None } else { Some(Duration::from_secs(seconds)) };
+                match self.client.watch(table, key.as_bytes(), timeout)? {
+                    Some(event) => Ok(Value::from(notifications::event_name(event))),
+                    None => Ok(Value::Null)
+                }
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,107 @@
+use std::{
+    fs,
+    io::Write,
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread,
+    time::Duration
+};
+use serde_json::Value;
+use dbms::{
+    server::{cfg::Config, server::Server},
+    storage::Storage
+};
+
+/// Runs the server in this process and returns its TCP address.
+fn start_server(dir: &PathBuf) -> String {
+    let _ = fs::remove_dir_all(dir);
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let tcp_addr = format!("127.0.0.1:{}", port);
+    let config = Config {
+        tcp_addr: tcp_addr.clone(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        password: "secret".to_string(),
+        ..Config::default()
+    };
+    thread::spawn(move || Server::with_config(storage, config).run());
+    for _ in 0..500 {
+        if TcpStream::connect(&tcp_addr).is_ok() {
+            return tcp_addr;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("server hasn't started");
+}
+
+fn run_script(addr: &str, script: &str) -> (Vec<Value>, bool) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_dbms-cli"))
+        .args(["--tcp", addr, "--password", "secret", "--json", "--script"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(script.as_bytes()).unwrap();
+    let output = child.wait_with_output().unwrap();
+    let lines = String::from_utf8(output.stdout).unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    (lines, output.status.success())
+}
+
+#[test]
+fn script() {
+    let dir: PathBuf = "test_data_cli".into();
+    let addr = start_server(&dir);
+
+    let (results, is_ok) = run_script(&addr, r#"
+# comments and empty lines are skipped
+ping
+create memory users {"sized_fields": {"age": "Uint8"}, "unsized_fields": {"name": "String", "avatar": "ByteSlice"}}
+create cache sessions 10 nolog
+tables
+set users bob {"age": 30, "name": "Bob Smith", "avatar": [1, 2, 255]}
+insert users bob {"age": 31, "name": "Bob", "avatar": "0x"}
+get users bob
+get users bob name age
+set sessions "key with spaces" hello world
+get 1 "key with spaces"
+delete users bob
+get users bob
+scheme users
+shards
+rebalance dry
+publish news hello world
+notifications users set delete
+watch users bob 1
+create lsm events
+"#);
+    assert!(is_ok);
+    let result = |i: usize| &results[i]["result"];
+    assert!(results.iter().all(|line| line["ok"] == true));
+    assert_eq!(result(0), "PONG");
+    assert_eq!(result(1)["number"], 0);
+    assert_eq!(result(2)["number"], 1);
+    assert_eq!(result(3)[1]["name"], "sessions");
+    assert_eq!(result(6), &serde_json::json!({ "age": 30, "name": "Bob Smith", "avatar": "0x0102ff" }));
+    assert_eq!(result(7), &serde_json::json!({ "age": 30, "name": "Bob Smith" }));
+    assert_eq!(result(9), "hello world");
+    assert_eq!(result(11), &Value::Null);
+    assert_eq!(result(12)["unsized_fields"]["avatar"], "ByteSlice");
+    assert_eq!(result(13)[0], serde_json::json!({ "from": 0, "to": 65535, "node": 0 }));
+    assert_eq!(result(14), &serde_json::json!([]));
+    assert_eq!(result(15), 0);
+    assert_eq!(result(16), &Value::Null);
+    assert_eq!(result(17), &Value::Null);
+    assert_eq!(result(18)["number"], 2);
+
+    let (results, is_ok) = run_script(&addr, "get users\nset users alice {\"age\": 300}\nget missing key\n");
+    assert!(!is_ok);
+    assert_eq!(results.len(), 3);
+    assert!(results.iter().all(|line| line["ok"] == false));
+    assert!(results[2]["error"]["message"].as_str().unwrap().contains("missing"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
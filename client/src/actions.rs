@@ -0,0 +1,45 @@
+//! Copy of the server `constants::actions`. Numbers are a part of the protocol, so they never change.
+pub const DONE: u8 = 0u8;
+pub const BAD_REQUEST: u8 = 1u8;
+pub const INTERNAL_ERROR: u8 = 2u8;
+pub const TABLE_NOT_FOUND: u8 = 3u8;
+pub const NOT_FOUND: u8 = 4u8;
+
+pub const CREATE_TABLE_IN_MEMORY: u8 = 5u8;
+pub const CREATE_TABLE_CACHE: u8 = 6u8;
+
+pub const CREATE_TABLE_ON_DISK: u8 = 7u8;
+pub const GET_TABLES_NAMES: u8 = 8u8;
+
+pub const PING: u8 = 9u8;
+pub const GET_SHARD_METADATA: u8 = 10u8;
+pub const GET_HIERARCHY: u8 = 11u8;
+
+pub const GET: u8 = 12u8;
+pub const GET_FIELD: u8 = 13u8;
+pub const GET_FIELDS: u8 = 14u8;
+pub const INSERT: u8 = 15u8;
+pub const SET: u8 = 16u8;
+pub const DELETE: u8 = 17u8;
+
+pub const GET_TABLE_SCHEME: u8 = 18u8;
+pub const INFO: u8 = 19u8;
+pub const SLOWLOG_GET: u8 = 20u8;
+pub const SLOWLOG_RESET: u8 = 21u8;
+pub const CONFIG_GET: u8 = 22u8;
+pub const CONFIG_SET: u8 = 23u8;
+pub const SHUTDOWN: u8 = 24u8;
+pub const MIGRATE_SHARDS: u8 = 27u8;
+pub const REBALANCE: u8 = 30u8;
+pub const ADD_NODE: u8 = 31u8;
+pub const REMOVE_NODE: u8 = 32u8;
+pub const ADD_MACHINE: u8 = 33u8;
+pub const REMOVE_MACHINE: u8 = 34u8;
+pub const SUBSCRIBE_CHANGES: u8 = 36u8;
+pub const PUBLISH: u8 = 37u8;
+pub const SUBSCRIBE: u8 = 38u8;
+pub const PSUBSCRIBE: u8 = 39u8;
+pub const UNSUBSCRIBE: u8 = 40u8;
+pub const SET_NOTIFICATIONS: u8 = 41u8;
+pub const WATCH: u8 = 42u8;
+pub const CREATE_TABLE_LSM: u8 = 43u8;
@@ -0,0 +1,129 @@
+//! Stream of changes of tables. See `server::changes` of the server.
+use crate::{
+    actions,
+    connection::Connection,
+    errors::{Error, Result}
+};
+
+const CHANGES: u8 = 0;
+const HEARTBEAT: u8 = 1;
+const NO_VALUE: u32 = u32::MAX;
+
+/// Starts the stream from the oldest log file, that the server has kept.
+pub const FROM_OLDEST: u64 = 0;
+/// Starts the stream from the end of the log, so only new changes are streamed.
+pub const FROM_NOW: u64 = u64::MAX;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Change {
+    /// [`actions::INSERT`], [`actions::SET`] or [`actions::DELETE`].
+    pub action: u8,
+    /// Resume the stream from it to get changes after this one.
+    pub sequence_number: u64,
+    pub table: u16,
+    pub key: Vec<u8>,
+    /// Deletes have no value.
+    pub value: Option<Vec<u8>>,
+    /// Is known only if it was asked for and the stream has seen the key before.
+    pub old_value: Option<Vec<u8>>
+}
+
+/// Iterator over changes. It owns the connection, that isn't returned to the pool.
+pub struct ChangeStream {
+    connection: Connection,
+    changes: std::vec::IntoIter<Change>,
+    sequence_number: u64,
+    is_ended: bool
+}
+
+impl ChangeStream {
+    /// Sends the request and waits for the first heartbeat, so errors of the request are returned here.
+    pub(crate) fn start(mut connection: Connection, message: &[u8]) -> Result<Self> {
+        connection.send(message, true)?;
+        let mut stream = Self { connection, changes: Vec::new().into_iter(), sequence_number: 0, is_ended: false };
+        stream.receive()?;
+        Ok(stream)
+    }
+
+    /// Returns the sequence number of the last change or heartbeat. Changes of other tables move it too.
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    /// Reads the next frame: changes or a heartbeat.
+    fn receive(&mut self) -> Result<()> {
+        let frame = self.connection.receive()??;
+        match frame.first() {
+            Some(&CHANGES) => {
+                let changes = parse_changes(&frame[1..])?;
+                if let Some(last) = changes.last() {
+                    self.sequence_number = last.sequence_number;
+                }
+                self.changes = changes.into_iter();
+                Ok(())
+            }
+            Some(&HEARTBEAT) if frame.len() == 9 => {
+                self.sequence_number = u64::from_le_bytes(frame[1..9].try_into().unwrap());
+                Ok(())
+            }
+            _ => Err(Error::Protocol("unknown frame of changes"))
+        }
+    }
+}
+
+impl Iterator for ChangeStream {
+    type Item = Result<Change>;
+
+    /// Waits for the next change. Heartbeats are skipped. After an error the stream ends.
+    fn next(&mut self) -> Option<Result<Change>> {
+        loop {
+            if let Some(change) = self.changes.next() {
+                return Some(Ok(change));
+            }
+            if self.is_ended {
+                return None;
+            }
+            if let Err(e) = self.receive() {
+                self.is_ended = true;
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if buf.len() < len {
+        return Err(Error::Protocol("the change is too short"));
+    }
+    let (taken, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(taken)
+}
+
+fn take_value(buf: &mut &[u8]) -> Result<Option<Vec<u8>>> {
+    let len = u32::from_le_bytes(take(buf, 4)?.try_into().unwrap());
+    if len == NO_VALUE {
+        return Ok(None);
+    }
+    Ok(Some(take(buf, len as usize)?.to_vec()))
+}
+
+/// Every change is [`action`, `sequence number` (8 bytes), `table` (2 bytes), `key length` (2 bytes), `key`,
+/// `value length` (4 bytes), `value`, `old value length` (4 bytes), `old value`].
+fn parse_changes(mut buf: &[u8]) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    while !buf.is_empty() {
+        let action = take(&mut buf, 1)?[0];
+        if !matches!(action, actions::INSERT | actions::SET | actions::DELETE) {
+            return Err(Error::Protocol("unknown action of the change"));
+        }
+        let sequence_number = u64::from_le_bytes(take(&mut buf, 8)?.try_into().unwrap());
+        let table = u16::from_le_bytes(take(&mut buf, 2)?.try_into().unwrap());
+        let key_len = u16::from_le_bytes(take(&mut buf, 2)?.try_into().unwrap());
+        let key = take(&mut buf, key_len as usize)?.to_vec();
+        let value = take_value(&mut buf)?;
+        let old_value = take_value(&mut buf)?;
+        changes.push(Change { action, sequence_number, table, key, value, old_value });
+    }
+    Ok(changes)
+}
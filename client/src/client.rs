@@ -0,0 +1,296 @@
+use std::{sync::Arc, time::Duration};
+use crate::{
+    actions,
+    changes::ChangeStream,
+    errors::{codes, Error, Result},
+    messages,
+    pipeline::Pipeline,
+    pool::{Pool, PoolConfig},
+    pubsub::Subscription,
+    scheme::{read_prefixed, Scheme},
+    stream::Address
+};
+
+/// Client of the dbms server. It is cheap to clone: all clones share one [`Pool`].
+///
+/// Tables are identified by numbers, that the server returns on table creation.
+#[derive(Clone)]
+pub struct Client {
+    pool: Arc<Pool>
+}
+
+impl Client {
+    /// Creates the pool and checks the connection (and the password) with `ping`.
+    pub fn connect(address: Address, config: PoolConfig) -> Result<Self> {
+        let client = Self { pool: Arc::new(Pool::new(address, config)) };
+        client.ping()?;
+        Ok(client)
+    }
+
+    pub fn pool(&self) -> &Pool {
+        &self.pool
+    }
+
+    fn execute_one(&self, message: &[u8], is_reading: bool) -> Result<Vec<u8>> {
+        self.pool.get()?.execute_one(message, is_reading)
+    }
+
+    /// Like [`Client::execute_one`], but the missing key is `None`.
+    fn execute_optional(&self, message: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.execute_one(message, true) {
+            Ok(answer) => Ok(Some(answer)),
+            Err(e) if e.code() == Some(codes::KEY_IS_NOT_FOUND) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Sends all messages of the pipeline in one request. See [`Pipeline`].
+    pub fn execute(&self, pipeline: &Pipeline) -> Result<Vec<Result<Vec<u8>>>> {
+        self.pool.get()?.execute(pipeline.messages(), pipeline.is_reading())
+    }
+
+    pub fn ping(&self) -> Result<()> {
+        let answer = self.execute_one(&messages::ping(), true)?;
+        if answer != [actions::PING] {
+            return Err(Error::Protocol("wrong answer to ping"));
+        }
+        Ok(())
+    }
+
+    /// Creates the table, that stores all data in memory. Logged tables are restored after the restart.
+    pub fn create_table_in_memory(&self, name: &str, scheme: &Scheme, is_logging: bool) -> Result<u16> {
+        parse_table_number(self.execute_one(&messages::create_table_in_memory(name, scheme, is_logging)?, false)?)
+    }
+
+    /// Creates the table, that removes keys after `cache_duration` minutes.
+    pub fn create_table_cache(&self, name: &str, scheme: &Scheme, cache_duration: u64, is_logging: bool) -> Result<u16> {
+        parse_table_number(self.execute_one(&messages::create_table_cache(name, scheme, cache_duration, is_logging)?, false)?)
+    }
+
+    /// Creates the table, that stores values on disk and keys in memory.
+    pub fn create_table_on_disk(&self, name: &str, scheme: &Scheme) -> Result<u16> {
+        parse_table_number(self.execute_one(&messages::create_table_on_disk(name, scheme)?, false)?)
+    }
+
+    /// Creates the table on the LSM tree. Keys and values are on disk, so it can be bigger than the memory.
+    pub fn create_table_lsm(&self, name: &str, scheme: &Scheme) -> Result<u16> {
+        parse_table_number(self.execute_one(&messages::create_table_lsm(name, scheme)?, false)?)
+    }
+
+    /// Returns names of all tables. The index of the name is the number of the table.
+    pub fn get_tables_names(&self) -> Result<Vec<String>> {
+        let answer = self.execute_one(&messages::get_tables_names(), true)?;
+        let mut names = Vec::new();
+        let mut offset = 0;
+        while offset < answer.len() {
+            let (name, read) = read_prefixed(&answer[offset..])?;
+            names.push(String::from_utf8_lossy(name).to_string());
+            offset += read;
+        }
+        Ok(names)
+    }
+
+    /// Returns the scheme, that the table was created with.
+    pub fn get_table_scheme(&self, table: u16) -> Result<Scheme> {
+        Scheme::from_json(&self.execute_one(&messages::get_table_scheme(table), true)?)
+    }
+
+    /// Returns the report of the server: uptime, persistence, tables, clients and the role in the cluster.
+    pub fn info(&self) -> Result<serde_json::Value> {
+        let answer = self.execute_one(&messages::info(), true)?;
+        serde_json::from_slice(&answer).map_err(|_| Error::Protocol("the info is not valid JSON"))
+    }
+
+    /// Returns at most `count` entries of the slow log, the newest first.
+    pub fn slowlog_get(&self, count: u16) -> Result<serde_json::Value> {
+        let answer = self.execute_one(&messages::slowlog_get(count), true)?;
+        serde_json::from_slice(&answer).map_err(|_| Error::Protocol("the slow log is not valid JSON"))
+    }
+
+    /// Removes all entries of the slow log.
+    pub fn slowlog_reset(&self) -> Result<()> {
+        self.execute_one(&messages::slowlog_reset(), false)?;
+        Ok(())
+    }
+
+    /// Asks the server to shut down gracefully. `dump` overrides the `dump_on_shutdown` setting of the server.
+    /// The server answers before it stops accepting connections.
+    pub fn shutdown(&self, dump: Option<bool>) -> Result<()> {
+        self.execute_one(&messages::shutdown(dump), false)?;
+        Ok(())
+    }
+
+    /// Returns settings of the server as a JSON object: all of them, if `name` is empty, otherwise only this one.
+    pub fn config_get(&self, name: &str) -> Result<serde_json::Value> {
+        let answer = self.execute_one(&messages::config_get(name), true)?;
+        serde_json::from_slice(&answer).map_err(|_| Error::Protocol("the config is not valid JSON"))
+    }
+
+    /// Changes the setting at runtime. Only reloadable settings can be changed.
+    pub fn config_set(&self, name: &str, value: &str) -> Result<()> {
+        self.execute_one(&messages::config_set(name, value), false)?;
+        Ok(())
+    }
+
+    /// Returns the number of the node for every one of 65536 shards.
+    pub fn get_shard_metadata(&self) -> Result<Vec<u16>> {
+        let answer = self.execute_one(&messages::get_shard_metadata(), true)?;
+        Ok(answer.chunks_exact(2).map(|node| u16::from_le_bytes([node[0], node[1]])).collect())
+    }
+
+    /// Moves shards from `from` to `to` inclusive to the node `node`. It must be sent to the node, that owns the shards.
+    /// Returns `{"from", "to", "node", "keys"}`, where `keys` is the number of moved keys.
+    pub fn migrate_shards(&self, from: u16, to: u16, node: u16) -> Result<serde_json::Value> {
+        let answer = self.execute_one(&messages::migrate_shards(from, to, node), true)?;
+        serde_json::from_slice(&answer).map_err(|_| Error::Protocol("the migration result is not valid JSON"))
+    }
+
+    /// Moves shards, so all nodes have the same number of them, and returns the moves `{"from", "to", "source", "target", "keys"}`.
+    /// The dry run returns the moves without `keys` and moves nothing.
+    pub fn rebalance(&self, is_dry_run: bool) -> Result<serde_json::Value> {
+        let answer = self.execute_one(&messages::rebalance(is_dry_run), true)?;
+        serde_json::from_slice(&answer).map_err(|_| Error::Protocol("the rebalance plan is not valid JSON"))
+    }
+
+    /// Adds the node with these machines to the end of the hierarchy. All hierarchy changes return
+    /// `{"epoch", "hierarchy", "unreachable"}`, where `unreachable` are machines, that haven't got the new hierarchy.
+    pub fn add_node(&self, machines: &[&str]) -> Result<serde_json::Value> {
+        self.change_hierarchy(&messages::add_node(machines))
+    }
+
+    /// Removes the node without shards. Next nodes get numbers one less.
+    pub fn remove_node(&self, node: u16) -> Result<serde_json::Value> {
+        self.change_hierarchy(&messages::remove_node(node))
+    }
+
+    pub fn add_machine(&self, node: u16, addr: &str) -> Result<serde_json::Value> {
+        self.change_hierarchy(&messages::add_machine(node, addr))
+    }
+
+    /// Removes the machine, that isn't the last one of its node.
+    pub fn remove_machine(&self, addr: &str) -> Result<serde_json::Value> {
+        self.change_hierarchy(&messages::remove_machine(addr))
+    }
+
+    fn change_hierarchy(&self, message: &[u8]) -> Result<serde_json::Value> {
+        let answer = self.execute_one(message, true)?;
+        serde_json::from_slice(&answer).map_err(|_| Error::Protocol("the hierarchy change result is not valid JSON"))
+    }
+
+    /// Returns addresses of machines of every node.
+    pub fn get_hierarchy(&self) -> Result<Vec<Vec<String>>> {
+        let answer = self.execute_one(&messages::get_hierarchy(), true)?;
+        let mut hierarchy = Vec::new();
+        let mut offset = 0;
+        while offset < answer.len() {
+            let number_of_machines = answer[offset] as usize;
+            offset += 1;
+            let mut node = Vec::with_capacity(number_of_machines);
+            for _ in 0..number_of_machines {
+                let (addr, read) = read_prefixed(&answer[offset..])?;
+                node.push(String::from_utf8_lossy(addr).to_string());
+                offset += read;
+            }
+            hierarchy.push(node);
+        }
+        Ok(hierarchy)
+    }
+
+    /// Streams inserts, sets and deletes of `tables` (all tables, if it is empty) after `sequence_number`.
+    /// Use [`changes::FROM_OLDEST`](crate::changes::FROM_OLDEST), [`changes::FROM_NOW`](crate::changes::FROM_NOW)
+    /// or [`ChangeStream::sequence_number`] of the previous stream. The stream has its own connection, and the server sends
+    /// a heartbeat every second, so the timeout of the pool must be longer.
+    pub fn subscribe_changes(&self, tables: &[u16], sequence_number: u64, with_old_values: bool) -> Result<ChangeStream> {
+        ChangeStream::start(self.pool.connect()?, &messages::subscribe_changes(tables, sequence_number, with_old_values))
+    }
+
+    /// Returns the number of subscribers, that have got the message.
+    pub fn publish(&self, channel: &[u8], message: &[u8]) -> Result<u32> {
+        let answer = self.execute_one(&messages::publish(channel, message)?, false)?;
+        if answer.len() != 4 {
+            return Err(Error::Protocol("wrong answer to publish"));
+        }
+        Ok(u32::from_le_bytes(answer[..4].try_into().unwrap()))
+    }
+
+    /// Subscribes to channels and patterns on a new connection. The server doesn't send heartbeats to subscribers,
+    /// so use a pool without the timeout to wait for rare messages.
+    pub fn subscribe(&self, channels: &[&[u8]], patterns: &[&[u8]]) -> Result<Subscription> {
+        if channels.is_empty() && patterns.is_empty() {
+            return Err(Error::Scheme("nothing to subscribe to".to_string()));
+        }
+        Subscription::start(self.pool.connect()?, channels, patterns)
+    }
+
+    /// Chooses events of the table, that are published to keyspace channels. See [`notifications`](crate::notifications).
+    /// Events are bits like [`notifications::SET`](crate::notifications::SET), 0 turns notifications off.
+    pub fn set_notifications(&self, table: u16, events: u8) -> Result<()> {
+        self.execute_one(&messages::set_notifications(table, events), false)?;
+        Ok(())
+    }
+
+    /// Waits, until the key is changed, and returns the event. Returns `None`, if the timeout has passed.
+    /// The timeout must be shorter than the timeout of the pool. `None` timeout waits forever.
+    pub fn watch(&self, table: u16, key: &[u8], timeout: Option<Duration>) -> Result<Option<u8>> {
+        let timeout_millis = timeout.map(|timeout| timeout.as_millis().clamp(1, u32::MAX as u128) as u32).unwrap_or(0);
+        let answer = self.execute_one(&messages::watch(table, key, timeout_millis)?, true)?;
+        match answer.first() {
+            Some(0) => Ok(None),
+            Some(&event) => Ok(Some(event)),
+            None => Err(Error::Protocol("wrong answer to watch"))
+        }
+    }
+
+    pub fn get(&self, table: u16, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.execute_optional(&messages::get(table, key)?)
+    }
+
+    /// Returns the field without the length prefix. Decode it with [`Scheme::decode_field`].
+    pub fn get_field(&self, table: u16, key: &[u8], field: u16) -> Result<Option<Vec<u8>>> {
+        match self.execute_optional(&messages::get_field(table, key, field)?)? {
+            Some(answer) => Ok(Some(read_prefixed(&answer)?.0.to_vec())),
+            None => Ok(None)
+        }
+    }
+
+    /// Returns fields in the requested order without length prefixes.
+    pub fn get_fields(&self, table: u16, key: &[u8], fields: &[u16]) -> Result<Option<Vec<Vec<u8>>>> {
+        match self.execute_optional(&messages::get_fields(table, key, fields)?)? {
+            Some(answer) => Ok(Some(split_fields(&answer)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Inserts the value, if the key doesn't exist.
+    pub fn insert(&self, table: u16, key: &[u8], value: &[u8]) -> Result<()> {
+        self.execute_one(&messages::insert(table, key, value)?, false).map(|_| ())
+    }
+
+    /// Inserts or replaces the value.
+    pub fn set(&self, table: u16, key: &[u8], value: &[u8]) -> Result<()> {
+        self.execute_one(&messages::set(table, key, value)?, false).map(|_| ())
+    }
+
+    pub fn delete(&self, table: u16, key: &[u8]) -> Result<()> {
+        self.execute_one(&messages::delete(table, key)?, false).map(|_| ())
+    }
+}
+
+fn parse_table_number(answer: Vec<u8>) -> Result<u16> {
+    if answer.len() < 2 {
+        return Err(Error::Protocol("the answer has no table number"));
+    }
+    Ok(u16::from_le_bytes([answer[0], answer[1]]))
+}
+
+/// Splits the answer of `get_fields` into fields without length prefixes.
+pub fn split_fields(answer: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    while offset < answer.len() {
+        let (field, read) = read_prefixed(&answer[offset..])?;
+        fields.push(field.to_vec());
+        offset += read;
+    }
+    Ok(fields)
+}
\ No newline at end of file
@@ -0,0 +1,179 @@
+use std::{
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    time::Duration
+};
+use crate::{
+    actions,
+    errors::{Error, Result},
+    stream::{Address, Stream}
+};
+
+/// Size of buffers for reading and writing. It is the same as the server uses.
+const BUFFER_SIZE: usize = u16::MAX as usize;
+
+/// One connection to the server.
+///
+/// The request is [`size` (4 bytes), `is reading` (1 byte), messages...], where every message is
+/// [`length` (2 bytes or [255, 255, 4 bytes]), `message`]. The server answers every message with one message
+/// in the same order and flushes answers only when the whole request is handled.
+pub struct Connection {
+    reader: BufReader<Stream>,
+    writer: BufWriter<Stream>,
+    is_broken: bool
+}
+
+impl Connection {
+    /// Connects to the server and sends the password, if it is not empty.
+    ///
+    /// The server reads exactly as many bytes as its password has, so a shorter password blocks until the timeout.
+    pub fn connect(address: &Address, password: &str, timeout: Option<Duration>) -> Result<Self> {
+        let stream = address.connect(timeout)?;
+        let mut connection = Self {
+            reader: BufReader::with_capacity(BUFFER_SIZE, stream.try_clone()?),
+            writer: BufWriter::with_capacity(BUFFER_SIZE, stream),
+            is_broken: false
+        };
+        if !password.is_empty() {
+            connection.writer.write_all(password.as_bytes())?;
+            connection.writer.flush()?;
+            let mut status = [0u8; 1];
+            match connection.reader.read_exact(&mut status) {
+                Ok(()) if status[0] == actions::DONE => {}
+                Ok(()) => return Err(Error::WrongPassword),
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof || e.kind() == ErrorKind::ConnectionReset => {
+                    return Err(Error::WrongPassword);
+                }
+                Err(e) => return Err(Error::Io(e))
+            }
+        }
+        Ok(connection)
+    }
+
+    /// Returns true, if the last request failed in the middle and the connection must be closed.
+    pub fn is_broken(&self) -> bool {
+        self.is_broken
+    }
+
+    /// Sends all messages in one request and returns the answer for every message.
+    ///
+    /// The outer error means, that the connection is broken. The inner errors are errors of the messages.
+    /// The answer is the message without the [`actions::DONE`] status.
+    pub fn execute<M: AsRef<[u8]>>(&mut self, messages: &[M], is_reading: bool) -> Result<Vec<Result<Vec<u8>>>> {
+        if self.is_broken {
+            return Err(Error::Protocol("the connection is broken"));
+        }
+        let res = self.execute_unchecked(messages, is_reading);
+        if res.is_err() {
+            self.is_broken = true;
+        }
+        res
+    }
+
+    /// Sends one message and returns its answer.
+    pub fn execute_one(&mut self, message: &[u8], is_reading: bool) -> Result<Vec<u8>> {
+        match self.execute(&[message], is_reading)?.pop() {
+            Some(res) => res,
+            None => Err(Error::Protocol("the server hasn't answered"))
+        }
+    }
+
+    /// Sends one message and doesn't wait for the answer. It is used for streams, that answer with many messages.
+    pub fn send(&mut self, message: &[u8], is_reading: bool) -> Result<()> {
+        let res = self.send_unchecked(message, is_reading);
+        if res.is_err() {
+            self.is_broken = true;
+        }
+        res
+    }
+
+    fn send_unchecked(&mut self, message: &[u8], is_reading: bool) -> Result<()> {
+        self.writer.write_all(&((len_size(message.len()) + message.len()) as u32).to_le_bytes())?;
+        self.writer.write_all(&[is_reading as u8])?;
+        write_len(&mut self.writer, message.len())?;
+        self.writer.write_all(message)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads the next message of the stream. The outer error means, that the connection is broken.
+    pub fn receive(&mut self) -> Result<Result<Vec<u8>>> {
+        match self.read_message() {
+            Ok(message) => Ok(parse_answer(message)),
+            Err(e) => {
+                self.is_broken = true;
+                Err(e)
+            }
+        }
+    }
+
+    fn execute_unchecked<M: AsRef<[u8]>>(&mut self, messages: &[M], is_reading: bool) -> Result<Vec<Result<Vec<u8>>>> {
+        let mut size = 0usize;
+        for message in messages.iter() {
+            size += len_size(message.as_ref().len()) + message.as_ref().len();
+        }
+        if size > u32::MAX as usize {
+            return Err(Error::Protocol("the request is too big"));
+        }
+        self.writer.write_all(&(size as u32).to_le_bytes())?;
+        self.writer.write_all(&[is_reading as u8])?;
+        for message in messages.iter() {
+            let message = message.as_ref();
+            write_len(&mut self.writer, message.len())?;
+            self.writer.write_all(message)?;
+        }
+        self.writer.flush()?;
+
+        let mut answers = Vec::with_capacity(messages.len());
+        for _ in 0..messages.len() {
+            answers.push(parse_answer(self.read_message()?));
+        }
+        Ok(answers)
+    }
+
+    fn read_message(&mut self) -> Result<Vec<u8>> {
+        let mut len_buf = [0u8; 4];
+        self.reader.read_exact(&mut len_buf[..2])?;
+        let mut len = u16::from_le_bytes([len_buf[0], len_buf[1]]) as usize;
+        if len == u16::MAX as usize {
+            self.reader.read_exact(&mut len_buf)?;
+            len = u32::from_le_bytes(len_buf) as usize;
+        }
+        let mut message = vec![0u8; len];
+        self.reader.read_exact(&mut message)?;
+        Ok(message)
+    }
+}
+
+#[inline(always)]
+fn len_size(len: usize) -> usize {
+    if len < u16::MAX as usize { 2 } else { 6 }
+}
+
+#[inline(always)]
+fn write_len<W: Write>(writer: &mut W, len: usize) -> std::io::Result<()> {
+    if len < u16::MAX as usize {
+        writer.write_all(&(len as u16).to_le_bytes())
+    } else {
+        writer.write_all(&[255, 255])?;
+        writer.write_all(&(len as u32).to_le_bytes())
+    }
+}
+
+/// Answer is [`actions::DONE`, `body`] or [`status`, `code` (2 bytes), `message`].
+fn parse_answer(mut answer: Vec<u8>) -> Result<Vec<u8>> {
+    if answer.is_empty() {
+        return Err(Error::Protocol("empty answer"));
+    }
+    if answer[0] == actions::DONE {
+        answer.remove(0);
+        return Ok(answer);
+    }
+    if answer.len() < 3 {
+        return Err(Error::Server { status: answer[0], code: 0, message: String::new() });
+    }
+    Err(Error::Server {
+        status: answer[0],
+        code: u16::from_le_bytes([answer[1], answer[2]]),
+        message: String::from_utf8_lossy(&answer[3..]).to_string()
+    })
+}
\ No newline at end of file
@@ -0,0 +1,151 @@
+use std::{fmt, io};
+
+/// Stable error codes, that the server sends with every error. See `constants::errors` in the server.
+pub mod codes {
+    pub const UNKNOWN_ACTION: u16 = 100;
+    pub const MESSAGE_IS_TOO_SHORT: u16 = 101;
+    pub const TABLE_NAME_IS_NOT_UTF8: u16 = 102;
+    pub const TABLE_NAME_IS_TOO_LONG: u16 = 103;
+
+    pub const SCHEME_IS_NOT_VALID_JSON: u16 = 200;
+    pub const FIELD_TYPE_IS_NOT_STRING: u16 = 201;
+    pub const UNKNOWN_FIELD_TYPE: u16 = 202;
+    pub const UNKNOWN_COMPRESSION: u16 = 203;
+
+    pub const TABLE_IS_NOT_FOUND: u16 = 300;
+    pub const TABLE_ALREADY_EXISTS: u16 = 301;
+
+    pub const KEY_IS_NOT_FOUND: u16 = 400;
+    pub const FIELD_IS_NOT_FOUND: u16 = 401;
+    pub const VALUE_DOES_NOT_MATCH_SCHEME: u16 = 402;
+    pub const KEY_IS_TOO_LONG: u16 = 403;
+    pub const KEY_ALREADY_EXISTS: u16 = 404;
+    pub const FIELD_IS_MISSING: u16 = 405;
+    pub const FIELD_HAS_WRONG_TYPE: u16 = 406;
+
+    pub const TABLES_LOCK_IS_POISONED: u16 = 500;
+    pub const CANT_READ_SHARD_METADATA: u16 = 501;
+    pub const CANT_CREATE_TABLE: u16 = 502;
+    pub const SERVER_IS_SHUTTING_DOWN: u16 = 503;
+    pub const VALUE_CANT_BE_READ: u16 = 504;
+    pub const VALUE_IS_BROKEN: u16 = 505;
+
+    pub const UNAUTHORIZED: u16 = 600;
+    pub const ROUTE_IS_NOT_FOUND: u16 = 601;
+    pub const METHOD_IS_NOT_ALLOWED: u16 = 602;
+    pub const BODY_IS_NOT_VALID_JSON: u16 = 603;
+    pub const BODY_IS_TOO_LARGE: u16 = 604;
+    pub const UNKNOWN_TABLE_ENGINE: u16 = 605;
+
+    pub const UNKNOWN_SETTING: u16 = 700;
+    pub const SETTING_VALUE_IS_NOT_VALID: u16 = 701;
+    pub const SETTING_IS_NOT_RELOADABLE: u16 = 702;
+    pub const SETTING_NAME_IS_NOT_UTF8: u16 = 703;
+    pub const CONFIG_LOCK_IS_POISONED: u16 = 704;
+
+    pub const SERVER_IS_READ_ONLY_FOLLOWER: u16 = 800;
+    pub const FOLLOWER_IS_TOO_FAR_BEHIND: u16 = 801;
+    pub const NOT_THE_LEADER: u16 = 802;
+    pub const KEY_IS_MOVED: u16 = 803;
+    pub const NODE_IS_NOT_FOUND: u16 = 804;
+    pub const SHARDS_ARE_NOT_OWNED: u16 = 805;
+    pub const MIGRATION_IS_RUNNING: u16 = 806;
+    pub const MIGRATION_HAS_FAILED: u16 = 807;
+    pub const TABLES_OF_NODES_DIFFER: u16 = 808;
+    pub const RECORDS_ARE_NOT_VALID: u16 = 809;
+    pub const MACHINE_ALREADY_EXISTS: u16 = 810;
+    pub const MACHINE_IS_NOT_FOUND: u16 = 811;
+    pub const NODE_OWNS_SHARDS: u16 = 812;
+    pub const NODE_NEEDS_A_MACHINE: u16 = 813;
+    pub const HIERARCHY_IS_NOT_VALID: u16 = 814;
+
+    pub const SEQUENCE_NUMBER_IS_NOT_RETAINED: u16 = 900;
+    pub const LOG_RECORD_IS_NOT_VALID: u16 = 901;
+    pub const CONNECTION_IS_SUBSCRIBED: u16 = 902;
+    pub const PUSH_MODE_IS_NOT_AVAILABLE: u16 = 903;
+    pub const EVENTS_ARE_NOT_VALID: u16 = 904;
+    pub const PATTERN_IS_TOO_LONG: u16 = 905;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The server has answered with an error.
+    Server {
+        status: u8,
+        code: u16,
+        message: String
+    },
+    /// The server has answered with something, that we can't parse.
+    Protocol(&'static str),
+    /// The server has closed the connection after the password.
+    WrongPassword,
+    /// The scheme or the value built by the user is not valid. It is never sent to the server.
+    Scheme(String)
+}
+
+impl Error {
+    /// Returns the stable code of the server error. See [`codes`].
+    pub fn code(&self) -> Option<u16> {
+        match self {
+            Error::Server { code, .. } => Some(*code),
+            _ => None
+        }
+    }
+
+    /// Returns the address of the leader of the node, if the request was sent to another machine of the node.
+    /// The request can be repeated there.
+    pub fn leader_addr(&self) -> Option<&str> {
+        match self {
+            Error::Server { code: codes::NOT_THE_LEADER, message, .. } => Some(message),
+            _ => None
+        }
+    }
+
+    /// Returns the shard of the key, the number of the node, that has the shard, and the address of the node,
+    /// if the key was sent to another node.
+    pub fn moved(&self) -> Option<(u16, u16, &str)> {
+        let message = match self {
+            Error::Server { code: codes::KEY_IS_MOVED, message, .. } => message.strip_prefix("MOVED ")?,
+            _ => return None
+        };
+        let mut parts = message.splitn(3, ' ');
+        let shard = parts.next()?.parse().ok()?;
+        let node = parts.next()?.parse().ok()?;
+        Some((shard, node, parts.next().unwrap_or("")))
+    }
+
+    /// Returns true, if the connection can't be used after this error.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Io(_) | Error::Protocol(_) | Error::WrongPassword)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "io error: {}", e),
+            Error::Server { status, code, message } => write!(f, "server error {} (status {}): {}", code, status, message),
+            Error::Protocol(message) => write!(f, "protocol error: {}", message),
+            Error::WrongPassword => write!(f, "wrong password"),
+            Error::Scheme(message) => write!(f, "scheme error: {}", message)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
@@ -0,0 +1,38 @@
+//! Client for the dbms server.
+//!
+//! ```no_run
+//! use dbms_client::{Address, Client, FieldType, PoolConfig, SchemeBuilder};
+//!
+//! let client = Client::connect(Address::tcp("localhost:10000"), PoolConfig::default()).unwrap();
+//! let scheme = SchemeBuilder::new()
+//!     .field("age", FieldType::Uint32)
+//!     .field("name", FieldType::String)
+//!     .build()
+//!     .unwrap();
+//! let users = client.create_table_in_memory("users", &scheme, true).unwrap();
+//! client.insert(users, b"bob", &scheme.encode(&[("age", 30u32.into()), ("name", "Bob".into())]).unwrap()).unwrap();
+//! ```
+pub mod actions;
+pub mod changes;
+pub mod client;
+pub mod connection;
+pub mod errors;
+pub mod messages;
+pub mod notifications;
+pub mod pipeline;
+pub mod pool;
+pub mod pubsub;
+pub mod scheme;
+pub mod sharding;
+pub mod stream;
+
+pub use changes::{Change, ChangeStream};
+pub use client::Client;
+pub use connection::Connection;
+pub use errors::{Error, Result};
+pub use pipeline::Pipeline;
+pub use pool::{Pool, PoolConfig, PooledConnection};
+pub use pubsub::{Message, Subscription};
+pub use scheme::{Compression, Field, FieldType, Scheme, SchemeBuilder};
+pub use sharding::shard_of;
+pub use stream::{Address, Stream};
\ No newline at end of file
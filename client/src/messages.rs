@@ -0,0 +1,302 @@
+//! Builders of messages. They mirror `server::reactions` of the server.
+use crate::{
+    actions,
+    errors::{Error, Result},
+    scheme::Scheme
+};
+
+fn table_message(action: u8, table: u16, capacity: usize) -> Vec<u8> {
+    let mut message = Vec::with_capacity(3 + capacity);
+    message.push(action);
+    message.extend_from_slice(&table.to_le_bytes());
+    message
+}
+
+fn check_key(key: &[u8]) -> Result<()> {
+    if key.len() > u16::MAX as usize {
+        return Err(Error::Scheme("key is too long".to_string()));
+    }
+    Ok(())
+}
+
+fn scheme_json(scheme: &Scheme) -> Result<String> {
+    let json = scheme.to_json();
+    if json.len() > u16::MAX as usize {
+        return Err(Error::Scheme("scheme is too long".to_string()));
+    }
+    Ok(json)
+}
+
+pub fn ping() -> Vec<u8> {
+    vec![actions::PING]
+}
+
+/// [`actions::CREATE_TABLE_IN_MEMORY`, `is logging`, `scheme length` (2 bytes), `scheme`, `name`]
+pub fn create_table_in_memory(name: &str, scheme: &Scheme, is_logging: bool) -> Result<Vec<u8>> {
+    let json = scheme_json(scheme)?;
+    let mut message = Vec::with_capacity(4 + json.len() + name.len());
+    message.extend_from_slice(&[actions::CREATE_TABLE_IN_MEMORY, is_logging as u8]);
+    message.extend_from_slice(&(json.len() as u16).to_le_bytes());
+    message.extend_from_slice(json.as_bytes());
+    message.extend_from_slice(name.as_bytes());
+    Ok(message)
+}
+
+/// [`actions::CREATE_TABLE_CACHE`, `is logging`, `cache duration` (8 bytes), `scheme length` (2 bytes), `scheme`, `name`]
+pub fn create_table_cache(name: &str, scheme: &Scheme, cache_duration: u64, is_logging: bool) -> Result<Vec<u8>> {
+    let json = scheme_json(scheme)?;
+    let mut message = Vec::with_capacity(12 + json.len() + name.len());
+    message.extend_from_slice(&[actions::CREATE_TABLE_CACHE, is_logging as u8]);
+    message.extend_from_slice(&cache_duration.to_le_bytes());
+    message.extend_from_slice(&(json.len() as u16).to_le_bytes());
+    message.extend_from_slice(json.as_bytes());
+    message.extend_from_slice(name.as_bytes());
+    Ok(message)
+}
+
+/// [`actions::CREATE_TABLE_ON_DISK`, `scheme length` (2 bytes), `scheme`, `name`]
+pub fn create_table_on_disk(name: &str, scheme: &Scheme) -> Result<Vec<u8>> {
+    let json = scheme_json(scheme)?;
+    let mut message = Vec::with_capacity(3 + json.len() + name.len());
+    message.push(actions::CREATE_TABLE_ON_DISK);
+    message.extend_from_slice(&(json.len() as u16).to_le_bytes());
+    message.extend_from_slice(json.as_bytes());
+    message.extend_from_slice(name.as_bytes());
+    Ok(message)
+}
+
+/// [`actions::CREATE_TABLE_LSM`, `scheme length` (2 bytes), `scheme`, `name`]
+pub fn create_table_lsm(name: &str, scheme: &Scheme) -> Result<Vec<u8>> {
+    let json = scheme_json(scheme)?;
+    let mut message = Vec::with_capacity(3 + json.len() + name.len());
+    message.push(actions::CREATE_TABLE_LSM);
+    message.extend_from_slice(&(json.len() as u16).to_le_bytes());
+    message.extend_from_slice(json.as_bytes());
+    message.extend_from_slice(name.as_bytes());
+    Ok(message)
+}
+
+pub fn get_tables_names() -> Vec<u8> {
+    vec![actions::GET_TABLES_NAMES]
+}
+
+/// [`actions::GET_TABLE_SCHEME`, `table` (2 bytes)]
+pub fn get_table_scheme(table: u16) -> Vec<u8> {
+    table_message(actions::GET_TABLE_SCHEME, table, 0)
+}
+
+pub fn info() -> Vec<u8> {
+    vec![actions::INFO]
+}
+
+/// [`actions::SLOWLOG_GET`, `count` (2 bytes)]
+pub fn slowlog_get(count: u16) -> Vec<u8> {
+    vec![actions::SLOWLOG_GET, count as u8, (count >> 8) as u8]
+}
+
+pub fn slowlog_reset() -> Vec<u8> {
+    vec![actions::SLOWLOG_RESET]
+}
+
+/// [`actions::SHUTDOWN`, `dump`]. Without `dump` the server uses its `dump_on_shutdown` setting.
+pub fn shutdown(dump: Option<bool>) -> Vec<u8> {
+    match dump {
+        Some(dump) => vec![actions::SHUTDOWN, dump as u8],
+        None => vec![actions::SHUTDOWN]
+    }
+}
+
+/// [`actions::CONFIG_GET`, `name`]. The empty name means all settings.
+pub fn config_get(name: &str) -> Vec<u8> {
+    let mut message = Vec::with_capacity(1 + name.len());
+    message.push(actions::CONFIG_GET);
+    message.extend_from_slice(name.as_bytes());
+    message
+}
+
+/// [`actions::CONFIG_SET`, `name size` (2 bytes), `name`, `value`]
+pub fn config_set(name: &str, value: &str) -> Vec<u8> {
+    let mut message = Vec::with_capacity(3 + name.len() + value.len());
+    message.push(actions::CONFIG_SET);
+    message.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    message.extend_from_slice(name.as_bytes());
+    message.extend_from_slice(value.as_bytes());
+    message
+}
+
+pub fn get_shard_metadata() -> Vec<u8> {
+    vec![actions::GET_SHARD_METADATA]
+}
+
+pub fn get_hierarchy() -> Vec<u8> {
+    vec![actions::GET_HIERARCHY]
+}
+
+/// [`actions::MIGRATE_SHARDS`, `from` (2 bytes), `to` (2 bytes), `node` (2 bytes)]
+pub fn migrate_shards(from: u16, to: u16, node: u16) -> Vec<u8> {
+    let mut message = Vec::with_capacity(7);
+    message.push(actions::MIGRATE_SHARDS);
+    message.extend_from_slice(&from.to_le_bytes());
+    message.extend_from_slice(&to.to_le_bytes());
+    message.extend_from_slice(&node.to_le_bytes());
+    message
+}
+
+/// [`actions::REBALANCE`, `is dry run`]
+pub fn rebalance(is_dry_run: bool) -> Vec<u8> {
+    vec![actions::REBALANCE, is_dry_run as u8]
+}
+
+/// [`actions::ADD_NODE`, [`address length` (2 bytes), `address`] for every machine]
+pub fn add_node(machines: &[&str]) -> Vec<u8> {
+    let mut message = vec![actions::ADD_NODE];
+    for addr in machines {
+        message.extend_from_slice(&(addr.len() as u16).to_le_bytes());
+        message.extend_from_slice(addr.as_bytes());
+    }
+    message
+}
+
+/// [`actions::REMOVE_NODE`, `node` (2 bytes)]
+pub fn remove_node(node: u16) -> Vec<u8> {
+    let mut message = vec![actions::REMOVE_NODE];
+    message.extend_from_slice(&node.to_le_bytes());
+    message
+}
+
+/// [`actions::ADD_MACHINE`, `node` (2 bytes), `address`]
+pub fn add_machine(node: u16, addr: &str) -> Vec<u8> {
+    let mut message = vec![actions::ADD_MACHINE];
+    message.extend_from_slice(&node.to_le_bytes());
+    message.extend_from_slice(addr.as_bytes());
+    message
+}
+
+/// [`actions::REMOVE_MACHINE`, `address`]
+pub fn remove_machine(addr: &str) -> Vec<u8> {
+    let mut message = vec![actions::REMOVE_MACHINE];
+    message.extend_from_slice(addr.as_bytes());
+    message
+}
+
+/// [`actions::SUBSCRIBE_CHANGES`, `flags`, `sequence number` (8 bytes), `tables` (2 bytes each)]
+pub fn subscribe_changes(tables: &[u16], sequence_number: u64, with_old_values: bool) -> Vec<u8> {
+    let mut message = Vec::with_capacity(10 + tables.len() * 2);
+    message.extend_from_slice(&[actions::SUBSCRIBE_CHANGES, with_old_values as u8]);
+    message.extend_from_slice(&sequence_number.to_le_bytes());
+    for table in tables {
+        message.extend_from_slice(&table.to_le_bytes());
+    }
+    message
+}
+
+/// [`actions::PUBLISH`, `channel length` (2 bytes), `channel`, `message`]
+pub fn publish(channel: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    let mut buf = names(actions::PUBLISH, &[channel])?;
+    buf.extend_from_slice(message);
+    Ok(buf)
+}
+
+/// [`action`, [`length` (2 bytes), `name`]...] for [`actions::SUBSCRIBE`] and [`actions::PSUBSCRIBE`]
+pub fn names(action: u8, names: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut message = vec![action];
+    for name in names {
+        if name.len() > u16::MAX as usize {
+            return Err(Error::Scheme("channel is too long".to_string()));
+        }
+        message.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        message.extend_from_slice(name);
+    }
+    Ok(message)
+}
+
+/// [`actions::UNSUBSCRIBE`, [`is pattern`, `length` (2 bytes), `name`]...]. No names mean all subscriptions.
+pub fn unsubscribe(channels: &[&[u8]], patterns: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut message = vec![actions::UNSUBSCRIBE];
+    for (is_pattern, names) in [(false, channels), (true, patterns)] {
+        for name in names {
+            if name.len() > u16::MAX as usize {
+                return Err(Error::Scheme("channel is too long".to_string()));
+            }
+            message.push(is_pattern as u8);
+            message.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            message.extend_from_slice(name);
+        }
+    }
+    Ok(message)
+}
+
+/// [`actions::SET_NOTIFICATIONS`, `table` (2 bytes), `events`]
+pub fn set_notifications(table: u16, events: u8) -> Vec<u8> {
+    let mut message = table_message(actions::SET_NOTIFICATIONS, table, 1);
+    message.push(events);
+    message
+}
+
+/// [`actions::WATCH`, `table` (2 bytes), `timeout` in milliseconds (4 bytes), `key`]
+pub fn watch(table: u16, key: &[u8], timeout_millis: u32) -> Result<Vec<u8>> {
+    check_key(key)?;
+    let mut message = table_message(actions::WATCH, table, 4 + key.len());
+    message.extend_from_slice(&timeout_millis.to_le_bytes());
+    message.extend_from_slice(key);
+    Ok(message)
+}
+
+/// [`actions::GET`, `table` (2 bytes), `key`]
+pub fn get(table: u16, key: &[u8]) -> Result<Vec<u8>> {
+    check_key(key)?;
+    let mut message = table_message(actions::GET, table, key.len());
+    message.extend_from_slice(key);
+    Ok(message)
+}
+
+/// [`actions::GET_FIELD`, `table` (2 bytes), `field` (2 bytes), `key`]
+pub fn get_field(table: u16, key: &[u8], field: u16) -> Result<Vec<u8>> {
+    check_key(key)?;
+    let mut message = table_message(actions::GET_FIELD, table, 2 + key.len());
+    message.extend_from_slice(&field.to_le_bytes());
+    message.extend_from_slice(key);
+    Ok(message)
+}
+
+/// [`actions::GET_FIELDS`, `table` (2 bytes), `number of fields` (2 bytes), `fields` (2 bytes each), `key`]
+pub fn get_fields(table: u16, key: &[u8], fields: &[u16]) -> Result<Vec<u8>> {
+    check_key(key)?;
+    if fields.len() > u16::MAX as usize {
+        return Err(Error::Scheme("too many fields".to_string()));
+    }
+    let mut message = table_message(actions::GET_FIELDS, table, 2 + fields.len() * 2 + key.len());
+    message.extend_from_slice(&(fields.len() as u16).to_le_bytes());
+    for field in fields.iter() {
+        message.extend_from_slice(&field.to_le_bytes());
+    }
+    message.extend_from_slice(key);
+    Ok(message)
+}
+
+fn write(action: u8, table: u16, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    check_key(key)?;
+    let mut message = table_message(action, table, 2 + key.len() + value.len());
+    message.extend_from_slice(&(key.len() as u16).to_le_bytes());
+    message.extend_from_slice(key);
+    message.extend_from_slice(value);
+    Ok(message)
+}
+
+/// [`actions::INSERT`, `table` (2 bytes), `key length` (2 bytes), `key`, `value`]
+pub fn insert(table: u16, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    write(actions::INSERT, table, key, value)
+}
+
+/// [`actions::SET`, `table` (2 bytes), `key length` (2 bytes), `key`, `value`]
+pub fn set(table: u16, key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    write(actions::SET, table, key, value)
+}
+
+/// [`actions::DELETE`, `table` (2 bytes), `key`]
+pub fn delete(table: u16, key: &[u8]) -> Result<Vec<u8>> {
+    check_key(key)?;
+    let mut message = table_message(actions::DELETE, table, key.len());
+    message.extend_from_slice(key);
+    Ok(message)
+}
\ No newline at end of file
@@ -0,0 +1,32 @@
+//! Keyspace notifications. See `table::notifications` of the server.
+//!
+//! Subscribe to [`keyspace_channel`] or [`keyevent_channel`] with [`Client::subscribe`](crate::Client::subscribe)
+//! after [`Client::set_notifications`](crate::Client::set_notifications).
+
+pub const SET: u8 = 1;
+pub const INSERT: u8 = 2;
+pub const DELETE: u8 = 4;
+pub const EXPIRE: u8 = 8;
+pub const ALL: u8 = SET | INSERT | DELETE | EXPIRE;
+
+pub fn event_name(event: u8) -> &'static str {
+    match event {
+        SET => "set",
+        INSERT => "insert",
+        DELETE => "delete",
+        EXPIRE => "expire",
+        _ => "unknown"
+    }
+}
+
+/// Gets names of events of the key.
+pub fn keyspace_channel(table: u16, key: &[u8]) -> Vec<u8> {
+    let mut channel = format!("__keyspace@{}__:", table).into_bytes();
+    channel.extend_from_slice(key);
+    channel
+}
+
+/// Gets keys of the event of the table.
+pub fn keyevent_channel(table: u16, event: u8) -> Vec<u8> {
+    format!("__keyevent@{}__:{}", table, event_name(event)).into_bytes()
+}
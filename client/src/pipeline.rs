@@ -0,0 +1,88 @@
+use crate::{
+    errors::Result,
+    messages
+};
+
+/// Many messages, that are sent in one request. The server answers all of them in one flush.
+///
+/// Answers are returned by [`crate::Client::execute`] in the same order as messages were added.
+/// Every answer is the body of the message without the status, like the answer of
+/// [`crate::Connection::execute`]. `get` of the missing key is an error with
+/// [`crate::errors::codes::KEY_IS_NOT_FOUND`] code.
+#[derive(Clone, Debug)]
+pub struct Pipeline {
+    messages: Vec<Vec<u8>>,
+    is_reading: bool
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self {
+            messages: Vec::new(),
+            is_reading: true
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.messages.clear();
+        self.is_reading = true;
+    }
+
+    pub fn messages(&self) -> &[Vec<u8>] {
+        &self.messages
+    }
+
+    /// Returns true, if the pipeline has only reading messages.
+    pub fn is_reading(&self) -> bool {
+        self.is_reading
+    }
+
+    /// Adds the message as is. Use it for actions, that the pipeline doesn't support.
+    pub fn raw(&mut self, message: Vec<u8>, is_reading: bool) -> &mut Self {
+        self.is_reading &= is_reading;
+        self.messages.push(message);
+        self
+    }
+
+    pub fn ping(&mut self) -> &mut Self {
+        self.raw(messages::ping(), true)
+    }
+
+    pub fn get(&mut self, table: u16, key: &[u8]) -> Result<&mut Self> {
+        Ok(self.raw(messages::get(table, key)?, true))
+    }
+
+    pub fn get_field(&mut self, table: u16, key: &[u8], field: u16) -> Result<&mut Self> {
+        Ok(self.raw(messages::get_field(table, key, field)?, true))
+    }
+
+    pub fn get_fields(&mut self, table: u16, key: &[u8], fields: &[u16]) -> Result<&mut Self> {
+        Ok(self.raw(messages::get_fields(table, key, fields)?, true))
+    }
+
+    pub fn insert(&mut self, table: u16, key: &[u8], value: &[u8]) -> Result<&mut Self> {
+        Ok(self.raw(messages::insert(table, key, value)?, false))
+    }
+
+    pub fn set(&mut self, table: u16, key: &[u8], value: &[u8]) -> Result<&mut Self> {
+        Ok(self.raw(messages::set(table, key, value)?, false))
+    }
+
+    pub fn delete(&mut self, table: u16, key: &[u8]) -> Result<&mut Self> {
+        Ok(self.raw(messages::delete(table, key)?, false))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,131 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Condvar, Mutex},
+    time::Duration
+};
+use crate::{
+    connection::Connection,
+    errors::{Error, Result},
+    stream::Address
+};
+
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// The maximum number of open connections. [`Pool::get`] waits for a free connection, when all of them are busy.
+    pub max_size: usize,
+    /// The server password. Empty password means, that the server has no password.
+    pub password: String,
+    /// Read and write timeout for every connection. `None` means no timeout.
+    pub timeout: Option<Duration>
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 16,
+            password: String::new(),
+            timeout: None
+        }
+    }
+}
+
+struct State {
+    idle: Vec<Connection>,
+    /// Number of idle and busy connections.
+    open: usize
+}
+
+/// Pool of connections to one server. Connections are opened lazily and are reused after [`PooledConnection`] is dropped.
+pub struct Pool {
+    address: Address,
+    config: PoolConfig,
+    state: Mutex<State>,
+    released: Condvar
+}
+
+impl Pool {
+    pub fn new(address: Address, config: PoolConfig) -> Self {
+        Self {
+            address,
+            state: Mutex::new(State { idle: Vec::with_capacity(config.max_size), open: 0 }),
+            config,
+            released: Condvar::new()
+        }
+    }
+
+    pub fn address(&self) -> &Address {
+        &self.address
+    }
+
+    /// Opens a connection, that isn't in the pool, like for streams.
+    pub fn connect(&self) -> Result<Connection> {
+        Connection::connect(&self.address, &self.config.password, self.config.timeout)
+    }
+
+    /// Returns an idle connection, opens a new one or waits until another thread releases a connection.
+    pub fn get(&self) -> Result<PooledConnection<'_>> {
+        let mut state = self.state.lock().map_err(|_| Error::Protocol("the pool lock is poisoned"))?;
+        loop {
+            if let Some(connection) = state.idle.pop() {
+                return Ok(PooledConnection { pool: self, connection: Some(connection) });
+            }
+            if state.open < self.config.max_size.max(1) {
+                state.open += 1;
+                drop(state);
+                return match Connection::connect(&self.address, &self.config.password, self.config.timeout) {
+                    Ok(connection) => Ok(PooledConnection { pool: self, connection: Some(connection) }),
+                    Err(e) => {
+                        self.forget();
+                        Err(e)
+                    }
+                };
+            }
+            state = self.released.wait(state).map_err(|_| Error::Protocol("the pool lock is poisoned"))?;
+        }
+    }
+
+    fn forget(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.open -= 1;
+        }
+        self.released.notify_one();
+    }
+
+    fn release(&self, connection: Connection) {
+        if connection.is_broken() {
+            return self.forget();
+        }
+        if let Ok(mut state) = self.state.lock() {
+            state.idle.push(connection);
+        }
+        self.released.notify_one();
+    }
+}
+
+/// Connection, that returns to the pool on drop. Broken connections are closed instead.
+pub struct PooledConnection<'pool> {
+    pool: &'pool Pool,
+    connection: Option<Connection>
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            self.pool.release(connection);
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,127 @@
+//! Pub/sub subscriptions. See `server::pubsub` of the server.
+use std::collections::VecDeque;
+use crate::{
+    actions,
+    connection::Connection,
+    errors::{Error, Result},
+    messages
+};
+
+const MESSAGE: u8 = 0;
+const PATTERN_MESSAGE: u8 = 1;
+const SUBSCRIPTIONS: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub channel: Vec<u8>,
+    /// The pattern, that has matched the channel, if the message is for a pattern subscription.
+    pub pattern: Option<Vec<u8>>,
+    pub payload: Vec<u8>
+}
+
+/// Connection in the push mode. It owns the connection, that isn't returned to the pool.
+///
+/// Messages, that come while a subscription is changed, are kept and returned by the iterator later.
+pub struct Subscription {
+    connection: Connection,
+    messages: VecDeque<Message>,
+    subscriptions: u32,
+    is_ended: bool
+}
+
+impl Subscription {
+    pub(crate) fn start(connection: Connection, channels: &[&[u8]], patterns: &[&[u8]]) -> Result<Self> {
+        let mut subscription = Self { connection, messages: VecDeque::new(), subscriptions: 0, is_ended: false };
+        subscription.subscribe(channels)?;
+        subscription.psubscribe(patterns)?;
+        Ok(subscription)
+    }
+
+    /// The number of channels and patterns. The server ends the push mode, when it is 0.
+    pub fn subscriptions(&self) -> u32 {
+        self.subscriptions
+    }
+
+    pub fn subscribe(&mut self, channels: &[&[u8]]) -> Result<u32> {
+        if channels.is_empty() {
+            return Ok(self.subscriptions);
+        }
+        self.change(&messages::names(actions::SUBSCRIBE, channels)?)
+    }
+
+    /// Patterns are globs: `*`, `?`, `[abc]`, `[^a-z]` and `\` for escaping.
+    pub fn psubscribe(&mut self, patterns: &[&[u8]]) -> Result<u32> {
+        if patterns.is_empty() {
+            return Ok(self.subscriptions);
+        }
+        self.change(&messages::names(actions::PSUBSCRIBE, patterns)?)
+    }
+
+    /// Removes subscriptions, all of them, if both lists are empty. Without subscriptions the connection can't get messages,
+    /// so subscribe again or drop it.
+    pub fn unsubscribe(&mut self, channels: &[&[u8]], patterns: &[&[u8]]) -> Result<u32> {
+        self.change(&messages::unsubscribe(channels, patterns)?)
+    }
+
+    /// Sends the message and returns the number of subscriptions from the answer.
+    fn change(&mut self, message: &[u8]) -> Result<u32> {
+        self.connection.send(message, false)?;
+        loop {
+            let frame = self.connection.receive()??;
+            if frame.first() == Some(&SUBSCRIPTIONS) {
+                if frame.len() != 5 {
+                    return Err(Error::Protocol("wrong answer to the subscription"));
+                }
+                self.subscriptions = u32::from_le_bytes(frame[1..5].try_into().unwrap());
+                return Ok(self.subscriptions);
+            }
+            let message = parse_message(&frame)?;
+            self.messages.push_back(message);
+        }
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Result<Message>;
+
+    /// Waits for the next message. It waits forever, if there are no subscriptions. After an error the iterator ends.
+    fn next(&mut self) -> Option<Result<Message>> {
+        if let Some(message) = self.messages.pop_front() {
+            return Some(Ok(message));
+        }
+        if self.is_ended {
+            return None;
+        }
+        let res = self.connection.receive().and_then(|frame| parse_message(&frame?));
+        if res.is_err() {
+            self.is_ended = true;
+        }
+        Some(res)
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    if buf.len() < 2 {
+        return Err(Error::Protocol("the message is too short"));
+    }
+    let len = u16::from_le_bytes([buf[0], buf[1]]) as usize;
+    if buf.len() < 2 + len {
+        return Err(Error::Protocol("the message is too short"));
+    }
+    let (taken, rest) = buf[2..].split_at(len);
+    *buf = rest;
+    Ok(taken)
+}
+
+/// [`MESSAGE`, `channel length` (2 bytes), `channel`, `payload`] or
+/// [`PATTERN_MESSAGE`, `pattern length` (2 bytes), `pattern`, `channel length` (2 bytes), `channel`, `payload`]
+fn parse_message(frame: &[u8]) -> Result<Message> {
+    let mut buf = frame.get(1..).unwrap_or_default();
+    let pattern = match frame.first() {
+        Some(&MESSAGE) => None,
+        Some(&PATTERN_MESSAGE) => Some(take(&mut buf)?.to_vec()),
+        _ => return Err(Error::Protocol("unknown frame of the subscription"))
+    };
+    let channel = take(&mut buf)?.to_vec();
+    Ok(Message { channel, pattern, payload: buf.to_vec() })
+}
@@ -0,0 +1,440 @@
+use serde_json::{Map, Value};
+use crate::errors::{Error, Result};
+
+/// Type of the field. Names are the same as the server accepts in the JSON scheme.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldType {
+    Byte,
+    Bool,
+    Uint8,
+    Uint16,
+    Uint32,
+    Uint64,
+    Uint128,
+    Int8,
+    Int16,
+    Int32,
+    Int64,
+    Int128,
+    Float32,
+    Float64,
+    String,
+    ByteSlice
+}
+
+impl FieldType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FieldType::Byte => "Byte",
+            FieldType::Bool => "Bool",
+            FieldType::Uint8 => "Uint8",
+            FieldType::Uint16 => "Uint16",
+            FieldType::Uint32 => "Uint32",
+            FieldType::Uint64 => "Uint64",
+            FieldType::Uint128 => "Uint128",
+            FieldType::Int8 => "Int8",
+            FieldType::Int16 => "Int16",
+            FieldType::Int32 => "Int32",
+            FieldType::Int64 => "Int64",
+            FieldType::Int128 => "Int128",
+            FieldType::Float32 => "Float32",
+            FieldType::Float64 => "Float64",
+            FieldType::String => "String",
+            FieldType::ByteSlice => "ByteSlice"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "Byte" => FieldType::Byte,
+            "Bool" => FieldType::Bool,
+            "Uint8" => FieldType::Uint8,
+            "Uint16" => FieldType::Uint16,
+            "Uint32" => FieldType::Uint32,
+            "Uint64" => FieldType::Uint64,
+            "Uint128" => FieldType::Uint128,
+            "Int8" => FieldType::Int8,
+            "Int16" => FieldType::Int16,
+            "Int32" => FieldType::Int32,
+            "Int64" => FieldType::Int64,
+            "Int128" => FieldType::Int128,
+            "Float32" => FieldType::Float32,
+            "Float64" => FieldType::Float64,
+            "String" => FieldType::String,
+            "ByteSlice" => FieldType::ByteSlice,
+            _ => return None
+        })
+    }
+
+    /// Returns the size of the sized field or `None` for String and ByteSlice.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            FieldType::Byte | FieldType::Bool | FieldType::Uint8 | FieldType::Int8 => Some(1),
+            FieldType::Uint16 | FieldType::Int16 => Some(2),
+            FieldType::Uint32 | FieldType::Int32 | FieldType::Float32 => Some(4),
+            FieldType::Uint64 | FieldType::Int64 | FieldType::Float64 => Some(8),
+            FieldType::Uint128 | FieldType::Int128 => Some(16),
+            FieldType::String | FieldType::ByteSlice => None
+        }
+    }
+}
+
+/// Value of one field. Numbers are stored in little endian.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Field {
+    Byte(u8),
+    Bool(bool),
+    Uint8(u8),
+    Uint16(u16),
+    Uint32(u32),
+    Uint64(u64),
+    Uint128(u128),
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    Int128(i128),
+    Float32(f32),
+    Float64(f64),
+    String(String),
+    ByteSlice(Vec<u8>)
+}
+
+impl Field {
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            Field::Byte(_) => FieldType::Byte,
+            Field::Bool(_) => FieldType::Bool,
+            Field::Uint8(_) => FieldType::Uint8,
+            Field::Uint16(_) => FieldType::Uint16,
+            Field::Uint32(_) => FieldType::Uint32,
+            Field::Uint64(_) => FieldType::Uint64,
+            Field::Uint128(_) => FieldType::Uint128,
+            Field::Int8(_) => FieldType::Int8,
+            Field::Int16(_) => FieldType::Int16,
+            Field::Int32(_) => FieldType::Int32,
+            Field::Int64(_) => FieldType::Int64,
+            Field::Int128(_) => FieldType::Int128,
+            Field::Float32(_) => FieldType::Float32,
+            Field::Float64(_) => FieldType::Float64,
+            Field::String(_) => FieldType::String,
+            Field::ByteSlice(_) => FieldType::ByteSlice
+        }
+    }
+
+    fn write_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Field::Byte(v) | Field::Uint8(v) => buf.push(*v),
+            Field::Bool(v) => buf.push(*v as u8),
+            Field::Uint16(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Uint32(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Uint64(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Uint128(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Int8(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Int16(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Int32(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Int64(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Int128(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Float32(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::Float64(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Field::String(v) => write_unsized(buf, v.as_bytes()),
+            Field::ByteSlice(v) => write_unsized(buf, v)
+        }
+    }
+
+    /// Parses the field from bytes without the length prefix.
+    pub fn from_bytes(field_type: FieldType, bytes: &[u8]) -> Result<Self> {
+        if let Some(size) = field_type.size() {
+            if bytes.len() != size {
+                return Err(Error::Scheme(format!("{} field must have {} bytes, but it has {}", field_type.name(), size, bytes.len())));
+            }
+        }
+        macro_rules! le {
+            ($t:ty) => { <$t>::from_le_bytes(bytes.try_into().unwrap()) };
+        }
+        Ok(match field_type {
+            FieldType::Byte => Field::Byte(bytes[0]),
+            FieldType::Bool => Field::Bool(bytes[0] != 0),
+            FieldType::Uint8 => Field::Uint8(bytes[0]),
+            FieldType::Uint16 => Field::Uint16(le!(u16)),
+            FieldType::Uint32 => Field::Uint32(le!(u32)),
+            FieldType::Uint64 => Field::Uint64(le!(u64)),
+            FieldType::Uint128 => Field::Uint128(le!(u128)),
+            FieldType::Int8 => Field::Int8(le!(i8)),
+            FieldType::Int16 => Field::Int16(le!(i16)),
+            FieldType::Int32 => Field::Int32(le!(i32)),
+            FieldType::Int64 => Field::Int64(le!(i64)),
+            FieldType::Int128 => Field::Int128(le!(i128)),
+            FieldType::Float32 => Field::Float32(le!(f32)),
+            FieldType::Float64 => Field::Float64(le!(f64)),
+            FieldType::String => match String::from_utf8(bytes.to_vec()) {
+                Ok(string) => Field::String(string),
+                Err(_) => return Err(Error::Scheme("String field is not valid UTF-8".to_string()))
+            },
+            FieldType::ByteSlice => Field::ByteSlice(bytes.to_vec())
+        })
+    }
+}
+
+macro_rules! impl_from_for_field {
+    ($($t:ty => $variant:ident),*) => {
+        $(impl From<$t> for Field {
+            fn from(v: $t) -> Self {
+                Field::$variant(v.into())
+            }
+        })*
+    };
+}
+
+impl_from_for_field!(
+    bool => Bool, u8 => Uint8, u16 => Uint16, u32 => Uint32, u64 => Uint64, u128 => Uint128,
+    i8 => Int8, i16 => Int16, i32 => Int32, i64 => Int64, i128 => Int128, f32 => Float32, f64 => Float64,
+    String => String, &str => String, Vec<u8> => ByteSlice, &[u8] => ByteSlice
+);
+
+/// Writes the unsized field as [`length` (2 bytes or [255, 255, 4 bytes]), `bytes`] like the server expects.
+fn write_unsized(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let len = bytes.len();
+    if len < u16::MAX as usize {
+        buf.extend_from_slice(&(len as u16).to_le_bytes());
+    } else {
+        buf.extend_from_slice(&[255, 255]);
+        buf.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    buf.extend_from_slice(bytes);
+}
+
+/// Reads the length prefixed field. Returns the field and the number of read bytes.
+pub fn read_prefixed(bytes: &[u8]) -> Result<(&[u8], usize)> {
+    if bytes.len() < 2 {
+        return Err(Error::Protocol("field length is cut"));
+    }
+    let (len, offset) = if bytes[0] < 255 || bytes[1] < 255 {
+        (u16::from_le_bytes([bytes[0], bytes[1]]) as usize, 2)
+    } else {
+        if bytes.len() < 6 {
+            return Err(Error::Protocol("field length is cut"));
+        }
+        (u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]) as usize, 6)
+    };
+    if bytes.len() < offset + len {
+        return Err(Error::Protocol("field is cut"));
+    }
+    Ok((&bytes[offset..offset + len], offset + len))
+}
+
+/// Codec of values and dumps of the table. It is chosen, when the table is created.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Lz4,
+    Zstd
+}
+
+impl Compression {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "none" => Compression::None,
+            "lz4" => Compression::Lz4,
+            "zstd" => Compression::Zstd,
+            _ => return None
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct SchemeBuilder {
+    fields: Vec<(String, FieldType)>,
+    compression: Compression,
+    compression_threshold: Option<usize>
+}
+
+impl SchemeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn field(mut self, name: impl Into<String>, field_type: FieldType) -> Self {
+        self.fields.push((name.into(), field_type));
+        self
+    }
+
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Values shorter than it aren't compressed. The server has a default.
+    pub fn compression_threshold(mut self, threshold: usize) -> Self {
+        self.compression_threshold = Some(threshold);
+        self
+    }
+
+    pub fn build(self) -> Result<Scheme> {
+        let mut fields = self.fields;
+        // The server sorts fields by name (JSON objects are maps) and puts sized fields before unsized ones.
+        fields.sort_by(|a, b| (a.1.size().is_none(), &a.0).cmp(&(b.1.size().is_none(), &b.0)));
+        for pair in fields.windows(2) {
+            if pair[0].0 == pair[1].0 {
+                return Err(Error::Scheme(format!("field {} is defined twice", pair[0].0)));
+            }
+        }
+        if fields.len() > u16::MAX as usize {
+            return Err(Error::Scheme("too many fields".to_string()));
+        }
+        Ok(Scheme { fields, compression: self.compression, compression_threshold: self.compression_threshold })
+    }
+}
+
+/// Scheme of the table. Fields are in the same order as on the server, so the index of the field
+/// is the number, that `get_field` and `get_fields` expect.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scheme {
+    fields: Vec<(String, FieldType)>,
+    compression: Compression,
+    compression_threshold: Option<usize>
+}
+
+impl Scheme {
+    /// Scheme without fields. Tables with an empty scheme accept any value.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn fields(&self) -> &[(String, FieldType)] {
+        &self.fields
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn compression_threshold(&self) -> Option<usize> {
+        self.compression_threshold
+    }
+
+    pub fn field_number(&self, name: &str) -> Option<u16> {
+        self.fields.iter().position(|(field_name, _)| field_name == name).map(|i| i as u16)
+    }
+
+    /// Returns the JSON, that the server expects on table creation. Empty scheme without compression is an empty string.
+    pub fn to_json(&self) -> String {
+        if self.is_empty() && self.compression == Compression::None && self.compression_threshold.is_none() {
+            return String::new();
+        }
+        let mut sized_fields = Map::new();
+        let mut unsized_fields = Map::new();
+        for (name, field_type) in self.fields.iter() {
+            let map = if field_type.size().is_some() { &mut sized_fields } else { &mut unsized_fields };
+            map.insert(name.clone(), Value::String(field_type.name().to_string()));
+        }
+        let mut json = Map::new();
+        json.insert("sized_fields".to_string(), Value::Object(sized_fields));
+        json.insert("unsized_fields".to_string(), Value::Object(unsized_fields));
+        if self.compression != Compression::None {
+            json.insert("compression".to_string(), Value::from(self.compression.name()));
+        }
+        if let Some(threshold) = self.compression_threshold {
+            json.insert("compression_threshold".to_string(), Value::from(threshold));
+        }
+        Value::Object(json).to_string()
+    }
+
+    /// Parses the JSON scheme in the server format. Empty input is an empty scheme.
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        if json.is_empty() {
+            return Ok(Self::empty());
+        }
+        let value: Value = serde_json::from_slice(json).map_err(|e| Error::Scheme(e.to_string()))?;
+        let mut builder = SchemeBuilder::new();
+        for part in ["sized_fields", "unsized_fields"] {
+            let fields = match value.get(part).and_then(Value::as_object) {
+                Some(fields) => fields,
+                None => return Err(Error::Scheme(format!("{} must be an object", part)))
+            };
+            for (name, field_type) in fields.iter() {
+                match field_type.as_str().and_then(FieldType::from_name) {
+                    Some(field_type) => builder = builder.field(name.clone(), field_type),
+                    None => return Err(Error::Scheme(format!("unknown type of the field {}", name)))
+                }
+            }
+        }
+        if let Some(compression) = value.get("compression") {
+            match compression.as_str().and_then(Compression::from_name) {
+                Some(compression) => builder = builder.compression(compression),
+                None => return Err(Error::Scheme("unknown compression".to_string()))
+            }
+        }
+        if let Some(threshold) = value.get("compression_threshold") {
+            match threshold.as_u64() {
+                Some(threshold) => builder = builder.compression_threshold(threshold as usize),
+                None => return Err(Error::Scheme("compression_threshold must be a number".to_string()))
+            }
+        }
+        builder.build()
+    }
+
+    /// Encodes the value. Every field of the scheme must be set.
+    pub fn encode(&self, values: &[(&str, Field)]) -> Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(64);
+        for (name, field_type) in self.fields.iter() {
+            let field = match values.iter().find(|(value_name, _)| value_name == name) {
+                Some((_, field)) => field,
+                None => return Err(Error::Scheme(format!("field {} is not set", name)))
+            };
+            if field.field_type() != *field_type {
+                return Err(Error::Scheme(format!("field {} must be {}, but it is {}", name, field_type.name(), field.field_type().name())));
+            }
+            field.write_to(&mut buf);
+        }
+        if values.len() != self.fields.len() {
+            return Err(Error::Scheme("the value has fields, that are not in the scheme".to_string()));
+        }
+        Ok(buf)
+    }
+
+    /// Decodes the value, that was encoded with [`Scheme::encode`].
+    pub fn decode(&self, value: &[u8]) -> Result<Vec<(String, Field)>> {
+        let mut fields = Vec::with_capacity(self.fields.len());
+        let mut offset = 0;
+        for (name, field_type) in self.fields.iter() {
+            let bytes = match field_type.size() {
+                Some(size) => {
+                    if value.len() < offset + size {
+                        return Err(Error::Scheme("the value is shorter than the scheme".to_string()));
+                    }
+                    offset += size;
+                    &value[offset - size..offset]
+                }
+                None => {
+                    let (bytes, read) = read_prefixed(&value[offset..]).map_err(|_| Error::Scheme("the value is shorter than the scheme".to_string()))?;
+                    offset += read;
+                    bytes
+                }
+            };
+            fields.push((name.clone(), Field::from_bytes(*field_type, bytes)?));
+        }
+        Ok(fields)
+    }
+
+    /// Decodes the field returned by `get_field` or `get_fields`.
+    pub fn decode_field(&self, number: u16, bytes: &[u8]) -> Result<Field> {
+        match self.fields.get(number as usize) {
+            Some((_, field_type)) => Field::from_bytes(*field_type, bytes),
+            None => Err(Error::Scheme(format!("there is no field number {}", number)))
+        }
+    }
+}
\ No newline at end of file
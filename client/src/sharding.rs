@@ -0,0 +1,29 @@
+//! Shards of keys. It is the same hash, that the server uses, see `server::sharding` in the server.
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC16_TABLE: [u16; 256] = crc16_table();
+
+/// Returns the shard of the key: CRC-16/XMODEM of it. [`Client::get_shard_metadata`](crate::Client::get_shard_metadata)
+/// returns the node of every shard.
+pub fn shard_of(key: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in key {
+        crc = (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ *byte) as usize];
+    }
+    crc
+}
@@ -0,0 +1,450 @@
+use std::{
+    fs,
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    thread::{self, JoinHandle},
+    time::Duration
+};
+use dbms::{
+    server::{cfg::Config, server::Server, sharding},
+    storage::Storage
+};
+use dbms_client::{
+    actions, changes, errors::codes, notifications, Address, Client, Compression, Error, Field, FieldType, Message, Pipeline, PoolConfig, SchemeBuilder
+};
+
+/// Runs the server in this process. Servers aren't shut down, so every test uses its own directory and addresses.
+fn start_server(name: &str, password: &str) -> (Address, Address, PathBuf) {
+    let (tcp, unix, dir, _) = spawn_server(name, password);
+    (tcp, unix, dir)
+}
+
+/// Like [`start_server`], but returns the thread of the server. It ends after the shutdown.
+fn spawn_server(name: &str, password: &str) -> (Address, Address, PathBuf, JoinHandle<bool>) {
+    spawn_server_with(name, Config { password: password.to_string(), ..Config::default() })
+}
+
+/// Like [`spawn_server`], but with the config. Addresses of the config are replaced.
+fn spawn_server_with(name: &str, config: Config) -> (Address, Address, PathBuf, JoinHandle<bool>) {
+    let dir: PathBuf = format!("test_data_client_{}", name).into();
+    let _ = fs::remove_dir_all(&dir);
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let tcp_addr = format!("127.0.0.1:{}", port);
+    let unix_addr = dir.join("dbms.sock");
+    let config = Config {
+        tcp_addr: tcp_addr.clone(),
+        unix_addr: unix_addr.to_str().unwrap().to_string(),
+        ..config
+    };
+    let server = thread::spawn(move || Server::with_config(storage, config).run());
+
+    for _ in 0..500 {
+        if TcpStream::connect(&tcp_addr).is_ok() && unix_addr.exists() {
+            return (Address::tcp(tcp_addr), Address::unix(unix_addr), dir, server);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("server {} hasn't started", name);
+}
+
+fn users_scheme() -> dbms_client::Scheme {
+    SchemeBuilder::new()
+        .field("name", FieldType::String)
+        .field("age", FieldType::Uint32)
+        .field("avatar", FieldType::ByteSlice)
+        .field("is_admin", FieldType::Bool)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn crud() {
+    let (tcp, _, dir) = start_server("crud", "");
+    let client = Client::connect(tcp, PoolConfig::default()).unwrap();
+    let scheme = users_scheme();
+
+    let users = client.create_table_in_memory("users", &scheme, false).unwrap();
+    let raw = client.create_table_cache("raw", &dbms_client::Scheme::empty(), 10, false).unwrap();
+    assert_ne!(users, raw);
+    assert_eq!(client.get_tables_names().unwrap(), vec!["users".to_string(), "raw".to_string()]);
+    assert_eq!(client.get_table_scheme(users).unwrap(), scheme);
+    assert!(client.get_table_scheme(raw).unwrap().is_empty());
+    match client.create_table_in_memory("users", &scheme, false) {
+        Err(e) => assert_eq!(e.code(), Some(codes::TABLE_ALREADY_EXISTS)),
+        Ok(_) => panic!("table was created twice")
+    }
+
+    let bob = scheme.encode(&[
+        ("name", "Bob".into()),
+        ("age", 30u32.into()),
+        ("avatar", vec![1u8, 2, 3].into()),
+        ("is_admin", false.into())
+    ]).unwrap();
+    client.insert(users, b"bob", &bob).unwrap();
+    assert_eq!(client.get(users, b"bob").unwrap(), Some(bob.clone()));
+    assert_eq!(client.get(users, b"alice").unwrap(), None);
+
+    let name = scheme.field_number("name").unwrap();
+    let age = scheme.field_number("age").unwrap();
+    let avatar = scheme.field_number("avatar").unwrap();
+    let field = client.get_field(users, b"bob", age).unwrap().unwrap();
+    assert_eq!(scheme.decode_field(age, &field).unwrap(), Field::Uint32(30));
+    let fields = client.get_fields(users, b"bob", &[name, age, avatar]).unwrap().unwrap();
+    assert_eq!(scheme.decode_field(name, &fields[0]).unwrap(), Field::String("Bob".to_string()));
+    assert_eq!(scheme.decode_field(age, &fields[1]).unwrap(), Field::Uint32(30));
+    assert_eq!(scheme.decode_field(avatar, &fields[2]).unwrap(), Field::ByteSlice(vec![1, 2, 3]));
+
+    let older_bob = scheme.encode(&[
+        ("name", "Bob".into()),
+        ("age", 31u32.into()),
+        ("avatar", vec![].into()),
+        ("is_admin", true.into())
+    ]).unwrap();
+    client.insert(users, b"bob", &older_bob).unwrap();
+    assert_eq!(client.get(users, b"bob").unwrap(), Some(bob));
+    client.set(users, b"bob", &older_bob).unwrap();
+    let decoded = scheme.decode(&client.get(users, b"bob").unwrap().unwrap()).unwrap();
+    assert!(decoded.contains(&("is_admin".to_string(), Field::Bool(true))));
+
+    match client.insert(users, b"broken", b"x") {
+        Err(e) => assert_eq!(e.code(), Some(codes::VALUE_DOES_NOT_MATCH_SCHEME)),
+        Ok(_) => panic!("value without the scheme was inserted")
+    }
+    match client.get(1000, b"bob") {
+        Err(e) => assert_eq!(e.code(), Some(codes::TABLE_IS_NOT_FOUND)),
+        Ok(_) => panic!("table 1000 exists")
+    }
+
+    client.delete(users, b"bob").unwrap();
+    assert_eq!(client.get(users, b"bob").unwrap(), None);
+
+    client.set(raw, b"key", b"any bytes").unwrap();
+    assert_eq!(client.get(raw, b"key").unwrap(), Some(b"any bytes".to_vec()));
+
+    assert_eq!(client.get_shard_metadata().unwrap().len(), 65536);
+    assert_eq!(client.get_hierarchy().unwrap().len(), 1);
+    assert_eq!(client.rebalance(true).unwrap(), serde_json::json!([]));
+    assert_eq!(client.migrate_shards(0, 100, 1).unwrap_err().code(), Some(codes::NODE_IS_NOT_FOUND));
+    assert_eq!(client.remove_machine("127.0.0.1:1").unwrap_err().code(), Some(codes::MACHINE_IS_NOT_FOUND));
+    assert_eq!(client.remove_node(0).unwrap_err().code(), Some(codes::NODE_OWNS_SHARDS));
+
+    let info = client.info().unwrap();
+    assert_eq!(info["tables"][users as usize]["name"], "users");
+    assert_eq!(info["tables"][users as usize]["count"], 0);
+    assert_eq!(info["tables"][raw as usize]["engine"], "cache");
+    assert!(info["tables"][raw as usize]["memory_bytes"].as_u64().unwrap() > 0);
+    assert!(info["clients"]["binary"].as_i64().unwrap() >= 1);
+    assert_eq!(info["cluster"]["role"], "single");
+
+    assert!(client.slowlog_get(10).unwrap().is_array());
+    client.slowlog_reset().unwrap();
+    assert_eq!(client.slowlog_get(10).unwrap().as_array().unwrap().len(), 0);
+
+    assert_eq!(client.config_get("slowlog_max_len").unwrap()["slowlog_max_len"], 128);
+    client.config_set("slowlog-max-len", "64").unwrap();
+    assert_eq!(client.config_get("").unwrap()["slowlog_max_len"], 64);
+    assert_eq!(client.config_set("slowlog_max_len", "many").unwrap_err().code(), Some(codes::SETTING_VALUE_IS_NOT_VALID));
+    assert_eq!(client.config_set("tcp_addr", "localhost:1").unwrap_err().code(), Some(codes::SETTING_IS_NOT_RELOADABLE));
+    assert_eq!(client.config_get("nothing").unwrap_err().code(), Some(codes::UNKNOWN_SETTING));
+    assert!(client.config_get("password").unwrap()["password"].is_null());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn lsm_table() {
+    let (tcp, _, dir) = start_server("lsm", "");
+    let client = Client::connect(tcp, PoolConfig::default()).unwrap();
+    let scheme = users_scheme();
+    let users = client.create_table_lsm("users", &scheme).unwrap();
+    assert_eq!(client.get_table_scheme(users).unwrap(), scheme);
+
+    let bob = scheme.encode(&[
+        ("name", "Bob".into()),
+        ("age", 30u32.into()),
+        ("avatar", vec![1u8, 2, 3].into()),
+        ("is_admin", false.into())
+    ]).unwrap();
+    client.insert(users, b"bob", &bob).unwrap();
+    assert_eq!(client.get(users, b"bob").unwrap(), Some(bob));
+    let age = scheme.field_number("age").unwrap();
+    let field = client.get_field(users, b"bob", age).unwrap().unwrap();
+    assert_eq!(scheme.decode_field(age, &field).unwrap(), Field::Uint32(30));
+    client.delete(users, b"bob").unwrap();
+    assert_eq!(client.get(users, b"bob").unwrap(), None);
+
+    let info = client.info().unwrap();
+    assert_eq!(info["tables"][users as usize]["engine"], "lsm");
+    assert_eq!(info["tables"][users as usize]["count"], 0);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn compressed_table() {
+    let (tcp, _, dir) = start_server("compression", "");
+    let client = Client::connect(tcp, PoolConfig::default()).unwrap();
+    let scheme = SchemeBuilder::new()
+        .field("name", FieldType::String)
+        .field("avatar", FieldType::ByteSlice)
+        .compression(Compression::Zstd)
+        .compression_threshold(16)
+        .build()
+        .unwrap();
+    let users = client.create_table_in_memory("users", &scheme, true).unwrap();
+    let restored = client.get_table_scheme(users).unwrap();
+    assert_eq!(restored, scheme);
+    assert_eq!(restored.compression(), Compression::Zstd);
+    assert_eq!(restored.compression_threshold(), Some(16));
+
+    let bob = scheme.encode(&[("name", "Bob".into()), ("avatar", vec![7u8; 10_000].into())]).unwrap();
+    client.insert(users, b"bob", &bob).unwrap();
+    assert_eq!(client.get(users, b"bob").unwrap(), Some(bob));
+    let avatar = scheme.field_number("avatar").unwrap();
+    let field = client.get_field(users, b"bob", avatar).unwrap().unwrap();
+    assert_eq!(scheme.decode_field(avatar, &field).unwrap(), Field::ByteSlice(vec![7u8; 10_000]));
+    assert!(client.info().unwrap()["tables"][users as usize]["memory_bytes"].as_u64().unwrap() < 1_000);
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pipeline_and_pool() {
+    let (tcp, unix, dir) = start_server("pipeline", "");
+    let client = Client::connect(tcp, PoolConfig { max_size: 4, ..PoolConfig::default() }).unwrap();
+    let table = client.create_table_in_memory("pipeline", &dbms_client::Scheme::empty(), false).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    for i in 0..1000 {
+        pipeline.set(table, format!("key{}", i).as_bytes(), format!("value{}", i).as_bytes()).unwrap();
+    }
+    // Bigger than the server buffer.
+    pipeline.set(table, b"big", &vec![7u8; 100_000]).unwrap();
+    pipeline.get(table, b"key999").unwrap().get(table, b"missing").unwrap().ping();
+    assert!(!pipeline.is_reading());
+    let answers = client.execute(&pipeline).unwrap();
+    assert_eq!(answers.len(), 1004);
+    assert!(answers[..1001].iter().all(|answer| answer.as_ref().unwrap().is_empty()));
+    assert_eq!(answers[1001].as_ref().unwrap(), b"value999");
+    assert_eq!(answers[1002].as_ref().unwrap_err().code(), Some(codes::KEY_IS_NOT_FOUND));
+    assert_eq!(answers[1003].as_ref().unwrap(), &[dbms_client::actions::PING]);
+
+    let mut joins = Vec::new();
+    for t in 0..16 {
+        let client = client.clone();
+        joins.push(thread::spawn(move || {
+            for i in 0..200 {
+                let key = format!("thread{}-{}", t, i);
+                client.set(table, key.as_bytes(), key.as_bytes()).unwrap();
+                assert_eq!(client.get(table, key.as_bytes()).unwrap(), Some(key.into_bytes()));
+            }
+        }));
+    }
+    for join in joins {
+        join.join().unwrap();
+    }
+
+    let unix_client = Client::connect(unix, PoolConfig::default()).unwrap();
+    assert_eq!(unix_client.get(table, b"big").unwrap(), Some(vec![7u8; 100_000]));
+    assert_eq!(unix_client.get(table, b"thread15-199").unwrap(), Some(b"thread15-199".to_vec()));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn password() {
+    let (tcp, unix, dir) = start_server("password", "secret");
+    let config = PoolConfig { password: "secret".to_string(), ..PoolConfig::default() };
+    let client = Client::connect(tcp.clone(), config.clone()).unwrap();
+    client.ping().unwrap();
+    Client::connect(unix, config).unwrap().ping().unwrap();
+
+    let wrong = PoolConfig { password: "SECRET".to_string(), ..PoolConfig::default() };
+    assert!(matches!(Client::connect(tcp, wrong), Err(Error::WrongPassword)));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn graceful_shutdown() {
+    let (tcp, unix, dir, server) = spawn_server("shutdown", "");
+    let client = Client::connect(tcp, PoolConfig::default()).unwrap();
+    let table = client.create_table_in_memory("kept", &dbms_client::Scheme::empty(), true).unwrap();
+    client.set(table, b"key", b"value").unwrap();
+    // The idle connection doesn't block the shutdown.
+    let idle = Client::connect(unix, PoolConfig::default()).unwrap();
+    idle.ping().unwrap();
+
+    client.shutdown(Some(true)).unwrap();
+    assert!(server.join().unwrap());
+    assert!(!dir.join("dbms.sock").exists());
+    // The final dump has replaced the first log file.
+    assert!(dir.join("log1.bin").exists());
+    assert!(!dir.join("log0.bin").exists());
+    assert!(idle.ping().is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+/// Waits until the condition is true.
+fn eventually(what: &str, condition: impl Fn() -> bool) {
+    for _ in 0..500 {
+        if condition() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("{} hasn't happened", what);
+}
+
+#[test]
+fn replication() {
+    let (leader_tcp, _, leader_dir) = start_server("leader", "secret");
+    let leader_addr = match &leader_tcp {
+        Address::Tcp(addr) => addr.clone(),
+        _ => unreachable!()
+    };
+    let leader = Client::connect(leader_tcp, PoolConfig { password: "secret".to_string(), ..PoolConfig::default() }).unwrap();
+    let users = leader.create_table_in_memory("users", &dbms_client::Scheme::empty(), true).unwrap();
+    leader.set(users, b"deleted", b"value").unwrap();
+    leader.set(users, &[b'k'; 300], &vec![7u8; 70_000]).unwrap();
+
+    let config = Config { leader_addr, leader_password: "secret".to_string(), ..Config::default() };
+    let (follower_tcp, _, follower_dir, _) = spawn_server_with("follower", config);
+    let follower = Client::connect(follower_tcp, PoolConfig::default()).unwrap();
+    eventually("the snapshot", || follower.info().unwrap()["replication"]["is_synced"] == true);
+    assert_eq!(follower.get(users, &[b'k'; 300]).unwrap(), Some(vec![7u8; 70_000]));
+
+    // The tail of the log: changes and new tables.
+    leader.delete(users, b"deleted").unwrap();
+    leader.insert(users, b"new", b"value").unwrap();
+    let later = leader.create_table_cache("later", &dbms_client::Scheme::empty(), 60, true).unwrap();
+    leader.set(later, b"key", b"cached").unwrap();
+    eventually("the tail", || follower.get(later, b"key").ok().flatten().is_some());
+    assert_eq!(follower.get(users, b"deleted").unwrap(), None);
+    assert_eq!(follower.get(users, b"new").unwrap(), Some(b"value".to_vec()));
+    assert_eq!(follower.get_tables_names().unwrap(), vec!["users".to_string(), "later".to_string()]);
+
+    let info = follower.info().unwrap();
+    assert_eq!(info["replication"]["role"], "follower");
+    assert_eq!(info["replication"]["lag_bytes"], 0);
+    let info = leader.info().unwrap();
+    assert_eq!(info["replication"]["role"], "leader");
+    assert_eq!(info["replication"]["followers"].as_array().unwrap().len(), 1);
+
+    // Followers are read-only.
+    let error = follower.set(users, b"key", b"value").unwrap_err();
+    assert_eq!(error.code(), Some(codes::SERVER_IS_READ_ONLY_FOLLOWER));
+    let error = follower.create_table_in_memory("local", &dbms_client::Scheme::empty(), true).unwrap_err();
+    assert_eq!(error.code(), Some(codes::SERVER_IS_READ_ONLY_FOLLOWER));
+
+    fs::remove_dir_all(leader_dir).unwrap();
+    fs::remove_dir_all(follower_dir).unwrap();
+}
+
+#[test]
+fn changes() {
+    let (tcp, _, dir) = start_server("changes", "");
+    let client = Client::connect(tcp, PoolConfig { timeout: Some(Duration::from_secs(5)), ..PoolConfig::default() }).unwrap();
+    let users = client.create_table_in_memory("users", &dbms_client::Scheme::empty(), true).unwrap();
+    client.set(users, b"bob", b"1").unwrap();
+    client.set(users, b"bob", &vec![2u8; 70_000]).unwrap();
+    client.delete(users, b"bob").unwrap();
+
+    let mut stream = client.subscribe_changes(&[users], changes::FROM_OLDEST, true).unwrap();
+    let first = stream.next().unwrap().unwrap();
+    assert_eq!((first.action, first.table, first.key.as_slice(), first.value.as_deref()), (actions::SET, users, &b"bob"[..], Some(&b"1"[..])));
+    assert_eq!(first.old_value, None);
+    let second = stream.next().unwrap().unwrap();
+    assert_eq!(second.old_value, Some(b"1".to_vec()));
+    let deleted = stream.next().unwrap().unwrap();
+    assert_eq!((deleted.action, deleted.value, deleted.old_value), (actions::DELETE, None, Some(vec![2u8; 70_000])));
+    assert_eq!(stream.sequence_number(), deleted.sequence_number);
+
+    // A new stream resumes after the handled change, and a stream from now gets only new changes.
+    let mut resumed = client.subscribe_changes(&[], first.sequence_number, false).unwrap();
+    assert_eq!(resumed.next().unwrap().unwrap().sequence_number, second.sequence_number);
+    let mut new_changes = client.subscribe_changes(&[], changes::FROM_NOW, false).unwrap();
+    assert_eq!(new_changes.sequence_number(), deleted.sequence_number);
+    client.insert(users, b"alice", b"3").unwrap();
+    assert_eq!(new_changes.next().unwrap().unwrap().key, b"alice".to_vec());
+    assert_eq!(stream.next().unwrap().unwrap().key, b"alice".to_vec());
+
+    let error = client.subscribe_changes(&[], 1 << 60, false).err().unwrap();
+    assert_eq!(error.code(), Some(codes::SEQUENCE_NUMBER_IS_NOT_RETAINED));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn pubsub() {
+    let (tcp, _, dir) = start_server("pubsub", "");
+    let client = Client::connect(tcp, PoolConfig { timeout: Some(Duration::from_secs(5)), ..PoolConfig::default() }).unwrap();
+    let mut subscription = client.subscribe(&[b"news"], &[b"user.*"]).unwrap();
+    assert_eq!(subscription.subscriptions(), 2);
+
+    assert_eq!(client.publish(b"news", b"hello").unwrap(), 1);
+    assert_eq!(client.publish(b"user.bob", b"online").unwrap(), 1);
+    assert_eq!(client.publish(b"other", b"").unwrap(), 0);
+    assert_eq!(subscription.next().unwrap().unwrap(), Message { channel: b"news".to_vec(), pattern: None, payload: b"hello".to_vec() });
+    // The message, that comes before the answer to the subscription, is returned later.
+    assert_eq!(subscription.subscribe(&[b"other"]).unwrap(), 3);
+    let message = subscription.next().unwrap().unwrap();
+    assert_eq!((message.channel, message.pattern), (b"user.bob".to_vec(), Some(b"user.*".to_vec())));
+
+    assert_eq!(subscription.unsubscribe(&[b"news"], &[]).unwrap(), 2);
+    assert_eq!(client.publish(b"news", b"").unwrap(), 0);
+    assert_eq!(subscription.unsubscribe(&[], &[]).unwrap(), 0);
+    assert_eq!(client.publish(b"other", b"").unwrap(), 0);
+    assert!(client.subscribe(&[], &[]).is_err());
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn notifications() {
+    let (tcp, _, dir) = start_server("notifications", "");
+    let client = Client::connect(tcp, PoolConfig { timeout: Some(Duration::from_secs(5)), ..PoolConfig::default() }).unwrap();
+    let users = client.create_table_in_memory("users", &dbms_client::Scheme::empty(), true).unwrap();
+    client.set_notifications(users, notifications::SET | notifications::DELETE).unwrap();
+    let mut subscription = client.subscribe(&[&notifications::keyevent_channel(users, notifications::DELETE)],
+        &[&notifications::keyspace_channel(users, b"*")]).unwrap();
+
+    client.set(users, b"bob", b"1").unwrap();
+    client.delete(users, b"bob").unwrap();
+    let events: Vec<Message> = (0..3).map(|_| subscription.next().unwrap().unwrap()).collect();
+    assert_eq!((events[0].channel.as_slice(), events[0].payload.as_slice()), (&b"__keyspace@0__:bob"[..], &b"set"[..]));
+    assert_eq!((events[1].channel.as_slice(), events[1].payload.as_slice()), (&b"__keyspace@0__:bob"[..], &b"delete"[..]));
+    assert_eq!((events[2].channel.as_slice(), events[2].payload.as_slice()), (&b"__keyevent@0__:delete"[..], &b"bob"[..]));
+
+    let watcher = {
+        let client = client.clone();
+        thread::spawn(move || client.watch(users, b"alice", Some(Duration::from_secs(3))))
+    };
+    // The watch has no answer before the change, so the key is changed until the watcher is woken.
+    while !watcher.is_finished() {
+        client.set(users, b"alice", b"1").unwrap();
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(watcher.join().unwrap().unwrap(), Some(notifications::SET));
+    assert_eq!(client.watch(users, b"alice", Some(Duration::from_millis(20))).unwrap(), None);
+    assert_eq!(client.set_notifications(users, 255).err().unwrap().code(), Some(codes::EVENTS_ARE_NOT_VALID));
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn shards() {
+    for key in [&b""[..], b"a", b"123456789", &[255; 300]] {
+        assert_eq!(dbms_client::shard_of(key), sharding::shard_of(key));
+    }
+
+    let moved = Error::Server { status: 1, code: codes::KEY_IS_MOVED, message: "MOVED 12739 1 127.0.0.1:10001".to_string() };
+    assert_eq!(moved.moved(), Some((12739, 1, "127.0.0.1:10001")));
+    assert_eq!(moved.leader_addr(), None);
+    let redirect = Error::Server { status: 1, code: codes::NOT_THE_LEADER, message: "127.0.0.1:10003".to_string() };
+    assert_eq!(redirect.leader_addr(), Some("127.0.0.1:10003"));
+    assert_eq!(redirect.moved(), None);
+}
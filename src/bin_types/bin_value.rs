@@ -67,7 +67,7 @@ impl<'a> BinValue {
     #[inline(always)]
     pub fn deref_with_len(&self, len: usize) -> &'a [u8] {
         unsafe {
-            if len < 65355 {
+            if len < 65535 {
                 return &(*ptr::slice_from_raw_parts(self.ptr.add(2), len))
             }
             return &(*ptr::slice_from_raw_parts(self.ptr.add(6), len));
@@ -91,7 +91,7 @@ impl<'a> BinValue {
     #[inline(always)]
     pub fn deref_all_with_len(&self, len: usize) -> &'a [u8] {
         unsafe {
-            if len < 65355 {
+            if len < 65535 {
                 return &(*ptr::slice_from_raw_parts(self.ptr, len + 2));
             }
             return &(*ptr::slice_from_raw_parts(self.ptr, len + 6));
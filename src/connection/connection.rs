@@ -1,4 +1,5 @@
 use std::marker::PhantomData;
+use crate::constants::errors::Error;
 use crate::stream::Stream;
 use crate::connection::{
     status::Status,
@@ -20,6 +21,8 @@ pub trait BufWriter<'stream, S: Stream> {
     fn flush(&mut self) -> std::io::Result<()>;
     fn write_message(&mut self, message: &[u8]) -> Status;
     fn write_message_and_status(&mut self, message: &[u8], status: u8) -> Status;
+    /// Writes the error as a message: [`status`, `code` (2 bytes), `message`].
+    fn write_error(&mut self, error: Error) -> Status;
     fn close(&mut self) -> std::io::Result<()>;
 }
 
@@ -83,6 +86,11 @@ impl<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> Buf
         self.writer.write_message_and_status(message, status)
     }
 
+    #[inline(always)]
+    pub fn write_error(&mut self, error: Error) -> Status {
+        self.writer.write_error(error)
+    }
+
     #[inline(always)]
     pub fn close(&mut self) -> std::io::Result<()> {
         self.writer.close()
@@ -4,6 +4,8 @@ mod reader;
 pub mod status;
 
 const BUFFER_SIZE: usize = u16::MAX as usize;
+/// We don't allocate more for one message, even if the client asks for it.
+const MAX_MESSAGE_SIZE: usize = 512 * 1024 * 1024;
 
 pub use connection::*;
 pub use status::Status;
\ No newline at end of file
@@ -1,4 +1,3 @@
-use std::io::Read;
 use crate::{
     stream::Stream,
     utils::bytes::uint::{u16, u32},
@@ -6,9 +5,10 @@ use crate::{
     connection::{
         status::Status,
         connection::BufReader as BufReaderTrait,
-        BUFFER_SIZE
+        BUFFER_SIZE,
+        MAX_MESSAGE_SIZE
     }
-}
+};
 
 pub struct BufReader<S: Stream> {
     pub buf: [u8; BUFFER_SIZE],
@@ -59,26 +59,21 @@ impl<'stream, S: Stream> BufReaderTrait<'stream, S> for BufReader<S> {
                 };
             }
         }
-        if needed > BUFFER_SIZE - self.write_offset {
+        if needed > BUFFER_SIZE - self.read_offset {
             let left = self.write_offset - self.read_offset;
-            let buf = Vec::from(&self.buf[self.read_offset..self.write_offset]);
-            self.buf[0..left].copy_from_slice(&buf);
+            self.buf.copy_within(self.read_offset..self.write_offset, 0);
             self.read_offset = 0;
             self.write_offset = left;
         }
 
-        let mut read = 0;
-        loop {
+        // `needed` is counted from `read_offset`, so we don't wait for bytes, that we have already read.
+        while self.write_offset - self.read_offset < needed {
             match Stream::read(&mut self.reader, &mut self.buf[self.write_offset..]) {
                 Ok(0) => {
                     return Status::Closed;
                 }
                 Ok(size) => {
                     self.write_offset += size;
-                    read += size;
-                    if read >= needed {
-                        return Status::Ok;
-                    }
                 }
                 Err(e) => {
                     error!("Read connection error: {:?}", e);
@@ -86,12 +81,17 @@ impl<'stream, S: Stream> BufReaderTrait<'stream, S> for BufReader<S> {
                 }
             };
         }
+        Status::Ok
     }
 
     /// read request returns status and is a request reading.
     #[inline(always)]
     fn read_request(&mut self) -> (Status, bool) {
-        self.write_offset = 0;
+        // The client can send the next request before it gets the response, so we can't drop the read bytes.
+        let left = self.write_offset - self.read_offset;
+        self.buf.copy_within(self.read_offset..self.write_offset, 0);
+        self.read_offset = 0;
+        self.write_offset = left;
         let status = self.read_more(5);
         if status != Status::Ok {
             return (status, false);
@@ -109,6 +109,9 @@ impl<'stream, S: Stream> BufReaderTrait<'stream, S> for BufReader<S> {
         if self.request_size == 0 {
             return (&[], Status::All);
         }
+        if self.request_size < 2 {
+            return (&[], Status::Error);
+        }
         if self.write_offset < self.read_offset + 2 {
             let status = self.read_more(2);
             if status != Status::Ok {
@@ -120,6 +123,9 @@ impl<'stream, S: Stream> BufReaderTrait<'stream, S> for BufReader<S> {
         self.read_offset += 2;
         self.request_size -= 2;
         if len == u16::MAX as usize {
+            if self.request_size < 4 {
+                return (&[], Status::Error);
+            }
             if self.write_offset < self.read_offset + 4 {
                 let status = self.read_more(4);
                 if status != Status::Ok {
@@ -131,6 +137,11 @@ impl<'stream, S: Stream> BufReaderTrait<'stream, S> for BufReader<S> {
             self.request_size -= 4;
         }
 
+        if len > self.request_size || len > MAX_MESSAGE_SIZE {
+            error!("Bad message length: {}, left in the request: {}", len, self.request_size);
+            return (&[], Status::Error);
+        }
+
         if self.write_offset < self.read_offset + len {
             let status = self.read_more(len);
             if status != Status::Ok {
@@ -139,7 +150,7 @@ impl<'stream, S: Stream> BufReaderTrait<'stream, S> for BufReader<S> {
         }
 
         self.request_size -= len;
-        if len < u16::MAX as usize {
+        if len <= BUFFER_SIZE {
             self.read_offset += len;
             let ptr = &self.buf[self.read_offset - len..self.read_offset];
             return (unsafe {std::mem::transmute::<&[u8], &'stream [u8]>(ptr)}, Status::Ok);
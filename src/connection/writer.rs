@@ -4,6 +4,7 @@ use crate::{
         BUFFER_SIZE,
         status::Status
     },
+    constants::errors::Error,
     stream::Stream
 };
 
@@ -108,6 +109,13 @@ impl<'stream, S: Stream> BufWriterTrait<'stream, S> for BufWriter<S> {
         return Status::Ok;
     }
 
+    fn write_error(&mut self, error: Error) -> Status {
+        let mut buf = Vec::with_capacity(3 + error.message.len());
+        buf.extend_from_slice(&[error.status, error.code as u8, (error.code >> 8) as u8]);
+        buf.extend_from_slice(error.message.as_bytes());
+        self.write_message(&buf)
+    }
+
     fn close(&mut self) -> std::io::Result<()> {
         self.flush()?;
         self.writer.shutdown()
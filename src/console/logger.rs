@@ -0,0 +1,287 @@
+//! The logger behind [`info!`](crate::info), [`warn!`](crate::warn), [`error!`](crate::error), [`success!`](crate::success),
+//! [`debug!`](crate::debug) and [`trace!`](crate::trace).
+//!
+//! It is configured by environment variables on the first use:
+//!
+//! - `LOG_LEVEL` is a filter like `info,dbms::server::resp=debug,dbms::storage=off`. The first item without `=` is the default level,
+//!   items with `=` set the level of modules, which paths start with the target. The longest target wins. The default filter is `info`.
+//! - `LOG_FORMAT` is `text` (colored lines, the default) or `json` (one JSON object per line).
+//!
+//! Fields of the request, like the connection id or the table, are set for the current thread with [`field`]
+//! and are added to every record until the guard is dropped.
+use std::{
+    cell::RefCell,
+    fmt::{self, Write as _},
+    env,
+    sync::{OnceLock, RwLock},
+    time::{SystemTime, UNIX_EPOCH}
+};
+use colored::Colorize;
+use serde_json::{Map, Value};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Off = 0,
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5
+}
+
+impl Level {
+    pub fn parse(name: &str) -> Option<Level> {
+        match name.trim().to_lowercase().as_str() {
+            "off" | "none" => Some(Level::Off),
+            "error" => Some(Level::Error),
+            "warn" | "warning" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Off => "off",
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace"
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name.trim().to_lowercase().as_str() {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    default: Level,
+    /// Sorted by the length of the target, the longest first.
+    targets: Vec<(String, Level)>
+}
+
+impl Filter {
+    /// Parses a filter like `info,dbms::server=debug`. Wrong items are ignored.
+    pub fn parse(filter: &str) -> Filter {
+        Self::parse_items(filter, false).unwrap_or_else(|_| Filter { default: Level::Info, targets: Vec::new() })
+    }
+
+    /// Like [`Filter::parse`], but returns the wrong item as an error.
+    pub fn try_parse(filter: &str) -> Result<Filter, String> {
+        Self::parse_items(filter, true)
+    }
+
+    fn parse_items(filter: &str, is_strict: bool) -> Result<Filter, String> {
+        let mut default = Level::Info;
+        let mut targets = Vec::new();
+        for item in filter.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (target, level) = match item.split_once('=') {
+                Some((target, level)) => (Some(target.trim()), level),
+                None => (None, item)
+            };
+            match (target, Level::parse(level)) {
+                (Some(target), Some(level)) => targets.push((target.to_string(), level)),
+                (None, Some(level)) => default = level,
+                (_, None) if is_strict => return Err(format!("unknown level \"{}\" in \"{}\"", level.trim(), item)),
+                (_, None) => {}
+            }
+        }
+        targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+        Ok(Filter { default, targets })
+    }
+
+    pub fn level(&self, target: &str) -> Level {
+        self.targets.iter()
+            .find(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+}
+
+struct Logger {
+    filter: RwLock<Filter>,
+    format: RwLock<Format>
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(|| {
+        let filter = env::var("LOG_LEVEL").map(|filter| Filter::parse(&filter)).unwrap_or_else(|_| Filter::parse("info"));
+        let format = env::var("LOG_FORMAT").ok().and_then(|format| Format::parse(&format)).unwrap_or(Format::Text);
+        Logger { filter: RwLock::new(filter), format: RwLock::new(format) }
+    })
+}
+
+/// Replaces the filter, that was read from `LOG_LEVEL`.
+pub fn set_filter(filter: Filter) {
+    if let Ok(mut current) = logger().filter.write() {
+        *current = filter;
+    }
+}
+
+/// Replaces the format, that was read from `LOG_FORMAT`.
+pub fn set_format(format: Format) {
+    if let Ok(mut current) = logger().format.write() {
+        *current = format;
+    }
+}
+
+#[inline(always)]
+pub fn enabled(level: Level, target: &str) -> bool {
+    match logger().filter.read() {
+        Ok(filter) => level <= filter.level(target),
+        Err(_) => level <= Level::Info
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum FieldValue {
+    Number(u64),
+    Text(String)
+}
+
+impl From<u64> for FieldValue {
+    fn from(value: u64) -> Self {
+        FieldValue::Number(value)
+    }
+}
+
+impl From<u16> for FieldValue {
+    fn from(value: u16) -> Self {
+        FieldValue::Number(value as u64)
+    }
+}
+
+impl From<usize> for FieldValue {
+    fn from(value: usize) -> Self {
+        FieldValue::Number(value as u64)
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(value: &str) -> Self {
+        FieldValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(value: String) -> Self {
+        FieldValue::Text(value)
+    }
+}
+
+thread_local! {
+    static FIELDS: RefCell<Vec<(&'static str, FieldValue)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Removes the field from records of the thread, when it is dropped.
+pub struct FieldGuard(());
+
+impl Drop for FieldGuard {
+    fn drop(&mut self) {
+        FIELDS.with(|fields| {
+            fields.borrow_mut().pop();
+        });
+    }
+}
+
+/// Adds the field to every record of the current thread until the guard is dropped.
+#[must_use]
+pub fn field(name: &'static str, value: impl Into<FieldValue>) -> FieldGuard {
+    let value = value.into();
+    FIELDS.with(|fields| fields.borrow_mut().push((name, value)));
+    FieldGuard(())
+}
+
+/// Writes the time like `2024-02-29T13:05:09.123Z`.
+fn write_timestamp(out: &mut String, time: SystemTime) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+    // Converts days since 1970-01-01 to the civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    let _ = write!(
+        out, "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, seconds_of_day / 3600, seconds_of_day / 60 % 60, seconds_of_day % 60, since_epoch.subsec_millis()
+    );
+}
+
+/// Formats the record without the trailing new line. `is_success` only changes the color of the text format.
+pub fn format_record(format: Format, time: SystemTime, level: Level, is_success: bool, target: &str, message: fmt::Arguments) -> String {
+    let mut timestamp = String::with_capacity(24);
+    write_timestamp(&mut timestamp, time);
+    FIELDS.with(|fields| {
+        let fields = fields.borrow();
+        match format {
+            Format::Json => {
+                let mut record = Map::with_capacity(4 + fields.len());
+                record.insert("ts".to_string(), Value::from(timestamp));
+                record.insert("level".to_string(), Value::from(level.name()));
+                record.insert("target".to_string(), Value::from(target));
+                record.insert("msg".to_string(), Value::from(message.to_string()));
+                for (name, value) in fields.iter() {
+                    let value = match value {
+                        FieldValue::Number(number) => Value::from(*number),
+                        FieldValue::Text(text) => Value::from(text.as_str())
+                    };
+                    record.insert(name.to_string(), value);
+                }
+                Value::Object(record).to_string()
+            }
+            Format::Text => {
+                let mut line = format!("{} {:<5} {}: {}", timestamp, level.name().to_uppercase(), target, message);
+                for (name, value) in fields.iter() {
+                    let _ = match value {
+                        FieldValue::Number(number) => write!(line, " {}={}", name, number),
+                        FieldValue::Text(text) => write!(line, " {}={:?}", name, text)
+                    };
+                }
+                let colored = match level {
+                    Level::Error => line.red(),
+                    Level::Warn => line.yellow(),
+                    Level::Info if is_success => line.bright_green(),
+                    Level::Info => line.blue(),
+                    _ => line.normal()
+                };
+                colored.to_string()
+            }
+        }
+    })
+}
+
+/// Is called by the macros. Use them instead.
+#[inline(always)]
+pub fn log(level: Level, is_success: bool, target: &str, message: fmt::Arguments) {
+    if !enabled(level, target) {
+        return;
+    }
+    let format = logger().format.read().map(|format| *format).unwrap_or(Format::Text);
+    let record = format_record(format, SystemTime::now(), level, is_success, target, message);
+    println!("{}", record);
+}
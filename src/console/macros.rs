@@ -1,47 +1,44 @@
+//! Logging macros. The first argument is a format string like in `println!`. See [`crate::console::logger`].
+
 #[macro_export]
 macro_rules! info {
-    ($msg:expr) => {
-        let blue = colored::Colorize::blue($msg);
-        println!("{}", blue);
-    };
-    ($msg:expr, $($arg:expr),*) => {
-        let blue = colored::Colorize::blue(format!($msg, $($arg),*).as_str());
-        println!("{blue}");
+    ($($arg:tt)+) => {
+        $crate::console::logger::log($crate::console::logger::Level::Info, false, module_path!(), format_args!($($arg)+))
     };
 }
 
 #[macro_export]
 macro_rules! warn {
-    ($msg:expr) => {
-        let yellow = colored::Colorize::yellow($msg);
-        println!("{}", yellow);
-    };
-    ($msg:expr, $($arg:expr),*) => {
-        let yellow = colored::Colorize::yellow(format!($msg, $($arg),*).as_str());
-        println!("{yellow}");
+    ($($arg:tt)+) => {
+        $crate::console::logger::log($crate::console::logger::Level::Warn, false, module_path!(), format_args!($($arg)+))
     };
 }
 
+/// Like [`info!`], but green in the text format.
 #[macro_export]
 macro_rules! success {
-    ($msg:expr) => {
-        let green = colored::Colorize::bright_green($msg);
-        println!("{}", green);
-    };
-    ($msg:expr, $($arg:expr),*) => {
-        let green = colored::Colorize::bright_green(format!($msg, $($arg),*).as_str());
-        println!("{green}");
+    ($($arg:tt)+) => {
+        $crate::console::logger::log($crate::console::logger::Level::Info, true, module_path!(), format_args!($($arg)+))
     };
 }
 
 #[macro_export]
 macro_rules! error {
-    ($msg:expr) => {
-        let red = colored::Colorize::red($msg);
-        println!("{}", red);
+    ($($arg:tt)+) => {
+        $crate::console::logger::log($crate::console::logger::Level::Error, false, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => {
+        $crate::console::logger::log($crate::console::logger::Level::Debug, false, module_path!(), format_args!($($arg)+))
     };
-    ($msg:expr, $($arg:expr),*) => {
-        let red = colored::Colorize::red(format!($msg, $($arg),*).as_str());
-        println!("{red}");
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => {
+        $crate::console::logger::log($crate::console::logger::Level::Trace, false, module_path!(), format_args!($($arg)+))
     };
-}
\ No newline at end of file
+}
@@ -1,2 +1,3 @@
 pub mod start_message;
-pub mod macros;
\ No newline at end of file
+pub mod macros;
+pub mod logger;
\ No newline at end of file
@@ -21,6 +21,47 @@ pub const INSERT: u8 = 15u8;
 pub const SET: u8 = 16u8;
 pub const DELETE: u8 = 17u8;
 
+pub const GET_TABLE_SCHEME: u8 = 18u8;
+pub const INFO: u8 = 19u8;
+pub const SLOWLOG_GET: u8 = 20u8;
+pub const SLOWLOG_RESET: u8 = 21u8;
+pub const CONFIG_GET: u8 = 22u8;
+pub const CONFIG_SET: u8 = 23u8;
+pub const SHUTDOWN: u8 = 24u8;
+/// Is sent by a follower to the leader. See [`crate::server::replication`].
+pub const REPLICATE: u8 = 25u8;
+/// Is sent by a machine to other machines of its node. See [`crate::server::failover`].
+pub const HEARTBEAT: u8 = 26u8;
+/// Moves a range of shards of this node to another node. See [`crate::server::migration`].
+pub const MIGRATE_SHARDS: u8 = 27u8;
+/// Is sent by the node, that moves shards, to the new node of the shards.
+pub const IMPORT_RECORDS: u8 = 28u8;
+/// Is sent by the node, that has moved shards, to other nodes.
+pub const SET_SHARDS_NODE: u8 = 29u8;
+/// Plans moves of shards, that even out numbers of shards of nodes, and makes them.
+pub const REBALANCE: u8 = 30u8;
+/// Change the hierarchy. See [`crate::server::hierarchy`].
+pub const ADD_NODE: u8 = 31u8;
+pub const REMOVE_NODE: u8 = 32u8;
+pub const ADD_MACHINE: u8 = 33u8;
+pub const REMOVE_MACHINE: u8 = 34u8;
+/// Is sent by the machine, that has changed the hierarchy, to other machines.
+pub const SET_HIERARCHY: u8 = 35u8;
+/// Makes the connection a stream of changes of tables. See [`crate::server::changes`].
+pub const SUBSCRIBE_CHANGES: u8 = 36u8;
+/// Pub/sub messaging. See [`crate::server::pubsub`].
+pub const PUBLISH: u8 = 37u8;
+/// Switches the connection into the push mode.
+pub const SUBSCRIBE: u8 = 38u8;
+pub const PSUBSCRIBE: u8 = 39u8;
+pub const UNSUBSCRIBE: u8 = 40u8;
+/// Chooses events of the table, that are published. See [`crate::table::notifications`].
+pub const SET_NOTIFICATIONS: u8 = 41u8;
+/// Waits, until the key is changed.
+pub const WATCH: u8 = 42u8;
+/// The message is the same as [`CREATE_TABLE_ON_DISK`]. See [`crate::table::lsm`].
+pub const CREATE_TABLE_LSM: u8 = 43u8;
+
 /// If the number of action is greater than 255, you need to use big action and add the action number after (like [255u8, 1u8, 254u8])
 #[allow(dead_code)]
 pub const BIG_ACTION: u8 = 255u8;
\ No newline at end of file
@@ -0,0 +1,109 @@
+use crate::constants::actions::{BAD_REQUEST, INTERNAL_ERROR, NOT_FOUND, TABLE_NOT_FOUND};
+
+/// Error is sent to the client as [`status`, `code` (2 bytes, little endian), `message`].
+///
+/// `status` is one of the old one-byte statuses, so a client can still check only the first byte.
+/// `code` is stable: we never change or reuse it. `message` is human-readable and can be changed at any time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Error {
+    pub status: u8,
+    pub code: u16,
+    pub message: &'static str,
+}
+
+impl Error {
+    pub const fn new(status: u8, code: u16, message: &'static str) -> Self {
+        Self { status, code, message }
+    }
+}
+
+// 1xx: the request is malformed.
+
+pub const UNKNOWN_ACTION: Error = Error::new(BAD_REQUEST, 100, "Unknown action");
+pub const MESSAGE_IS_TOO_SHORT: Error = Error::new(BAD_REQUEST, 101, "Message is too short");
+pub const TABLE_NAME_IS_NOT_UTF8: Error = Error::new(BAD_REQUEST, 102, "Table name is not valid UTF-8");
+pub const TABLE_NAME_IS_TOO_LONG: Error = Error::new(BAD_REQUEST, 103, "Table name is too long");
+
+// 2xx: the scheme is not valid.
+
+pub const SCHEME_IS_NOT_VALID_JSON: Error = Error::new(BAD_REQUEST, 200, "Scheme is not valid JSON");
+pub const FIELD_TYPE_IS_NOT_STRING: Error = Error::new(BAD_REQUEST, 201, "Fields type must be a string");
+pub const UNKNOWN_FIELD_TYPE: Error = Error::new(BAD_REQUEST, 202, "Unknown field type");
+pub const UNKNOWN_COMPRESSION: Error = Error::new(BAD_REQUEST, 203, "Unknown compression, use none, lz4 or zstd");
+
+// 3xx: tables.
+
+pub const TABLE_IS_NOT_FOUND: Error = Error::new(TABLE_NOT_FOUND, 300, "Table not found");
+pub const TABLE_ALREADY_EXISTS: Error = Error::new(BAD_REQUEST, 301, "Table with this name already exists");
+
+// 4xx: keys and values.
+
+pub const KEY_IS_NOT_FOUND: Error = Error::new(NOT_FOUND, 400, "Key not found");
+pub const FIELD_IS_NOT_FOUND: Error = Error::new(BAD_REQUEST, 401, "Field not found in the table scheme");
+pub const VALUE_DOES_NOT_MATCH_SCHEME: Error = Error::new(BAD_REQUEST, 402, "Value does not match the table scheme");
+pub const KEY_IS_TOO_LONG: Error = Error::new(BAD_REQUEST, 403, "Key is too long");
+pub const KEY_ALREADY_EXISTS: Error = Error::new(BAD_REQUEST, 404, "Key already exists");
+pub const FIELD_IS_MISSING: Error = Error::new(BAD_REQUEST, 405, "Value misses a field of the table scheme");
+pub const FIELD_HAS_WRONG_TYPE: Error = Error::new(BAD_REQUEST, 406, "Field has a wrong type");
+
+// 5xx: the server can't handle a valid request.
+
+pub const TABLES_LOCK_IS_POISONED: Error = Error::new(INTERNAL_ERROR, 500, "Tables lock is poisoned");
+pub const CANT_READ_SHARD_METADATA: Error = Error::new(INTERNAL_ERROR, 501, "Can't read shard metadata file");
+pub const CANT_CREATE_TABLE: Error = Error::new(INTERNAL_ERROR, 502, "Can't create table");
+pub const SERVER_IS_SHUTTING_DOWN: Error = Error::new(INTERNAL_ERROR, 503, "Server is shutting down");
+/// A file of the table can't be read or is broken.
+pub const VALUE_CANT_BE_READ: Error = Error::new(INTERNAL_ERROR, 504, "Value can't be read from the disk");
+/// The stored value of the table with compression can't be decompressed.
+pub const VALUE_IS_BROKEN: Error = Error::new(INTERNAL_ERROR, 505, "Stored value is broken");
+
+// 6xx: the HTTP gateway.
+
+pub const UNAUTHORIZED: Error = Error::new(BAD_REQUEST, 600, "Wrong or missing password");
+pub const ROUTE_IS_NOT_FOUND: Error = Error::new(NOT_FOUND, 601, "Route not found");
+pub const METHOD_IS_NOT_ALLOWED: Error = Error::new(BAD_REQUEST, 602, "Method not allowed");
+pub const BODY_IS_NOT_VALID_JSON: Error = Error::new(BAD_REQUEST, 603, "Body is not valid JSON");
+pub const BODY_IS_TOO_LARGE: Error = Error::new(BAD_REQUEST, 604, "Body is too large");
+pub const UNKNOWN_TABLE_ENGINE: Error = Error::new(BAD_REQUEST, 605, "Unknown table engine");
+
+// 7xx: settings.
+
+pub const UNKNOWN_SETTING: Error = Error::new(BAD_REQUEST, 700, "Unknown setting");
+pub const SETTING_VALUE_IS_NOT_VALID: Error = Error::new(BAD_REQUEST, 701, "Setting value is not valid");
+pub const SETTING_IS_NOT_RELOADABLE: Error = Error::new(BAD_REQUEST, 702, "Setting can't be changed at runtime");
+pub const SETTING_NAME_IS_NOT_UTF8: Error = Error::new(BAD_REQUEST, 703, "Setting name or value is not valid UTF-8");
+pub const CONFIG_LOCK_IS_POISONED: Error = Error::new(INTERNAL_ERROR, 704, "Config lock is poisoned");
+
+// 8xx: replication and the cluster.
+
+pub const SERVER_IS_READ_ONLY_FOLLOWER: Error = Error::new(BAD_REQUEST, 800, "Server is a read-only follower, write to the leader");
+pub const FOLLOWER_IS_TOO_FAR_BEHIND: Error = Error::new(INTERNAL_ERROR, 801, "Follower is too far behind, the replication backlog has dropped its records");
+/// Is sent with the address of the leader instead of the message, so the client can repeat the request there.
+pub const NOT_THE_LEADER: Error = Error::new(BAD_REQUEST, 802, "Machine is not the leader of the node");
+/// Is sent with `MOVED shard node address` instead of the message, see [`crate::server::sharding`].
+pub const KEY_IS_MOVED: Error = Error::new(BAD_REQUEST, 803, "Key belongs to another node");
+pub const NODE_IS_NOT_FOUND: Error = Error::new(BAD_REQUEST, 804, "Node not found in the hierarchy");
+pub const SHARDS_ARE_NOT_OWNED: Error = Error::new(BAD_REQUEST, 805, "Shards belong to another node");
+pub const MIGRATION_IS_RUNNING: Error = Error::new(BAD_REQUEST, 806, "Another migration of shards is running");
+/// The shards stay on the source node, the error is in its log.
+pub const MIGRATION_HAS_FAILED: Error = Error::new(INTERNAL_ERROR, 807, "Migration of shards has failed");
+pub const TABLES_OF_NODES_DIFFER: Error = Error::new(BAD_REQUEST, 808, "Tables of nodes have different numbers");
+pub const RECORDS_ARE_NOT_VALID: Error = Error::new(BAD_REQUEST, 809, "Records are not valid");
+pub const MACHINE_ALREADY_EXISTS: Error = Error::new(BAD_REQUEST, 810, "Machine is already in the hierarchy");
+pub const MACHINE_IS_NOT_FOUND: Error = Error::new(BAD_REQUEST, 811, "Machine not found in the hierarchy");
+pub const NODE_OWNS_SHARDS: Error = Error::new(BAD_REQUEST, 812, "Node owns shards, move them to other nodes first");
+pub const NODE_NEEDS_A_MACHINE: Error = Error::new(BAD_REQUEST, 813, "Node must have at least one machine");
+pub const HIERARCHY_IS_NOT_VALID: Error = Error::new(BAD_REQUEST, 814, "Hierarchy is not valid");
+
+// 9xx: streams of changes and messages.
+
+pub const SEQUENCE_NUMBER_IS_NOT_RETAINED: Error = Error::new(BAD_REQUEST, 900, "Sequence number is not in the retained log files");
+/// The sequence number doesn't point to the start of a record, or the log file is damaged.
+pub const LOG_RECORD_IS_NOT_VALID: Error = Error::new(INTERNAL_ERROR, 901, "Log record at the sequence number is not valid");
+/// A connection in the push mode accepts only `SUBSCRIBE`, `PSUBSCRIBE`, `UNSUBSCRIBE` and `PING`.
+pub const CONNECTION_IS_SUBSCRIBED: Error = Error::new(BAD_REQUEST, 902, "Connection is subscribed, only subscriptions and PING are allowed");
+/// Only connections of the binary protocol switch into the push mode.
+pub const PUSH_MODE_IS_NOT_AVAILABLE: Error = Error::new(BAD_REQUEST, 903, "Subscriptions are not available on this connection");
+pub const EVENTS_ARE_NOT_VALID: Error = Error::new(BAD_REQUEST, 904, "Unknown events of notifications");
+/// Patterns of `PSUBSCRIBE` are limited, because they are matched on every `PUBLISH`.
+pub const PATTERN_IS_TOO_LONG: Error = Error::new(BAD_REQUEST, 905, "Pattern is too long");
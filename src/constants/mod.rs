@@ -1,2 +1,3 @@
 pub mod actions;
+pub mod errors;
 pub mod paths;
\ No newline at end of file
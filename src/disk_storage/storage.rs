@@ -1,332 +1,372 @@
 use std::{
-    fs::{File, metadata},
+    cell::RefCell,
+    fs::{self, File, OpenOptions},
     hash::{BuildHasher, Hash, Hasher},
-    io::{Read},
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
     sync:: {
         {Arc, RwLock, Mutex},
-        atomic::AtomicU64
+        atomic::{AtomicU64, Ordering::SeqCst}
     },
     path::PathBuf
 };
-#[cfg(not(target_os = "windows"))]
-use std::fs::OpenOptions;
-use ahash::{HashMap, HashMapExt, RandomState};
+use ahash::RandomState;
 use positioned_io::{ReadAt};
 use crate::{
     bin_types::{BinKey, BinValue},
+    disk_storage::value_cache::{Location, ValueCache},
     index::Index,
-    writers::{get_size_for_key_len, get_size_for_value_len, SizedWriter}
+    lsm::bloom::{self, GrowingBloomFilter},
+    metrics::METRICS,
+    utils::bytes::uint,
+    writers::{get_size_for_key_len, get_size_for_value_len, SizedWriter},
+    warn
 };
 
 const BUFFER_SIZE: usize = 4100;
 const DELETE_BUFFER_SIZE: usize = 66;
-
+const READ_BUFFER_SIZE: usize = 1024 * 1024;
+const HINT_FILE: &str = "hint.bin";
+const HINT_TMP_FILE: &str = "hint.tmp";
+/// Keys, that a new filter of a file is for.
+const MIN_FILTER_KEYS: usize = 256;
+
+/// Values are stored in `size` append-only files `{i}.bin` as records: [`key` with its size, `value` with its size].
+/// A key is in the file with the number of its hash.
+///
+/// When a record becomes old (it is replaced by `set` or deleted), [`key` with its size, `offset` of the value (8 bytes)] is
+/// appended to `{i}D.bin`. So [`DiskStorage::rise`] takes the last record of every key and removes records, that are in `D` files.
+///
+/// [`DiskStorage::write_hint`] writes `infos` to `hint.bin`, so the rise reads only records after the hint:
+/// [`size` (4 bytes), [`length of {i}.bin` (8 bytes), `length of {i}D.bin` (8 bytes)] for every file,
+/// [`key` with its size, `value length` (4 bytes), `offset` (8 bytes)]...].
+///
+/// Every file has the bloom filter of its keys, so lookups of missing keys usually don't touch `infos` and files. Deleted keys
+/// stay in filters, until they are rebuilt on the rise.
 pub struct DiskStorage<I: Index<BinKey, (u64, u64)>> {
     /// Be careful! Size and offset to the VALUE, not to the value and key and 6 bytes for the size of the value and key.
     /// You can think, that we can use a struct instead. We can't, it is make this code too slow.
     pub infos: I,
     path: PathBuf,
+    /// Lengths of `{i}.bin`. They are changed with the file locked.
     atomic_indexes: Box<[Arc<AtomicU64>]>,
     files: Box<[Arc<Mutex<SizedWriter<File>>>]>,
     read_files: Box<[Arc<RwLock<File>>]>,
     files_for_need_to_delete: Box<[Arc<Mutex<SizedWriter<File>>>]>,
     size: usize,
     lob: usize,
-    rs: RandomState
+    rs: RandomState,
+    cache: Arc<ValueCache>,
+    /// The number of the table in the cache.
+    cache_table: u32,
+    /// Filters of keys of files. Keys are added with the file locked.
+    filters: Box<[RwLock<GrowingBloomFilter>]>,
 }
 
 // CRUD
 impl<I: Index<BinKey, (u64, u64)>> DiskStorage<I> {
+    /// `infos` is changed with the file locked, so [`DiskStorage::write_hint`] sees the index, that matches lengths of files.
     #[inline(always)]
     pub fn insert(&self, key: BinKey, value: BinValue) -> bool {
-        if self.infos.contains(&key) {
+        let number = self.get_number(&key);
+        let hash = bloom::hash(key.deref());
+        if self.may_contain(number, hash) && self.infos.contains(&key) {
             return false;
         }
 
-        let (file, atomic_index) = self.get_file(&key);
-
         let kl = key.len();
         let k_size = get_size_for_key_len(kl);
         let vl = value.len();
         let v_size = get_size_for_value_len(vl);
-        let index;
-        {
-            let mut file = file.lock().unwrap();
-            file.write_key_with_size(&key, k_size).expect("failed to write to file");
-            file.write_value_with_size(&value, v_size).expect("failed to write to file");
-            file.flush().expect("failed to flush");
-            index = atomic_index.fetch_add((vl + v_size) as u64, std::sync::atomic::Ordering::SeqCst);
-        }
+        let mut file = self.files[number].lock().unwrap();
+        file.write_key_with_size(&key, k_size).expect("failed to write to file");
+        file.write_value_with_size(&value, v_size).expect("failed to write to file");
+        file.flush().expect("failed to flush");
+        let index = self.atomic_indexes[number].fetch_add((k_size + kl + v_size + vl) as u64, SeqCst);
+        self.add_to_filter(number, hash);
 
         // TODO: should we not to use usize in indexes?
-        self.infos.insert(key, (vl as u64, index + (k_size + kl) as u64));
+        self.infos.insert(key, (vl as u64, index + (k_size + kl + v_size) as u64));
         true
     }
 
     #[inline(always)]
     pub fn get(&self, key: &BinKey) -> Option<BinValue>{
-        // TODO: uncomment
-        // TODO: should we use BufReader?
-        let (file, info) = self.get_index_and_file(key)?;
+        let number = self.get_number(key);
+        if !self.may_contain(number, bloom::hash(key.deref())) {
+            METRICS.bloom_filter_skips.add(1);
+            return None;
+        }
+        let (file, info) = self.get_index_and_file(number, key)?;
+        let location = self.location(number, info.1);
+        if let Some(value) = self.cache.get(&location, BinValue::new) {
+            return Some(value);
+        }
 
         let mut buf = vec![0; info.0 as usize];
         file.read().unwrap().read_at(info.1, &mut buf).expect("failed to read");
+        self.cache.insert(location, &buf);
 
         return Some(BinValue::new(buf.as_slice()));
     }
 
+    /// Returns false, if there was no key.
     #[inline(always)]
-    pub fn delete(&self, key: &BinKey) {
-        let file_lock = self.get_need_to_delete(key);
-        let mut file = file_lock.lock().unwrap();
-        if self.infos.remove(key).is_none() {
-            return;
+    pub fn delete(&self, key: &BinKey) -> bool {
+        let number = self.get_number(key);
+        if !self.may_contain(number, bloom::hash(key.deref())) {
+            return false;
         }
+        let mut file = self.files_for_need_to_delete[number].lock().unwrap();
+        let Some(info) = self.infos.remove(key) else {
+            return false;
+        };
+        self.cache.remove(&self.location(number, info.1));
 
         file.write_key(key).expect("failed to write");
+        file.write(&uint::u64tob(info.1)).expect("failed to write");
         file.flush().expect("failed to flush");
+        true
     }
 
     #[inline(always)]
     pub fn set(&self, key: BinKey, value: BinValue) -> Option<BinValue> {
-        let mut hasher = RandomState::build_hasher(&self.rs);
-        key.hash(&mut hasher);
-        let number = hasher.finish() as usize & self.lob;
-        let file = self.files[number].clone();
-        let atomic_index = self.atomic_indexes[number].clone();
-
+        let number = self.get_number(&key);
         let kl = key.len();
         let size_kl= get_size_for_key_len(kl);
         let vl = value.len();
         let size_vl = get_size_for_value_len(vl);
-        let index;
+        let mut file = self.files[number].lock().unwrap();
+        file.write_key_with_size(&key, size_kl).expect("failed to write");
+        file.write_value_with_size(&value, size_vl).expect("failed to write");
+        file.flush().expect("failed to flush");
+        let index = self.atomic_indexes[number].fetch_add((size_kl + kl + size_vl + vl) as u64, SeqCst);
+        self.add_to_filter(number, bloom::hash(key.deref()));
+
+        let info = self.infos.set(key.clone(), (vl as u64, index + (size_kl + kl + size_vl) as u64))?;
         {
-            let mut file = file.lock().unwrap();
-            file.write_key_with_size(&key, size_kl).expect("failed to write");
-            file.flush().expect("failed to flush");
-            index = atomic_index.fetch_add((vl + size_vl) as u64, std::sync::atomic::Ordering::SeqCst);
+            let mut delete_file = self.files_for_need_to_delete[number].lock().unwrap();
+            delete_file.write_key_with_size(&key, size_kl).expect("failed to write");
+            delete_file.write(&uint::u64tob(info.1)).expect("failed to write");
+            delete_file.flush().expect("failed to flush");
         }
+        drop(file);
 
-        let old_value = self.infos.set(key.clone(), (vl as u64, index + (size_kl + kl) as u64));
+        if let Some(value) = self.cache.remove(&self.location(number, info.1)) {
+            return Some(BinValue::new(&value));
+        }
+        let mut buf = vec![0; info.0 as usize];
+        self.read_files[number].read().unwrap().read_at(info.1, &mut buf).expect("failed to read");
 
-        if old_value.is_some() {
-            let delete_file_ = self.files_for_need_to_delete[number].clone();
-            let mut delete_file = delete_file_.lock().unwrap();
-            delete_file.write_key_with_size(&key, size_kl).expect("failed to write");
-            delete_file.flush().expect("failed to flush");
+        Some(BinValue::new(buf.as_slice()))
+    }
+}
 
-            let info = unsafe { old_value.unwrap_unchecked() };
-            let mut buf = vec![0; info.0 as usize];
-            let file = self.read_files[number].clone();
-            // TODO: should we use BufReader?
-            file.read().unwrap().read_at(info.1, &mut buf).expect("failed to read");
+/// Reads the key with its size. Returns the key and the number of read bytes.
+fn read_key(reader: &mut impl Read) -> io::Result<(Vec<u8>, u64)> {
+    let mut size = [0u8; 2];
+    reader.read_exact(&mut size[..1])?;
+    let (len, read) = if size[0] < 255 {
+        (size[0] as usize, 1)
+    } else {
+        reader.read_exact(&mut size)?;
+        (uint::u16(&size) as usize, 3)
+    };
+    let mut key = vec![0; len];
+    reader.read_exact(&mut key)?;
+    Ok((key, read + len as u64))
+}
 
-            return Some(BinValue::new(buf.as_slice()));
-        } else {
-            return None;
-        }
+/// Reads the size of the value. Returns the length of the value and the number of read bytes.
+fn read_value_len(reader: &mut impl Read) -> io::Result<(u64, u64)> {
+    let mut size = [0u8; 4];
+    reader.read_exact(&mut size[..2])?;
+    if size[..2] != [255, 255] {
+        return Ok((uint::u16(&size[..2]) as u64, 2));
     }
+    reader.read_exact(&mut size)?;
+    Ok((uint::u32(&size) as u64, 6))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(uint::u64(&buf))
 }
 
 // Persistence
 impl<I: Index<BinKey, (u64, u64)>> DiskStorage<I> {
-    // TODO: test it, because I get error in mutexes above. Maybe I didn't wait for it, but check it.
-    pub fn rise(&mut self) -> bool {
-        let path = self.path.clone();
-        // check for the existence of the directory
-        if !metadata(path.clone()).is_ok() {
-            return false;
+    /// Rebuilds `infos` from the hint and files. Without the hint all files are read.
+    pub fn rise(&mut self) -> io::Result<()> {
+        let ends = match self.read_hint() {
+            Ok(Some(ends)) => ends,
+            Ok(None) => vec![(0, 0); self.size],
+            Err(e) => {
+                warn!("The hint of {} is not valid, all files are read: {}", self.path.to_string_lossy(), e);
+                self.infos.clear();
+                vec![(0, 0); self.size]
+            }
+        };
+        for (number, (data_from, deletes_from)) in ends.into_iter().enumerate() {
+            self.replay(number, data_from, deletes_from)?;
         }
+        self.rebuild_filters();
+        Ok(())
+    }
 
-        let mut files = Vec::with_capacity(self.size);
-        let mut read_files = Vec::with_capacity(self.size);
-        let mut files_for_need_to_delete = Vec::with_capacity(self.size);
-        let mut atomic_indexes = Vec::with_capacity(self.size);
+    /// Builds filters from `infos`, so they don't have deleted keys.
+    fn rebuild_filters(&mut self) {
+        let hashes = RefCell::new(vec![Vec::new(); self.size]);
+        self.infos.for_each(|key, _| hashes.borrow_mut()[self.get_number(key)].push(bloom::hash(key.deref())));
+        let bits_per_key = bloom::bits_per_key();
+        self.filters = hashes.into_inner().into_iter().map(|hashes| {
+            let mut filter = GrowingBloomFilter::new((hashes.len() * 2).max(MIN_FILTER_KEYS), bits_per_key);
+            for hash in hashes {
+                filter.insert_hash(hash);
+            }
+            RwLock::new(filter)
+        }).collect();
+    }
 
-        for i in 0..self.size {
-            files.push(Arc::new(Mutex::new(SizedWriter::new_with_capacity(File::open(path.clone().join(format!("{i}.bin"))).unwrap(), BUFFER_SIZE))));
-            read_files.push(Arc::new(RwLock::new(File::open(path.clone().join(format!("{i}.bin"))).unwrap())));
-            files_for_need_to_delete.push(Arc::new(Mutex::new(SizedWriter::new_with_capacity(File::open(path.clone().join(format!("{i}.bin"))).unwrap(), DELETE_BUFFER_SIZE))));
-            atomic_indexes.push(Arc::new(AtomicU64::new(0)));
+    /// Loads `infos` from the hint. Returns lengths of files, that the hint has, or `None` without the hint.
+    fn read_hint(&self) -> io::Result<Option<Vec<(u64, u64)>>> {
+        let file = match File::open(self.path.join(HINT_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e)
+        };
+        let hint_len = file.metadata()?.len();
+        let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, file);
+        let mut size = [0u8; 4];
+        reader.read_exact(&mut size)?;
+        if uint::u32(&size) as usize != self.size {
+            return Err(io::Error::new(ErrorKind::InvalidData, "the number of files is different"));
+        }
+        let mut ends = Vec::with_capacity(self.size);
+        for number in 0..self.size {
+            let data_end = read_u64(&mut reader)?;
+            let deletes_end = read_u64(&mut reader)?;
+            if data_end > fs::metadata(self.data_path(number))?.len() || deletes_end > fs::metadata(self.deletes_path(number))?.len() {
+                return Err(io::Error::new(ErrorKind::InvalidData, "files are shorter than the hint"));
+            }
+            ends.push((data_end, deletes_end));
         }
 
-        self.files = files.into_boxed_slice();
-        self.read_files = read_files.into_boxed_slice();
-        self.files_for_need_to_delete = files_for_need_to_delete.into_boxed_slice();
-        self.atomic_indexes = atomic_indexes.into_boxed_slice();
-
-        let mut chunk = vec![0u8; 1024 * 1024];
-        let mut read = 0;
-        let mut file_len;
-
-        let mut offset_last_record = 0;
-        let mut offset;
-        let mut start_offset = 0;
-        let mut key_offset;
-
-        let mut tmp_set = HashMap::with_capacity(2<<16);
-
-        for i in 0..self.size {
-            let lock = self.files_for_need_to_delete[i].lock().unwrap();
-            let mut file = lock.inner.get_ref();
-            file_len = file.metadata().unwrap().len();
-            'read_loop: loop {
-                if read == file_len {
-                    break;
-                }
+        let mut read = 4 + 16 * self.size as u64;
+        while read < hint_len {
+            let (key, key_read) = read_key(&mut reader)?;
+            let mut len = [0u8; 4];
+            reader.read_exact(&mut len)?;
+            let offset = read_u64(&mut reader)?;
+            self.infos.set(BinKey::new(&key), (uint::u32(&len) as u64, offset));
+            read += key_read + 12;
+        }
+        Ok(Some(ends))
+    }
 
-                let mut bytes_read = file.read(&mut chunk[offset_last_record..]).expect("Failed to read");
-                if bytes_read == 0 {
+    /// Reads records of the file from `data_from` and old records from `deletes_from`. A broken record at the end
+    /// (the write was interrupted) is cut off.
+    fn replay(&self, number: usize, data_from: u64, deletes_from: u64) -> io::Result<()> {
+        let data_end = {
+            let file = OpenOptions::new().read(true).write(true).open(self.data_path(number))?;
+            let len = file.metadata()?.len();
+            let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, &file);
+            reader.seek(SeekFrom::Start(data_from))?;
+            let mut end = data_from;
+            loop {
+                let res = read_key(&mut reader).and_then(|(key, key_read)| {
+                    let (vl, value_read) = read_value_len(&mut reader)?;
+                    Ok((key, key_read + value_read, vl))
+                });
+                let (key, read, vl) = match res {
+                    Ok(res) => res,
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e)
+                };
+                if end + read + vl > len {
                     break;
                 }
-
-                bytes_read += offset_last_record;
-                offset = 0;
-                read += bytes_read as u64;
-
-                loop {
-                    if offset + 1 > bytes_read {
-                        let slice_to_copy = &mut Vec::with_capacity(0);
-                        chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                        offset_last_record = bytes_read - start_offset;
-                        chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                        continue 'read_loop;
-                    }
-                    start_offset = offset;
-                    let mut kl = chunk[offset + 1] as u32;
-                    if kl < 255 {
-                        offset += 1;
-                    } else {
-                        kl = (chunk[offset + 1] as u32) << 8 | (chunk[offset] as u32);
-                        if offset + 3 > bytes_read {
-                            let slice_to_copy = &mut Vec::with_capacity(0);
-                            chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                            offset_last_record = bytes_read - start_offset;
-                            chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                            continue 'read_loop;
-                        }
-                        offset += 3;
-                    }
-
-                    if offset + kl as usize > bytes_read {
-                        let slice_to_copy = &mut Vec::with_capacity(0);
-                        chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                        offset_last_record = bytes_read - start_offset;
-                        chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                        continue 'read_loop;
-                    }
-                    key_offset = offset;
-                    offset += kl as usize;
-
-                    let key = chunk[key_offset..key_offset+kl as usize].to_vec();
-                    tmp_set.entry(key).and_modify(|value| {
-                        *value += 1;
-                    }).or_insert(1);
-                }
+                self.infos.set(BinKey::new(&key), (vl, end + read));
+                reader.seek_relative(vl as i64)?;
+                end += read + vl;
             }
-            offset_last_record = 0;
-            start_offset = 0;
-            read = 0;
-            let lock = self.files[i].lock().unwrap();
-            let mut file = lock.inner.get_ref();
-            file_len = file.metadata().unwrap().len();
-            'read_loop: loop {
-                if read == file_len {
-                    break;
-                }
-
-                let mut bytes_read = file.read(&mut chunk[offset_last_record..]).expect("Failed to read");
-                if bytes_read == 0 {
-                    break;
-                }
-
-                bytes_read += offset_last_record;
-                offset = 0;
-                read += bytes_read as u64;
-
-                loop {
-                    if offset + 1 > bytes_read {
-                        let slice_to_copy = &mut Vec::with_capacity(0);
-                        chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                        offset_last_record = bytes_read - start_offset;
-                        chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                        continue 'read_loop;
-                    }
-                    start_offset = offset;
-                    let mut kl = chunk[offset + 1] as u32;
-                    if kl < 255 {
-                        offset += 1;
-                    } else {
-                        if offset + 3 > bytes_read {
-                            let slice_to_copy = &mut Vec::with_capacity(0);
-                            chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                            offset_last_record = bytes_read - start_offset;
-                            chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                            continue 'read_loop;
-                        }
-                        kl = (chunk[offset + 1] as u32) << 8 | (chunk[offset] as u32);
-                        offset += 3;
-                    }
-
-                    if offset + kl as usize + 3 /*3 here is bytes for vl*/ > bytes_read {
-                        let slice_to_copy = &mut Vec::with_capacity(0);
-                        chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                        offset_last_record = bytes_read - start_offset;
-                        chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                        continue 'read_loop;
-                    }
-                    key_offset = offset;
-                    offset += kl as usize;
-
-                    let vl = (chunk[offset + 2] as u32) << 16 | (chunk[offset + 1] as u32) << 8 | (chunk[offset] as u32);
-                    offset += 3;
-
-                    if offset + vl as usize > bytes_read {
-                        let slice_to_copy = &mut Vec::with_capacity(0);
-                        chunk[start_offset..bytes_read].clone_into(slice_to_copy);
-                        offset_last_record = bytes_read - start_offset;
-                        chunk[0..offset_last_record].copy_from_slice(slice_to_copy);
-                        continue 'read_loop;
-                    }
-
-                    offset += vl as usize;
-
-                    let key = chunk[key_offset..key_offset+kl as usize].to_vec();
-                    let mut hasher = self.rs.build_hasher();
-                    key.hash(&mut hasher);
-                    let number = hasher.finish() as usize & self.lob;
-
-                    unsafe {
-                        let atomic = self.atomic_indexes.get_unchecked(number);
-                        let res = tmp_set.get_mut(&chunk[key_offset..key_offset+kl as usize]);
-                        if res.is_some() {
-                            let number = res.unwrap();
-                            if *number != 0 {
-                                *number -= 1;
-                                atomic.fetch_add((6 + vl + kl) as u64, std::sync::atomic::Ordering::SeqCst);
-                                continue;
-                            }
-                        }
-                        self.infos.insert(BinKey::new(key.as_slice()), (vl as u64, atomic.fetch_add((6 + vl + kl) as u64, std::sync::atomic::Ordering::SeqCst) + 6 +(kl as u64)));
-                    }
-                }
+            if end < len {
+                warn!("The broken record at the end of {} is cut off", self.data_path(number).to_string_lossy());
+                file.set_len(end)?;
+            }
+            end
+        };
+        self.atomic_indexes[number].store(data_end, SeqCst);
+
+        let file = OpenOptions::new().read(true).write(true).open(self.deletes_path(number))?;
+        let len = file.metadata()?.len();
+        let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, &file);
+        reader.seek(SeekFrom::Start(deletes_from))?;
+        let mut end = deletes_from;
+        loop {
+            let res = read_key(&mut reader).and_then(|(key, read)| Ok((key, read, read_u64(&mut reader)?)));
+            let (key, read, offset) = match res {
+                Ok(res) => res,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e)
+            };
+            let key = BinKey::new(&key);
+            if self.infos.get(&key).is_some_and(|info| info.1 == offset) {
+                self.infos.remove(&key);
             }
-            offset_last_record = 0;
-            start_offset = 0;
-            read = 0;
-            tmp_set.clear();
+            end += read + 8;
+        }
+        if end < len {
+            file.set_len(end)?;
         }
+        Ok(())
+    }
 
-        return true;
+    /// Writes `infos` to the hint file. Writes aren't stopped: the hint has lengths of files, that were taken before, and
+    /// the rise reads records after them.
+    pub fn write_hint(&self) -> io::Result<()> {
+        let mut ends = Vec::with_capacity(self.size);
+        for number in 0..self.size {
+            let _file = self.files[number].lock().unwrap();
+            let deletes = self.files_for_need_to_delete[number].lock().unwrap();
+            ends.push((self.atomic_indexes[number].load(SeqCst), deletes.inner.get_ref().metadata()?.len()));
+        }
+
+        let tmp_path = self.path.join(HINT_TMP_FILE);
+        let mut writer = BufWriter::with_capacity(READ_BUFFER_SIZE, File::create(&tmp_path)?);
+        writer.write_all(&uint::u32tob(self.size as u32))?;
+        for (data_end, deletes_end) in ends.iter() {
+            writer.write_all(&uint::u64tob(*data_end))?;
+            writer.write_all(&uint::u64tob(*deletes_end))?;
+        }
+        let writer = RefCell::new(writer);
+        let res = RefCell::new(Ok(()));
+        self.infos.for_each(|key, info| {
+            // Records after the lengths are read by the rise.
+            if info.1 >= ends[self.get_number(key)].0 || res.borrow().is_err() {
+                return;
+            }
+            let mut writer = writer.borrow_mut();
+            let written = writer.write_all(key.deref_all())
+                .and_then(|_| writer.write_all(&uint::u32tob(info.0 as u32)))
+                .and_then(|_| writer.write_all(&uint::u64tob(info.1)));
+            if written.is_err() {
+                *res.borrow_mut() = written;
+            }
+        });
+        res.into_inner()?;
+        let file = writer.into_inner().into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        fs::rename(tmp_path, self.path.join(HINT_FILE))
     }
 }
 
 // some helpers function
 impl<I: Index<BinKey, (u64, u64)>> DiskStorage<I> {
+    /// Opens files in the directory and rises, if they exist.
     #[allow(unused_variables)]
-    pub fn new(path: PathBuf, size: usize, index: I) -> DiskStorage<I> {
+    pub fn new(path: PathBuf, size: usize, index: I, cache: Arc<ValueCache>) -> io::Result<DiskStorage<I>> {
         #[cfg(target_os = "windows")] {
-            panic!("Do not use windows for `on disk` storage. It is not implemented yet.");
+            return Err(io::Error::new(ErrorKind::Unsupported, "Do not use windows for `on disk` storage. It is not implemented yet."));
         }
         #[cfg(not(target_os = "windows"))] {
             let size = {
@@ -350,29 +390,30 @@ impl<I: Index<BinKey, (u64, u64)>> DiskStorage<I> {
                 size,
                 lob,
                 rs,
+                cache_table: cache.register(),
+                cache,
+                filters: (0..size).map(|_| RwLock::new(GrowingBloomFilter::new(MIN_FILTER_KEYS, bloom::bits_per_key()))).collect(),
             };
 
-            let does_exist = storage.rise();
-            if does_exist {
-                return storage;
-            }
-
+            let does_exist = path.exists();
             let mut files = Vec::with_capacity(size);
             let mut read_files = Vec::with_capacity(size);
             let mut files_for_need_to_delete = Vec::with_capacity(size);
             let mut atomic_indexes = Vec::with_capacity(size);
-            // TODO handle error
-            std::fs::DirBuilder::new().recursive(true).create(path.clone()).unwrap();
+            std::fs::DirBuilder::new().recursive(true).create(path.clone())?;
 
             for i in 0..size {
-                // TODO handle error
                 let write_file = OpenOptions::new()
                     .append(true)
                     .create(true)
-                    .open(path.clone().join(format!("{i}.bin"))).expect("[Error] Failed to open file");
+                    .open(storage.data_path(i))?;
                 files.push(Arc::new(Mutex::new(SizedWriter::new_with_capacity(write_file, BUFFER_SIZE))));
-                read_files.push(Arc::new(RwLock::new(File::open(path.clone().join(format!("{i}.bin"))).unwrap())));
-                files_for_need_to_delete.push(Arc::new(Mutex::new(SizedWriter::new_with_capacity(File::create(path.clone().join(format!("{i}D.bin"))).unwrap(), DELETE_BUFFER_SIZE))));
+                read_files.push(Arc::new(RwLock::new(File::open(storage.data_path(i))?)));
+                let delete_file = OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(storage.deletes_path(i))?;
+                files_for_need_to_delete.push(Arc::new(Mutex::new(SizedWriter::new_with_capacity(delete_file, DELETE_BUFFER_SIZE))));
                 atomic_indexes.push(Arc::new(AtomicU64::new(0)));
             }
 
@@ -381,40 +422,58 @@ impl<I: Index<BinKey, (u64, u64)>> DiskStorage<I> {
             storage.files_for_need_to_delete = files_for_need_to_delete.into_boxed_slice();
             storage.atomic_indexes = atomic_indexes.into_boxed_slice();
 
-            return storage;
+            if does_exist {
+                storage.rise()?;
+            }
+
+            Ok(storage)
         }
     }
 
+    fn data_path(&self, number: usize) -> PathBuf {
+        self.path.join(format!("{number}.bin"))
+    }
+
+    fn deletes_path(&self, number: usize) -> PathBuf {
+        self.path.join(format!("{number}D.bin"))
+    }
+
     #[inline(always)]
-    fn get_file(&self, key: &BinKey) -> (Arc<Mutex<SizedWriter<File>>>, Arc<AtomicU64>) {
+    fn get_number(&self, key: &BinKey) -> usize {
         let mut hasher = RandomState::build_hasher(&self.rs);
         key.hash(&mut hasher);
-        let number = hasher.finish() as usize & self.lob;
-        return (self.files[number].clone(), self.atomic_indexes[number].clone());
+        hasher.finish() as usize & self.lob
     }
 
     #[inline(always)]
-    fn get_index_and_file(&self, key: &BinKey) -> Option<(Arc<RwLock<File>>, (u64, u64))> {
-        let mut hasher = RandomState::build_hasher(&self.rs);
-        key.hash(&mut hasher);
-        let number = hasher.finish() as usize & self.lob;
-        let info;
-        {
-            let index_ = self.infos.get(key);
-            if index_.is_none() {
-                return None;
-            }
-            info = unsafe { index_.unwrap_unchecked() };
-        }
+    fn location(&self, number: usize, offset: u64) -> Location {
+        Location { table: self.cache_table, file: number as u32, offset }
+    }
+
+    #[inline(always)]
+    fn get_index_and_file(&self, number: usize, key: &BinKey) -> Option<(Arc<RwLock<File>>, (u64, u64))> {
+        let info = self.infos.get(key)?;
 
-        return Some((self.read_files[number].clone(), info));
+        Some((self.read_files[number].clone(), info))
     }
 
+    /// Returns false, if the file surely doesn't have the key.
     #[inline(always)]
-    fn get_need_to_delete(&self, key: &BinKey) -> Arc<Mutex<SizedWriter<File>>> {
-        let mut hasher = RandomState::build_hasher(&self.rs);
-        key.hash(&mut hasher);
-        let number = hasher.finish() as usize & self.lob;
-        return self.files_for_need_to_delete[number].clone();
+    fn may_contain(&self, number: usize, hash: u64) -> bool {
+        self.filters[number].read().unwrap().may_contain_hash(hash)
+    }
+
+    /// The key is added only once, so `set` of an existing key doesn't grow the filter.
+    #[inline(always)]
+    fn add_to_filter(&self, number: usize, hash: u64) {
+        let mut filter = self.filters[number].write().unwrap();
+        if !filter.may_contain_hash(hash) {
+            filter.insert_hash(hash);
+        }
+    }
+
+    /// Bytes of bloom filters of files.
+    pub fn filters_memory_usage(&self) -> usize {
+        self.filters.iter().map(|filter| filter.read().unwrap().memory_usage()).sum()
     }
 }
@@ -0,0 +1,166 @@
+//! The memory-bounded cache of values, that are read from shard files of on-disk tables.
+//!
+//! Files of [`DiskStorage`](super::storage::DiskStorage) are append-only, so a value is found by its table, file and offset,
+//! and bytes at the offset are never changed. `set` and `delete` remove the old value from the cache only to free memory.
+//!
+//! The cache is split into shards with their own locks. Every shard evicts with the CLOCK algorithm: a value, that was read
+//! since the last pass, gets the second chance.
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, atomic::{AtomicU32, AtomicUsize, Ordering::Relaxed}}
+};
+use ahash::{HashMap, RandomState};
+use crate::metrics::METRICS;
+
+/// 64 MiB.
+pub const DEFAULT_CAPACITY: usize = 64 * 1024 * 1024;
+const NUMBER_OF_SHARDS: usize = 64;
+/// Bytes of the map and the queue, that every value takes besides its bytes.
+const ENTRY_OVERHEAD: usize = 64;
+/// A value, that is bigger than this part of a shard, isn't cached, so one value doesn't evict the whole shard.
+const MAX_PART_OF_SHARD: usize = 8;
+
+/// The table (see [`ValueCache::register`]), the number of the file and the offset of the value in the file.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub table: u32,
+    pub file: u32,
+    pub offset: u64,
+}
+
+struct Entry {
+    value: Box<[u8]>,
+    /// The value was read since the last pass of the clock.
+    is_referenced: bool,
+}
+
+#[derive(Default)]
+struct Shard {
+    entries: HashMap<Location, Entry>,
+    /// The clock. It can have locations of removed values, they are skipped.
+    queue: VecDeque<Location>,
+    bytes: usize,
+}
+
+impl Shard {
+    fn remove(&mut self, location: &Location) -> Option<Box<[u8]>> {
+        let entry = self.entries.remove(location)?;
+        self.bytes -= entry.value.len() + ENTRY_OVERHEAD;
+        if self.queue.len() > self.entries.len() * 2 + 64 {
+            let entries = &self.entries;
+            self.queue.retain(|location| entries.contains_key(location));
+        }
+        Some(entry.value)
+    }
+
+    fn evict(&mut self, capacity: usize) {
+        while self.bytes > capacity {
+            let Some(location) = self.queue.pop_front() else {
+                break;
+            };
+            let Some(entry) = self.entries.get_mut(&location) else {
+                continue;
+            };
+            if entry.is_referenced {
+                entry.is_referenced = false;
+                self.queue.push_back(location);
+                continue;
+            }
+            let entry = self.entries.remove(&location).unwrap();
+            self.bytes -= entry.value.len() + ENTRY_OVERHEAD;
+        }
+    }
+}
+
+pub struct ValueCache {
+    shards: Box<[Mutex<Shard>]>,
+    /// Bytes of all shards. It is the `disk_cache_bytes` setting, 0 disables the cache.
+    capacity: AtomicUsize,
+    next_table: AtomicU32,
+    rs: RandomState,
+}
+
+impl Default for ValueCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ValueCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            shards: (0..NUMBER_OF_SHARDS).map(|_| Mutex::new(Shard::default())).collect(),
+            capacity: AtomicUsize::new(capacity),
+            next_table: AtomicU32::new(0),
+            rs: RandomState::new(),
+        }
+    }
+
+    /// Returns the number, that the table uses in its locations.
+    pub fn register(&self) -> u32 {
+        self.next_table.fetch_add(1, Relaxed)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Relaxed)
+    }
+
+    /// Evicts values right away, if the cache is smaller now.
+    pub fn set_capacity(&self, capacity: usize) {
+        self.capacity.store(capacity, Relaxed);
+        let shard_capacity = capacity / NUMBER_OF_SHARDS;
+        for shard in self.shards.iter() {
+            shard.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).evict(shard_capacity);
+        }
+    }
+
+    /// Bytes of cached values with the overhead.
+    pub fn used_bytes(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).bytes).sum()
+    }
+
+    #[inline(always)]
+    fn shard(&self, location: &Location) -> &Mutex<Shard> {
+        &self.shards[self.rs.hash_one(location) as usize % NUMBER_OF_SHARDS]
+    }
+
+    /// Calls `f` with the cached value. Counts a hit or a miss.
+    #[inline(always)]
+    pub fn get<T>(&self, location: &Location, f: impl FnOnce(&[u8]) -> T) -> Option<T> {
+        if self.capacity() == 0 {
+            return None;
+        }
+        let mut shard = self.shard(location).lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match shard.entries.get_mut(location) {
+            Some(entry) => {
+                entry.is_referenced = true;
+                METRICS.disk_cache_hits.add(1);
+                Some(f(&entry.value))
+            }
+            None => {
+                METRICS.disk_cache_misses.add(1);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, location: Location, value: &[u8]) {
+        let shard_capacity = self.capacity() / NUMBER_OF_SHARDS;
+        if value.len() + ENTRY_OVERHEAD > shard_capacity / MAX_PART_OF_SHARD {
+            return;
+        }
+        let mut shard = self.shard(&location).lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if shard.entries.contains_key(&location) {
+            return;
+        }
+        shard.bytes += value.len() + ENTRY_OVERHEAD;
+        shard.entries.insert(location, Entry { value: Box::from(value), is_referenced: false });
+        shard.queue.push_back(location);
+        shard.evict(shard_capacity);
+    }
+
+    /// Removes the value and returns it, if it was cached.
+    pub fn remove(&self, location: &Location) -> Option<Box<[u8]>> {
+        self.shard(location).lock().unwrap_or_else(|poisoned| poisoned.into_inner()).remove(location)
+    }
+}
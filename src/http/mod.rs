@@ -0,0 +1,73 @@
+//! Minimal HTTP/1.1 server. It is enough for the gateway and the metrics endpoint: no chunked bodies, no TLS.
+pub mod request;
+pub mod response;
+
+pub use request::*;
+pub use response::*;
+
+use std::{
+    io::{BufReader, BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+    thread
+};
+use crate::{error, success};
+
+/// Binds the address and handles every connection in its own thread, like the binary protocol does.
+///
+/// `new_state` is called once per connection, `handler` is called for every request of the connection.
+pub fn serve<S, N, H>(name: &'static str, addr: &str, new_state: N, handler: H)
+where
+    N: Fn() -> S + Send + Sync + 'static,
+    H: Fn(&mut S, &Request) -> Response + Send + Sync + 'static,
+{
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            panic!("Can't bind {} to address: {}, the error is: {:?}", name, addr, e);
+        }
+    };
+    success!("{} listening on address {}", name, addr);
+
+    let new_state = Arc::new(new_state);
+    let handler = Arc::new(handler);
+    for stream in listener.incoming() {
+        let new_state = new_state.clone();
+        let handler = handler.clone();
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || {
+                    let mut state = new_state();
+                    handle_connection(stream, &mut state, handler.as_ref());
+                });
+            }
+            Err(e) => {
+                error!("Error: {}", e);
+            }
+        }
+    }
+}
+
+fn handle_connection<S, H: Fn(&mut S, &Request) -> Response>(stream: TcpStream, state: &mut S, handler: &H) {
+    let reader_stream = match stream.try_clone() {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(reader_stream);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let (response, keep_alive) = match read_request(&mut reader) {
+            Ok(Some(request)) => {
+                let keep_alive = request.keep_alive();
+                (handler(state, &request), keep_alive)
+            }
+            Ok(None) => return,
+            Err(RequestError::Io(_)) => return,
+            Err(RequestError::BodyIsTooLarge) => (Response::text(413, "Body is too large"), false),
+            Err(RequestError::Malformed(message)) => (Response::text(400, message), false),
+        };
+        if response.write_to(&mut writer, keep_alive).is_err() || writer.flush().is_err() || !keep_alive {
+            return;
+        }
+    }
+}
\ No newline at end of file
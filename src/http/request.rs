@@ -0,0 +1,146 @@
+use std::{
+    io::{self, BufRead, Read},
+    sync::atomic::{AtomicUsize, Ordering::Relaxed}
+};
+
+/// We don't read headers longer than it.
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+/// We don't read bodies longer than it. It is the `http_max_body_bytes` setting.
+pub static MAX_BODY_SIZE: AtomicUsize = AtomicUsize::new(64 * 1024 * 1024);
+
+pub enum RequestError {
+    Io(io::Error),
+    Malformed(&'static str),
+    BodyIsTooLarge,
+}
+
+pub struct Request {
+    pub method: String,
+    /// The path without the query.
+    pub path: String,
+    pub query: String,
+    pub version: String,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(header, _)| header == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        match self.header("connection") {
+            Some(connection) if connection.eq_ignore_ascii_case("close") => false,
+            Some(connection) if connection.eq_ignore_ascii_case("keep-alive") => true,
+            _ => self.version == "HTTP/1.1",
+        }
+    }
+
+    /// Returns percent-decoded parts of the path.
+    pub fn segments(&self) -> Vec<Vec<u8>> {
+        self.path.split('/').filter(|segment| !segment.is_empty()).map(percent_decode).collect()
+    }
+
+    /// Returns the percent-decoded value of the query parameter.
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        for pair in self.query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            if key == name {
+                return Some(String::from_utf8_lossy(&percent_decode(&value.replace('+', " "))).to_string());
+            }
+        }
+        None
+    }
+}
+
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let (Some(high), Some(low)) = ((bytes[i + 1] as char).to_digit(16), (bytes[i + 2] as char).to_digit(16)) {
+                decoded.push((high * 16 + low) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    decoded
+}
+
+/// Reads one request. Returns `None`, if the connection was closed before the request.
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Option<Request>, RequestError> {
+    let mut head_size = 0;
+    let mut line = String::new();
+    let mut read_line = |line: &mut String| -> Result<usize, RequestError> {
+        line.clear();
+        let read = reader.by_ref().take((MAX_HEAD_SIZE - head_size) as u64).read_line(line).map_err(|e| {
+            if e.kind() == io::ErrorKind::InvalidData {
+                RequestError::Malformed("Request is not valid UTF-8")
+            } else {
+                RequestError::Io(e)
+            }
+        })?;
+        head_size += read;
+        if head_size >= MAX_HEAD_SIZE {
+            return Err(RequestError::Malformed("Headers are too large"));
+        }
+        Ok(read)
+    };
+
+    // Clients can send empty lines between requests.
+    loop {
+        if read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        if !line.trim().is_empty() {
+            break;
+        }
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) => (method.to_string(), target.to_string(), version.to_string()),
+        _ => return Err(RequestError::Malformed("Bad request line")),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (target, String::new()),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        if read_line(&mut line)? == 0 {
+            return Err(RequestError::Malformed("Headers are cut"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        match header.split_once(':') {
+            Some((name, value)) => headers.push((name.trim().to_lowercase(), value.trim().to_string())),
+            None => return Err(RequestError::Malformed("Bad header")),
+        }
+    }
+
+    let mut request = Request { method, path, query, version, headers, body: Vec::new() };
+    if request.header("transfer-encoding").is_some() {
+        return Err(RequestError::Malformed("Transfer-Encoding is not supported, use Content-Length"));
+    }
+    if let Some(length) = request.header("content-length") {
+        let length: usize = match length.parse() {
+            Ok(length) => length,
+            Err(_) => return Err(RequestError::Malformed("Bad Content-Length")),
+        };
+        if length > MAX_BODY_SIZE.load(Relaxed) {
+            return Err(RequestError::BodyIsTooLarge);
+        }
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body).map_err(RequestError::Io)?;
+    }
+    Ok(Some(request))
+}
\ No newline at end of file
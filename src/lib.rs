@@ -0,0 +1,54 @@
+#[cfg(test)]
+use std::fs;
+#[cfg(test)]
+use std::mem;
+
+pub mod index;
+
+pub mod bin_types;
+pub mod constants;
+pub mod utils;
+pub mod storage;
+
+#[cfg(test)]
+use storage::*;
+#[cfg(test)]
+use crate::tests::{crud, crud_bench, persistence};
+
+pub mod table;
+pub mod console;
+pub mod disk_storage;
+pub mod lsm;
+pub mod writers;
+pub mod server;
+pub mod http;
+pub mod metrics;
+pub mod resp;
+mod tests;
+pub mod scheme;
+pub mod connection;
+pub mod stream;
+pub mod node;
+
+#[test]
+fn main() {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(async {
+            info!("Starting test");
+            let storage = Storage::new(["test_data"].iter().collect());
+            let storage_static = unsafe { mem::transmute::<&Storage, &'static Storage>(&storage) };
+            info!("Storage created");
+            Storage::init(storage_static);
+            info!("Storage initialized");
+            crud(storage_static);
+            persistence(storage_static);
+
+            println!();
+            crud_bench(storage_static);
+
+            fs::remove_dir_all("test_data").unwrap();
+        });
+}
\ No newline at end of file
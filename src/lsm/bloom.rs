@@ -0,0 +1,156 @@
+//! Bloom filters of keys. They are persisted, so the hash doesn't depend on the process, the platform or versions of crates.
+//!
+//! Disk-backed tables check them before indexes and files, so most lookups of missing keys don't touch the disk.
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+pub const DEFAULT_FALSE_POSITIVE_RATE: f64 = 0.01;
+/// Filters with it have about 1% of false positives.
+pub const DEFAULT_BITS_PER_KEY: usize = 10;
+
+/// Bits per key of new filters. Filters, that already exist, keep their size until they are rebuilt.
+static BITS_PER_KEY: AtomicUsize = AtomicUsize::new(DEFAULT_BITS_PER_KEY);
+
+/// Returns the number of bits per key, that gives this rate of false positives.
+pub fn bits_per_key_for(false_positive_rate: f64) -> usize {
+    // -ln(p) / ln(2)^2
+    (-false_positive_rate.ln() / (2f64.ln() * 2f64.ln())).ceil().max(1.0) as usize
+}
+
+/// Sets the rate of false positives of new and rebuilt filters. It is the `bloom_false_positive_rate` setting.
+pub fn set_false_positive_rate(false_positive_rate: f64) {
+    BITS_PER_KEY.store(bits_per_key_for(false_positive_rate), Relaxed);
+}
+
+pub fn bits_per_key() -> usize {
+    BITS_PER_KEY.load(Relaxed)
+}
+
+/// FNV-1a with the finalizer of MurmurHash3, so close keys get different bits.
+#[inline(always)]
+pub fn hash(key: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in key {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Double hashing: the key sets bits h1, h1 + h2, h1 + 2 * h2, ...
+#[inline(always)]
+fn positions(words: usize, hashes: u32, hash: u64) -> impl Iterator<Item = usize> {
+    let number_of_bits = words as u64 * 64;
+    let h2 = hash.rotate_right(32) | 1;
+    (0..hashes as u64).map(move |i| (hash.wrapping_add(i.wrapping_mul(h2)) % number_of_bits) as usize)
+}
+
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    /// The number of bits, that a key sets.
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Creates the filter of hashes of keys (see [`hash`]).
+    pub fn new(hashes_of_keys: &[u64], bits_per_key: usize) -> Self {
+        let mut filter = Self::with_capacity(hashes_of_keys.len(), bits_per_key);
+        for hash in hashes_of_keys {
+            filter.insert_hash(*hash);
+        }
+        filter
+    }
+
+    /// Creates the empty filter for `keys` keys.
+    pub fn with_capacity(keys: usize, bits_per_key: usize) -> Self {
+        let bits_per_key = bits_per_key.max(1);
+        let len = (keys * bits_per_key).max(64).div_ceil(64);
+        // ln(2) * bits per key is the best number of hashes.
+        let hashes = ((bits_per_key as f64) * 0.69).round().clamp(1.0, 30.0) as u32;
+        Self { bits: vec![0; len], hashes }
+    }
+
+    pub fn insert_hash(&mut self, hash: u64) {
+        for position in positions(self.bits.len(), self.hashes, hash) {
+            self.bits[position / 64] |= 1 << (position % 64);
+        }
+    }
+
+    /// Returns false, if the key is surely not in the filter.
+    #[inline(always)]
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.may_contain_hash(hash(key))
+    }
+
+    #[inline(always)]
+    pub fn may_contain_hash(&self, hash: u64) -> bool {
+        positions(self.bits.len(), self.hashes, hash).all(|position| self.bits[position / 64] & (1 << (position % 64)) != 0)
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    /// [`hashes` (4 bytes), `number of words` (4 bytes), words (8 bytes each)]
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.hashes.to_le_bytes());
+        buf.extend_from_slice(&(self.bits.len() as u32).to_le_bytes());
+        for word in self.bits.iter() {
+            buf.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let hashes = u32::from_le_bytes(buf.get(0..4)?.try_into().ok()?);
+        let len = u32::from_le_bytes(buf.get(4..8)?.try_into().ok()?) as usize;
+        let words = buf.get(8..8 + len * 8)?;
+        if len == 0 || hashes == 0 {
+            return None;
+        }
+        let bits = words.chunks_exact(8).map(|word| u64::from_le_bytes(word.try_into().unwrap())).collect();
+        Some(Self { bits, hashes })
+    }
+}
+
+/// The filter, that isn't persisted and grows with keys. When its last part is full, a new part for twice more keys and with
+/// 2 more bits per key is added. The rate of a new part is less than a half of the rate of the last one, so the rate of all
+/// parts stays less than twice the rate of the first one.
+pub struct GrowingBloomFilter {
+    parts: Vec<BloomFilter>,
+    /// Keys, that the last part is for.
+    capacity: usize,
+    /// Keys, that were added to the last part.
+    len: usize,
+    bits_per_key: usize,
+}
+
+impl GrowingBloomFilter {
+    pub fn new(capacity: usize, bits_per_key: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self { parts: vec![BloomFilter::with_capacity(capacity, bits_per_key)], capacity, len: 0, bits_per_key }
+    }
+
+    pub fn insert_hash(&mut self, hash: u64) {
+        if self.len >= self.capacity {
+            self.capacity *= 2;
+            self.bits_per_key += 2;
+            self.len = 0;
+            self.parts.push(BloomFilter::with_capacity(self.capacity, self.bits_per_key));
+        }
+        self.parts.last_mut().unwrap().insert_hash(hash);
+        self.len += 1;
+    }
+
+    /// Returns false, if the key is surely not in the filter.
+    #[inline(always)]
+    pub fn may_contain_hash(&self, hash: u64) -> bool {
+        self.parts.iter().any(|part| part.may_contain_hash(hash))
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        self.parts.iter().map(BloomFilter::memory_usage).sum()
+    }
+}
@@ -0,0 +1,4 @@
+//! The log-structured merge tree of [`crate::table::lsm::LsmTable`].
+pub mod bloom;
+pub mod sstable;
+pub mod tree;
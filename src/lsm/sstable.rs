@@ -0,0 +1,301 @@
+//! Sorted string tables: immutable files with records sorted by keys.
+//!
+//! [data blocks][sparse index][bloom filter][meta][footer]
+//!
+//! - A record is [`key length` (2 bytes), `key`, `kind` (1 byte)] and [`value length` (4 bytes), `value`] for values.
+//! - The sparse index has the first key of every block: [`key length` (2 bytes), `key`, `offset` (8 bytes), `length` (4 bytes)].
+//! - The meta is [`number of records` (8 bytes), `largest key length` (2 bytes), `largest key`].
+//! - The footer is [`index offset` (8 bytes), `bloom offset` (8 bytes), `meta offset` (8 bytes), [`MAGIC`] (8 bytes)].
+//!
+//! Only the sparse index and the bloom filter are kept in memory.
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, ErrorKind, Write},
+    mem::size_of,
+    path::{Path, PathBuf}
+};
+use positioned_io::ReadAt;
+use crate::{
+    lsm::bloom::{self, BloomFilter},
+    metrics::METRICS
+};
+
+pub const MAGIC: u64 = 0x4c534d5353544142;
+const FOOTER_SIZE: usize = 32;
+/// A new block is started, when the block is bigger.
+pub const BLOCK_SIZE: usize = 4096;
+
+const VALUE: u8 = 0;
+const TOMBSTONE: u8 = 1;
+
+/// The value or `None` for the deleted key.
+pub type Entry = (Vec<u8>, Option<Vec<u8>>);
+
+struct BlockHandle {
+    first_key: Vec<u8>,
+    offset: u64,
+    len: u32,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn u16_at(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(buf.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+fn u32_at(buf: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+fn u64_at(buf: &[u8], pos: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(buf.get(pos..pos + 8)?.try_into().ok()?))
+}
+
+/// Parses the record at `pos` and moves `pos` after it.
+fn parse_record<'a>(block: &'a [u8], pos: &mut usize) -> Option<(&'a [u8], Option<&'a [u8]>)> {
+    let key_len = u16_at(block, *pos)? as usize;
+    let key = block.get(*pos + 2..*pos + 2 + key_len)?;
+    *pos += 2 + key_len;
+    let kind = *block.get(*pos)?;
+    *pos += 1;
+    if kind == TOMBSTONE {
+        return Some((key, None));
+    }
+    let value_len = u32_at(block, *pos)? as usize;
+    let value = block.get(*pos + 4..*pos + 4 + value_len)?;
+    *pos += 4 + value_len;
+    Some((key, Some(value)))
+}
+
+pub struct SsTable {
+    /// The file is `{number}.sst`. Bigger numbers are newer.
+    pub number: u64,
+    file: File,
+    index: Vec<BlockHandle>,
+    bloom: BloomFilter,
+    pub records: u64,
+    pub smallest: Vec<u8>,
+    pub largest: Vec<u8>,
+    /// The size of the file.
+    pub size: u64,
+}
+
+pub fn path(dir: &Path, number: u64) -> PathBuf {
+    dir.join(format!("{number}.sst"))
+}
+
+impl SsTable {
+    pub fn open(dir: &Path, number: u64) -> io::Result<Self> {
+        let file = File::open(path(dir, number))?;
+        let size = file.metadata()?.len();
+        if size < FOOTER_SIZE as u64 {
+            return Err(invalid("the table is too short"));
+        }
+        let mut footer = [0u8; FOOTER_SIZE];
+        file.read_exact_at(size - FOOTER_SIZE as u64, &mut footer)?;
+        let (index_offset, bloom_offset, meta_offset) = (u64_at(&footer, 0).unwrap(), u64_at(&footer, 8).unwrap(), u64_at(&footer, 16).unwrap());
+        if u64_at(&footer, 24) != Some(MAGIC) || index_offset > bloom_offset || bloom_offset > meta_offset || meta_offset > size - FOOTER_SIZE as u64 {
+            return Err(invalid("the footer of the table is broken"));
+        }
+        let mut buf = vec![0u8; (size - FOOTER_SIZE as u64 - index_offset) as usize];
+        file.read_exact_at(index_offset, &mut buf)?;
+        let (index_buf, rest) = buf.split_at((bloom_offset - index_offset) as usize);
+        let (bloom_buf, meta) = rest.split_at((meta_offset - bloom_offset) as usize);
+
+        let mut index = Vec::new();
+        let mut pos = 0;
+        while pos < index_buf.len() {
+            let key_len = u16_at(index_buf, pos).ok_or_else(|| invalid("the index of the table is broken"))? as usize;
+            let first_key = index_buf.get(pos + 2..pos + 2 + key_len).ok_or_else(|| invalid("the index of the table is broken"))?.to_vec();
+            pos += 2 + key_len;
+            let offset = u64_at(index_buf, pos).ok_or_else(|| invalid("the index of the table is broken"))?;
+            let len = u32_at(index_buf, pos + 8).ok_or_else(|| invalid("the index of the table is broken"))?;
+            pos += 12;
+            index.push(BlockHandle { first_key, offset, len });
+        }
+        let bloom = BloomFilter::decode(bloom_buf).ok_or_else(|| invalid("the bloom filter of the table is broken"))?;
+        let records = u64_at(meta, 0).ok_or_else(|| invalid("the meta of the table is broken"))?;
+        let largest_len = u16_at(meta, 8).ok_or_else(|| invalid("the meta of the table is broken"))? as usize;
+        let largest = meta.get(10..10 + largest_len).ok_or_else(|| invalid("the meta of the table is broken"))?.to_vec();
+        let smallest = index.first().map(|block| block.first_key.clone()).unwrap_or_default();
+
+        Ok(Self { number, file, index, bloom, records, smallest, largest, size })
+    }
+
+    fn read_block(&self, block: &BlockHandle) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0u8; block.len as usize];
+        self.file.read_exact_at(block.offset, &mut buf)?;
+        Ok(buf)
+    }
+
+    /// Returns `None`, if the table doesn't have the key, and `Some(None)`, if the key is deleted.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<Option<Vec<u8>>>> {
+        if !self.bloom.may_contain(key) {
+            METRICS.bloom_filter_skips.add(1);
+            return Ok(None);
+        }
+        self.get_without_bloom(key)
+    }
+
+    fn get_without_bloom(&self, key: &[u8]) -> io::Result<Option<Option<Vec<u8>>>> {
+        let position = self.index.partition_point(|block| block.first_key.as_slice() <= key);
+        let Some(handle) = position.checked_sub(1).and_then(|position| self.index.get(position)) else {
+            return Ok(None);
+        };
+        let block = self.read_block(handle)?;
+        let mut pos = 0;
+        while pos < block.len() {
+            let (record_key, value) = parse_record(&block, &mut pos).ok_or_else(|| invalid("the block of the table is broken"))?;
+            if record_key == key {
+                return Ok(Some(value.map(|value| value.to_vec())));
+            }
+            if record_key > key {
+                break;
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn is_overlapping(&self, smallest: &[u8], largest: &[u8]) -> bool {
+        self.smallest.as_slice() <= largest && smallest <= self.largest.as_slice()
+    }
+
+    /// The sparse index and the bloom filter.
+    pub fn memory_usage(&self) -> usize {
+        self.bloom.memory_usage() + self.index.iter().map(|block| block.first_key.len() + size_of::<BlockHandle>()).sum::<usize>()
+    }
+
+    pub fn iter(&self) -> SsTableIter<'_> {
+        SsTableIter { table: self, block: 0, buf: Vec::new(), pos: 0 }
+    }
+}
+
+/// Reads records of the table in the order of keys, one block at a time. It stops after the first error.
+pub struct SsTableIter<'table> {
+    table: &'table SsTable,
+    block: usize,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Iterator for SsTableIter<'_> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        while self.pos >= self.buf.len() {
+            let block = self.table.index.get(self.block)?;
+            self.block += 1;
+            self.pos = 0;
+            self.buf = match self.table.read_block(block) {
+                Ok(buf) => buf,
+                Err(e) => return self.stop(e)
+            };
+        }
+        match parse_record(&self.buf, &mut self.pos) {
+            Some((key, value)) => Some(Ok((key.to_vec(), value.map(|value| value.to_vec())))),
+            None => self.stop(invalid("the block of the table is broken"))
+        }
+    }
+}
+
+impl SsTableIter<'_> {
+    fn stop(&mut self, e: io::Error) -> Option<io::Result<Entry>> {
+        self.block = self.table.index.len();
+        self.buf.clear();
+        self.pos = 0;
+        Some(Err(e))
+    }
+}
+
+/// Writes records, that are added in the order of keys, to a new table.
+pub struct SsTableWriter {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    bits_per_key: usize,
+    block: Vec<u8>,
+    offset: u64,
+    index: Vec<u8>,
+    hashes: Vec<u64>,
+    largest: Vec<u8>,
+}
+
+impl SsTableWriter {
+    pub fn create(dir: &Path, number: u64, bits_per_key: usize) -> io::Result<Self> {
+        let path = path(dir, number);
+        let writer = BufWriter::with_capacity(64 * 1024, File::create(&path)?);
+        Ok(Self { writer, path, bits_per_key, block: Vec::with_capacity(BLOCK_SIZE * 2), offset: 0, index: Vec::new(), hashes: Vec::new(), largest: Vec::new() })
+    }
+
+    pub fn add(&mut self, key: &[u8], value: Option<&[u8]>) -> io::Result<()> {
+        if self.block.is_empty() {
+            self.index.extend_from_slice(&(key.len() as u16).to_le_bytes());
+            self.index.extend_from_slice(key);
+        }
+        self.block.extend_from_slice(&(key.len() as u16).to_le_bytes());
+        self.block.extend_from_slice(key);
+        match value {
+            Some(value) => {
+                self.block.push(VALUE);
+                self.block.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                self.block.extend_from_slice(value);
+            }
+            None => self.block.push(TOMBSTONE)
+        }
+        self.hashes.push(bloom::hash(key));
+        self.largest.clear();
+        self.largest.extend_from_slice(key);
+        if self.block.len() >= BLOCK_SIZE {
+            self.finish_block()?;
+        }
+        Ok(())
+    }
+
+    fn finish_block(&mut self) -> io::Result<()> {
+        if self.block.is_empty() {
+            return Ok(());
+        }
+        self.writer.write_all(&self.block)?;
+        self.index.extend_from_slice(&self.offset.to_le_bytes());
+        self.index.extend_from_slice(&(self.block.len() as u32).to_le_bytes());
+        self.offset += self.block.len() as u64;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Bytes of records, that were added.
+    pub fn size(&self) -> u64 {
+        self.offset + self.block.len() as u64
+    }
+
+    pub fn records(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Writes the rest of the table and syncs the file.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.finish_block()?;
+        let index_offset = self.offset;
+        let mut tail = std::mem::take(&mut self.index);
+        let bloom_offset = index_offset + tail.len() as u64;
+        BloomFilter::new(&self.hashes, self.bits_per_key).encode(&mut tail);
+        let meta_offset = index_offset + tail.len() as u64;
+        tail.extend_from_slice(&(self.hashes.len() as u64).to_le_bytes());
+        tail.extend_from_slice(&(self.largest.len() as u16).to_le_bytes());
+        tail.extend_from_slice(&self.largest);
+        for number in [index_offset, bloom_offset, meta_offset, MAGIC] {
+            tail.extend_from_slice(&number.to_le_bytes());
+        }
+        self.writer.write_all(&tail)?;
+        let file = self.writer.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+
+    /// Removes the file of the table, that isn't finished.
+    pub fn abandon(self) {
+        let path = self.path.clone();
+        drop(self);
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,549 @@
+//! The LSM tree of one table.
+//!
+//! Changes go to the memtable. A full memtable becomes immutable and is written to a new table of the level 0, while new
+//! changes go to a new memtable. Tables of the level 0 can overlap, tables of other levels can't. When the level 0 has too
+//! many tables, they are merged with overlapping tables of the level 1. When another level is too big, one of its tables is
+//! merged with overlapping tables of the next level. Deleted keys are kept as tombstones, until they reach the last level.
+//! Every table has the bloom filter of its keys, so lookups skip tables without the key. Filters are built, when tables are
+//! written by flushes and compactions.
+//!
+//! The `MANIFEST` file has tables of levels. It is replaced after every flush and compaction, so files of tables, that aren't
+//! in it, are removed on the start. The memtable isn't written here: tables log changes (see [`crate::table::lsm`]) and
+//! the log is replayed after the restart.
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, ErrorKind, Read, Write},
+    iter::Peekable,
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering::SeqCst}}
+};
+use crate::lsm::{
+    bloom,
+    sstable::{self, Entry, SsTable, SsTableWriter}
+};
+use crate::error;
+
+const MANIFEST_FILE: &str = "MANIFEST";
+const MANIFEST_TMP_FILE: &str = "MANIFEST.tmp";
+pub const MAX_LEVELS: usize = 7;
+/// Changes of keys with the same lock are made one by one.
+const KEY_LOCKS: usize = 64;
+/// Bytes of the map, that every record of the memtable takes besides the key and the value.
+const RECORD_OVERHEAD: usize = 32;
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The memtable is flushed, when it is bigger.
+    pub memtable_bytes: usize,
+    /// Compactions split new tables at this size.
+    pub table_bytes: u64,
+    /// The level 0 is compacted, when it has so many tables.
+    pub l0_compaction_trigger: usize,
+    /// The maximum size of the level 1. Every next level is 10 times bigger.
+    pub level_base_bytes: u64,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            memtable_bytes: 4 * 1024 * 1024,
+            table_bytes: 2 * 1024 * 1024,
+            l0_compaction_trigger: 4,
+            level_base_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Memtable {
+    /// `None` is the tombstone of the deleted key.
+    records: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    bytes: usize,
+}
+
+impl Memtable {
+    fn record_size(key: &[u8], value: &Option<Vec<u8>>) -> usize {
+        key.len() + value.as_ref().map_or(0, Vec::len) + RECORD_OVERHEAD
+    }
+
+    fn put(&mut self, key: &[u8], value: Option<Vec<u8>>) {
+        self.bytes += Self::record_size(key, &value);
+        if let Some(old) = self.records.insert(key.to_vec(), value) {
+            self.bytes -= Self::record_size(key, &old);
+        }
+    }
+}
+
+/// The memtable, that is written to the disk, and the number of keys of the tree with it.
+struct Frozen {
+    memtable: Memtable,
+    count: u64,
+}
+
+/// Tables of levels. The level 0 is sorted from the newest table, other levels are sorted by keys.
+#[derive(Clone)]
+struct Version {
+    levels: Vec<Vec<Arc<SsTable>>>,
+}
+
+impl Version {
+    fn get(&self, key: &[u8]) -> io::Result<Option<Option<Vec<u8>>>> {
+        for table in self.levels[0].iter() {
+            if let Some(value) = table.get(key)? {
+                return Ok(Some(value));
+            }
+        }
+        for level in self.levels[1..].iter() {
+            let position = level.partition_point(|table| table.largest.as_slice() < key);
+            if let Some(table) = level.get(position).filter(|table| table.smallest.as_slice() <= key) {
+                if let Some(value) = table.get(key)? {
+                    return Ok(Some(value));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn level_bytes(&self, level: usize) -> u64 {
+        self.levels[level].iter().map(|table| table.size).sum()
+    }
+}
+
+type Source<'a> = Box<dyn Iterator<Item = io::Result<Entry>> + 'a>;
+
+/// Merges sorted sources. If sources have the same key, the record of the first source is returned.
+/// An error of any source is returned as soon as it is met.
+struct Merge<'a> {
+    sources: Vec<Peekable<Source<'a>>>,
+}
+
+impl<'a> Merge<'a> {
+    fn new(sources: Vec<Source<'a>>) -> Self {
+        Self { sources: sources.into_iter().map(Iterator::peekable).collect() }
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<io::Result<Entry>> {
+        let mut smallest: Option<(usize, &[u8])> = None;
+        for (number, source) in self.sources.iter_mut().enumerate() {
+            match source.peek() {
+                Some(Ok((key, _))) if smallest.is_none_or(|(_, smallest)| key.as_slice() < smallest) => {
+                    smallest = Some((number, key.as_slice()));
+                }
+                Some(Err(_)) => {
+                    smallest = Some((number, &[]));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let (number, key) = smallest.map(|(number, key)| (number, key.to_vec()))?;
+        let entry = self.sources[number].next();
+        if let Some(Ok(_)) = entry {
+            for source in self.sources[number + 1..].iter_mut() {
+                source.next_if(|other| matches!(other, Ok((other, _)) if *other == key));
+            }
+        }
+        entry
+    }
+}
+
+/// Records of the memtable as a source.
+fn memtable_source<'a>(memtable: &'a Memtable) -> Source<'a> {
+    Box::new(memtable.records.iter().map(|(key, value)| Ok((key.clone(), value.clone()))))
+}
+
+/// Tables of a level other than 0 as one source.
+fn level_source<'a>(tables: &'a [Arc<SsTable>]) -> Source<'a> {
+    Box::new(tables.iter().flat_map(|table| table.iter()))
+}
+
+pub struct Lsm {
+    dir: PathBuf,
+    options: Options,
+    memtable: RwLock<Memtable>,
+    immutable: RwLock<Option<Arc<Frozen>>>,
+    version: RwLock<Arc<Version>>,
+    /// Flushes and compactions are made one by one.
+    flush_lock: Mutex<()>,
+    key_locks: Box<[Mutex<()>]>,
+    count: AtomicU64,
+    /// The number of keys in tables. It is written to the manifest.
+    persisted_count: AtomicU64,
+    next_number: AtomicU64,
+    /// The largest key of the last compacted table of every level, so compactions go around the level.
+    compaction_pointers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl Lsm {
+    /// Opens the tree in the directory or creates a new one.
+    pub fn open(dir: PathBuf, options: Options) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut levels = vec![Vec::new(); MAX_LEVELS];
+        let (mut count, mut next_number) = (0, 0);
+        if let Some(manifest) = read_manifest(&dir)? {
+            count = manifest.count;
+            next_number = manifest.next_number;
+            for (level, numbers) in manifest.levels.into_iter().enumerate().take(MAX_LEVELS) {
+                for number in numbers {
+                    levels[level].push(Arc::new(SsTable::open(&dir, number)?));
+                }
+            }
+        }
+
+        // Tables, that were written before a crash, but aren't in the manifest.
+        let used: Vec<u64> = levels.iter().flatten().map(|table| table.number).collect();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let number = path.file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".sst"))
+                .and_then(|number| number.parse::<u64>().ok());
+            if let Some(number) = number {
+                next_number = next_number.max(number + 1);
+                if !used.contains(&number) {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
+
+        Ok(Self {
+            dir,
+            options,
+            memtable: RwLock::new(Memtable::default()),
+            immutable: RwLock::new(None),
+            version: RwLock::new(Arc::new(Version { levels })),
+            flush_lock: Mutex::new(()),
+            key_locks: (0..KEY_LOCKS).map(|_| Mutex::new(())).collect(),
+            count: AtomicU64::new(count),
+            persisted_count: AtomicU64::new(count),
+            next_number: AtomicU64::new(next_number),
+            compaction_pointers: Mutex::new(vec![Vec::new(); MAX_LEVELS]),
+        })
+    }
+
+    fn current(&self) -> Arc<Version> {
+        self.version.read().unwrap().clone()
+    }
+
+    /// Returns `None`, if the tree doesn't know the key, and `Some(None)`, if the key is deleted.
+    fn lookup(&self, key: &[u8]) -> io::Result<Option<Option<Vec<u8>>>> {
+        if let Some(value) = self.memtable.read().unwrap().records.get(key) {
+            return Ok(Some(value.clone()));
+        }
+        if let Some(frozen) = self.immutable.read().unwrap().as_ref() {
+            if let Some(value) = frozen.memtable.records.get(key) {
+                return Ok(Some(value.clone()));
+            }
+        }
+        self.current().get(key)
+    }
+
+    /// Fails, if a file of the tree can't be read or is broken.
+    pub fn get(&self, key: &[u8]) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.lookup(key)?.flatten())
+    }
+
+    fn lock_key(&self, key: &[u8]) -> std::sync::MutexGuard<'_, ()> {
+        self.key_locks[bloom::hash(key) as usize % KEY_LOCKS].lock().unwrap()
+    }
+
+    /// Puts the record to the memtable and changes the count.
+    fn put(&self, key: &[u8], value: Option<Vec<u8>>, count_change: i64) {
+        let mut memtable = self.memtable.write().unwrap();
+        memtable.put(key, value);
+        if count_change > 0 {
+            self.count.fetch_add(1, SeqCst);
+        } else if count_change < 0 {
+            self.count.fetch_sub(1, SeqCst);
+        }
+    }
+
+    /// Returns the old value. Nothing is changed, if the old value can't be read.
+    pub fn set(&self, key: &[u8], value: Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+        let guard = self.lock_key(key);
+        let old = self.get(key)?;
+        self.put(key, Some(value), if old.is_none() { 1 } else { 0 });
+        drop(guard);
+        self.flush_if_full();
+        Ok(old)
+    }
+
+    /// Returns false, if the key exists.
+    pub fn insert(&self, key: &[u8], value: Vec<u8>) -> io::Result<bool> {
+        let guard = self.lock_key(key);
+        if self.get(key)?.is_some() {
+            return Ok(false);
+        }
+        self.put(key, Some(value), 1);
+        drop(guard);
+        self.flush_if_full();
+        Ok(true)
+    }
+
+    /// Returns false, if there was no key.
+    pub fn delete(&self, key: &[u8]) -> io::Result<bool> {
+        let guard = self.lock_key(key);
+        if self.get(key)?.is_none() {
+            return Ok(false);
+        }
+        self.put(key, None, -1);
+        drop(guard);
+        self.flush_if_full();
+        Ok(true)
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(SeqCst)
+    }
+
+    /// Returns all keys in the order. It reads all tables.
+    pub fn keys(&self) -> io::Result<Vec<Vec<u8>>> {
+        let memtable: Vec<Entry> = self.memtable.read().unwrap().records.iter().map(|(key, value)| (key.clone(), value.clone())).collect();
+        let immutable = self.immutable.read().unwrap().clone();
+        let version = self.current();
+        let mut sources: Vec<Source> = vec![Box::new(memtable.into_iter().map(Ok))];
+        if let Some(frozen) = immutable.as_ref() {
+            sources.push(memtable_source(&frozen.memtable));
+        }
+        for table in version.levels[0].iter() {
+            sources.push(Box::new(table.iter()));
+        }
+        for level in version.levels[1..].iter() {
+            sources.push(level_source(level));
+        }
+        let mut keys = Vec::new();
+        for entry in Merge::new(sources) {
+            if let (key, Some(_)) = entry? {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Memtables, sparse indexes and bloom filters.
+    pub fn memory_usage(&self) -> u64 {
+        let immutable = self.immutable.read().unwrap().as_ref().map_or(0, |frozen| frozen.memtable.bytes);
+        let tables: usize = self.current().levels.iter().flatten().map(|table| table.memory_usage() + size_of::<SsTable>()).sum();
+        (self.memtable.read().unwrap().bytes + immutable + tables) as u64
+    }
+
+    /// Numbers of tables of levels.
+    pub fn tables_per_level(&self) -> Vec<usize> {
+        self.current().levels.iter().map(Vec::len).collect()
+    }
+
+    /// The change is already in the memtable, so a failed flush is only logged. It is retried with the next one.
+    fn flush_if_full(&self) {
+        if self.memtable.read().unwrap().bytes < self.options.memtable_bytes {
+            return;
+        }
+        let _flush = self.flush_lock.lock().unwrap();
+        // Another thread could have flushed it, while this one waited.
+        if self.memtable.read().unwrap().bytes < self.options.memtable_bytes {
+            return;
+        }
+        if let Err(e) = self.flush_locked() {
+            error!("Failed to flush the memtable to {:?}: {}", self.dir, e);
+        }
+    }
+
+    /// Writes the memtable to the level 0 and compacts levels, that need it.
+    pub fn flush(&self) -> io::Result<()> {
+        let _flush = self.flush_lock.lock().unwrap();
+        self.flush_locked()
+    }
+
+    fn flush_locked(&self) -> io::Result<()> {
+        // The immutable memtable is left, if the last flush failed.
+        let frozen = self.immutable.read().unwrap().clone();
+        let frozen = match frozen {
+            Some(frozen) => frozen,
+            None => {
+                let mut memtable = self.memtable.write().unwrap();
+                if memtable.records.is_empty() {
+                    return Ok(());
+                }
+                let frozen = Arc::new(Frozen { memtable: std::mem::take(&mut *memtable), count: self.count.load(SeqCst) });
+                *self.immutable.write().unwrap() = Some(frozen.clone());
+                frozen
+            }
+        };
+
+        let number = self.next_number.fetch_add(1, SeqCst);
+        let mut writer = SsTableWriter::create(&self.dir, number, bloom::bits_per_key())?;
+        for (key, value) in frozen.memtable.records.iter() {
+            if let Err(e) = writer.add(key, value.as_deref()) {
+                writer.abandon();
+                return Err(e);
+            }
+        }
+        writer.finish()?;
+        let table = Arc::new(SsTable::open(&self.dir, number)?);
+
+        let mut version = Version::clone(&self.current());
+        version.levels[0].insert(0, table);
+        if let Err(e) = write_manifest(&self.dir, frozen.count, self.next_number.load(SeqCst), &version) {
+            let _ = fs::remove_file(sstable::path(&self.dir, number));
+            return Err(e);
+        }
+        self.persisted_count.store(frozen.count, SeqCst);
+        *self.version.write().unwrap() = Arc::new(version);
+        *self.immutable.write().unwrap() = None;
+
+        while let Some((level, inputs)) = self.pick_compaction() {
+            self.compact(level, inputs)?;
+        }
+        Ok(())
+    }
+
+    fn level_target(&self, level: usize) -> u64 {
+        self.options.level_base_bytes.saturating_mul(10u64.saturating_pow(level as u32 - 1))
+    }
+
+    /// Returns the level and its tables, that must be merged with the next level.
+    fn pick_compaction(&self) -> Option<(usize, Vec<Arc<SsTable>>)> {
+        let version = self.current();
+        if version.levels[0].len() >= self.options.l0_compaction_trigger {
+            return Some((0, version.levels[0].clone()));
+        }
+        for level in 1..MAX_LEVELS - 1 {
+            if version.level_bytes(level) <= self.level_target(level) {
+                continue;
+            }
+            let pointers = self.compaction_pointers.lock().unwrap();
+            let tables = &version.levels[level];
+            let table = tables.iter()
+                .find(|table| table.smallest > pointers[level])
+                .unwrap_or(&tables[0]);
+            return Some((level, vec![table.clone()]));
+        }
+        None
+    }
+
+    fn compact(&self, level: usize, inputs: Vec<Arc<SsTable>>) -> io::Result<()> {
+        let version = self.current();
+        let smallest = inputs.iter().map(|table| &table.smallest).min().unwrap().clone();
+        let largest = inputs.iter().map(|table| &table.largest).max().unwrap().clone();
+        let overlapping: Vec<Arc<SsTable>> = version.levels[level + 1].iter()
+            .filter(|table| table.is_overlapping(&smallest, &largest))
+            .cloned()
+            .collect();
+        // Tombstones aren't needed, if older records can't be under them.
+        let is_last = version.levels[level + 2..].iter().all(Vec::is_empty);
+
+        let mut sources: Vec<Source> = inputs.iter().map(|table| Box::new(table.iter()) as Source).collect();
+        sources.push(level_source(&overlapping));
+        let mut outputs = Vec::new();
+        let mut writer: Option<SsTableWriter> = None;
+        let res = (|| {
+            for entry in Merge::new(sources) {
+                let (key, value) = entry?;
+                if value.is_none() && is_last {
+                    continue;
+                }
+                if writer.is_none() {
+                    let number = self.next_number.fetch_add(1, SeqCst);
+                    writer = Some(SsTableWriter::create(&self.dir, number, bloom::bits_per_key())?);
+                    outputs.push(number);
+                }
+                let current = writer.as_mut().unwrap();
+                current.add(&key, value.as_deref())?;
+                if current.size() >= self.options.table_bytes {
+                    writer.take().unwrap().finish()?;
+                }
+            }
+            if let Some(writer) = writer.take() {
+                writer.finish()?;
+            }
+            outputs.iter().map(|number| SsTable::open(&self.dir, *number).map(Arc::new)).collect::<io::Result<Vec<_>>>()
+        })();
+        let outputs = match res {
+            Ok(tables) => tables,
+            Err(e) => {
+                for number in outputs {
+                    let _ = fs::remove_file(sstable::path(&self.dir, number));
+                }
+                return Err(e);
+            }
+        };
+
+        let mut new_version = Version::clone(&version);
+        let is_removed = |table: &Arc<SsTable>| inputs.iter().chain(overlapping.iter()).any(|old| old.number == table.number);
+        new_version.levels[level].retain(|table| !is_removed(table));
+        new_version.levels[level + 1].retain(|table| !is_removed(table));
+        new_version.levels[level + 1].extend(outputs);
+        new_version.levels[level + 1].sort_by(|a, b| a.smallest.cmp(&b.smallest));
+        write_manifest(&self.dir, self.persisted_count.load(SeqCst), self.next_number.load(SeqCst), &new_version)?;
+        *self.version.write().unwrap() = Arc::new(new_version);
+        self.compaction_pointers.lock().unwrap()[level] = largest;
+
+        // Readers, that still have old tables, read them with open files.
+        for table in inputs.iter().chain(overlapping.iter()) {
+            let _ = fs::remove_file(sstable::path(&self.dir, table.number));
+        }
+        Ok(())
+    }
+}
+
+/// [`count` (8 bytes), `next number` (8 bytes), `number of levels` (4 bytes),
+/// [`number of tables` (4 bytes), `numbers of tables` (8 bytes each)] for every level]
+fn write_manifest(dir: &Path, count: u64, next_number: u64, version: &Version) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(1024);
+    buf.extend_from_slice(&count.to_le_bytes());
+    buf.extend_from_slice(&next_number.to_le_bytes());
+    buf.extend_from_slice(&(version.levels.len() as u32).to_le_bytes());
+    for level in version.levels.iter() {
+        buf.extend_from_slice(&(level.len() as u32).to_le_bytes());
+        for table in level {
+            buf.extend_from_slice(&table.number.to_le_bytes());
+        }
+    }
+    let tmp_path = dir.join(MANIFEST_TMP_FILE);
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(&buf)?;
+    file.sync_all()?;
+    fs::rename(tmp_path, dir.join(MANIFEST_FILE))
+}
+
+struct Manifest {
+    count: u64,
+    next_number: u64,
+    /// Numbers of tables of levels.
+    levels: Vec<Vec<u64>>,
+}
+
+/// Returns `None`, if there is no manifest.
+fn read_manifest(dir: &Path) -> io::Result<Option<Manifest>> {
+    let mut buf = Vec::new();
+    match File::open(dir.join(MANIFEST_FILE)) {
+        Ok(mut file) => file.read_to_end(&mut buf)?,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e)
+    };
+    let broken = || io::Error::new(ErrorKind::InvalidData, "the manifest is broken");
+    let mut pos = 0;
+    let mut take = |len: usize| {
+        let bytes = buf.get(pos..pos + len).ok_or_else(broken)?;
+        pos += len;
+        Ok::<&[u8], io::Error>(bytes)
+    };
+    let count = u64::from_le_bytes(take(8)?.try_into().unwrap());
+    let next_number = u64::from_le_bytes(take(8)?.try_into().unwrap());
+    let number_of_levels = u32::from_le_bytes(take(4)?.try_into().unwrap());
+    let mut levels = Vec::new();
+    for _ in 0..number_of_levels {
+        let number_of_tables = u32::from_le_bytes(take(4)?.try_into().unwrap());
+        let mut level = Vec::new();
+        for _ in 0..number_of_tables {
+            level.push(u64::from_le_bytes(take(8)?.try_into().unwrap()));
+        }
+        levels.push(level);
+    }
+    Ok(Some(Manifest { count, next_number, levels }))
+}
@@ -1,57 +1,23 @@
-#[cfg(test)]
-use std::fs;
-use std::mem;
-mod index;
+use std::{mem, process};
+use dbms::{constants, server::{cfg::Config, server::Server, shutdown::wait_for_signal}, storage::Storage};
 
-mod bin_types;
-mod constants;
-mod utils;
-mod storage;
-
-use storage::*;
-#[cfg(test)]
-use crate::tests::{crud, crud_bench, persistence};
-
-mod table;
-mod console;
-mod disk_storage;
-mod writers;
-mod server;
-mod tests;
-mod scheme;
-mod connection;
-mod stream;
-mod node;
-
-#[cfg(not(test))]
 #[tokio::main]
 async fn main() {
+    // The config is read first, so a wrong config stops the process before the storage is loaded.
+    let config = Config::from_args();
+
     let storage = Storage::new(["..", constants::paths::PERSISTENCE_DIR].iter().collect());
     let storage_static = unsafe { mem::transmute::<&Storage, &'static Storage>(&storage) };
     storage_static.init();
 
-    server::server::Server::new(storage_static).run();
-}
-
-#[test]
-fn main() {
-    tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .unwrap()
-        .block_on(async {
-            info!("Starting test");
-            let storage = Storage::new(["test_data"].iter().collect());
-            let storage_static = unsafe { mem::transmute::<&Storage, &'static Storage>(&storage) };
-            info!("Storage created");
-            Storage::init(storage_static);
-            info!("Storage initialized");
-            crud(storage_static);
-            persistence(storage_static);
-
-            println!();
-            crud_bench(storage_static);
+    let server = Server::with_config(storage_static, config);
+    let shutdown = server.shutdown_handle();
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        shutdown.request(None);
+    });
 
-            fs::remove_dir_all("test_data").unwrap();
-        });
+    // `Server::run` blocks this thread, other tasks use workers of the runtime.
+    let is_clean = server.run();
+    process::exit(if is_clean { 0 } else { 1 });
 }
\ No newline at end of file
@@ -0,0 +1,316 @@
+//! Process-wide metrics. They are atomics, so recording is cheap and doesn't need locks.
+//!
+//! [`render`] writes them in the Prometheus text format.
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering::Relaxed},
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use crate::{
+    constants::actions,
+    storage::storage::Storage
+};
+
+/// Upper bounds of histogram buckets in seconds.
+const BUCKETS: [f64; 14] = [0.00001, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 10.0];
+
+pub struct Counter(AtomicU64);
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Counter {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    #[inline(always)]
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Relaxed)
+    }
+}
+
+pub struct Gauge(AtomicI64);
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gauge {
+    pub const fn new() -> Self {
+        Self(AtomicI64::new(0))
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Relaxed)
+    }
+
+    /// Increments the gauge until the guard is dropped.
+    pub fn track(&'static self) -> GaugeGuard {
+        self.0.fetch_add(1, Relaxed);
+        GaugeGuard(self)
+    }
+}
+
+pub struct GaugeGuard(&'static Gauge);
+
+impl Drop for GaugeGuard {
+    fn drop(&mut self) {
+        self.0.0.fetch_sub(1, Relaxed);
+    }
+}
+
+pub struct Histogram {
+    /// Not cumulative, [`render`] sums them.
+    buckets: [AtomicU64; BUCKETS.len()],
+    sum_nanos: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS.len()],
+            sum_nanos: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    #[inline(always)]
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket].fetch_add(1, Relaxed);
+        }
+        self.sum_nanos.fetch_add(duration.as_nanos() as u64, Relaxed);
+        self.count.fetch_add(1, Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Relaxed)
+    }
+}
+
+pub struct Metrics {
+    /// Latency of messages of the binary protocol by the action. The count of the histogram is the count of requests.
+    pub actions: [Histogram; 256],
+    pub log_bytes_written: Counter,
+    pub log_flush: Histogram,
+    pub dump: Histogram,
+    /// Unix time in seconds.
+    pub last_dump_time: Gauge,
+    pub binary_connections: Gauge,
+    pub http_connections: Gauge,
+    pub resp_connections: Gauge,
+    pub cache_expirations: Counter,
+    /// Bytes of the leader's log, that the follower hasn't applied yet.
+    pub replication_lag_bytes: Gauge,
+    pub replication_followers: Gauge,
+    /// The epoch of the node, see [`crate::server::failover`].
+    pub node_epoch: Gauge,
+    pub leader_changes: Counter,
+    /// Keys, that this node has sent to other nodes with moved shards.
+    pub migrated_keys: Counter,
+    pub change_subscribers: Gauge,
+    /// Connections in the push mode of pub/sub.
+    pub pubsub_subscribers: Gauge,
+    pub pubsub_messages: Counter,
+    /// Reads of on-disk tables, that were answered from the cache of values.
+    pub disk_cache_hits: Counter,
+    pub disk_cache_misses: Counter,
+    /// Lookups of files of disk-backed tables, that bloom filters have skipped.
+    pub bloom_filter_skips: Counter,
+}
+
+pub static METRICS: Metrics = Metrics {
+    actions: [const { Histogram::new() }; 256],
+    log_bytes_written: Counter::new(),
+    log_flush: Histogram::new(),
+    dump: Histogram::new(),
+    last_dump_time: Gauge::new(),
+    binary_connections: Gauge::new(),
+    http_connections: Gauge::new(),
+    resp_connections: Gauge::new(),
+    cache_expirations: Counter::new(),
+    replication_lag_bytes: Gauge::new(),
+    replication_followers: Gauge::new(),
+    node_epoch: Gauge::new(),
+    leader_changes: Counter::new(),
+    migrated_keys: Counter::new(),
+    change_subscribers: Gauge::new(),
+    pubsub_subscribers: Gauge::new(),
+    pubsub_messages: Counter::new(),
+    disk_cache_hits: Counter::new(),
+    disk_cache_misses: Counter::new(),
+    bloom_filter_skips: Counter::new(),
+};
+
+impl Metrics {
+    pub fn dumped(&self, duration: Duration) {
+        self.dump.observe(duration);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0);
+        self.last_dump_time.set(now as i64);
+    }
+}
+
+pub fn action_name(action: u8) -> &'static str {
+    match action {
+        actions::CREATE_TABLE_IN_MEMORY => "create_table_in_memory",
+        actions::CREATE_TABLE_CACHE => "create_table_cache",
+        actions::CREATE_TABLE_ON_DISK => "create_table_on_disk",
+        actions::CREATE_TABLE_LSM => "create_table_lsm",
+        actions::GET_TABLES_NAMES => "get_tables_names",
+        actions::PING => "ping",
+        actions::GET_SHARD_METADATA => "get_shard_metadata",
+        actions::GET_HIERARCHY => "get_hierarchy",
+        actions::GET => "get",
+        actions::GET_FIELD => "get_field",
+        actions::GET_FIELDS => "get_fields",
+        actions::INSERT => "insert",
+        actions::SET => "set",
+        actions::DELETE => "delete",
+        actions::GET_TABLE_SCHEME => "get_table_scheme",
+        actions::INFO => "info",
+        actions::SLOWLOG_GET => "slowlog_get",
+        actions::SLOWLOG_RESET => "slowlog_reset",
+        actions::CONFIG_GET => "config_get",
+        actions::CONFIG_SET => "config_set",
+        actions::SHUTDOWN => "shutdown",
+        actions::REPLICATE => "replicate",
+        actions::HEARTBEAT => "heartbeat",
+        actions::MIGRATE_SHARDS => "migrate_shards",
+        actions::IMPORT_RECORDS => "import_records",
+        actions::SET_SHARDS_NODE => "set_shards_node",
+        actions::REBALANCE => "rebalance",
+        actions::ADD_NODE => "add_node",
+        actions::REMOVE_NODE => "remove_node",
+        actions::ADD_MACHINE => "add_machine",
+        actions::REMOVE_MACHINE => "remove_machine",
+        actions::SET_HIERARCHY => "set_hierarchy",
+        actions::SUBSCRIBE_CHANGES => "subscribe_changes",
+        actions::PUBLISH => "publish",
+        actions::SUBSCRIBE => "subscribe",
+        actions::PSUBSCRIBE => "psubscribe",
+        actions::UNSUBSCRIBE => "unsubscribe",
+        actions::SET_NOTIFICATIONS => "set_notifications",
+        actions::WATCH => "watch",
+        _ => "unknown",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+    for (bound, bucket) in BUCKETS.iter().zip(histogram.buckets.iter()) {
+        cumulative += bucket.load(Relaxed);
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative);
+    }
+    let count = histogram.count.load(Relaxed);
+    let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, count);
+    let braces = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+    let _ = writeln!(out, "{}_sum{} {}", name, braces, histogram.sum_nanos.load(Relaxed) as f64 / 1e9);
+    let _ = writeln!(out, "{}_count{} {}", name, braces, count);
+}
+
+/// Writes all metrics in the Prometheus text format. Key counts of tables are read now.
+pub fn render(storage: &Storage) -> String {
+    let metrics = &METRICS;
+    let mut out = String::with_capacity(4096);
+
+    header(&mut out, "dbms_requests_total", "counter", "Messages of the binary protocol by the action.");
+    for (action, histogram) in metrics.actions.iter().enumerate() {
+        if histogram.count() > 0 {
+            let _ = writeln!(out, "dbms_requests_total{{action=\"{}\",code=\"{}\"}} {}", action_name(action as u8), action, histogram.count());
+        }
+    }
+    header(&mut out, "dbms_request_duration_seconds", "histogram", "Time to handle a message of the binary protocol.");
+    for (action, histogram) in metrics.actions.iter().enumerate() {
+        if histogram.count() > 0 {
+            let labels = format!("action=\"{}\",code=\"{}\"", action_name(action as u8), action);
+            write_histogram(&mut out, "dbms_request_duration_seconds", &labels, histogram);
+        }
+    }
+
+    header(&mut out, "dbms_table_keys", "gauge", "Number of keys in the table.");
+    if let Ok(names) = storage.tables_names.read() {
+        for (table, name) in storage.tables.get().iter().zip(names.iter()) {
+            let _ = writeln!(out, "dbms_table_keys{{table=\"{}\"}} {}", escape_label(name), table.count());
+        }
+    }
+
+    header(&mut out, "dbms_log_written_bytes_total", "counter", "Bytes written to the log file.");
+    let _ = writeln!(out, "dbms_log_written_bytes_total {}", metrics.log_bytes_written.get());
+    header(&mut out, "dbms_log_flush_duration_seconds", "histogram", "Time to flush a log writer to the log file.");
+    write_histogram(&mut out, "dbms_log_flush_duration_seconds", "", &metrics.log_flush);
+
+    header(&mut out, "dbms_dump_duration_seconds", "histogram", "Time to dump all tables.");
+    write_histogram(&mut out, "dbms_dump_duration_seconds", "", &metrics.dump);
+    header(&mut out, "dbms_last_dump_timestamp_seconds", "gauge", "Unix time of the end of the last dump, 0 if there was no dump.");
+    let _ = writeln!(out, "dbms_last_dump_timestamp_seconds {}", metrics.last_dump_time.get());
+
+    header(&mut out, "dbms_active_connections", "gauge", "Open client connections by the protocol.");
+    let _ = writeln!(out, "dbms_active_connections{{protocol=\"binary\"}} {}", metrics.binary_connections.get());
+    let _ = writeln!(out, "dbms_active_connections{{protocol=\"http\"}} {}", metrics.http_connections.get());
+    let _ = writeln!(out, "dbms_active_connections{{protocol=\"resp\"}} {}", metrics.resp_connections.get());
+
+    header(&mut out, "dbms_cache_expirations_total", "counter", "Keys removed from cache tables, because they expired.");
+    let _ = writeln!(out, "dbms_cache_expirations_total {}", metrics.cache_expirations.get());
+
+    header(&mut out, "dbms_replication_lag_bytes", "gauge", "Bytes of the leader's log, that the follower hasn't applied yet.");
+    let _ = writeln!(out, "dbms_replication_lag_bytes {}", metrics.replication_lag_bytes.get());
+    header(&mut out, "dbms_replication_followers", "gauge", "Followers connected to the leader.");
+    let _ = writeln!(out, "dbms_replication_followers {}", metrics.replication_followers.get());
+
+    header(&mut out, "dbms_node_epoch", "gauge", "The epoch of the node, it is increased on every failover.");
+    let _ = writeln!(out, "dbms_node_epoch {}", metrics.node_epoch.get());
+    header(&mut out, "dbms_leader_changes_total", "counter", "Times this machine has seen a new leader of its node.");
+    let _ = writeln!(out, "dbms_leader_changes_total {}", metrics.leader_changes.get());
+    header(&mut out, "dbms_migrated_keys_total", "counter", "Keys, that this node has sent to other nodes with moved shards.");
+    let _ = writeln!(out, "dbms_migrated_keys_total {}", metrics.migrated_keys.get());
+
+    header(&mut out, "dbms_change_subscribers", "gauge", "Connections, that stream changes of tables.");
+    let _ = writeln!(out, "dbms_change_subscribers {}", metrics.change_subscribers.get());
+    header(&mut out, "dbms_pubsub_subscribers", "gauge", "Connections, that are subscribed to channels or patterns.");
+    let _ = writeln!(out, "dbms_pubsub_subscribers {}", metrics.pubsub_subscribers.get());
+    header(&mut out, "dbms_pubsub_messages_total", "counter", "Messages, that were published.");
+    let _ = writeln!(out, "dbms_pubsub_messages_total {}", metrics.pubsub_messages.get());
+
+    header(&mut out, "dbms_disk_cache_hits_total", "counter", "Reads of on-disk tables, that were answered from the cache.");
+    let _ = writeln!(out, "dbms_disk_cache_hits_total {}", metrics.disk_cache_hits.get());
+    header(&mut out, "dbms_disk_cache_misses_total", "counter", "Reads of on-disk tables, that went to the file.");
+    let _ = writeln!(out, "dbms_disk_cache_misses_total {}", metrics.disk_cache_misses.get());
+    header(&mut out, "dbms_disk_cache_bytes", "gauge", "Memory used by the cache of values of on-disk tables.");
+    let _ = writeln!(out, "dbms_disk_cache_bytes {}", storage.disk_cache.used_bytes());
+    header(&mut out, "dbms_bloom_filter_skips_total", "counter", "Lookups of files of disk-backed tables, that bloom filters have skipped.");
+    let _ = writeln!(out, "dbms_bloom_filter_skips_total {}", metrics.bloom_filter_skips.get());
+
+    out
+}
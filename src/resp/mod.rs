@@ -0,0 +1,142 @@
+//! Minimal RESP2/RESP3 codec. It is enough for the RESP listener: commands are arrays of bulk strings or inline commands.
+use std::io::{self, BufRead, Read, Write};
+
+/// We don't read bulk strings longer than it.
+pub const MAX_BULK_SIZE: usize = 64 * 1024 * 1024;
+/// We don't read commands with more arguments than it.
+const MAX_ARGUMENTS: usize = 1024 * 1024;
+/// We don't read lines (headers and inline commands) longer than it.
+const MAX_LINE_SIZE: usize = 64 * 1024;
+
+pub enum RespError {
+    Io(io::Error),
+    Protocol(&'static str),
+}
+
+pub enum Reply {
+    Simple(&'static str),
+    /// The message must start with the error prefix, like `ERR`.
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Null,
+    Array(Vec<Reply>),
+    /// RESP3 map. RESP2 clients get a flat array of keys and values.
+    Map(Vec<(Reply, Reply)>),
+}
+
+impl Reply {
+    pub fn ok() -> Self {
+        Reply::Simple("OK")
+    }
+
+    pub fn error(message: &str) -> Self {
+        Reply::Error(format!("ERR {}", message))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W, resp3: bool) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(writer, "+{}\r\n", s),
+            Reply::Error(s) => write!(writer, "-{}\r\n", s),
+            Reply::Integer(i) => write!(writer, ":{}\r\n", i),
+            Reply::Bulk(bytes) => {
+                write!(writer, "${}\r\n", bytes.len())?;
+                writer.write_all(bytes)?;
+                writer.write_all(b"\r\n")
+            }
+            Reply::Null => writer.write_all(if resp3 { b"_\r\n" } else { b"$-1\r\n" }),
+            Reply::Array(items) => {
+                write!(writer, "*{}\r\n", items.len())?;
+                for item in items.iter() {
+                    item.write_to(writer, resp3)?;
+                }
+                Ok(())
+            }
+            Reply::Map(pairs) => {
+                if resp3 {
+                    write!(writer, "%{}\r\n", pairs.len())?;
+                } else {
+                    write!(writer, "*{}\r\n", pairs.len() * 2)?;
+                }
+                for (key, value) in pairs.iter() {
+                    key.write_to(writer, resp3)?;
+                    value.write_to(writer, resp3)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reads the line without `\r\n`. Returns `None`, if the connection was closed before the line.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<Vec<u8>>, RespError> {
+    let mut line = Vec::with_capacity(16);
+    let read = reader.by_ref().take(MAX_LINE_SIZE as u64).read_until(b'\n', &mut line).map_err(RespError::Io)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(RespError::Protocol(if read >= MAX_LINE_SIZE { "line is too long" } else { "unexpected end of stream" }));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    Ok(Some(line))
+}
+
+fn parse_len(bytes: &[u8], max: usize) -> Result<usize, RespError> {
+    match std::str::from_utf8(bytes).ok().and_then(|s| s.parse::<usize>().ok()) {
+        Some(len) if len <= max => Ok(len),
+        Some(_) => Err(RespError::Protocol("length is too large")),
+        None => Err(RespError::Protocol("invalid length")),
+    }
+}
+
+/// Reads one command. Returns `None`, if the connection was closed before the command.
+pub fn read_command<R: BufRead>(reader: &mut R) -> Result<Option<Vec<Vec<u8>>>, RespError> {
+    loop {
+        let line = match read_line(reader)? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        if line.is_empty() {
+            continue;
+        }
+        if line[0] != b'*' {
+            // Inline command, like `PING` from telnet.
+            let arguments: Vec<Vec<u8>> = line.split(|byte| byte.is_ascii_whitespace())
+                .filter(|argument| !argument.is_empty())
+                .map(|argument| argument.to_vec())
+                .collect();
+            if arguments.is_empty() {
+                continue;
+            }
+            return Ok(Some(arguments));
+        }
+
+        let number = parse_len(&line[1..], MAX_ARGUMENTS)?;
+        let mut arguments = Vec::with_capacity(number);
+        for _ in 0..number {
+            let header = match read_line(reader)? {
+                Some(header) => header,
+                None => return Err(RespError::Protocol("unexpected end of stream")),
+            };
+            if header.first() != Some(&b'$') {
+                return Err(RespError::Protocol("expected a bulk string"));
+            }
+            let len = parse_len(&header[1..], MAX_BULK_SIZE)?;
+            let mut argument = vec![0; len + 2];
+            reader.read_exact(&mut argument).map_err(RespError::Io)?;
+            if !argument.ends_with(b"\r\n") {
+                return Err(RespError::Protocol("bulk string is not terminated with CRLF"));
+            }
+            argument.truncate(len);
+            arguments.push(argument);
+        }
+        if arguments.is_empty() {
+            continue;
+        }
+        return Ok(Some(arguments));
+    }
+}
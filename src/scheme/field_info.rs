@@ -1,3 +1,5 @@
+use crate::constants::errors::{self, Error};
+
 #[derive(Debug)]
 pub struct FieldInfo {
     pub size: usize,
@@ -13,10 +15,8 @@ impl FieldInfo {
     }
 }
 
-const UNKNOWN_FIELD_TYPE: &'static str = "Unknown field type";
-
 #[inline(always)]
-pub fn field_type_from_string(field_type: &str) -> Result<FieldType, &'static str> {
+pub fn field_type_from_string(field_type: &str) -> Result<FieldType, Error> {
     match field_type {
         "Byte" => Ok(FieldType::Byte),
         "Bool" => Ok(FieldType::Bool),
@@ -34,11 +34,12 @@ pub fn field_type_from_string(field_type: &str) -> Result<FieldType, &'static st
         "Float64" => Ok(FieldType::Float64),
         "String" => Ok(FieldType::String),
         "ByteSlice" => Ok(FieldType::ByteSlice),
-        _ => Err(UNKNOWN_FIELD_TYPE),
+        _ => Err(errors::UNKNOWN_FIELD_TYPE),
     }
 }
 
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum FieldType {
     Byte,
     Bool,
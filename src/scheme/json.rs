@@ -0,0 +1,186 @@
+use serde_json::{Map, Number, Value};
+use crate::{
+    constants::errors::{self, Error},
+    scheme::field_info::FieldType,
+    utils::bytes::uint
+};
+
+/// Encodes the JSON object to a value of the scheme. Numbers are little endian like all numbers in the protocol.
+/// 128-bit numbers can be strings, because JSON numbers can't keep them. ByteSlice is an array of bytes.
+///
+/// Every field of the scheme must be in the object and the object must not have other fields.
+pub fn value_from_json(fields: &[(String, FieldType)], json: &Value) -> Result<Vec<u8>, Error> {
+    let object = match json.as_object() {
+        Some(object) => object,
+        None => return Err(errors::VALUE_DOES_NOT_MATCH_SCHEME),
+    };
+    if object.len() != fields.len() {
+        return Err(if object.len() < fields.len() { errors::FIELD_IS_MISSING } else { errors::FIELD_IS_NOT_FOUND });
+    }
+
+    let mut value = Vec::with_capacity(64);
+    for (name, field_type) in fields.iter() {
+        match object.get(name) {
+            Some(field) => write_field(&mut value, *field_type, field)?,
+            None => return Err(errors::FIELD_IS_MISSING),
+        }
+    }
+    Ok(value)
+}
+
+fn write_field(buf: &mut Vec<u8>, field_type: FieldType, field: &Value) -> Result<(), Error> {
+    macro_rules! int {
+        ($t:ty, $get:ident) => {
+            match field.$get().and_then(|v| <$t>::try_from(v).ok()) {
+                Some(v) => buf.extend_from_slice(&v.to_le_bytes()),
+                None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+            }
+        };
+    }
+    macro_rules! big_int {
+        ($t:ty) => {
+            let parsed = match field {
+                Value::String(v) => v.parse::<$t>().ok(),
+                Value::Number(v) => v.to_string().parse::<$t>().ok(),
+                _ => None,
+            };
+            match parsed {
+                Some(v) => buf.extend_from_slice(&v.to_le_bytes()),
+                None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+            }
+        };
+    }
+
+    match field_type {
+        FieldType::Byte | FieldType::Uint8 => int!(u8, as_u64),
+        FieldType::Uint16 => int!(u16, as_u64),
+        FieldType::Uint32 => int!(u32, as_u64),
+        FieldType::Uint64 => int!(u64, as_u64),
+        FieldType::Int8 => int!(i8, as_i64),
+        FieldType::Int16 => int!(i16, as_i64),
+        FieldType::Int32 => int!(i32, as_i64),
+        FieldType::Int64 => int!(i64, as_i64),
+        FieldType::Uint128 => { big_int!(u128); }
+        FieldType::Int128 => { big_int!(i128); }
+        FieldType::Bool => match field.as_bool() {
+            Some(v) => buf.push(v as u8),
+            None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+        },
+        FieldType::Float32 => match field.as_f64() {
+            Some(v) => buf.extend_from_slice(&(v as f32).to_le_bytes()),
+            None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+        },
+        FieldType::Float64 => match field.as_f64() {
+            Some(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+        },
+        FieldType::String => match field.as_str() {
+            Some(v) => write_unsized(buf, v.as_bytes()),
+            None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+        },
+        FieldType::ByteSlice => {
+            let bytes = field.as_array().and_then(|items| {
+                items.iter().map(|item| item.as_u64().and_then(|v| u8::try_from(v).ok())).collect::<Option<Vec<u8>>>()
+            });
+            match bytes {
+                Some(bytes) => write_unsized(buf, &bytes),
+                None => return Err(errors::FIELD_HAS_WRONG_TYPE),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_unsized(buf: &mut Vec<u8>, bytes: &[u8]) {
+    let len = bytes.len();
+    if len < u16::MAX as usize {
+        buf.extend_from_slice(&[len as u8, (len >> 8) as u8]);
+    } else {
+        buf.extend_from_slice(&[255, 255, len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8]);
+    }
+    buf.extend_from_slice(bytes);
+}
+
+/// Reads the field with the length prefix, like [`crate::scheme::scheme::get_field`] returns it.
+///
+/// Returns the field without the prefix and the number of read bytes.
+pub fn read_prefixed_field(bytes: &[u8]) -> Option<(&[u8], usize)> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let (len, offset) = if bytes[1] < 255 || bytes[0] < 255 {
+        (uint::u16(&bytes[0..2]) as usize, 2)
+    } else {
+        if bytes.len() < 6 {
+            return None;
+        }
+        (uint::u32(&bytes[2..6]) as usize, 6)
+    };
+    if bytes.len() < offset + len {
+        return None;
+    }
+    Some((&bytes[offset..offset + len], offset + len))
+}
+
+/// Decodes the field without the length prefix.
+pub fn field_to_json(field_type: FieldType, bytes: &[u8]) -> Value {
+    macro_rules! le {
+        ($t:ty) => {
+            match bytes.try_into() {
+                Ok(bytes) => <$t>::from_le_bytes(bytes),
+                Err(_) => return Value::Null,
+            }
+        };
+    }
+    match field_type {
+        FieldType::Byte | FieldType::Uint8 => Value::from(le!(u8)),
+        FieldType::Bool => Value::from(le!(u8) != 0),
+        FieldType::Uint16 => Value::from(le!(u16)),
+        FieldType::Uint32 => Value::from(le!(u32)),
+        FieldType::Uint64 => Value::from(le!(u64)),
+        FieldType::Int8 => Value::from(le!(i8)),
+        FieldType::Int16 => Value::from(le!(i16)),
+        FieldType::Int32 => Value::from(le!(i32)),
+        FieldType::Int64 => Value::from(le!(i64)),
+        FieldType::Uint128 => Value::from(le!(u128).to_string()),
+        FieldType::Int128 => Value::from(le!(i128).to_string()),
+        FieldType::Float32 => Number::from_f64(le!(f32) as f64).map(Value::Number).unwrap_or(Value::Null),
+        FieldType::Float64 => Number::from_f64(le!(f64)).map(Value::Number).unwrap_or(Value::Null),
+        FieldType::String => Value::from(String::from_utf8_lossy(bytes).to_string()),
+        FieldType::ByteSlice => Value::from(bytes.to_vec()),
+    }
+}
+
+/// Decodes the value of the scheme to the JSON object. It is the reverse of [`value_from_json`].
+pub fn value_to_json(fields: &[(String, FieldType)], value: &[u8]) -> Result<Value, Error> {
+    let mut object = Map::new();
+    let mut offset = 0;
+    for (name, field_type) in fields.iter() {
+        let size = match field_type {
+            FieldType::String | FieldType::ByteSlice => None,
+            FieldType::Byte | FieldType::Bool | FieldType::Uint8 | FieldType::Int8 => Some(1),
+            FieldType::Uint16 | FieldType::Int16 => Some(2),
+            FieldType::Uint32 | FieldType::Int32 | FieldType::Float32 => Some(4),
+            FieldType::Uint64 | FieldType::Int64 | FieldType::Float64 => Some(8),
+            FieldType::Uint128 | FieldType::Int128 => Some(16),
+        };
+        let bytes = match size {
+            Some(size) => {
+                if value.len() < offset + size {
+                    return Err(errors::VALUE_DOES_NOT_MATCH_SCHEME);
+                }
+                offset += size;
+                &value[offset - size..offset]
+            }
+            None => match read_prefixed_field(&value[offset..]) {
+                Some((bytes, read)) => {
+                    offset += read;
+                    bytes
+                }
+                None => return Err(errors::VALUE_DOES_NOT_MATCH_SCHEME),
+            },
+        };
+        object.insert(name.clone(), field_to_json(*field_type, bytes));
+    }
+    Ok(Value::Object(object))
+}
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod scheme;
-mod field_info;
\ No newline at end of file
+pub mod field_info;
+pub mod json;
\ No newline at end of file
@@ -1,6 +1,8 @@
 use crate::{
     bin_types::BinValue,
-    scheme::field_info::{field_type_from_string, get_size, FieldInfo},
+    constants::errors::{self, Error},
+    scheme::field_info::{field_type_from_string, get_size, FieldInfo, FieldType},
+    utils::{bytes::uint, compression::Compression},
     writers::get_size_for_value_len
 };
 #[cfg(test)]
@@ -16,16 +18,24 @@ pub type Scheme = Box<[FieldInfo]>;
 pub struct SchemeJSON {
     pub sized_fields: Map<String, Value>,
     pub unsized_fields: Map<String, Value>,
+    /// The codec of values and dumps of the table, see [`crate::utils::compression`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression: Option<String>,
+    /// Values shorter than it aren't compressed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compression_threshold: Option<usize>,
 }
 
 /// Get JSON scheme with 2 fields: sized_fields and unsized_fields.
 ///
 /// sized_fields and unsized_fields are maps with key = name and value = type.
-pub fn scheme_from_bytes(data: &[u8]) -> Result<Scheme, &'static str> {
+/// The optional `compression` is checked too, see [`Compression::from_scheme_json`].
+pub fn scheme_from_bytes(data: &[u8]) -> Result<Scheme, Error> {
     let scheme_json: SchemeJSON = match serde_json::from_slice(data) {
         Ok(scheme_json) => scheme_json,
-        Err(_) => return Err("Scheme is not valid JSON"),
+        Err(_) => return Err(errors::SCHEME_IS_NOT_VALID_JSON),
     };
+    Compression::from_scheme_json(&scheme_json)?;
     let mut scheme =
         Vec::with_capacity(scheme_json.sized_fields.len() + scheme_json.unsized_fields.len());
 
@@ -33,7 +43,7 @@ pub fn scheme_from_bytes(data: &[u8]) -> Result<Scheme, &'static str> {
     let mut number_of_unsized_fields = 0;
     for (_key, field_type) in scheme_json.sized_fields {
         if !field_type.is_string() {
-            return Err("Fields type must be a string");
+            return Err(errors::FIELD_TYPE_IS_NOT_STRING);
         }
 
         let res = field_type_from_string(field_type.as_str().unwrap());
@@ -49,7 +59,7 @@ pub fn scheme_from_bytes(data: &[u8]) -> Result<Scheme, &'static str> {
 
     for (_key, field_type) in scheme_json.unsized_fields {
         if !field_type.is_string() {
-            return Err("Fields type must be a string");
+            return Err(errors::FIELD_TYPE_IS_NOT_STRING);
         }
 
         let res = field_type_from_string(field_type.as_str().unwrap());
@@ -65,10 +75,72 @@ pub fn scheme_from_bytes(data: &[u8]) -> Result<Scheme, &'static str> {
     return Ok(scheme.into_boxed_slice());
 }
 
+/// Returns names and types of fields in the same order as [`scheme_from_bytes`] puts them.
+/// Empty data is an empty scheme.
+pub fn named_fields_from_bytes(data: &[u8]) -> Result<Vec<(String, FieldType)>, Error> {
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    let scheme_json: SchemeJSON = match serde_json::from_slice(data) {
+        Ok(scheme_json) => scheme_json,
+        Err(_) => return Err(errors::SCHEME_IS_NOT_VALID_JSON),
+    };
+    let mut fields = Vec::with_capacity(scheme_json.sized_fields.len() + scheme_json.unsized_fields.len());
+    for (name, field_type) in scheme_json.sized_fields.into_iter().chain(scheme_json.unsized_fields) {
+        match field_type.as_str() {
+            Some(field_type) => fields.push((name, field_type_from_string(field_type)?)),
+            None => return Err(errors::FIELD_TYPE_IS_NOT_STRING),
+        }
+    }
+    Ok(fields)
+}
+
 pub fn empty_scheme() -> Scheme {
     vec![].into_boxed_slice()
 }
 
+/// Checks that all fields of the scheme are inside the value. [`get_field`] and [`get_fields`] don't check it,
+/// so we need to check every value from the user before we store it.
+///
+/// Empty scheme accepts any value.
+pub fn is_value_valid(value: &[u8], scheme: &Scheme) -> bool {
+    let mut offset = 0;
+    let mut number_of_unsized_fields = 0;
+    for info in scheme.iter() {
+        if info.size < 17 {
+            offset += info.size;
+        } else {
+            number_of_unsized_fields += 1;
+        }
+    }
+    if value.len() < offset {
+        return false;
+    }
+
+    let mut len_of_field;
+    for _ in 0..number_of_unsized_fields {
+        if value.len() < offset + 2 {
+            return false;
+        }
+        if value[offset + 1] < 255 || value[offset] < 255 {
+            len_of_field = value[offset] as usize | (value[offset + 1] as usize) << 8;
+            offset += 2;
+        } else {
+            if value.len() < offset + 6 {
+                return false;
+            }
+            len_of_field = uint::u32(&value[offset + 2..offset + 6]) as usize;
+            offset += 6;
+        }
+        offset += len_of_field;
+        if value.len() < offset {
+            return false;
+        }
+    }
+
+    true
+}
+
 #[inline(always)]
 pub fn get_field(value: &BinValue, scheme: &Scheme, number: usize) -> Vec<u8> {
     let info = &scheme[number];
@@ -183,6 +255,11 @@ pub fn get_fields(value: &BinValue, scheme: &Scheme, numbers: &[usize]) -> Vec<u
         }
 
         let necessary_to_read_len = size - 17;
+        if necessary_to_read_len < read {
+            // Fields can be requested in any order, so we have to start from the first unsized field again.
+            unsized_field_offset = offset;
+            read = 0;
+        }
         let mut len_of_field;
         // Here we read a few fields to find an offset to needed field.
         for _ in read..necessary_to_read_len {
@@ -251,6 +328,7 @@ pub fn get_fields(value: &BinValue, scheme: &Scheme, numbers: &[usize]) -> Vec<u
                 real_len,
             );
         }
+        written += real_len;
     }
 
     return response;
@@ -457,6 +535,8 @@ fn test_scheme_get() {
     let mut scheme_to_json: SchemeJSON = SchemeJSON {
         sized_fields: Map::with_capacity(10),
         unsized_fields: Map::with_capacity(100),
+        compression: None,
+        compression_threshold: None,
     };
     for i in 0..10 {
         scheme_to_json
@@ -580,6 +660,8 @@ fn test_scheme_update() {
     let mut scheme_to_json: SchemeJSON = SchemeJSON {
         sized_fields: Map::with_capacity(10),
         unsized_fields: Map::with_capacity(100),
+        compression: None,
+        compression_threshold: None,
     };
     for i in 0..10 {
         scheme_to_json
@@ -1,59 +1,417 @@
-use std::{env};
-use crate::info;
+//! Settings of the server.
+//!
+//! Every setting is read from, in order of priority: a command line flag (`--tcp-addr localhost:10000` or `--tcp-addr=localhost:10000`),
+//! an environment variable (`TCP_ADDR`), the TOML file (`--config dbms.toml` or `CONFIG_FILE`) and the default.
+//! The file has the same names as flags, but with underscores, like `tcp_addr = "localhost:10000"`.
+//!
+//! Every value is validated: a wrong value or an unknown setting is an error, not a silent fallback.
+//!
+//! Settings with [`Setting::is_reloadable`] can be changed at runtime by `CONFIG_SET` or by editing the file.
+//! Other settings need a restart.
+use std::{env, fmt, fs, process};
+use serde_json::{Map, Value};
+use crate::{
+    console::logger::{Filter, Format},
+    disk_storage::value_cache,
+    error, info,
+    lsm::bloom,
+    server::pubsub::DEFAULT_MAX_PENDING_BYTES,
+    storage::storage::{DEFAULT_DUMP_INTERVAL, DEFAULT_RETAINED_LOGS},
+    writers::DEFAULT_BACKLOG_BYTES
+};
 
+#[derive(Debug, PartialEq)]
+pub struct Setting {
+    pub name: &'static str,
+    pub env: &'static str,
+    pub is_reloadable: bool,
+    /// The value is never logged and never returned by `CONFIG_GET`.
+    pub is_secret: bool,
+}
+
+const fn setting(name: &'static str, env: &'static str, is_reloadable: bool) -> Setting {
+    Setting { name, env, is_reloadable, is_secret: false }
+}
+
+pub const SETTINGS: [Setting; 27] = [
+    setting("tcp_addr", "TCP_ADDR", false),
+    setting("unix_addr", "UNIX_ADDR", false),
+    Setting { name: "password", env: "PASSWORD", is_reloadable: false, is_secret: true },
+    setting("node_addr", "NODE_ADDR", false),
+    setting("leader_addr", "LEADER_ADDR", false),
+    Setting { name: "leader_password", env: "LEADER_PASSWORD", is_reloadable: false, is_secret: true },
+    setting("http_addr", "HTTP_ADDR", false),
+    setting("resp_addr", "RESP_ADDR", false),
+    setting("resp_table", "RESP_TABLE", false),
+    setting("metrics_addr", "METRICS_ADDR", false),
+    setting("slowlog_threshold_micros", "SLOWLOG_THRESHOLD", true),
+    setting("slowlog_max_len", "SLOWLOG_MAX_LEN", true),
+    setting("slowlog_file", "SLOWLOG_FILE", false),
+    setting("dump_interval", "DUMP_INTERVAL", true),
+    setting("retained_logs", "RETAINED_LOGS", true),
+    setting("log_level", "LOG_LEVEL", true),
+    setting("log_format", "LOG_FORMAT", true),
+    setting("http_max_body_bytes", "HTTP_MAX_BODY_BYTES", true),
+    setting("dump_on_shutdown", "DUMP_ON_SHUTDOWN", true),
+    setting("shutdown_timeout_secs", "SHUTDOWN_TIMEOUT", true),
+    setting("replication_backlog_bytes", "REPLICATION_BACKLOG_BYTES", true),
+    setting("heartbeat_interval_millis", "HEARTBEAT_INTERVAL", true),
+    setting("failover_timeout_millis", "FAILOVER_TIMEOUT", true),
+    setting("pubsub_max_pending_bytes", "PUBSUB_MAX_PENDING_BYTES", true),
+    setting("disk_cache_bytes", "DISK_CACHE_BYTES", true),
+    setting("bloom_false_positive_rate", "BLOOM_FALSE_POSITIVE_RATE", true),
+    setting("config_file", "CONFIG_FILE", false),
+];
+
+pub fn find_setting(name: &str) -> Option<&'static Setting> {
+    let name = name.replace('-', "_");
+    SETTINGS.iter().find(|setting| setting.name == name)
+}
+
+/// The error has the setting, the source and the reason, so it can be shown to the user as is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError(pub String);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Config {
     pub tcp_addr: String,
     pub unix_addr: String,
     pub password: String,
     pub node_addr: String,
+    /// Address of the leader. The server is a read-only follower of it, if the address is not empty.
+    /// Writes to all tables are replicated, including tables without logging and on-disk tables.
+    /// Writes to these tables aren't in the log files, so `SUBSCRIBE_CHANGES` doesn't stream them.
+    pub leader_addr: String,
+    /// The password of the leader, that the follower sends. Machines of a node send it to each other with heartbeats.
+    pub leader_password: String,
+    /// Address of the HTTP gateway. Empty address means, that the gateway is disabled.
+    pub http_addr: String,
+    /// Address of the RESP listener. Empty address means, that the listener is disabled.
+    pub resp_addr: String,
+    /// The table for RESP keys without the `table:` prefix. It is created on the first write.
+    pub resp_table: String,
+    /// Address of the metrics endpoint. Empty address means, that the endpoint is disabled.
+    pub metrics_addr: String,
+    /// Messages handled longer than this are recorded in the slow log.
+    pub slowlog_threshold_micros: u64,
+    /// Maximum number of entries in the in-memory slow log. The oldest entries are dropped.
+    pub slowlog_max_len: usize,
+    /// File, to which slow log entries are appended as JSON lines. Empty path means, that the file sink is disabled.
+    pub slowlog_file: String,
+    /// In minutes.
+    pub dump_interval: u32,
+    /// How many log files before the current one are kept after the dump, so subscribers of changes can resume from them.
+    pub retained_logs: u32,
+    /// A filter like `info,dbms::server=debug`, see [`crate::console::logger`].
+    pub log_level: String,
+    /// `text` or `json`.
+    pub log_format: String,
+    /// Bodies of HTTP requests are limited, so a client can't take all memory.
+    pub http_max_body_bytes: usize,
+    /// Dump the storage after the graceful shutdown. The `SHUTDOWN` action can override it.
+    pub dump_on_shutdown: bool,
+    /// How long the graceful shutdown waits for requests, that are handled now.
+    pub shutdown_timeout_secs: u64,
+    /// How many bytes of the log the leader keeps in memory for followers. A follower, that is further behind, gets a new snapshot.
+    pub replication_backlog_bytes: usize,
+    /// How often a machine sends heartbeats to other machines of its node.
+    pub heartbeat_interval_millis: u64,
+    /// A machine is dead, if it hasn't answered heartbeats for this time. The next machine replaces the dead leader.
+    pub failover_timeout_millis: u64,
+    /// A pub/sub subscriber, that has more bytes of messages waiting to be written, is disconnected.
+    pub pubsub_max_pending_bytes: usize,
+    /// Memory for values of on-disk tables, that were read from files. 0 disables the cache.
+    pub disk_cache_bytes: usize,
+    /// The rate of false positives of bloom filters of on-disk and LSM tables. Filters, that are built after the change, use it.
+    pub bloom_false_positive_rate: f64,
+    /// The TOML file, that is watched for changes. Empty path means, that there is no file.
+    pub config_file: String,
+    /// Values of environment variables and flags, that [`Config::load`] has read. They override the file, when it is reloaded.
+    pub overrides: Vec<(&'static Setting, String)>,
+}
+
+impl Default for Config {
+    /// Returns the config, that [`Config::new`] returns without the file, environment variables and flags.
+    fn default() -> Self {
+        Self {
+            tcp_addr: "localhost:10000".to_string(),
+            unix_addr: "localhost:10002".to_string(),
+            password: String::new(),
+            node_addr: String::new(),
+            leader_addr: String::new(),
+            leader_password: String::new(),
+            http_addr: String::new(),
+            resp_addr: String::new(),
+            resp_table: "resp".to_string(),
+            metrics_addr: String::new(),
+            slowlog_threshold_micros: 10000,
+            slowlog_max_len: 128,
+            slowlog_file: String::new(),
+            dump_interval: DEFAULT_DUMP_INTERVAL,
+            retained_logs: DEFAULT_RETAINED_LOGS,
+            log_level: "info".to_string(),
+            log_format: "text".to_string(),
+            http_max_body_bytes: 64 * 1024 * 1024,
+            dump_on_shutdown: true,
+            shutdown_timeout_secs: 30,
+            replication_backlog_bytes: DEFAULT_BACKLOG_BYTES,
+            heartbeat_interval_millis: 1000,
+            failover_timeout_millis: 5000,
+            pubsub_max_pending_bytes: DEFAULT_MAX_PENDING_BYTES,
+            disk_cache_bytes: value_cache::DEFAULT_CAPACITY,
+            bloom_false_positive_rate: bloom::DEFAULT_FALSE_POSITIVE_RATE,
+            config_file: String::new(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+fn invalid(setting: &Setting, value: &str, reason: &str) -> ConfigError {
+    if setting.is_secret {
+        return ConfigError(format!("invalid value of the setting \"{}\": {}", setting.name, reason));
+    }
+    ConfigError(format!("invalid value \"{}\" of the setting \"{}\": {}", value, setting.name, reason))
+}
+
+fn parse_number<T: std::str::FromStr + PartialOrd + fmt::Display>(setting: &Setting, value: &str, min: T, max: T) -> Result<T, ConfigError> {
+    match value.trim().parse::<T>() {
+        Ok(number) if number >= min && number <= max => Ok(number),
+        _ => Err(invalid(setting, value, &format!("must be a number from {} to {}", min, max)))
+    }
+}
+
+/// Checks, that the address is like `host:port`. Empty address is allowed, if the listener is optional.
+fn parse_addr(setting: &Setting, value: &str, is_optional: bool) -> Result<String, ConfigError> {
+    if value.is_empty() && is_optional {
+        return Ok(String::new());
+    }
+    match value.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(value.to_string()),
+        _ => Err(invalid(setting, value, "must be an address like \"host:port\""))
+    }
+}
+
+fn parse_bool(setting: &Setting, value: &str) -> Result<bool, ConfigError> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "1" => Ok(true),
+        "false" | "no" | "0" => Ok(false),
+        _ => Err(invalid(setting, value, "must be true or false"))
+    }
+}
+
+fn parse_not_empty(setting: &Setting, value: &str) -> Result<String, ConfigError> {
+    if value.is_empty() {
+        return Err(invalid(setting, value, "must not be empty"));
+    }
+    Ok(value.to_string())
 }
 
 impl Config {
+    /// Reads the config from environment variables and the file. Exits the process, if the config is wrong.
     pub fn new() -> Self {
-        let tcp_addr = match env::var("TCP_ADDR") {
-            Ok(value) => {
-                info!("The address was set to: {} using the environment variable \"TCP_ADDR\"", value);
-                value.parse().unwrap_or("localhost:10000".to_string())
-            },
-            Err(_) => {
-                info!("The address was not set using the environment variable \"TCP_ADDR\", setting it to \"localhost:10000\"");
-                "localhost:10000".to_string()
+        Self::load_or_exit(Vec::new())
+    }
+
+    /// Like [`Config::new`], but flags of the process override other sources.
+    pub fn from_args() -> Self {
+        Self::load_or_exit(env::args().skip(1).collect())
+    }
+
+    fn load_or_exit(args: Vec<String>) -> Self {
+        match Self::load(args, |name| env::var(name).ok()) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Wrong config: {}", e);
+                process::exit(2);
             }
-        };
-
-        let unix_addr = match env::var("UNIX_ADDR") {
-            Ok(value) => {
-                info!("The address was set to: {} using the environment variable \"UNIX_ADDR\"", value);
-                value.parse().unwrap_or("localhost:10002".to_string())
-            },
-            Err(_) => {
-                info!("The address was not set using the environment variable \"UNIX_PORT\", setting it to \"localhost:10002\"");
-                "localhost:10002".to_string()
+        }
+    }
+
+    /// Reads the config from `args` (without the program name), variables returned by `env` and the file.
+    pub fn load<I: IntoIterator<Item = String>, E: Fn(&str) -> Option<String>>(args: I, env: E) -> Result<Self, ConfigError> {
+        let mut flags = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) => flag,
+                None => return Err(ConfigError(format!("unexpected argument \"{}\", flags look like \"--tcp-addr localhost:10000\"", arg)))
+            };
+            let (name, value) = match flag.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => match args.next() {
+                    Some(value) => (flag.to_string(), value),
+                    None => return Err(ConfigError(format!("the flag \"--{}\" has no value", flag)))
+                }
+            };
+            // `--config` is a short form of `--config-file`.
+            let setting = if name == "config" { find_setting("config_file") } else { find_setting(&name) };
+            let setting = setting.ok_or_else(|| ConfigError(format!("unknown flag \"--{}\"", name)))?;
+            flags.push((setting, value));
+        }
+
+        let mut overrides = Vec::new();
+        for setting in SETTINGS.iter() {
+            if let Some(value) = env(setting.env) {
+                overrides.push((setting, value, format!("the environment variable \"{}\"", setting.env)));
             }
-        };
-
-        let password = match env::var("PASSWORD") {
-            Ok(value) => {
-                info!("The password was set to: {} using the environment variable \"PASSWORD\"", value);
-                value
-            },
-            Err(_) => {
-                info!("The password was not set using the environment variable \"PASSWORD\", setting it to \"\" (empty string)");
-                String::new()
+        }
+        for (setting, value) in flags {
+            overrides.push((setting, value, format!("the flag \"--{}\"", setting.name.replace('_', "-"))));
+        }
+
+        let mut config = Self::default();
+        // The file path itself can come only from the environment and flags.
+        for (setting, value, source) in overrides.iter().filter(|(setting, _, _)| setting.name == "config_file") {
+            config.set_from(setting, value, source)?;
+        }
+        if !config.config_file.is_empty() {
+            let path = config.config_file.clone();
+            for (setting, value) in Self::read_file(&path)? {
+                config.set_from(setting, &value, &format!("the file \"{}\"", path))?;
             }
-        };
-
-        let node_addr = match env::var("NODE_ADDR") {
-            Ok(value) => {
-                info!("The node address was set to: {} using the environment variable \"NODE_ADDR\"", value);
-                value
-            },
-            Err(_) => {
-                info!("The node address was not set using the environment variable \"NODE_ADDR\". Single mode is used.");
-                String::new()
+        }
+        for (setting, value, source) in overrides.iter() {
+            config.set_from(setting, value, source)?;
+        }
+        config.overrides = overrides.into_iter().map(|(setting, value, _)| (setting, value)).collect();
+        Ok(config)
+    }
+
+    /// Reads the file again and applies the same environment variables and flags. Returns the new config.
+    pub fn reload(&self) -> Result<Self, ConfigError> {
+        let mut config = Self { config_file: self.config_file.clone(), ..Self::default() };
+        for (setting, value) in Self::read_file(&self.config_file)? {
+            config.set(setting.name, &value).map_err(|e| ConfigError(format!("{} in the file \"{}\"", e, self.config_file)))?;
+        }
+        for (setting, value) in self.overrides.iter() {
+            config.set(setting.name, value)?;
+        }
+        config.overrides = self.overrides.clone();
+        Ok(config)
+    }
+
+    /// Returns settings of the TOML file. Numbers and booleans are converted to strings, so they are parsed like flags.
+    pub fn read_file(path: &str) -> Result<Vec<(&'static Setting, String)>, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError(format!("can't read the config file \"{}\": {}", path, e)))?;
+        let table: toml::Table = text.parse().map_err(|e| ConfigError(format!("the config file \"{}\" is not valid TOML: {}", path, e)))?;
+        let mut settings = Vec::with_capacity(table.len());
+        for (name, value) in table {
+            let setting = match find_setting(&name) {
+                Some(setting) if setting.name != "config_file" => setting,
+                _ => return Err(ConfigError(format!("unknown setting \"{}\" in the config file \"{}\"", name, path)))
+            };
+            let value = match value {
+                toml::Value::String(value) => value,
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                _ => return Err(ConfigError(format!("the setting \"{}\" in the config file \"{}\" must be a string or a number", name, path)))
+            };
+            settings.push((setting, value));
+        }
+        Ok(settings)
+    }
+
+    fn set_from(&mut self, setting: &Setting, value: &str, source: &str) -> Result<(), ConfigError> {
+        self.set(setting.name, value).map_err(|e| ConfigError(format!("{} from {}", e, source)))?;
+        if setting.is_secret {
+            info!("The setting \"{}\" was set using {}", setting.name, source);
+        } else {
+            info!("The setting \"{}\" was set to: {} using {}", setting.name, value, source);
+        }
+        Ok(())
+    }
+
+    /// Parses and validates the value and sets it. The config isn't changed, if the value is wrong.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        let setting = find_setting(name).ok_or_else(|| ConfigError(format!("unknown setting \"{}\"", name)))?;
+        match setting.name {
+            "tcp_addr" => self.tcp_addr = parse_addr(setting, value, false)?,
+            "unix_addr" => self.unix_addr = parse_not_empty(setting, value)?,
+            "password" => self.password = value.to_string(),
+            "node_addr" => self.node_addr = parse_addr(setting, value, true)?,
+            "leader_addr" => self.leader_addr = parse_addr(setting, value, true)?,
+            "leader_password" => self.leader_password = value.to_string(),
+            "http_addr" => self.http_addr = parse_addr(setting, value, true)?,
+            "resp_addr" => self.resp_addr = parse_addr(setting, value, true)?,
+            "resp_table" => self.resp_table = parse_not_empty(setting, value)?,
+            "metrics_addr" => self.metrics_addr = parse_addr(setting, value, true)?,
+            "slowlog_threshold_micros" => self.slowlog_threshold_micros = parse_number(setting, value, 0, u64::MAX)?,
+            "slowlog_max_len" => self.slowlog_max_len = parse_number(setting, value, 0, 1_000_000)?,
+            "slowlog_file" => self.slowlog_file = value.to_string(),
+            "dump_interval" => self.dump_interval = parse_number(setting, value, 1, u32::MAX)?,
+            "retained_logs" => self.retained_logs = parse_number(setting, value, 0, 10_000)?,
+            "log_level" => {
+                Filter::try_parse(value).map_err(|e| invalid(setting, value, &e))?;
+                self.log_level = value.to_string();
+            }
+            "log_format" => {
+                Format::parse(value).ok_or_else(|| invalid(setting, value, "must be \"text\" or \"json\""))?;
+                self.log_format = value.trim().to_lowercase();
             }
-        };
+            "http_max_body_bytes" => self.http_max_body_bytes = parse_number(setting, value, 1, u32::MAX as usize)?,
+            "dump_on_shutdown" => self.dump_on_shutdown = parse_bool(setting, value)?,
+            "shutdown_timeout_secs" => self.shutdown_timeout_secs = parse_number(setting, value, 0, 24 * 60 * 60)?,
+            "replication_backlog_bytes" => self.replication_backlog_bytes = parse_number(setting, value, 1024, u32::MAX as usize)?,
+            "heartbeat_interval_millis" => self.heartbeat_interval_millis = parse_number(setting, value, 10, 60_000)?,
+            "failover_timeout_millis" => self.failover_timeout_millis = parse_number(setting, value, 50, 600_000)?,
+            "pubsub_max_pending_bytes" => self.pubsub_max_pending_bytes = parse_number(setting, value, 1024, u32::MAX as usize)?,
+            "disk_cache_bytes" => self.disk_cache_bytes = parse_number(setting, value, 0, usize::MAX)?,
+            "bloom_false_positive_rate" => self.bloom_false_positive_rate = parse_number(setting, value, 0.000001, 0.5)?,
+            "config_file" => self.config_file = value.to_string(),
+            _ => unreachable!("every setting is handled")
+        }
+        Ok(())
+    }
+
+    /// Returns the value of the setting. Secrets are `null`.
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let setting = find_setting(name)?;
+        if setting.is_secret {
+            return Some(Value::Null);
+        }
+        Some(match setting.name {
+            "tcp_addr" => Value::from(self.tcp_addr.as_str()),
+            "unix_addr" => Value::from(self.unix_addr.as_str()),
+            "node_addr" => Value::from(self.node_addr.as_str()),
+            "leader_addr" => Value::from(self.leader_addr.as_str()),
+            "http_addr" => Value::from(self.http_addr.as_str()),
+            "resp_addr" => Value::from(self.resp_addr.as_str()),
+            "resp_table" => Value::from(self.resp_table.as_str()),
+            "metrics_addr" => Value::from(self.metrics_addr.as_str()),
+            "slowlog_threshold_micros" => Value::from(self.slowlog_threshold_micros),
+            "slowlog_max_len" => Value::from(self.slowlog_max_len),
+            "slowlog_file" => Value::from(self.slowlog_file.as_str()),
+            "dump_interval" => Value::from(self.dump_interval),
+            "retained_logs" => Value::from(self.retained_logs),
+            "log_level" => Value::from(self.log_level.as_str()),
+            "log_format" => Value::from(self.log_format.as_str()),
+            "http_max_body_bytes" => Value::from(self.http_max_body_bytes),
+            "dump_on_shutdown" => Value::from(self.dump_on_shutdown),
+            "shutdown_timeout_secs" => Value::from(self.shutdown_timeout_secs),
+            "replication_backlog_bytes" => Value::from(self.replication_backlog_bytes),
+            "heartbeat_interval_millis" => Value::from(self.heartbeat_interval_millis),
+            "failover_timeout_millis" => Value::from(self.failover_timeout_millis),
+            "pubsub_max_pending_bytes" => Value::from(self.pubsub_max_pending_bytes),
+            "disk_cache_bytes" => Value::from(self.disk_cache_bytes),
+            "bloom_false_positive_rate" => Value::from(self.bloom_false_positive_rate),
+            "config_file" => Value::from(self.config_file.as_str()),
+            _ => unreachable!("every setting is handled")
+        })
+    }
 
-        Self { tcp_addr, password, unix_addr, node_addr }
+    /// Returns all settings except secrets.
+    pub fn to_json(&self) -> Value {
+        let mut settings = Map::with_capacity(SETTINGS.len());
+        for setting in SETTINGS.iter().filter(|setting| !setting.is_secret) {
+            settings.insert(setting.name.to_string(), self.get(setting.name).unwrap_or(Value::Null));
+        }
+        Value::Object(settings)
     }
 }
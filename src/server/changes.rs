@@ -0,0 +1,252 @@
+//! Change data capture: a stream of inserts, sets and deletes, that is read from the log files.
+//!
+//! A client sends `[SUBSCRIBE_CHANGES, flags, sequence number (8 bytes), tables (2 bytes each)]`. No tables means all tables.
+//! The flag [`WITH_OLD_VALUES`] asks for old values. The server answers with frames, that are messages of the binary protocol:
+//!
+//! - `[DONE, CHANGES, changes]`, where every change is [`action` (`INSERT`, `SET` or `DELETE`), `sequence number` (8 bytes),
+//!   `table` (2 bytes), `key length` (2 bytes), `key`, `value length` (4 bytes), `value`, `old value length` (4 bytes), `old value`].
+//!   The length [`NO_VALUE`] means, that there is no value, like the new value of a delete;
+//! - `[DONE, HEARTBEAT, sequence number (8 bytes)]`: is sent first and then every [`HEARTBEAT_INTERVAL`], if there are no changes;
+//! - an error, then the server closes the connection.
+//!
+//! The sequence number is a position in the log: the number of the `log{N}.bin` file in the high 24 bits and the offset in the file
+//! in the low 40 bits. The sequence number of a change is the position after its record, so the client resumes from the last one,
+//! that it has handled. [`FROM_OLDEST`] starts from the oldest retained file and [`FROM_NOW`] starts from the end of the log.
+//! The dump starts a new file and keeps `retained_logs` files before it (none by default), so the client can resume after that many dumps.
+//!
+//! The log has only new values, so old values are known only for keys, that the stream has already seen since it started.
+//! Tables without logging and on-disk tables don't write the log, so their changes aren't streamed. Followers don't write the log too.
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::atomic::Ordering::SeqCst,
+    thread,
+    time::{Duration, Instant}
+};
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors::{self, Error}},
+    metrics::METRICS,
+    server::{replication::write_frame, server::Server},
+    storage::{log_record::{self, LogRecord}, storage::Storage},
+    stream::Stream,
+    utils::bytes::uint,
+    warn
+};
+
+pub const CHANGES: u8 = 0;
+pub const HEARTBEAT: u8 = 1;
+
+pub const WITH_OLD_VALUES: u8 = 1;
+pub const FROM_OLDEST: u64 = 0;
+pub const FROM_NOW: u64 = u64::MAX;
+pub const NO_VALUE: u32 = u32::MAX;
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the end of the log is checked for new records.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+/// Changes are sent in frames of this size at most, if changes are smaller.
+const MAX_FRAME_BYTES: usize = 256 * 1024;
+const OFFSET_BITS: u32 = 40;
+
+pub fn sequence_number(log_number: u32, offset: u64) -> u64 {
+    ((log_number as u64) << OFFSET_BITS) | offset
+}
+
+/// Returns the number of the log file and the offset in it.
+pub fn split_sequence_number(sequence_number: u64) -> (u32, u64) {
+    ((sequence_number >> OFFSET_BITS) as u32, sequence_number & ((1 << OFFSET_BITS) - 1))
+}
+
+/// The last value of every key, that the stream has seen, by the table and the key.
+type OldValues = HashMap<(u16, Vec<u8>), Vec<u8>>;
+
+/// The log file, that is read now.
+struct Cursor {
+    number: u32,
+    file: File,
+    /// The offset of the first byte of `pending`, it is the end of the last decoded record.
+    offset: u64,
+    /// Bytes of a record, that isn't read whole yet.
+    pending: Vec<u8>,
+}
+
+impl Cursor {
+    fn open(storage: &Storage, sequence_number: u64) -> Result<Self, Error> {
+        let (number, offset) = match sequence_number {
+            FROM_NOW => {
+                let log_file = storage.log_file.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+                let len = log_file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                (storage.log_number.load(SeqCst), len)
+            }
+            FROM_OLDEST => {
+                let mut number = storage.log_number.load(SeqCst);
+                while number > 0 && storage.log_file_path(number - 1).exists() {
+                    number -= 1;
+                }
+                (number, 0)
+            }
+            sequence_number => split_sequence_number(sequence_number)
+        };
+        if number > storage.log_number.load(SeqCst) {
+            return Err(errors::SEQUENCE_NUMBER_IS_NOT_RETAINED);
+        }
+        let mut file = File::open(storage.log_file_path(number)).map_err(|_| errors::SEQUENCE_NUMBER_IS_NOT_RETAINED)?;
+        let len = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        if offset > len || file.seek(SeekFrom::Start(offset)).is_err() {
+            return Err(errors::SEQUENCE_NUMBER_IS_NOT_RETAINED);
+        }
+        Ok(Self { number, file, offset, pending: Vec::new() })
+    }
+
+    fn sequence_number(&self) -> u64 {
+        sequence_number(self.number, self.offset)
+    }
+}
+
+/// Appends the change, if it is a write to one of `tables`. Old values are remembered, if `old_values` is given.
+fn push_change(frame: &mut Vec<u8>, record: LogRecord, sequence_number: u64, tables: &HashSet<u16>, old_values: Option<&mut OldValues>) {
+    let (action, table, key, value) = match record {
+        LogRecord::Insert { table, key, value } => (actions::INSERT, table, key, Some(value)),
+        LogRecord::Set { table, key, value } => (actions::SET, table, key, Some(value)),
+        LogRecord::Delete { table, key } => (actions::DELETE, table, key, None),
+        LogRecord::CreateTable { .. } => return
+    };
+    if !tables.is_empty() && !tables.contains(&table) {
+        return;
+    }
+    let old_value = old_values.and_then(|old_values| match value {
+        Some(value) => old_values.insert((table, key.to_vec()), value.to_vec()),
+        None => old_values.remove(&(table, key.to_vec()))
+    });
+    frame.push(action);
+    frame.extend_from_slice(&uint::u64tob(sequence_number));
+    frame.extend_from_slice(&uint::u16tob(table));
+    frame.extend_from_slice(&uint::u16tob(key.len() as u16));
+    frame.extend_from_slice(key);
+    for value in [value, old_value.as_deref()] {
+        match value {
+            Some(value) => {
+                frame.extend_from_slice(&uint::u32tob(value.len() as u32));
+                frame.extend_from_slice(value);
+            }
+            None => frame.extend_from_slice(&uint::u32tob(NO_VALUE))
+        }
+    }
+}
+
+fn write_heartbeat<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    sequence_number: u64
+) -> bool {
+    let mut frame = vec![actions::DONE, HEARTBEAT];
+    frame.extend_from_slice(&uint::u64tob(sequence_number));
+    write_frame(connection, &frame)
+}
+
+/// Streams changes, until the client disconnects or the shutdown is requested.
+pub fn subscribe_changes<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 10 || !(message.len() - 10).is_multiple_of(2) {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let storage = server.storage;
+    let tables: HashSet<u16> = message[10..].chunks_exact(2).map(uint::u16).collect();
+    let mut old_values = if message[1] & WITH_OLD_VALUES != 0 { Some(HashMap::new()) } else { None };
+    let mut cursor = match Cursor::open(storage, uint::u64(&message[2..10])) {
+        Ok(cursor) => cursor,
+        Err(e) => return connection.write_error(e)
+    };
+    let _subscriber = METRICS.change_subscribers.track();
+    if !write_heartbeat(connection, cursor.sequence_number()) {
+        return Status::Closed;
+    }
+
+    let mut chunk = vec![0u8; 64 * 1024];
+    let mut frame = Vec::with_capacity(MAX_FRAME_BYTES + 1024);
+    let mut sent_at = Instant::now();
+    while !server.shutdown.is_requested() {
+        let read = match Read::read(&mut cursor.file, &mut chunk) {
+            Ok(read) => read,
+            Err(e) => {
+                warn!("Can't read the log file {} for changes: {}", cursor.number, e);
+                return Status::Closed;
+            }
+        };
+        if read > 0 {
+            cursor.pending.extend_from_slice(&chunk[..read]);
+            frame.clear();
+            frame.extend_from_slice(&[actions::DONE, CHANGES]);
+            let mut decoded = 0;
+            loop {
+                match log_record::decode(&cursor.pending[decoded..]) {
+                    Ok(Some((record, len))) => {
+                        decoded += len;
+                        let sequence_number = sequence_number(cursor.number, cursor.offset + decoded as u64);
+                        push_change(&mut frame, record, sequence_number, &tables, old_values.as_mut());
+                        if frame.len() >= MAX_FRAME_BYTES {
+                            if !write_frame(connection, &frame) {
+                                return Status::Closed;
+                            }
+                            frame.truncate(2);
+                            sent_at = Instant::now();
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(action) => {
+                        warn!("Unknown record {} in the log file {} at the offset {}", action, cursor.number, cursor.offset + decoded as u64);
+                        let _ = connection.write_error(errors::LOG_RECORD_IS_NOT_VALID);
+                        return Status::Closed;
+                    }
+                }
+            }
+            cursor.pending.drain(..decoded);
+            cursor.offset += decoded as u64;
+            if frame.len() > 2 {
+                if !write_frame(connection, &frame) {
+                    return Status::Closed;
+                }
+                sent_at = Instant::now();
+            }
+            continue;
+        }
+
+        // The end of the file. Nothing is written now, while the log file is locked, so the length is the length of whole writes,
+        // and the file isn't changed to the next one.
+        let (log_number, len) = {
+            let _log_file = storage.log_file.file.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let len = cursor.file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+            (storage.log_number.load(SeqCst), len)
+        };
+        if len > cursor.offset + cursor.pending.len() as u64 {
+            continue;
+        }
+        if log_number > cursor.number {
+            if !cursor.pending.is_empty() {
+                warn!("The log file {} ends in the middle of a record", cursor.number);
+                let _ = connection.write_error(errors::LOG_RECORD_IS_NOT_VALID);
+                return Status::Closed;
+            }
+            cursor = match Cursor::open(storage, sequence_number(cursor.number + 1, 0)) {
+                Ok(cursor) => cursor,
+                Err(e) => {
+                    let _ = connection.write_error(e);
+                    return Status::Closed;
+                }
+            };
+            continue;
+        }
+        if sent_at.elapsed() >= HEARTBEAT_INTERVAL {
+            if !write_heartbeat(connection, cursor.sequence_number()) {
+                return Status::Closed;
+            }
+            sent_at = Instant::now();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    Status::Closed
+}
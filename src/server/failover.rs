@@ -0,0 +1,327 @@
+//! Automatic failover within a node.
+//!
+//! Machines of a node are ordered like in the hierarchy, and the leftmost one is the leader at the start.
+//! Every machine sends `[HEARTBEAT, epoch (8 bytes), sender length (2 bytes), sender, leader]` to other machines
+//! of its node (see [`Node::get_other_machines_addr`](crate::node::Node::get_other_machines_addr))
+//! every `heartbeat_interval_millis` and gets `[DONE, epoch (8 bytes), leader]`.
+//!
+//! The epoch is [`Node::version`](crate::node::Node::version). A machine, that sees a greater epoch, takes the leader of it.
+//! So a stale leader steps down after the first heartbeat with the new leader, and its writes are rejected from then on.
+//! If epochs are equal, but leaders are different, the leader, that is earlier in the order, wins.
+//!
+//! If the leader hasn't answered and sent heartbeats for `failover_timeout_millis`, the next alive machine after it
+//! increases the epoch and becomes the leader. Every machine chooses the same one, if they see the same alive machines.
+//!
+//! Other machines follow the leader (see [`crate::server::replication`]) and answer writes with
+//! [`NOT_THE_LEADER`](errors::NOT_THE_LEADER), that has the address of the leader instead of the message.
+use std::{
+    collections::HashMap,
+    net::TcpStream,
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicU64, Ordering::SeqCst}},
+    thread,
+    time::{Duration, Instant}
+};
+use serde_json::{json, Value};
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors},
+    metrics::METRICS,
+    server::{replication, server::Server},
+    stream::Stream,
+    utils::bytes::uint,
+    debug, info, warn
+};
+
+pub struct Failover {
+    this_addr: RwLock<String>,
+    /// Machines of the node in the order of the hierarchy with this machine.
+    machines: RwLock<Vec<String>>,
+    /// The epoch is changed only with this lock.
+    leader: Mutex<String>,
+    /// When the machine has answered a heartbeat or sent one. All machines are seen at the start.
+    last_seen: Mutex<HashMap<String, Instant>>,
+    heartbeat_interval_millis: AtomicU64,
+    timeout_millis: AtomicU64,
+}
+
+impl Default for Failover {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Failover {
+    pub fn new() -> Self {
+        Self {
+            this_addr: RwLock::new(String::new()),
+            machines: RwLock::new(Vec::new()),
+            leader: Mutex::new(String::new()),
+            last_seen: Mutex::new(HashMap::new()),
+            heartbeat_interval_millis: AtomicU64::new(1000),
+            timeout_millis: AtomicU64::new(5000),
+        }
+    }
+
+    pub fn set_timeouts(&self, heartbeat_interval: Duration, timeout: Duration) {
+        self.heartbeat_interval_millis.store(heartbeat_interval.as_millis() as u64, SeqCst);
+        self.timeout_millis.store(timeout.as_millis() as u64, SeqCst);
+    }
+
+    fn heartbeat_interval(&self) -> Duration {
+        Duration::from_millis(self.heartbeat_interval_millis.load(SeqCst))
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(self.timeout_millis.load(SeqCst))
+    }
+
+    /// Sets machines of the node. The first one is the leader. Returns `true`, if there are other machines.
+    pub(crate) fn start(&self, this_addr: &str, machines: Vec<String>) -> bool {
+        let now = Instant::now();
+        if let Ok(mut last_seen) = self.last_seen.lock() {
+            *last_seen = machines.iter().map(|addr| (addr.clone(), now)).collect();
+        }
+        if let Ok(mut leader) = self.leader.lock() {
+            *leader = machines.first().cloned().unwrap_or_else(|| this_addr.to_string());
+        }
+        if let Ok(mut addr) = self.this_addr.write() {
+            *addr = this_addr.to_string();
+        }
+        let has_other_machines = machines.len() > 1;
+        if let Ok(mut current) = self.machines.write() {
+            *current = machines;
+        }
+        has_other_machines
+    }
+
+    /// Changes machines of the node, when the hierarchy is changed. New machines are seen now.
+    fn set_machines(&self, machines: &[String]) {
+        let now = Instant::now();
+        if let Ok(mut last_seen) = self.last_seen.lock() {
+            last_seen.retain(|addr, _| machines.contains(addr));
+            for addr in machines.iter() {
+                last_seen.entry(addr.clone()).or_insert(now);
+            }
+        }
+        if let Ok(mut current) = self.machines.write() {
+            *current = machines.to_vec();
+        }
+    }
+
+    fn this_addr(&self) -> String {
+        self.this_addr.read().map(|addr| addr.clone()).unwrap_or_default()
+    }
+
+    fn machines(&self) -> Vec<String> {
+        self.machines.read().map(|machines| machines.clone()).unwrap_or_default()
+    }
+
+    pub fn leader(&self) -> String {
+        self.leader.lock().map(|leader| leader.clone()).unwrap_or_default()
+    }
+
+    /// A machine without other machines in the node is always the leader.
+    #[inline(always)]
+    pub fn is_leader(&self) -> bool {
+        if self.machines.read().map(|machines| machines.len() <= 1).unwrap_or(true) {
+            return true;
+        }
+        self.leader() == self.this_addr()
+    }
+
+    fn seen(&self, addr: &str) {
+        if let Ok(mut last_seen) = self.last_seen.lock() {
+            if let Some(time) = last_seen.get_mut(addr) {
+                *time = Instant::now();
+            }
+        }
+    }
+
+    fn is_alive(&self, addr: &str) -> bool {
+        let timeout = self.timeout();
+        self.last_seen.lock().ok()
+            .and_then(|last_seen| last_seen.get(addr).map(|time| time.elapsed() < timeout))
+            .unwrap_or(false)
+    }
+
+    pub fn report(&self, server: &Server) -> Value {
+        let this_addr = self.this_addr();
+        let last_seen = self.last_seen.lock().map(|last_seen| last_seen.clone()).unwrap_or_default();
+        let machines: Vec<Value> = self.machines().iter().map(|addr| json!({
+            "addr": addr,
+            "is_alive": *addr == this_addr || self.is_alive(addr),
+            "last_seen_millis": last_seen.get(addr).map(|time| time.elapsed().as_millis() as u64),
+        })).collect();
+        json!({
+            "epoch": server.node.version.load(SeqCst),
+            "leader": self.leader(),
+            "is_leader": self.is_leader(),
+            "machines": machines,
+        })
+    }
+}
+
+/// Takes the leader of the epoch, if the epoch is newer. Leaders, that aren't machines of the node, are ignored.
+fn observe(server: &Server, epoch: u64, leader: &str) {
+    let failover = &server.failover;
+    let machines = failover.machines();
+    let position = match machines.iter().position(|addr| addr == leader) {
+        Some(position) => position,
+        None => return
+    };
+    let mut current = match failover.leader.lock() {
+        Ok(current) => current,
+        Err(_) => return
+    };
+    let current_epoch = server.node.version.load(SeqCst) as u64;
+    let current_position = machines.iter().position(|addr| *addr == *current).unwrap_or(usize::MAX);
+    if epoch < current_epoch || (epoch == current_epoch && position >= current_position) {
+        return;
+    }
+    server.node.version.store(epoch as usize, SeqCst);
+    METRICS.node_epoch.set(epoch as i64);
+    if *current != leader {
+        change_leader(server, &mut current, leader, epoch);
+    }
+}
+
+fn change_leader(server: &Server, current: &mut String, leader: &str, epoch: u64) {
+    let this_addr = server.failover.this_addr();
+    if *current == this_addr {
+        warn!("This machine is not the leader anymore, the leader is {} in the epoch {}", leader, epoch);
+    } else if leader == this_addr {
+        warn!("This machine is the leader now in the epoch {}, the previous leader was {}", epoch, current);
+    } else {
+        info!("The leader is {} in the epoch {}", leader, epoch);
+    }
+    *current = leader.to_string();
+    METRICS.leader_changes.add(1);
+    server.replication.set_leader_addr(if leader == this_addr { "" } else { leader });
+}
+
+/// Changes machines of the node. If the leader is removed, the first machine becomes the leader.
+pub(crate) fn set_machines(server: &Server, machines: Vec<String>) {
+    let failover = &server.failover;
+    failover.set_machines(&machines);
+    if let Ok(mut leader) = failover.leader.lock() {
+        if !machines.contains(&leader) {
+            let first = machines.first().cloned().unwrap_or_else(|| failover.this_addr());
+            change_leader(server, &mut leader, &first, server.node.version.load(SeqCst) as u64);
+        }
+    }
+}
+
+/// Promotes this machine, if the leader is dead, and this machine is the next alive one after it.
+fn check_leader(server: &Server) {
+    let failover = &server.failover;
+    let this_addr = failover.this_addr();
+    let machines = failover.machines();
+    let mut leader = match failover.leader.lock() {
+        Ok(leader) => leader,
+        Err(_) => return
+    };
+    if *leader == this_addr || failover.is_alive(&leader) {
+        return;
+    }
+    let position = match machines.iter().position(|addr| *addr == *leader) {
+        Some(position) => position,
+        None => return
+    };
+    let next = (1..machines.len())
+        .map(|i| &machines[(position + i) % machines.len()])
+        .find(|addr| **addr == this_addr || failover.is_alive(addr));
+    if next != Some(&this_addr) {
+        return;
+    }
+    let epoch = server.node.version.fetch_add(1, SeqCst) as u64 + 1;
+    METRICS.node_epoch.set(epoch as i64);
+    warn!("The leader {} hasn't answered for {:?}", leader, failover.timeout());
+    change_leader(server, &mut leader, &this_addr, epoch);
+}
+
+fn heartbeat_message(this_addr: &str, epoch: u64, leader: &str) -> Vec<u8> {
+    let mut message = Vec::with_capacity(11 + this_addr.len() + leader.len());
+    message.push(actions::HEARTBEAT);
+    message.extend_from_slice(&uint::u64tob(epoch));
+    message.extend_from_slice(&[this_addr.len() as u8, (this_addr.len() >> 8) as u8]);
+    message.extend_from_slice(this_addr.as_bytes());
+    message.extend_from_slice(leader.as_bytes());
+    message
+}
+
+/// Sends the heartbeat and returns the epoch and the leader of the other machine.
+fn send_heartbeat(stream: &mut TcpStream, message: &[u8]) -> Result<(u64, String), String> {
+    let answer = replication::request(stream, message)?;
+    if answer.len() < 9 || answer[0] != actions::DONE {
+        return Err("the answer is not valid".to_string());
+    }
+    Ok((uint::u64(&answer[1..9]), String::from_utf8_lossy(&answer[9..]).to_string()))
+}
+
+/// Sends heartbeats and checks the leader until the shutdown is requested.
+pub fn run(server: Arc<Server>) {
+    let failover = &server.failover;
+    let this_addr = failover.this_addr();
+    let password = server.replication.leader_password.clone();
+    let mut streams: HashMap<String, TcpStream> = HashMap::new();
+    while !server.shutdown.is_requested() {
+        let interval = failover.heartbeat_interval();
+        for addr in server.node.get_other_machines_addr().iter() {
+            let message = heartbeat_message(&this_addr, server.node.version.load(SeqCst) as u64, &failover.leader());
+            let stream = match streams.remove(addr) {
+                Some(stream) => Ok(stream),
+                None => replication::connect(addr, &password, interval)
+            };
+            let answer = stream.and_then(|mut stream| send_heartbeat(&mut stream, &message).map(|answer| (stream, answer)));
+            match answer {
+                Ok((stream, (epoch, leader))) => {
+                    streams.insert(addr.clone(), stream);
+                    failover.seen(addr);
+                    observe(&server, epoch, &leader);
+                }
+                Err(e) => debug!("The heartbeat to {} has failed: {}", addr, e)
+            }
+        }
+        check_leader(&server);
+        thread::sleep(interval);
+    }
+}
+
+/// Answers the heartbeat of another machine with the epoch and the leader, that this machine knows after it.
+pub fn heartbeat<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 11 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let sender_len = uint::u16(&message[9..11]) as usize;
+    if message.len() < 11 + sender_len {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let failover = &server.failover;
+    let sender = String::from_utf8_lossy(&message[11..11 + sender_len]);
+    failover.seen(&sender);
+    observe(server, uint::u64(&message[1..9]), &String::from_utf8_lossy(&message[11 + sender_len..]));
+
+    let leader = failover.leader();
+    let mut answer = Vec::with_capacity(9 + leader.len());
+    answer.push(actions::DONE);
+    answer.extend_from_slice(&uint::u64tob(server.node.version.load(SeqCst) as u64));
+    answer.extend_from_slice(leader.as_bytes());
+    connection.write_message(&answer)
+}
+
+/// Answers a write with the address of the leader.
+pub fn redirect<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server
+) -> Status {
+    let leader = server.failover.leader();
+    let error = errors::NOT_THE_LEADER;
+    let mut answer = Vec::with_capacity(3 + leader.len());
+    answer.extend_from_slice(&[error.status, error.code as u8, (error.code >> 8) as u8]);
+    answer.extend_from_slice(leader.as_bytes());
+    connection.write_message(&answer)
+}
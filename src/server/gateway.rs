@@ -0,0 +1,390 @@
+//! HTTP/JSON gateway. It works over the same storage as the binary protocol, so all changes are logged the same way.
+//!
+//! Routes:
+//! - `GET /ping`
+//! - `GET /tables`, `POST /tables`
+//! - `GET /tables/{table}`
+//! - `GET|PUT|POST|DELETE /tables/{table}/keys/{key}`
+//! - `GET /tables/{table}/keys/{key}/fields?names=a,b` and `GET /tables/{table}/keys/{key}/fields/{field}`
+//!
+//! `{table}` is a name or a number of the table. Values of tables with a scheme are JSON objects,
+//! values of tables without a scheme are raw bytes.
+//!
+//! Keys of other nodes and writes to machines, that aren't the leader of the node, are answered with `421 Misdirected Request`,
+//! like the binary protocol answers them with [`KEY_IS_MOVED`](errors::KEY_IS_MOVED) and [`NOT_THE_LEADER`](errors::NOT_THE_LEADER).
+//! The body has the `code`, the `message` and the `addr` of the binary protocol of the right machine.
+use std::sync::Arc;
+use serde_json::{json, Map, Value};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    constants::{actions, errors::{self, Error}},
+    http::{self, Request, Response},
+    metrics::METRICS,
+    scheme::{
+        field_info::FieldType,
+        json::{field_to_json, read_prefixed_field, value_from_json, value_to_json},
+        scheme::{is_value_valid, named_fields_from_bytes}
+    },
+    server::{reactions::table::create_table, server::Server, sharding},
+    storage::storage::Storage,
+    table::table::{Table, TableEngine},
+    writers::LogWriter
+};
+
+/// [`BinKey`] stores the length of the key in 2 bytes.
+const MAX_KEY_LEN: usize = u16::MAX as usize;
+
+pub fn run(server: Arc<Server>) {
+    let addr = server.http_addr.clone();
+    let storage = server.storage;
+    let new_state = move || (LogWriter::new(storage.log_file.clone()), METRICS.http_connections.track());
+    http::serve("HTTP gateway", &addr, new_state, move |(log_writer, _), request| {
+        let _request = server.shutdown.track_request();
+        if server.shutdown.is_requested() {
+            return error_response(errors::SERVER_IS_SHUTTING_DOWN);
+        }
+        let response = match handle(&server, request, log_writer) {
+            Ok(response) => response,
+            Err(error) => error_response(error),
+        };
+        log_writer.flush();
+        response
+    });
+}
+
+fn http_status(error: &Error) -> u16 {
+    if *error == errors::UNAUTHORIZED {
+        return 401;
+    }
+    if *error == errors::METHOD_IS_NOT_ALLOWED {
+        return 405;
+    }
+    if *error == errors::KEY_ALREADY_EXISTS || *error == errors::TABLE_ALREADY_EXISTS {
+        return 409;
+    }
+    if *error == errors::BODY_IS_TOO_LARGE {
+        return 413;
+    }
+    if *error == errors::SERVER_IS_SHUTTING_DOWN {
+        return 503;
+    }
+    if *error == errors::SERVER_IS_READ_ONLY_FOLLOWER {
+        return 403;
+    }
+    match error.status {
+        actions::BAD_REQUEST => 400,
+        actions::NOT_FOUND | actions::TABLE_NOT_FOUND => 404,
+        _ => 500,
+    }
+}
+
+fn error_response(error: Error) -> Response {
+    let response = Response::json(http_status(&error), &json!({ "code": error.code, "message": error.message }));
+    if error == errors::UNAUTHORIZED {
+        return response.with_header("WWW-Authenticate", "Basic realm=\"dbms\"".to_string());
+    }
+    response
+}
+
+fn moved(server: &Server, shard: u16, node: u16) -> Response {
+    let addr = sharding::addr_of(server, node);
+    let error = errors::KEY_IS_MOVED;
+    Response::json(421, &json!({
+        "code": error.code,
+        "message": format!("MOVED {} {} {}", shard, node, addr),
+        "shard": shard,
+        "node": node,
+        "addr": addr,
+    }))
+}
+
+fn not_the_leader(server: &Server) -> Response {
+    let error = errors::NOT_THE_LEADER;
+    Response::json(421, &json!({ "code": error.code, "message": error.message, "addr": server.failover.leader() }))
+}
+
+/// Accepts `Authorization: Bearer <password>` and `Authorization: Basic` with any user and the password.
+fn is_authorized(server: &Server, request: &Request) -> bool {
+    if server.password.is_empty() {
+        return true;
+    }
+    let authorization = match request.header("authorization") {
+        Some(authorization) => authorization,
+        None => return false,
+    };
+    if let Some(token) = authorization.strip_prefix("Bearer ") {
+        return token.trim().as_bytes() == server.password.as_bytes();
+    }
+    if let Some(credentials) = authorization.strip_prefix("Basic ") {
+        return match base64_decode(credentials.trim()) {
+            Some(credentials) => match credentials.iter().position(|byte| *byte == b':') {
+                Some(colon) => &credentials[colon + 1..] == server.password.as_bytes(),
+                None => false,
+            },
+            None => false,
+        };
+    }
+    false
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(s.len() / 4 * 3);
+    let mut buf = 0u32;
+    let mut bits = 0;
+    for byte in s.bytes().filter(|byte| *byte != b'=') {
+        let sextet = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        buf = (buf << 6) | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buf >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+fn handle(server: &Server, request: &Request, log_writer: &mut LogWriter) -> Result<Response, Error> {
+    if !is_authorized(server, request) {
+        return Err(errors::UNAUTHORIZED);
+    }
+    let storage = server.storage;
+    let method = request.method.as_str();
+    let segments = request.segments();
+    let segments: Vec<&[u8]> = segments.iter().map(|segment| segment.as_slice()).collect();
+    // Like in the binary protocol, keys of other nodes are redirected first, then writes to machines, that aren't the leader.
+    if let [b"tables", _, b"keys", key, ..] = segments.as_slice() {
+        if let Some((shard, node)) = sharding::foreign_key(server, key) {
+            return Ok(moved(server, shard, node));
+        }
+    }
+    // Every method except GET writes.
+    if method != "GET" && !server.failover.is_leader() {
+        return Ok(not_the_leader(server));
+    }
+    if method != "GET" && server.replication.is_follower() {
+        return Err(errors::SERVER_IS_READ_ONLY_FOLLOWER);
+    }
+
+    match segments.as_slice() {
+        [b"ping"] => match method {
+            "GET" => Ok(Response::json(200, &json!({ "status": "ok" }))),
+            _ => Err(errors::METHOD_IS_NOT_ALLOWED),
+        },
+        [b"tables"] => match method {
+            "GET" => list_tables(storage),
+            "POST" => create(storage, request, log_writer),
+            _ => Err(errors::METHOD_IS_NOT_ALLOWED),
+        },
+        [b"tables", table] => match method {
+            "GET" => {
+                let (number, table) = find_table(storage, table)?;
+                Ok(Response::json(200, &describe_table(number, table)))
+            }
+            _ => Err(errors::METHOD_IS_NOT_ALLOWED),
+        },
+        [b"tables", table, b"keys", key] => {
+            let (_, table) = find_table(storage, table)?;
+            if key.len() > MAX_KEY_LEN {
+                return Err(errors::KEY_IS_TOO_LONG);
+            }
+            match method {
+                "GET" => get(table, key),
+                "PUT" => {
+                    let value = value_from_body(table, request)?;
+                    table.set(BinKey::new(key), BinValue::new(&value), log_writer)?;
+                    Ok(Response::empty(204))
+                }
+                "POST" => {
+                    let value = value_from_body(table, request)?;
+                    if !table.insert(BinKey::new(key), BinValue::new(&value), log_writer)? {
+                        return Err(errors::KEY_ALREADY_EXISTS);
+                    }
+                    Ok(Response::empty(201))
+                }
+                "DELETE" => {
+                    table.delete(&BinKey::new(key), log_writer)?;
+                    Ok(Response::empty(204))
+                }
+                _ => Err(errors::METHOD_IS_NOT_ALLOWED),
+            }
+        }
+        [b"tables", table, b"keys", key, b"fields"] => match method {
+            "GET" => {
+                let (_, table) = find_table(storage, table)?;
+                let names = request.query_param("names");
+                get_fields(table, key, names.as_deref())
+            }
+            _ => Err(errors::METHOD_IS_NOT_ALLOWED),
+        },
+        [b"tables", table, b"keys", key, b"fields", field] => match method {
+            "GET" => {
+                let (_, table) = find_table(storage, table)?;
+                get_field(table, key, field)
+            }
+            _ => Err(errors::METHOD_IS_NOT_ALLOWED),
+        },
+        _ => Err(errors::ROUTE_IS_NOT_FOUND),
+    }
+}
+
+/// Finds the table by the name or, if there is no table with this name, by the number.
+fn find_table(storage: &'static Storage, name: &[u8]) -> Result<(usize, &'static dyn Table), Error> {
+    let number = {
+        let tables_names = storage.tables_names.read().map_err(|_| errors::TABLES_LOCK_IS_POISONED)?;
+        match tables_names.iter().position(|table_name| table_name.as_bytes() == name) {
+            Some(number) => number,
+            None => match std::str::from_utf8(name).ok().and_then(|name| name.parse::<usize>().ok()) {
+                Some(number) => number,
+                None => return Err(errors::TABLE_IS_NOT_FOUND),
+            },
+        }
+    };
+    match storage.tables.get().get(number) {
+        Some(table) => Ok((number, table.as_ref())),
+        None => Err(errors::TABLE_IS_NOT_FOUND),
+    }
+}
+
+fn describe_table(number: usize, table: &dyn Table) -> Value {
+    let user_scheme = table.user_scheme();
+    let scheme = if user_scheme.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_slice(&user_scheme).unwrap_or(Value::Null)
+    };
+    let mut description = json!({
+        "number": number,
+        "name": table.name(),
+        "engine": table.engine().name(),
+        "logging": table.is_it_logging(),
+        "count": table.count(),
+        "scheme": scheme,
+    });
+    if table.engine() == TableEngine::CACHE {
+        description["cache_duration"] = Value::from(table.cache_duration());
+    }
+    description
+}
+
+fn list_tables(storage: &'static Storage) -> Result<Response, Error> {
+    let tables = storage.tables.get();
+    let list: Vec<Value> = tables.iter().enumerate().map(|(number, table)| describe_table(number, table.as_ref())).collect();
+    Ok(Response::json(200, &Value::Array(list)))
+}
+
+fn parse_body(request: &Request) -> Result<Value, Error> {
+    serde_json::from_slice(&request.body).map_err(|_| errors::BODY_IS_NOT_VALID_JSON)
+}
+
+/// Body is `{"name": "users", "engine": "in_memory" | "on_disk" | "cache" | "lsm", "logging": true, "cache_duration": 60, "scheme": {...}}`.
+/// Only `name` is required. The scheme may set `"compression": "lz4" | "zstd"` and `"compression_threshold"`.
+fn create(storage: &'static Storage, request: &Request, log_writer: &mut LogWriter) -> Result<Response, Error> {
+    let body = parse_body(request)?;
+    let name = match body.get("name").and_then(Value::as_str) {
+        Some(name) => name,
+        None => return Err(errors::BODY_IS_NOT_VALID_JSON),
+    };
+    let engine = match body.get("engine").and_then(Value::as_str).unwrap_or("in_memory") {
+        "in_memory" => TableEngine::InMemory,
+        "on_disk" => TableEngine::OnDisk,
+        "cache" => TableEngine::CACHE,
+        "lsm" => TableEngine::Lsm,
+        _ => return Err(errors::UNKNOWN_TABLE_ENGINE),
+    };
+    let is_it_logging = body.get("logging").and_then(Value::as_bool).unwrap_or(true);
+    let cache_duration = body.get("cache_duration").and_then(Value::as_u64).unwrap_or(0);
+    let user_scheme = match body.get("scheme") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(scheme) => scheme.to_string().into_bytes(),
+    };
+
+    let number = create_table(storage, engine, name.as_bytes(), is_it_logging, cache_duration, &user_scheme, log_writer)?;
+    Ok(Response::json(201, &json!({ "number": number, "name": name })))
+}
+
+fn fields_of(table: &dyn Table) -> Result<Vec<(String, FieldType)>, Error> {
+    named_fields_from_bytes(&table.user_scheme())
+}
+
+fn value_from_body(table: &dyn Table, request: &Request) -> Result<Vec<u8>, Error> {
+    let fields = fields_of(table)?;
+    let value = if fields.is_empty() {
+        request.body.clone()
+    } else {
+        value_from_json(&fields, &parse_body(request)?)?
+    };
+    if !is_value_valid(&value, table.scheme()) {
+        return Err(errors::VALUE_DOES_NOT_MATCH_SCHEME);
+    }
+    Ok(value)
+}
+
+fn get(table: &dyn Table, key: &[u8]) -> Result<Response, Error> {
+    let value = match table.get(&BinKey::new(key))? {
+        Some(value) => value,
+        None => return Err(errors::KEY_IS_NOT_FOUND),
+    };
+    let fields = fields_of(table)?;
+    if fields.is_empty() {
+        return Ok(Response::new(200, "application/octet-stream", value.deref().to_vec()));
+    }
+    Ok(Response::json(200, &value_to_json(&fields, value.deref())?))
+}
+
+fn field_number(fields: &[(String, FieldType)], name: &[u8]) -> Result<usize, Error> {
+    match fields.iter().position(|(field, _)| field.as_bytes() == name) {
+        Some(number) => Ok(number),
+        None => Err(errors::FIELD_IS_NOT_FOUND),
+    }
+}
+
+fn get_field(table: &dyn Table, key: &[u8], name: &[u8]) -> Result<Response, Error> {
+    let fields = fields_of(table)?;
+    let number = field_number(&fields, name)?;
+    let field = match table.get_field(&BinKey::new(key), number)? {
+        Some(field) => field,
+        None => return Err(errors::KEY_IS_NOT_FOUND),
+    };
+    match read_prefixed_field(&field) {
+        Some((bytes, _)) => Ok(Response::json(200, &field_to_json(fields[number].1, bytes))),
+        None => Err(errors::VALUE_DOES_NOT_MATCH_SCHEME),
+    }
+}
+
+/// `names` is a comma-separated list of fields. Returns all fields, if it is `None`.
+fn get_fields(table: &dyn Table, key: &[u8], names: Option<&str>) -> Result<Response, Error> {
+    let fields = fields_of(table)?;
+    if fields.is_empty() {
+        return Err(errors::FIELD_IS_NOT_FOUND);
+    }
+    let numbers = match names {
+        Some(names) => names.split(',').filter(|name| !name.is_empty())
+            .map(|name| field_number(&fields, name.as_bytes()))
+            .collect::<Result<Vec<usize>, Error>>()?,
+        None => (0..fields.len()).collect(),
+    };
+    let value = match table.get_fields(&BinKey::new(key), &numbers)? {
+        Some(value) => value,
+        None => return Err(errors::KEY_IS_NOT_FOUND),
+    };
+
+    let mut object = Map::new();
+    let mut offset = 0;
+    for number in numbers {
+        match read_prefixed_field(&value[offset..]) {
+            Some((bytes, read)) => {
+                object.insert(fields[number].0.clone(), field_to_json(fields[number].1, bytes));
+                offset += read;
+            }
+            None => return Err(errors::VALUE_DOES_NOT_MATCH_SCHEME),
+        }
+    }
+    Ok(Response::json(200, &Value::Object(object)))
+}
@@ -0,0 +1,306 @@
+//! Membership of the cluster.
+//!
+//! The hierarchy is the list of nodes, and every node is the list of addresses of its machines. It is stored in
+//! the file `hierarchy.bin` and is returned by `GET_HIERARCHY` in the same format:
+//! [`number of machines` (1 byte), [`address length` (2 bytes), `address`] for every machine] for every node.
+//!
+//! Admin actions change the hierarchy of the machine, that gets them, and send the new hierarchy to all other machines
+//! with `SET_HIERARCHY` `[action, hierarchy]`:
+//!
+//! - `ADD_NODE` `[action, [address length (2 bytes), address] for every machine]` adds the node to the end;
+//! - `REMOVE_NODE` `[action, node (2 bytes)]` removes the node, that has no shards (see [`crate::server::migration`]).
+//!   Next nodes get numbers one less, in the shard metadata too;
+//! - `ADD_MACHINE` `[action, node (2 bytes), address]` adds the machine to the end of the node;
+//! - `REMOVE_MACHINE` `[action, address]` removes the machine, that isn't the last one of its node.
+//!
+//! They answer with `{"epoch", "hierarchy", "unreachable"}`, where `unreachable` are machines, that haven't got the hierarchy.
+//! Every change increases [`Node::version`](crate::node::Node::version). Changes must be sent to one machine at a time.
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::Ordering::SeqCst,
+    time::Duration
+};
+use serde_json::json;
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors::{self, Error}},
+    server::{failover, replication, server::Server},
+    stream::Stream,
+    utils::bytes::uint,
+    error, info, warn
+};
+
+/// Machines, that don't answer for this time, don't get the new hierarchy.
+const PROPAGATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Returns the hierarchy in the format of the file.
+pub fn encode(hierarchy: &[Vec<String>]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(hierarchy.len() * 3 * 20);
+    for machines in hierarchy.iter() {
+        buf.push(machines.len() as u8);
+        for addr in machines.iter() {
+            buf.extend_from_slice(&uint::u16tob(addr.len() as u16));
+            buf.extend_from_slice(addr.as_bytes());
+        }
+    }
+    buf
+}
+
+/// Reads the hierarchy in the format of the file.
+pub fn decode(buf: &[u8]) -> Result<Vec<Vec<String>>, String> {
+    let mut hierarchy = Vec::new();
+    let mut offset = 0;
+    while offset < buf.len() {
+        let number_of_machines = buf[offset] as usize;
+        offset += 1;
+        let mut machines = Vec::with_capacity(number_of_machines);
+        for _ in 0..number_of_machines {
+            let len = buf.get(offset..offset + 2).map(uint::u16).ok_or("the address length is cut off")? as usize;
+            offset += 2;
+            let addr = buf.get(offset..offset + len).ok_or("the address is cut off")?;
+            offset += len;
+            machines.push(String::from_utf8(addr.to_vec()).map_err(|_| "the address is not valid UTF-8")?);
+        }
+        hierarchy.push(machines);
+    }
+    validate(&hierarchy).map_err(|error| error.message.to_string())?;
+    Ok(hierarchy)
+}
+
+/// Every node has from 1 to 255 machines, and every machine is in one node.
+fn validate(hierarchy: &[Vec<String>]) -> Result<(), Error> {
+    if hierarchy.is_empty() || hierarchy.len() > u16::MAX as usize {
+        return Err(errors::HIERARCHY_IS_NOT_VALID);
+    }
+    let mut addrs: Vec<&String> = Vec::new();
+    for machines in hierarchy.iter() {
+        if machines.is_empty() {
+            return Err(errors::NODE_NEEDS_A_MACHINE);
+        }
+        if machines.len() > u8::MAX as usize {
+            return Err(errors::HIERARCHY_IS_NOT_VALID);
+        }
+        for addr in machines.iter() {
+            if addr.is_empty() || addr.len() > u16::MAX as usize {
+                return Err(errors::HIERARCHY_IS_NOT_VALID);
+            }
+            if addrs.contains(&addr) {
+                return Err(errors::MACHINE_ALREADY_EXISTS);
+            }
+            addrs.push(addr);
+        }
+    }
+    Ok(())
+}
+
+/// Writes a new file and renames it, so the file is never half-written.
+pub fn save(path: &Path, hierarchy: &[Vec<String>]) {
+    let tmp_path = path.with_extension("bin.tmp");
+    let result = fs::write(&tmp_path, encode(hierarchy)).and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(e) = result {
+        error!("Can't write the hierarchy file {}: {}", path.display(), e);
+    }
+}
+
+/// Returns the number of the node, that was removed, if `new` is `old` without one node.
+fn removed_node(old: &[Vec<String>], new: &[Vec<String>]) -> Option<usize> {
+    if old.len() != new.len() + 1 {
+        return None;
+    }
+    let node = (0..old.len()).find(|node| new.get(*node) != Some(&old[*node]))?;
+    if old[..node] == new[..node] && old[node + 1..] == new[node..] {
+        Some(node)
+    } else {
+        None
+    }
+}
+
+/// Makes the hierarchy current: writes the file, renumbers nodes of shards and updates machines of the node of this machine.
+/// The lock of the hierarchy must be held.
+fn apply(server: &Server, old: &[Vec<String>], new: &[Vec<String>]) {
+    save(&server.hierarchy_file_path, new);
+    if let Some(node) = removed_node(old, new) {
+        server.shard_map.remove_node(node as u16);
+    }
+    server.shard_map.fit(new.len());
+
+    let this_addr = server.tcp_addr.clone();
+    let machines = match new.iter().find(|machines| machines.contains(&this_addr)) {
+        Some(machines) => machines.clone(),
+        None => vec![this_addr.clone()]
+    };
+    let other_machines: Vec<String> = machines.iter().filter(|addr| **addr != this_addr).cloned().collect();
+    server.node.set(&other_machines);
+    failover::set_machines(server, machines);
+    info!("The hierarchy has {} nodes now in the epoch {}", new.len(), server.node.version.load(SeqCst));
+}
+
+/// Sends the hierarchy to machines of both hierarchies. Returns machines, that haven't got it.
+fn propagate(server: &Server, old: &[Vec<String>], new: &[Vec<String>]) -> Vec<String> {
+    let this_addr = server.tcp_addr.clone();
+    let mut message = vec![actions::SET_HIERARCHY];
+    message.extend_from_slice(&encode(new));
+    let mut peers: Vec<&String> = Vec::new();
+    for addr in new.iter().chain(old.iter()).flatten() {
+        if *addr != this_addr && !peers.contains(&addr) {
+            peers.push(addr);
+        }
+    }
+    let mut unreachable = Vec::new();
+    for addr in peers {
+        let result = replication::connect(addr, &server.replication.leader_password, PROPAGATION_TIMEOUT)
+            .and_then(|mut stream| replication::request(&mut stream, &message));
+        match result {
+            Ok(answer) if answer.first() == Some(&actions::DONE) => {}
+            Ok(answer) => {
+                warn!("{} has rejected the hierarchy: {}", addr, String::from_utf8_lossy(answer.get(3..).unwrap_or(&[])));
+                unreachable.push(addr.clone());
+            }
+            Err(e) => {
+                warn!("{} hasn't got the hierarchy: {}", addr, e);
+                unreachable.push(addr.clone());
+            }
+        }
+    }
+    unreachable
+}
+
+/// Validates the change of the hierarchy, applies it and sends the new hierarchy to other machines.
+fn change<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    change: impl FnOnce(&mut Vec<Vec<String>>) -> Result<(), Error>
+) -> Status {
+    let (old, new) = {
+        let mut hierarchy = match server.hierarchy.write() {
+            Ok(hierarchy) => hierarchy,
+            Err(_) => return connection.write_error(errors::HIERARCHY_IS_NOT_VALID)
+        };
+        let old = hierarchy.clone();
+        let mut new = old.clone();
+        if let Err(error) = change(&mut new).and_then(|_| validate(&new)) {
+            return connection.write_error(error);
+        }
+        apply(server, &old, &new);
+        *hierarchy = new.clone();
+        (old, new)
+    };
+    let unreachable = propagate(server, &old, &new);
+    let mut answer = vec![actions::DONE];
+    answer.extend_from_slice(json!({
+        "epoch": server.node.version.load(SeqCst),
+        "hierarchy": new,
+        "unreachable": unreachable,
+    }).to_string().as_bytes());
+    connection.write_message(&answer)
+}
+
+fn addr_of(bytes: &[u8]) -> Result<String, Error> {
+    String::from_utf8(bytes.to_vec()).map_err(|_| errors::HIERARCHY_IS_NOT_VALID)
+}
+
+pub fn add_node<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    let mut machines = Vec::new();
+    let mut offset = 1;
+    while offset < message.len() {
+        let len = match message.get(offset..offset + 2) {
+            Some(len) => uint::u16(len) as usize,
+            None => return connection.write_error(errors::MESSAGE_IS_TOO_SHORT)
+        };
+        offset += 2;
+        let addr = match message.get(offset..offset + len).map(addr_of) {
+            Some(Ok(addr)) => addr,
+            Some(Err(error)) => return connection.write_error(error),
+            None => return connection.write_error(errors::MESSAGE_IS_TOO_SHORT)
+        };
+        offset += len;
+        machines.push(addr);
+    }
+    change(connection, server, |hierarchy| {
+        hierarchy.push(machines);
+        Ok(())
+    })
+}
+
+pub fn remove_node<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 3 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let node = uint::u16(&message[1..3]);
+    change(connection, server, |hierarchy| {
+        if node as usize >= hierarchy.len() {
+            return Err(errors::NODE_IS_NOT_FOUND);
+        }
+        if server.shard_map.nodes().contains(&node) {
+            return Err(errors::NODE_OWNS_SHARDS);
+        }
+        hierarchy.remove(node as usize);
+        Ok(())
+    })
+}
+
+pub fn add_machine<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 3 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let node = uint::u16(&message[1..3]) as usize;
+    let addr = match addr_of(&message[3..]) {
+        Ok(addr) => addr,
+        Err(error) => return connection.write_error(error)
+    };
+    change(connection, server, |hierarchy| {
+        hierarchy.get_mut(node).ok_or(errors::NODE_IS_NOT_FOUND)?.push(addr);
+        Ok(())
+    })
+}
+
+pub fn remove_machine<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    let addr = match addr_of(&message[1..]) {
+        Ok(addr) => addr,
+        Err(error) => return connection.write_error(error)
+    };
+    change(connection, server, |hierarchy| {
+        let machines = hierarchy.iter_mut().find(|machines| machines.contains(&addr)).ok_or(errors::MACHINE_IS_NOT_FOUND)?;
+        machines.retain(|machine| *machine != addr);
+        Ok(())
+    })
+}
+
+/// Takes the hierarchy of another machine.
+pub fn set_hierarchy<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    let new = match decode(&message[1..]) {
+        Ok(new) => new,
+        Err(e) => {
+            warn!("The hierarchy of another machine is not valid: {}", e);
+            return connection.write_error(errors::HIERARCHY_IS_NOT_VALID);
+        }
+    };
+    if let Ok(mut hierarchy) = server.hierarchy.write() {
+        if *hierarchy != new {
+            apply(server, &hierarchy, &new);
+            *hierarchy = new;
+        }
+    }
+    connection.write_message(&[actions::DONE])
+}
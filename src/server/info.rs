@@ -0,0 +1,69 @@
+//! The report of the `INFO` action. It is JSON, so the CLI and scripts can read it without a special parser.
+use std::sync::atomic::Ordering::SeqCst;
+use serde_json::{json, Value};
+use crate::{
+    metrics::METRICS,
+    server::server::Server
+};
+
+pub fn report(server: &Server) -> Value {
+    let storage = server.storage;
+
+    // The log file is renamed on every dump, so its number is the number of dumps.
+    let number_of_dumps = storage.number_of_dumps.load(SeqCst);
+    let log_size = storage.log_file.file.lock().ok().and_then(|file| file.metadata().ok()).map(|metadata| metadata.len());
+
+    let names = storage.tables_names.read().map(|names| names.clone()).unwrap_or_default();
+    let tables: Vec<Value> = storage.tables.get().iter().zip(names.iter()).enumerate().map(|(number, (table, name))| {
+        json!({
+            "number": number,
+            "name": name,
+            "engine": table.engine().name(),
+            "count": table.count(),
+            "memory_bytes": table.memory_usage(),
+            "notifications": table.notifications().events(),
+        })
+    }).collect();
+
+    let other_machines = server.node.get_other_machines_addr();
+    let role = if server.node_addr.is_empty() && server.number_of_nodes() <= 1 { "single" } else { "cluster" };
+
+    json!({
+        "server": {
+            "version": env!("CARGO_PKG_VERSION"),
+            "uptime_seconds": server.started_at.elapsed().as_secs(),
+        },
+        "persistence": {
+            "dir": storage.persistence_dir_path.to_string_lossy(),
+            "dump_interval_minutes": storage.dump_interval.load(SeqCst),
+            "number_of_dumps": number_of_dumps,
+            "log_number": storage.log_number.load(SeqCst),
+            "retained_logs": storage.retained_logs.load(SeqCst),
+            "log_size_bytes": log_size,
+        },
+        "tables": tables,
+        "disk_cache": {
+            "capacity_bytes": storage.disk_cache.capacity(),
+            "used_bytes": storage.disk_cache.used_bytes(),
+            "hits": METRICS.disk_cache_hits.get(),
+            "misses": METRICS.disk_cache_misses.get(),
+        },
+        "clients": {
+            "binary": METRICS.binary_connections.get(),
+            "http": METRICS.http_connections.get(),
+            "resp": METRICS.resp_connections.get(),
+            "change_subscribers": METRICS.change_subscribers.get(),
+            "pubsub_subscribers": METRICS.pubsub_subscribers.get(),
+        },
+        "cluster": {
+            "role": role,
+            "node_addr": server.node_addr,
+            "nodes": server.number_of_nodes(),
+            "this_node": server.this_node(),
+            "hierarchy": server.hierarchy(),
+            "other_machines_of_node": other_machines.to_vec(),
+        },
+        "replication": server.replication.report(server),
+        "failover": server.failover.report(server),
+    })
+}
@@ -0,0 +1,19 @@
+//! The metrics endpoint. See [`crate::metrics`].
+use std::sync::Arc;
+use crate::{
+    http::{self, Response},
+    metrics::render,
+    server::server::Server
+};
+
+pub fn run(server: Arc<Server>) {
+    let addr = server.metrics_addr.clone();
+    let storage = server.storage;
+    http::serve("Metrics", &addr, || (), move |_, request| {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/metrics") => Response::new(200, "text/plain; version=0.0.4; charset=utf-8", render(storage).into_bytes()),
+            (_, "/metrics") => Response::text(405, "Method not allowed"),
+            _ => Response::text(404, "Not found"),
+        }
+    });
+}
@@ -0,0 +1,473 @@
+//! Moving of shards between nodes.
+//!
+//! `MIGRATE_SHARDS` `[action, from (2 bytes), to (2 bytes), node (2 bytes)]` is sent to the node, that owns shards
+//! from `from` to `to` inclusive. The node connects to the leftmost machine of the target node and sends it
+//! `IMPORT_RECORDS` `[action, records]` (see [`crate::storage::log_record`]): tables first, then all keys of the shards.
+//! Tables of nodes must have the same numbers, missing tables are created on the target.
+//!
+//! While keys are copied, the source node serves reads and writes of the shards. Every write to them
+//! waits for the copying of a key, and the new state of the key is forwarded to the target after it.
+//! To flip the ownership, the source node stops writes to the shards, sends the rest of records,
+//! sends `SET_SHARDS_NODE` `[action, from, to, node]` to the target and writes its own shard metadata file.
+//! Then writes to the shards get [`KEY_IS_MOVED`](errors::KEY_IS_MOVED), the source deletes its copy
+//! and sends `SET_SHARDS_NODE` to other nodes. If the target fails before the flip, the shards stay on the source.
+//!
+//! `REBALANCE` `[action, is dry run]` plans moves, that even out numbers of shards of nodes (see [`plan`]), and makes them
+//! one by one. The dry run only returns the plan.
+use std::{
+    mem,
+    net::TcpStream,
+    sync::{Mutex, PoisonError, atomic::{AtomicBool, Ordering::SeqCst}},
+    time::Duration
+};
+use serde_json::{json, Value};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors::{self, Error}},
+    console::logger,
+    metrics::METRICS,
+    server::{
+        reactions::{table::create_table, work_with_tables::{delete, insert, set}},
+        replication,
+        server::Server,
+        sharding::{self, key_of, shard_of, NUMBER_OF_SHARDS}
+    },
+    storage::{log_record::{self, LogRecord}, storage::Storage},
+    stream::Stream,
+    table::table::TableEngine,
+    utils::bytes::uint,
+    writers::LogWriter,
+    error, info, success, warn
+};
+
+/// The source sends records in messages of this size at most.
+const MAX_RECORDS_BYTES: usize = 256 * 1024;
+/// The timeout of answers of the target node.
+const TARGET_TIMEOUT: Duration = Duration::from_secs(30);
+/// `REBALANCE` waits for a migration of another node for this time.
+const MIGRATION_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Shards, that are being moved, and records of writes to them, that the target hasn't got yet.
+struct Moving {
+    from: u16,
+    to: u16,
+    records: Vec<u8>,
+}
+
+impl Moving {
+    #[inline(always)]
+    fn contains(&self, shard: u16) -> bool {
+        self.from <= shard && shard <= self.to
+    }
+}
+
+/// The running migration of this node. Only one migration runs at a time.
+pub struct Migration {
+    /// Is locked by writes to the moving shards, by the copying of every key and by the flip of the ownership.
+    moving: Mutex<Option<Moving>>,
+    is_running: AtomicBool,
+}
+
+impl Default for Migration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Migration {
+    pub fn new() -> Self {
+        Self { moving: Mutex::new(None), is_running: AtomicBool::new(false) }
+    }
+
+    /// Writes check the migration only when it is running, so they don't wait for the lock otherwise.
+    #[inline(always)]
+    pub fn is_running(&self) -> bool {
+        self.is_running.load(SeqCst)
+    }
+}
+
+/// A move of shards from `from` to `to` inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub from: u16,
+    pub to: u16,
+    pub source: u16,
+    pub target: u16,
+}
+
+impl Move {
+    fn to_json(self) -> Value {
+        json!({ "from": self.from, "to": self.to, "source": self.source, "target": self.target })
+    }
+}
+
+/// Returns moves, after which every node has 65,536 / `number_of_nodes` shards, and the first nodes have one shard more.
+/// Only shards of nodes with too many shards are moved, and moved shards are joined in ranges.
+pub fn plan(nodes: &[u16], number_of_nodes: usize) -> Vec<Move> {
+    let number_of_nodes = number_of_nodes.max(1);
+    let quota = |node: usize| NUMBER_OF_SHARDS / number_of_nodes + (node < NUMBER_OF_SHARDS % number_of_nodes) as usize;
+    let mut counts = vec![0usize; number_of_nodes];
+    for node in nodes.iter().filter(|node| (**node as usize) < number_of_nodes) {
+        counts[*node as usize] += 1;
+    }
+
+    let mut moves: Vec<Move> = Vec::new();
+    let mut target = 0;
+    for (shard, source) in nodes.iter().enumerate() {
+        let source = *source as usize;
+        if source >= number_of_nodes || counts[source] <= quota(source) {
+            continue;
+        }
+        while target < number_of_nodes && counts[target] >= quota(target) {
+            target += 1;
+        }
+        if target == number_of_nodes {
+            break;
+        }
+        counts[source] -= 1;
+        counts[target] += 1;
+        let (shard, source, target) = (shard as u16, source as u16, target as u16);
+        match moves.last_mut() {
+            Some(last) if last.to + 1 == shard && last.source == source && last.target == target => last.to = shard,
+            _ => moves.push(Move { from: shard, to: shard, source, target })
+        }
+    }
+    moves
+}
+
+/// Sends records to the target and checks the answer.
+fn send_records(stream: &mut TcpStream, records: &[u8]) -> Result<(), String> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let mut message = Vec::with_capacity(records.len() + 1);
+    message.push(actions::IMPORT_RECORDS);
+    message.extend_from_slice(records);
+    check_answer(&replication::request(stream, &message)?)
+}
+
+fn check_answer(answer: &[u8]) -> Result<(), String> {
+    if answer.first() == Some(&actions::DONE) {
+        return Ok(());
+    }
+    let code = answer.get(1..3).map(uint::u16).unwrap_or(0);
+    Err(format!("the target has answered with the error {}: {}", code, String::from_utf8_lossy(answer.get(3..).unwrap_or(&[]))))
+}
+
+fn set_shards_node_message(from: u16, to: u16, node: u16) -> Vec<u8> {
+    let mut message = vec![actions::SET_SHARDS_NODE];
+    message.extend_from_slice(&uint::u16tob(from));
+    message.extend_from_slice(&uint::u16tob(to));
+    message.extend_from_slice(&uint::u16tob(node));
+    message
+}
+
+/// Copies keys of the shards to the target and flips the ownership. Returns the number of copied keys.
+fn copy_and_flip(server: &Server, from: u16, to: u16, node: u16, addr: &str) -> Result<u64, String> {
+    let migration = &server.migration;
+    let storage = server.storage;
+    let mut stream = replication::connect(addr, &server.replication.leader_password, TARGET_TIMEOUT)?;
+
+    let names = storage.tables_names.read().map(|names| names.clone()).unwrap_or_default();
+    let tables = storage.tables.get();
+    let mut records = Vec::new();
+    for (table, name) in tables.iter().zip(names.iter()) {
+        let engine = table.engine();
+        let is_it_logging = engine != TableEngine::OnDisk && table.is_it_logging();
+        let cache_duration = if engine == TableEngine::CACHE { table.cache_duration() } else { 0 };
+        log_record::encode_create_table(&mut records, engine, name.as_bytes(), is_it_logging, cache_duration, &table.user_scheme());
+    }
+    send_records(&mut stream, &records)?;
+
+    let mut keys = 0;
+    for (number, table) in tables.iter().enumerate() {
+        let table_keys = table.keys().map_err(|error| format!("can't list keys of the table {}: {}", number, error.message))?;
+        for key in table_keys {
+            let shard = shard_of(&key);
+            if shard < from || shard > to {
+                continue;
+            }
+            let mut guard = migration.moving.lock().unwrap_or_else(PoisonError::into_inner);
+            let Some(moving) = guard.as_mut() else {
+                return Err("the migration was stopped".to_string());
+            };
+            match table.get(&BinKey::new(&key)) {
+                Ok(Some(value)) => {
+                    log_record::encode_key_and_value(&mut moving.records, actions::SET, number as u16, &key, value.deref());
+                    keys += 1;
+                }
+                Ok(None) => {}
+                Err(error) => return Err(format!("can't read a key of the table {}: {}", number, error.message))
+            }
+            if moving.records.len() >= MAX_RECORDS_BYTES {
+                records = mem::take(&mut moving.records);
+                drop(guard);
+                send_records(&mut stream, &records)?;
+            }
+        }
+    }
+
+    // Writes to the shards wait, until the target has all records and owns the shards.
+    let mut moving = migration.moving.lock().unwrap_or_else(PoisonError::into_inner);
+    records = match moving.as_mut() {
+        Some(moving) => mem::take(&mut moving.records),
+        None => return Err("the migration was stopped".to_string())
+    };
+    send_records(&mut stream, &records)?;
+    check_answer(&replication::request(&mut stream, &set_shards_node_message(from, to, node))?)?;
+    server.shard_map.set_node(from, to, node);
+    *moving = None;
+    Ok(keys)
+}
+
+/// Moves shards from `from` to `to` inclusive of this node to the node `node`. Returns the number of moved keys.
+pub(crate) fn migrate(server: &Server, from: u16, to: u16, node: u16) -> Result<u64, Error> {
+    let this_node = server.this_node().ok_or(errors::NODE_IS_NOT_FOUND)?;
+    let addr = match server.first_machine_of(node as usize) {
+        Some(addr) if node as usize != this_node => addr,
+        _ => return Err(errors::NODE_IS_NOT_FOUND)
+    };
+    if from > to || (from..=to).any(|shard| server.shard_map.node_of(shard) as usize != this_node) {
+        return Err(errors::SHARDS_ARE_NOT_OWNED);
+    }
+    let migration = &server.migration;
+    if migration.is_running.compare_exchange(false, true, SeqCst, SeqCst).is_err() {
+        return Err(errors::MIGRATION_IS_RUNNING);
+    }
+    let _target = logger::field("target", addr.as_str());
+    info!("Moving shards {}..={} to the node {}", from, to, node);
+    *migration.moving.lock().unwrap_or_else(PoisonError::into_inner) = Some(Moving { from, to, records: Vec::new() });
+    let result = copy_and_flip(server, from, to, node, &addr);
+    *migration.moving.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    migration.is_running.store(false, SeqCst);
+    let keys = match result {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!("Shards {}..={} stay on this node: {}", from, to, e);
+            return Err(errors::MIGRATION_HAS_FAILED);
+        }
+    };
+    METRICS.migrated_keys.add(keys);
+
+    let storage = server.storage;
+    let mut log_writer = LogWriter::new(storage.log_file.clone());
+    for table in storage.tables.get().iter() {
+        let table_keys = match table.keys() {
+            Ok(table_keys) => table_keys,
+            Err(error) => {
+                warn!("Moved keys stay on this node: {}", error.message);
+                continue;
+            }
+        };
+        for key in table_keys {
+            let shard = shard_of(&key);
+            if from <= shard && shard <= to {
+                if let Err(error) = table.delete(&BinKey::new(&key), &mut log_writer) {
+                    warn!("A moved key stays on this node: {}", error.message);
+                }
+            }
+        }
+    }
+    log_writer.flush();
+
+    // Other nodes answer with MOVED to the target, but they would redirect clients here until they know it.
+    let message = set_shards_node_message(from, to, node);
+    for (number, machines) in server.hierarchy().iter().enumerate() {
+        if number == this_node || number == node as usize {
+            continue;
+        }
+        let Some(other) = machines.first() else { continue };
+        let result = replication::connect(other, &server.replication.leader_password, TARGET_TIMEOUT)
+            .and_then(|mut stream| replication::request(&mut stream, &message))
+            .and_then(|answer| check_answer(&answer));
+        if let Err(e) = result {
+            warn!("The node {} doesn't know, that shards {}..={} are moved: {}", number, from, to, e);
+        }
+    }
+    success!("{} keys of shards {}..={} are moved to the node {}", keys, from, to, node);
+    Ok(keys)
+}
+
+/// Runs the write to the moving shards. Its key is forwarded to the target after it.
+pub fn write<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    storage: &'static Storage,
+    message: &[u8],
+    log_writer: &mut LogWriter
+) -> Status {
+    let react = |connection: &mut BufConnection<'stream, S, R, W>, log_writer: &mut LogWriter| match message[0] {
+        actions::INSERT => insert(connection, storage, message, log_writer),
+        actions::SET => set(connection, storage, message, log_writer),
+        _ => delete(connection, storage, message, log_writer)
+    };
+    let Some(key) = key_of(message) else {
+        return react(connection, log_writer);
+    };
+    let shard = shard_of(key);
+    let mut guard = server.migration.moving.lock().unwrap_or_else(PoisonError::into_inner);
+    match guard.as_mut() {
+        Some(moving) if moving.contains(shard) => {
+            let status = react(connection, log_writer);
+            let number = uint::u16(&message[1..3]);
+            if let Some(table) = storage.tables.get().get(number as usize) {
+                match table.get(&BinKey::new(key)) {
+                    Ok(Some(value)) => log_record::encode_key_and_value(&mut moving.records, actions::SET, number, key, value.deref()),
+                    Ok(None) => log_record::encode_key(&mut moving.records, actions::DELETE, number, key),
+                    // The target would miss the write, so the migration is stopped.
+                    Err(error) => {
+                        error!("Can't forward the write to the moving shard {}: {}", shard, error.message);
+                        *guard = None;
+                    }
+                }
+            }
+            status
+        }
+        _ => {
+            drop(guard);
+            // The shards could be moved, while the write waited for the lock.
+            if let Some((shard, node)) = sharding::foreign_shard(server, message) {
+                return sharding::moved(connection, server, shard, node);
+            }
+            react(connection, log_writer)
+        }
+    }
+}
+
+pub fn migrate_shards<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 7 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let (from, to, node) = (uint::u16(&message[1..3]), uint::u16(&message[3..5]), uint::u16(&message[5..7]));
+    match migrate(server, from, to, node) {
+        Ok(keys) => {
+            let mut answer = vec![actions::DONE];
+            answer.extend_from_slice(json!({ "from": from, "to": to, "node": node, "keys": keys }).to_string().as_bytes());
+            connection.write_message(&answer)
+        }
+        Err(error) => connection.write_error(error)
+    }
+}
+
+/// Applies records of the source node with the log. Tables are created in the order of numbers of the source.
+fn import(storage: &'static Storage, records: &[u8], log_writer: &mut LogWriter) -> Result<(), Error> {
+    let tables = || storage.tables.get();
+    let mut offset = 0;
+    let mut number_of_created = 0;
+    while offset < records.len() {
+        let (record, len) = match log_record::decode(&records[offset..]) {
+            Ok(Some(record)) => record,
+            _ => return Err(errors::RECORDS_ARE_NOT_VALID)
+        };
+        offset += len;
+        match record {
+            LogRecord::Insert { table, key, value } | LogRecord::Set { table, key, value } => {
+                let table = tables().get(table as usize).ok_or(errors::TABLE_IS_NOT_FOUND)?;
+                table.set(BinKey::new(key), BinValue::new(value), log_writer)?;
+            }
+            LogRecord::Delete { table, key } => {
+                let table = tables().get(table as usize).ok_or(errors::TABLE_IS_NOT_FOUND)?;
+                table.delete(&BinKey::new(key), log_writer)?;
+            }
+            LogRecord::CreateTable { engine, name, is_it_logging, cache_duration, user_scheme } => {
+                let number = match storage.table_by_name(name) {
+                    Some((number, _)) => number,
+                    None => create_table(storage, engine, name, is_it_logging, cache_duration, user_scheme, log_writer)?
+                };
+                if number != number_of_created {
+                    return Err(errors::TABLES_OF_NODES_DIFFER);
+                }
+                number_of_created += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn import_records<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    storage: &'static Storage,
+    message: &[u8],
+    log_writer: &mut LogWriter
+) -> Status {
+    let result = import(storage, &message[1..], log_writer);
+    log_writer.flush();
+    match result {
+        Ok(()) => connection.write_message(&[actions::DONE]),
+        Err(error) => connection.write_error(error)
+    }
+}
+
+pub fn set_shards_node<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 7 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let (from, to, node) = (uint::u16(&message[1..3]), uint::u16(&message[3..5]), uint::u16(&message[5..7]));
+    if node as usize >= server.number_of_nodes() {
+        return connection.write_error(errors::NODE_IS_NOT_FOUND);
+    }
+    server.shard_map.set_node(from, to, node);
+    info!("Shards {}..={} are on the node {} now", from, to, node);
+    connection.write_message(&[actions::DONE])
+}
+
+/// Makes the move on its source node and returns the number of moved keys.
+fn make_move(server: &Server, this_node: Option<usize>, step: &Move) -> Result<u64, Error> {
+    if this_node == Some(step.source as usize) {
+        return migrate(server, step.from, step.to, step.target);
+    }
+    let addr = server.first_machine_of(step.source as usize).ok_or(errors::NODE_IS_NOT_FOUND)?;
+    let mut message = vec![actions::MIGRATE_SHARDS];
+    message.extend_from_slice(&set_shards_node_message(step.from, step.to, step.target)[1..]);
+    let answer = replication::connect(&addr, &server.replication.leader_password, MIGRATION_TIMEOUT)
+        .and_then(|mut stream| replication::request(&mut stream, &message))
+        .map_err(|e| {
+            error!("The node {} hasn't moved shards {}..={}: {}", step.source, step.from, step.to, e);
+            errors::MIGRATION_HAS_FAILED
+        })?;
+    if answer.first() != Some(&actions::DONE) {
+        error!("The node {} hasn't moved shards {}..={}: {}", step.source, step.from, step.to, String::from_utf8_lossy(answer.get(3..).unwrap_or(&[])));
+        return Err(errors::MIGRATION_HAS_FAILED);
+    }
+    let stats: Value = serde_json::from_slice(&answer[1..]).unwrap_or_default();
+    Ok(stats["keys"].as_u64().unwrap_or(0))
+}
+
+pub fn rebalance<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if message.len() < 2 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let moves = plan(&server.shard_map.nodes(), server.number_of_nodes());
+    let mut report = Vec::with_capacity(moves.len());
+    if message[1] != 0 {
+        report.extend(moves.iter().map(|step| step.to_json()));
+    } else {
+        let this_node = server.this_node();
+        for step in moves.iter() {
+            match make_move(server, this_node, step) {
+                Ok(keys) => {
+                    let mut json = step.to_json();
+                    json["keys"] = json!(keys);
+                    report.push(json);
+                }
+                Err(error) => return connection.write_error(error)
+            }
+        }
+    }
+    let mut answer = vec![actions::DONE];
+    answer.extend_from_slice(Value::Array(report).to_string().as_bytes());
+    connection.write_message(&answer)
+}
@@ -1,3 +1,17 @@
 pub mod server;
 pub mod cfg;
+pub mod gateway;
+pub mod resp;
+pub mod metrics;
+pub mod info;
+pub mod slowlog;
+pub mod reload;
+pub mod shutdown;
+pub mod replication;
+pub mod failover;
+pub mod sharding;
+pub mod migration;
+pub mod hierarchy;
+pub mod changes;
+pub mod pubsub;
 mod reactions;
\ No newline at end of file
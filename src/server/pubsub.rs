@@ -0,0 +1,397 @@
+//! Pub/sub messaging channels.
+//!
+//! - `[PUBLISH, channel length (2 bytes), channel, message]` sends the message to subscribers of the channel and of patterns,
+//!   that match it. The answer is `[DONE, receivers (4 bytes)]`;
+//! - `[SUBSCRIBE, [length (2 bytes), channel]...]` and `[PSUBSCRIBE, [length (2 bytes), pattern]...]` switch the connection
+//!   into the push mode. Patterns are Redis globs: `*`, `?`, `[abc]`, `[^a-z]` and `\` for escaping, up to 256 bytes;
+//! - `[UNSUBSCRIBE, [is pattern, length (2 bytes), name]...]` removes subscriptions, all of them without names.
+//!
+//! In the push mode the connection gets frames `[DONE, MESSAGE, channel length (2 bytes), channel, message]` and
+//! `[DONE, PATTERN_MESSAGE, pattern length (2 bytes), pattern, channel length (2 bytes), channel, message]`.
+//! Subscriptions and unsubscriptions are answered with `[DONE, SUBSCRIPTIONS, subscriptions (4 bytes)]`, `PING` with `[DONE, PING]`,
+//! and other actions with [`CONNECTION_IS_SUBSCRIBED`](errors::CONNECTION_IS_SUBSCRIBED). The connection returns
+//! to the usual mode, when it has no subscriptions.
+//!
+//! Publishers never wait for subscribers: frames are put to the outbox of the subscriber, and its own thread writes them.
+//! A subscriber with more than `pubsub_max_pending_bytes` in the outbox is disconnected. Messages are delivered only to subscribers
+//! of this machine.
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Condvar, Mutex, RwLock, atomic::{AtomicU64, AtomicUsize, Ordering::SeqCst}},
+    thread
+};
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors::{self, Error}},
+    metrics::METRICS,
+    server::{resp::{glob_match, MAX_PATTERN_LEN}, server::Server},
+    stream::Stream,
+    utils::bytes::uint,
+    warn
+};
+
+pub const MESSAGE: u8 = 0;
+pub const PATTERN_MESSAGE: u8 = 1;
+pub const SUBSCRIPTIONS: u8 = 2;
+
+/// The default of the `pubsub_max_pending_bytes` setting.
+pub const DEFAULT_MAX_PENDING_BYTES: usize = 32 * 1024 * 1024;
+
+#[derive(Default)]
+struct Outbox {
+    frames: VecDeque<Arc<Vec<u8>>>,
+    bytes: usize,
+    /// No frames are added. The pusher writes the rest and stops.
+    is_closed: bool,
+    /// The subscriber is too slow or its connection is broken, so the rest isn't written.
+    is_dropped: bool,
+}
+
+/// A connection in the push mode.
+#[derive(Default)]
+pub struct Subscriber {
+    outbox: Mutex<Outbox>,
+    pushed: Condvar,
+}
+
+impl Subscriber {
+    /// Returns false, if the subscriber is dropped. The subscriber is dropped, if more than `max_bytes` would be pending.
+    /// A frame is always put to the empty outbox, so big messages are delivered to subscribers, that keep up.
+    fn push(&self, frame: Arc<Vec<u8>>, max_bytes: usize) -> bool {
+        let mut outbox = self.outbox.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if outbox.is_closed {
+            return false;
+        }
+        if outbox.bytes > 0 && outbox.bytes + frame.len() > max_bytes {
+            outbox.frames.clear();
+            outbox.is_closed = true;
+            outbox.is_dropped = true;
+            drop(outbox);
+            self.pushed.notify_all();
+            return false;
+        }
+        outbox.bytes += frame.len();
+        outbox.frames.push_back(frame);
+        drop(outbox);
+        self.pushed.notify_all();
+        true
+    }
+
+    fn close(&self, is_dropped: bool) {
+        let mut outbox = self.outbox.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        outbox.is_closed = true;
+        outbox.is_dropped |= is_dropped;
+        drop(outbox);
+        self.pushed.notify_all();
+    }
+
+    /// Waits for frames. Returns `None`, when the outbox is closed and empty or dropped.
+    fn take(&self) -> Option<Vec<Arc<Vec<u8>>>> {
+        let mut outbox = self.outbox.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while outbox.frames.is_empty() && !outbox.is_closed {
+            outbox = self.pushed.wait(outbox).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        if outbox.is_dropped || outbox.frames.is_empty() {
+            return None;
+        }
+        outbox.bytes = 0;
+        Some(outbox.frames.drain(..).collect())
+    }
+
+    fn is_dropped(&self) -> bool {
+        self.outbox.lock().map(|outbox| outbox.is_dropped).unwrap_or(true)
+    }
+}
+
+type Subscribers = RwLock<HashMap<Vec<u8>, HashMap<u64, Arc<Subscriber>>>>;
+
+/// Subscribers of channels and patterns.
+pub struct PubSub {
+    channels: Subscribers,
+    patterns: Subscribers,
+    next_id: AtomicU64,
+    max_pending_bytes: AtomicUsize,
+}
+
+impl Default for PubSub {
+    fn default() -> Self {
+        Self {
+            channels: RwLock::new(HashMap::new()),
+            patterns: RwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+            max_pending_bytes: AtomicUsize::new(DEFAULT_MAX_PENDING_BYTES),
+        }
+    }
+}
+
+impl PubSub {
+    pub fn set_max_pending_bytes(&self, max_pending_bytes: usize) {
+        self.max_pending_bytes.store(max_pending_bytes, SeqCst);
+    }
+
+    /// Puts the message to outboxes of subscribers and returns the number of them.
+    pub fn publish(&self, channel: &[u8], message: &[u8]) -> u32 {
+        let max_bytes = self.max_pending_bytes.load(SeqCst);
+        let mut receivers = 0;
+        if let Some(subscribers) = self.channels.read().unwrap_or_else(|poisoned| poisoned.into_inner()).get(channel) {
+            let mut frame = Vec::with_capacity(4 + channel.len() + message.len());
+            frame.extend_from_slice(&[actions::DONE, MESSAGE]);
+            push_name(&mut frame, channel);
+            frame.extend_from_slice(message);
+            let frame = Arc::new(frame);
+            receivers += subscribers.values().filter(|subscriber| subscriber.push(frame.clone(), max_bytes)).count();
+        }
+        for (pattern, subscribers) in self.patterns.read().unwrap_or_else(|poisoned| poisoned.into_inner()).iter() {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            let mut frame = Vec::with_capacity(6 + pattern.len() + channel.len() + message.len());
+            frame.extend_from_slice(&[actions::DONE, PATTERN_MESSAGE]);
+            push_name(&mut frame, pattern);
+            push_name(&mut frame, channel);
+            frame.extend_from_slice(message);
+            let frame = Arc::new(frame);
+            receivers += subscribers.values().filter(|subscriber| subscriber.push(frame.clone(), max_bytes)).count();
+        }
+        METRICS.pubsub_messages.add(1);
+        receivers as u32
+    }
+
+    fn subscribers(&self, is_pattern: bool) -> &Subscribers {
+        if is_pattern { &self.patterns } else { &self.channels }
+    }
+
+    fn subscribe(&self, is_pattern: bool, name: &[u8], id: u64, subscriber: &Arc<Subscriber>) {
+        let mut subscribers = self.subscribers(is_pattern).write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        subscribers.entry(name.to_vec()).or_default().insert(id, subscriber.clone());
+    }
+
+    fn unsubscribe(&self, is_pattern: bool, name: &[u8], id: u64) {
+        let mut subscribers = self.subscribers(is_pattern).write().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(of_name) = subscribers.get_mut(name) {
+            of_name.remove(&id);
+            if of_name.is_empty() {
+                subscribers.remove(name);
+            }
+        }
+    }
+}
+
+fn push_name(frame: &mut Vec<u8>, name: &[u8]) {
+    frame.extend_from_slice(&uint::u16tob(name.len() as u16));
+    frame.extend_from_slice(name);
+}
+
+/// Reads names of `SUBSCRIBE` and `PSUBSCRIBE` or, with `with_kind`, of `UNSUBSCRIBE`. Returns `None`, if the message ends too early.
+fn names(mut buf: &[u8], with_kind: bool) -> Option<Vec<(bool, &[u8])>> {
+    let mut names = Vec::new();
+    while !buf.is_empty() {
+        let is_pattern = if with_kind {
+            let is_pattern = buf[0] != 0;
+            buf = &buf[1..];
+            is_pattern
+        } else {
+            false
+        };
+        let len = uint::u16(buf.get(..2)?) as usize;
+        names.push((is_pattern, buf.get(2..2 + len)?));
+        buf = &buf[2 + len..];
+    }
+    Some(names)
+}
+
+pub fn publish<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    let channel_len = match message.get(1..3) {
+        Some(len) => uint::u16(len) as usize,
+        None => return connection.write_error(errors::MESSAGE_IS_TOO_SHORT)
+    };
+    let Some(channel) = message.get(3..3 + channel_len) else {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    };
+    let receivers = server.pubsub.publish(channel, &message[3 + channel_len..]);
+    let mut answer = vec![actions::DONE];
+    answer.extend_from_slice(&uint::u32tob(receivers));
+    connection.write_message(&answer)
+}
+
+/// `UNSUBSCRIBE` of a connection without subscriptions.
+pub fn unsubscribe<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    message: &[u8]
+) -> Status {
+    if names(&message[1..], true).is_none() {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    connection.write_message(&[actions::DONE, SUBSCRIPTIONS, 0, 0, 0, 0])
+}
+
+fn error_frame(error: Error) -> Vec<u8> {
+    let mut frame = vec![error.status];
+    frame.extend_from_slice(&uint::u16tob(error.code));
+    frame.extend_from_slice(error.message.as_bytes());
+    frame
+}
+
+/// Writes frames of the outbox to the stream, until the outbox is closed. Shuts the stream down, if the subscriber is dropped,
+/// so the reading thread stops too.
+fn push<S: Stream>(mut stream: S, subscriber: &Subscriber) {
+    let mut buf = Vec::new();
+    while let Some(frames) = subscriber.take() {
+        buf.clear();
+        for frame in frames.iter() {
+            if frame.len() < u16::MAX as usize {
+                buf.extend_from_slice(&uint::u16tob(frame.len() as u16));
+            } else {
+                buf.extend_from_slice(&[255, 255]);
+                buf.extend_from_slice(&uint::u32tob(frame.len() as u32));
+            }
+            buf.extend_from_slice(frame);
+        }
+        if Stream::write_all(&mut stream, &buf).is_err() {
+            subscriber.close(true);
+            break;
+        }
+    }
+    if subscriber.is_dropped() {
+        warn!("The subscriber is disconnected, because it is too slow or its connection is broken");
+        let _ = stream.shutdown();
+    }
+}
+
+/// Subscriptions of one connection.
+struct Session<'pubsub> {
+    pubsub: &'pubsub PubSub,
+    id: u64,
+    subscriber: Arc<Subscriber>,
+    channels: HashSet<Vec<u8>>,
+    patterns: HashSet<Vec<u8>>,
+}
+
+impl Session<'_> {
+    fn len(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+
+    fn names(&mut self, is_pattern: bool) -> &mut HashSet<Vec<u8>> {
+        if is_pattern { &mut self.patterns } else { &mut self.channels }
+    }
+
+    /// Handles the message and returns the answer.
+    fn handle(&mut self, message: &[u8]) -> Vec<u8> {
+        let Some(&action) = message.first() else {
+            return error_frame(errors::MESSAGE_IS_TOO_SHORT);
+        };
+        match action {
+            actions::SUBSCRIBE | actions::PSUBSCRIBE => {
+                let is_pattern = action == actions::PSUBSCRIBE;
+                let names = match names(&message[1..], false) {
+                    Some(names) if !names.is_empty() => names,
+                    _ => return error_frame(errors::MESSAGE_IS_TOO_SHORT)
+                };
+                if is_pattern && names.iter().any(|(_, pattern)| pattern.len() > MAX_PATTERN_LEN) {
+                    return error_frame(errors::PATTERN_IS_TOO_LONG);
+                }
+                for (_, name) in names {
+                    if self.names(is_pattern).insert(name.to_vec()) {
+                        self.pubsub.subscribe(is_pattern, name, self.id, &self.subscriber);
+                    }
+                }
+            }
+            actions::UNSUBSCRIBE => {
+                let Some(names) = names(&message[1..], true) else {
+                    return error_frame(errors::MESSAGE_IS_TOO_SHORT);
+                };
+                if names.is_empty() {
+                    self.unsubscribe_all();
+                }
+                for (is_pattern, name) in names {
+                    if self.names(is_pattern).remove(name) {
+                        self.pubsub.unsubscribe(is_pattern, name, self.id);
+                    }
+                }
+            }
+            actions::PING => return vec![actions::DONE, actions::PING],
+            _ => return error_frame(errors::CONNECTION_IS_SUBSCRIBED)
+        }
+        let mut answer = vec![actions::DONE, SUBSCRIPTIONS];
+        answer.extend_from_slice(&uint::u32tob(self.len() as u32));
+        answer
+    }
+
+    fn unsubscribe_all(&mut self) {
+        for channel in self.channels.drain() {
+            self.pubsub.unsubscribe(false, &channel, self.id);
+        }
+        for pattern in self.patterns.drain() {
+            self.pubsub.unsubscribe(true, &pattern, self.id);
+        }
+    }
+}
+
+impl Drop for Session<'_> {
+    fn drop(&mut self) {
+        self.unsubscribe_all();
+    }
+}
+
+/// Handles the connection in the push mode from the message, that has switched it, until the connection has no subscriptions.
+/// Answers are written by the pusher thread in order with messages.
+pub fn serve<'stream, S: Stream + Send + 'static, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    message: &[u8]
+) -> Status {
+    if connection.flush().is_err() {
+        return Status::Closed;
+    }
+    let pubsub = &server.pubsub;
+    let subscriber = Arc::new(Subscriber::default());
+    let mut session = Session {
+        pubsub,
+        id: pubsub.next_id.fetch_add(1, SeqCst),
+        subscriber: subscriber.clone(),
+        channels: HashSet::new(),
+        patterns: HashSet::new(),
+    };
+    let _subscribed = METRICS.pubsub_subscribers.track();
+    let stream = connection.writer.stream().clone_ptr();
+    let pusher = {
+        let subscriber = subscriber.clone();
+        thread::spawn(move || push(stream, &subscriber))
+    };
+
+    // Answers aren't limited, so the subscriber gets them, even if it is slow.
+    subscriber.push(Arc::new(session.handle(message)), usize::MAX);
+    // The connection leaves the push mode at the end of a request, so the next request is read in the usual mode.
+    let mut status = Status::Closed;
+    while !subscriber.is_dropped() {
+        let (message, message_status) = connection.read_message();
+        match message_status {
+            Status::Ok => {
+                subscriber.push(Arc::new(session.handle(message)), usize::MAX);
+            }
+            Status::All if session.len() == 0 => {
+                status = Status::Ok;
+                break;
+            }
+            Status::All => {
+                if connection.read_request().0 != Status::Ok || server.shutdown.is_requested() {
+                    break;
+                }
+            }
+            _ => break
+        }
+    }
+
+    drop(session);
+    subscriber.close(false);
+    let _ = pusher.join();
+    if subscriber.is_dropped() {
+        return Status::Closed;
+    }
+    status
+}
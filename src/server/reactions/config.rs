@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use serde_json::{Map, Value};
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors},
+    server::{reload, server::Server},
+    stream::Stream,
+    utils::bytes::uint
+};
+
+/// [`actions::CONFIG_GET`, `name`]. Returns all settings as a JSON object, if the name is empty, otherwise only this setting.
+#[inline(always)]
+pub fn config_get<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>,
+    message: &[u8]
+) -> Status {
+    let name = match std::str::from_utf8(&message[1..]) {
+        Ok(name) => name,
+        Err(_) => return connection.write_error(errors::SETTING_NAME_IS_NOT_UTF8)
+    };
+    let config = match server.config.read() {
+        Ok(config) => config,
+        Err(_) => return connection.write_error(errors::CONFIG_LOCK_IS_POISONED)
+    };
+    let settings = if name.is_empty() {
+        config.to_json()
+    } else {
+        match config.get(name) {
+            Some(value) => {
+                let mut settings = Map::with_capacity(1);
+                settings.insert(name.replace('-', "_"), value);
+                Value::Object(settings)
+            }
+            None => return connection.write_error(errors::UNKNOWN_SETTING)
+        }
+    };
+    drop(config);
+    connection.write_message_and_status(settings.to_string().as_bytes(), actions::DONE)
+}
+
+/// [`actions::CONFIG_SET`, `name size` (2 bytes), `name`, `value`]. The value is a string like the value of the flag.
+#[inline(always)]
+pub fn config_set<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>,
+    message: &[u8]
+) -> Status {
+    if message.len() < 3 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let name_size = uint::u16(&message[1..3]) as usize;
+    if message.len() < 3 + name_size {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let (name, value) = match (std::str::from_utf8(&message[3..3 + name_size]), std::str::from_utf8(&message[3 + name_size..])) {
+        (Ok(name), Ok(value)) => (name, value),
+        _ => return connection.write_error(errors::SETTING_NAME_IS_NOT_UTF8)
+    };
+    match reload::set(server, name, value) {
+        Ok(()) => connection.write_message(&[actions::DONE]),
+        Err(e) => connection.write_error(e)
+    }
+}
@@ -1,4 +1,6 @@
 pub mod status;
+pub mod config;
 
 pub mod table;
-pub mod work_with_tables;
\ No newline at end of file
+pub mod work_with_tables;
+pub mod notifications;
\ No newline at end of file
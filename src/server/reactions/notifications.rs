@@ -0,0 +1,70 @@
+use std::{sync::Arc, time::Duration};
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors},
+    server::server::Server,
+    storage::storage::Storage,
+    stream::Stream,
+    table::notifications,
+    utils::bytes::uint
+};
+
+/// [`BinKey`](crate::bin_types::BinKey) stores the length of the key in 2 bytes.
+const MAX_KEY_LEN: usize = u16::MAX as usize;
+
+/// [`actions::SET_NOTIFICATIONS`, `table` (2 bytes), `events`]. Events are bits of [`notifications::ALL`], 0 turns notifications off.
+#[inline(always)]
+pub fn set_notifications<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    storage: &'static Storage,
+    message: &[u8]
+) -> Status {
+    if message.len() != 4 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let events = message[3];
+    if events & !notifications::ALL != 0 {
+        return connection.write_error(errors::EVENTS_ARE_NOT_VALID);
+    }
+    match storage.tables.get().get(uint::u16(&message[1..3]) as usize) {
+        Some(table) => {
+            table.notifications().set_events(events);
+            connection.write_message(&[actions::DONE])
+        }
+        None => connection.write_error(errors::TABLE_IS_NOT_FOUND)
+    }
+}
+
+/// [`actions::WATCH`, `table` (2 bytes), `timeout` in milliseconds (4 bytes), `key`]. Waits, until the key is set, inserted,
+/// deleted or expired, and answers with [`actions::DONE`] and the event. The event is [`notifications::TIMEOUT`], if the timeout
+/// has passed or the shutdown is requested. The timeout 0 means no timeout.
+///
+/// Changes before the message aren't seen, so read the key after the answer.
+#[inline(always)]
+pub fn watch<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>,
+    message: &[u8]
+) -> Status {
+    if message.len() < 7 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let table = uint::u16(&message[1..3]);
+    let key = &message[7..];
+    if key.len() > MAX_KEY_LEN {
+        return connection.write_error(errors::KEY_IS_TOO_LONG);
+    }
+    if server.storage.tables.get().get(table as usize).is_none() {
+        return connection.write_error(errors::TABLE_IS_NOT_FOUND);
+    }
+    // Answers of the previous messages of the request are sent before the wait.
+    if connection.flush().is_err() {
+        return Status::Closed;
+    }
+    let timeout = match uint::u32(&message[3..7]) {
+        0 => None,
+        millis => Some(Duration::from_millis(millis as u64))
+    };
+    let event = server.storage.notifications.watch(table, key, timeout, || server.shutdown.is_requested());
+    connection.write_message(&[actions::DONE, event])
+}
@@ -1,13 +1,10 @@
-use std::{
-    fs::File,
-    io::Read,
-    sync::Arc
-};
+use std::sync::Arc;
 use crate::{
     connection::{BufConnection, BufReader, BufWriter, Status},
     constants::actions,
-    server::server::Server,
-    stream::Stream
+    server::{hierarchy, info::report, server::Server},
+    stream::Stream,
+    utils::bytes::uint
 };
 
 #[inline(always)]
@@ -22,11 +19,8 @@ pub fn get_shard_metadata<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWr
     connection: &mut BufConnection<'stream, S, R, W>,
     server: &Arc<Server>
 ) -> Status {
-    let ref path= server.shard_metadata_file_path;
-    let mut file = File::open(path).expect("Can't open shard metadata file!");
-    let mut buf = Vec::with_capacity(65536 * 2);
-    file.read_to_end(&mut buf).expect("Can't read shard metadata file!");
-    connection.write_message_and_status(&buf, actions::DONE)
+    // The map in memory is the same as the file, but it can't be read in the middle of a change.
+    connection.write_message_and_status(&server.shard_map.to_bytes(), actions::DONE)
 }
 
 #[inline(always)]
@@ -34,20 +28,48 @@ pub fn get_hierarchy<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<
     connection: &mut BufConnection<'stream, S, R, W>,
     server: &Arc<Server>
 ) -> Status {
-    let ref hierarchy = server.hierarchy;
-    // We think that average production machine address length is 20 and average node contains 3 machines.
-    // Anyway get_hierarchy is rare action, so we are ready to be patient, even if it is slow.
-    let mut buf = Vec::with_capacity(hierarchy.len() * 20 * 3);
+    let buf = hierarchy::encode(&server.hierarchy());
+    connection.write_message_and_status(&buf, actions::DONE)
+}
+#[inline(always)]
+pub fn info<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>
+) -> Status {
+    connection.write_message_and_status(report(server).to_string().as_bytes(), actions::DONE)
+}
 
-    let mut machine_addr_len;
-    for machines_addr in hierarchy.iter() {
-        buf.extend_from_slice(&[machines_addr.len() as u8]);
-        for machine_addr in machines_addr.iter() {
-            machine_addr_len = machine_addr.len();
-            buf.extend_from_slice(&[machine_addr_len as u8, (machine_addr_len >> 8) as u8]);
-            buf.extend_from_slice(machine_addr.as_bytes());
-        }
-    }
+/// Number of slow log entries, that are returned, if the message has no count.
+const DEFAULT_SLOWLOG_COUNT: usize = 10;
 
-    connection.write_message_and_status(&buf, actions::DONE)
-}
\ No newline at end of file
+#[inline(always)]
+pub fn slowlog_get<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>,
+    message: &[u8]
+) -> Status {
+    let count = if message.len() >= 3 { uint::u16(&message[1..3]) as usize } else { DEFAULT_SLOWLOG_COUNT };
+    connection.write_message_and_status(server.slowlog.get(count).to_string().as_bytes(), actions::DONE)
+}
+
+#[inline(always)]
+pub fn slowlog_reset<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>
+) -> Status {
+    server.slowlog.reset();
+    connection.write_message(&[actions::DONE])
+}
+
+/// [`actions::SHUTDOWN`, `dump` (optional, 1 byte)]. `dump` overrides the `dump_on_shutdown` setting: 0 is no dump, 1 is a dump.
+/// The answer is sent before the shutdown.
+#[inline(always)]
+pub fn shutdown<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>,
+    message: &[u8]
+) -> Status {
+    let dump = message.get(1).map(|dump| *dump != 0);
+    server.shutdown.request(dump);
+    connection.write_message(&[actions::DONE])
+}
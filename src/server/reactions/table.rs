@@ -1,14 +1,59 @@
 use crate::{
     connection::{BufWriter, BufReader, Status, BufConnection},
-    constants::actions,
+    constants::{actions, errors::{self, Error}},
     index::HashInMemoryIndex,
     scheme::scheme::{empty_scheme, scheme_from_bytes},
-    storage::storage::Storage,
+    storage::{log_record, storage::{Storage, CANT_CREATE_TABLE_NUMBER}},
     stream::Stream,
+    table::table::TableEngine,
     utils::bytes::uint,
     writers::{LogWriter}
 };
 
+/// Checks the table and creates it. It is used by all ways to create a table, so they return the same errors.
+///
+/// Returns the number of the created table.
+pub(crate) fn create_table(
+    storage: &'static Storage,
+    engine: TableEngine,
+    name: &[u8],
+    is_it_logging: bool,
+    cache_duration: u64,
+    user_scheme: &[u8],
+    log_writer: &mut LogWriter
+) -> Result<usize, Error> {
+    let scheme = if user_scheme.is_empty() {
+        empty_scheme()
+    } else {
+        scheme_from_bytes(user_scheme)?
+    };
+    let name = match String::from_utf8(name.to_vec()) {
+        Ok(name) => name,
+        Err(_) => return Err(errors::TABLE_NAME_IS_NOT_UTF8),
+    };
+    if name.len() > u16::MAX as usize {
+        return Err(errors::TABLE_NAME_IS_TOO_LONG);
+    }
+    if storage.table_exists(&name) {
+        return Err(errors::TABLE_ALREADY_EXISTS);
+    }
+
+    let mut buf = Vec::with_capacity(name.len() + 14 + user_scheme.len());
+    log_record::encode_create_table(&mut buf, engine, name.as_bytes(), is_it_logging, cache_duration, user_scheme);
+    log_writer.write_slice(&buf);
+
+    let number = match engine {
+        TableEngine::InMemory => Storage::create_in_memory_table(storage, name, HashInMemoryIndex::new(), is_it_logging, scheme, user_scheme),
+        TableEngine::OnDisk => Storage::create_on_disk_table(storage, name, HashInMemoryIndex::new(), scheme, user_scheme),
+        TableEngine::CACHE => Storage::create_cache_table(storage, name, HashInMemoryIndex::new(), cache_duration, is_it_logging, scheme, user_scheme),
+        TableEngine::Lsm => Storage::create_lsm_table(storage, name, scheme, user_scheme),
+    };
+    if number == CANT_CREATE_TABLE_NUMBER {
+        return Err(errors::CANT_CREATE_TABLE);
+    }
+    Ok(number)
+}
+
 #[inline(always)]
 pub fn create_table_in_memory<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
     connection: &mut BufConnection<'stream, S, R, W>,
@@ -17,162 +62,77 @@ pub fn create_table_in_memory<'stream, S: Stream, R: BufReader<'stream, S>, W: B
     log_writer: &mut LogWriter
 ) -> Status {
     if message.len() < 7 {
-        return connection.write_message(&[actions::BAD_REQUEST]);
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
     let is_it_logging = message[1] != 0;
     let scheme_len = ((message[3] as u16) << 8 | message[2] as u16) as usize;
     if scheme_len + 4 + 2 > message.len() {
-        return connection.write_message(&[actions::BAD_REQUEST]);
-    }
-    let user_scheme: &[u8];
-    let scheme;
-    if scheme_len == 0 {
-        user_scheme = &[];
-        scheme = Ok(empty_scheme());
-    } else {
-        user_scheme = &message[4..4 + scheme_len];
-        scheme = scheme_from_bytes(user_scheme);
-        if scheme.is_err() {
-            return connection.write_message(&[actions::BAD_REQUEST]);
-        }
-    }
-
-    let name = String::from_utf8(message[4 + scheme_len..].to_vec()).unwrap();
-    let name_len = name.len();
-    {
-        let mut buf = vec![0; name_len + 6 + scheme_len];
-        buf[0] = actions::CREATE_TABLE_IN_MEMORY;
-        buf[1] = name_len as u8;
-        buf[2] = (name_len >> 8) as u8;
-        buf[3] = if is_it_logging { 1 } else { 0 };
-        let mut offset = 4;
-        buf[offset..offset + name_len].copy_from_slice(name.as_bytes());
-        offset += name_len;
-        buf[offset] = scheme_len as u8;
-        offset += 1;
-        buf[offset] = (scheme_len >> 8) as u8;
-        offset += 1;
-        buf[offset..].copy_from_slice(user_scheme);
-        log_writer.write_slice(&buf);
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
 
-
-    let l = Storage::create_in_memory_table(storage, name, HashInMemoryIndex::new(), is_it_logging, scheme.unwrap(), user_scheme);
-    if l == (u16::MAX - 1u16) as usize {
-        return connection.write_message(&[actions::BAD_REQUEST]);
+    let user_scheme = &message[4..4 + scheme_len];
+    let name = &message[4 + scheme_len..];
+    match create_table(storage, TableEngine::InMemory, name, is_it_logging, 0, user_scheme, log_writer) {
+        Ok(l) => connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8]),
+        Err(error) => connection.write_error(error)
     }
-    
-    
-    connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8])
 }
 
 #[inline(always)]
 pub fn create_table_on_disk<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (connection: &mut BufConnection<'stream, S, R, W>, storage: &'static Storage, message: &[u8],  log_writer: &mut LogWriter) -> Status {
     if message.len() < 6 {
-        return connection.write_message(&[actions::BAD_REQUEST]);
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
     let scheme_len = ((message[2] as u16) << 8 | message[1] as u16) as usize;
     if scheme_len + 4 + 2 > message.len() {
-        return connection.write_message(&[actions::BAD_REQUEST]);
-    }
-    let user_scheme: &[u8];
-    let scheme;
-    if scheme_len == 0 {
-        user_scheme = &[];
-        scheme = Ok(empty_scheme());
-    } else {
-        user_scheme = &message[3..3 + scheme_len];
-        scheme = scheme_from_bytes(user_scheme);
-        if scheme.is_err() {
-            return connection.write_message(&[actions::BAD_REQUEST]);
-        }
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
 
-    let name = String::from_utf8(message[3 + scheme_len..].to_vec()).unwrap();
-    let name_len = name.len();
-    {
-        let mut buf = vec![0; name_len + 5 + scheme_len];
-        buf[0] = actions::CREATE_TABLE_ON_DISK;
-        buf[1] = name_len as u8;
-        buf[2] = (name_len >> 8) as u8;
-        let mut offset = 3;
-        buf[offset..offset + name_len].copy_from_slice(name.as_bytes());
-        offset += name_len;
-        buf[offset] = scheme_len as u8;
-        offset += 1;
-        buf[offset] = (scheme_len >> 8) as u8;
-        offset += 1;
-        buf[offset..].copy_from_slice(user_scheme);
-        log_writer.write_slice(&buf);
+    let user_scheme = &message[3..3 + scheme_len];
+    let name = &message[3 + scheme_len..];
+    match create_table(storage, TableEngine::OnDisk, name, false, 0, user_scheme, log_writer) {
+        Ok(l) => connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8]),
+        Err(error) => connection.write_error(error)
     }
+}
 
-
-    let l = Storage::create_on_disk_table(storage, name, HashInMemoryIndex::new(), scheme.unwrap(), user_scheme);
-    if l == (u16::MAX - 1u16) as usize {
-        return connection.write_message(&[actions::BAD_REQUEST]);
+/// The message is the same as for [`create_table_on_disk`].
+#[inline(always)]
+pub fn create_table_lsm<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (connection: &mut BufConnection<'stream, S, R, W>, storage: &'static Storage, message: &[u8],  log_writer: &mut LogWriter) -> Status {
+    if message.len() < 6 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let scheme_len = ((message[2] as u16) << 8 | message[1] as u16) as usize;
+    if scheme_len + 4 + 2 > message.len() {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
 
-
-    connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8])
+    let user_scheme = &message[3..3 + scheme_len];
+    let name = &message[3 + scheme_len..];
+    match create_table(storage, TableEngine::Lsm, name, true, 0, user_scheme, log_writer) {
+        Ok(l) => connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8]),
+        Err(error) => connection.write_error(error)
+    }
 }
 
 #[inline(always)]
 pub fn create_table_cache<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (connection: &mut BufConnection<'stream, S, R, W>, storage: &'static Storage, message: &[u8],  log_writer: &mut LogWriter) -> Status {
-    if message.len() < 11 {
-        return connection.write_message(&[actions::BAD_REQUEST]);
+    if message.len() < 12 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
     let is_it_logging = message[1] != 0;
     let cache_duration = uint::u64(&message[2..10]);
     let scheme_len = ((message[11] as u16) << 8 | message[10] as u16) as usize;
     if scheme_len + 12 + 2 > message.len() {
-        return connection.write_message(&[actions::BAD_REQUEST]);
-    }
-    let user_scheme: &[u8];
-    let scheme;
-    if scheme_len == 0 {
-        user_scheme = &[];
-        scheme = Ok(empty_scheme());
-    } else {
-        user_scheme = &message[12..12 + scheme_len];
-        scheme = scheme_from_bytes(user_scheme);
-        if scheme.is_err() {
-            return connection.write_message(&[actions::BAD_REQUEST]);
-        }
-    }
-
-    let name = String::from_utf8(message[12 + scheme_len..].to_vec()).unwrap();
-    let name_len = name.len();
-    {
-        // TODO: maybe extra two bytes?
-        let mut buf = vec![0; name_len + 14 + scheme_len];
-        buf[0] = actions::CREATE_TABLE_CACHE;
-        buf[1] = name_len as u8;
-        buf[2] = (name_len >> 8) as u8;
-        buf[3] = if is_it_logging { 1 } else { 0 };
-        buf[4] = (cache_duration >> 56) as u8;
-        buf[5] = (cache_duration >> 48) as u8;
-        buf[6] = (cache_duration >> 40) as u8;
-        buf[7] = (cache_duration >> 32) as u8;
-        buf[8] = (cache_duration >> 24) as u8;
-        buf[9] = (cache_duration >> 16) as u8;
-        buf[10] = (cache_duration >> 8) as u8;
-        buf[11] = cache_duration as u8;
-        let mut offset = 12;
-        buf[offset..offset + name_len].copy_from_slice(name.as_bytes());
-        offset += name_len;
-        buf[offset] = scheme_len as u8;
-        offset += 1;
-        buf[offset] = (scheme_len >> 8) as u8;
-        offset += 1;
-        buf[offset..].copy_from_slice(user_scheme);
-        log_writer.write_slice(&buf);
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
     }
 
-    let l = Storage::create_cache_table(storage, name, HashInMemoryIndex::new(), cache_duration, is_it_logging, scheme.unwrap(), user_scheme);
-    if l == (u16::MAX - 1u16) as usize {
-        return connection.write_message(&[actions::BAD_REQUEST]);
+    let user_scheme = &message[12..12 + scheme_len];
+    let name = &message[12 + scheme_len..];
+    match create_table(storage, TableEngine::CACHE, name, is_it_logging, cache_duration, user_scheme, log_writer) {
+        Ok(l) => connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8]),
+        Err(error) => connection.write_error(error)
     }
-    connection.write_message(&[actions::DONE, l as u8, ((l as u16) >> 8) as u8])
 }
 
 #[inline(always)]
@@ -184,12 +144,12 @@ pub fn get_tables_names<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWrit
             tables_names = tables_names_unwrapped;
         }
         Err(_) => {
-            return connection.write_message(&[actions::INTERNAL_ERROR]);
+            return connection.write_error(errors::TABLES_LOCK_IS_POISONED);
         }
     }
 
     let mut local_buffer = Vec::with_capacity(4096);
-    local_buffer[0] = actions::DONE;
+    local_buffer.push(actions::DONE);
     for name in tables_names.iter() {
         let name_len = name.len();
         if name_len < u16::MAX as usize {
@@ -201,4 +161,21 @@ pub fn get_tables_names<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWrit
     }
 
     connection.write_message(&local_buffer)
+}
+
+#[inline(always)]
+pub fn get_table_scheme<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    connection: &mut BufConnection<'stream, S, R, W>,
+    storage: &'static Storage,
+    message: &[u8]
+) -> Status {
+    if message.len() < 3 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
+    let tables = storage.tables.get();
+    match tables.get(uint::u16(&message[1..3]) as usize) {
+        // Empty scheme is an empty message, like in create_table_*.
+        Some(table) => connection.write_message_and_status(&table.user_scheme(), actions::DONE),
+        None => connection.write_error(errors::TABLE_IS_NOT_FOUND)
+    }
 }
\ No newline at end of file
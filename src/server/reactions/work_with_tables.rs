@@ -1,31 +1,44 @@
 use crate::{
     bin_types::{BinKey, BinValue},
     connection::{BufConnection, BufReader, BufWriter, Status},
-    constants::actions,
+    constants::{actions, errors},
+    scheme::scheme::is_value_valid,
     storage::storage::Storage,
     stream::Stream,
     utils::bytes::uint,
     writers::LogWriter
 };
 
+/// [`BinKey`] stores the length of the key in 2 bytes.
+const MAX_KEY_LEN: usize = u16::MAX as usize;
+
 #[inline(always)]
 pub fn get<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
     connection: &mut BufConnection<'stream, S, R, W>,
     storage: &'static Storage,
     message: &[u8]
 ) -> Status {
+    if message.len() < 3 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let tables = storage.tables.get();
     return match tables.get(uint::u16(&message[1..3]) as usize) {
         Some(table) => {
-            let res = table.get(&BinKey::new(&message[3..]));
+            if message.len() - 3 > MAX_KEY_LEN {
+                return connection.write_error(errors::KEY_IS_TOO_LONG);
+            }
+            let res = match table.get(&BinKey::new(&message[3..])) {
+                Ok(res) => res,
+                Err(error) => return connection.write_error(error)
+            };
             if res.is_none() {
-                connection.write_message(&[actions::NOT_FOUND]);
+                return connection.write_error(errors::KEY_IS_NOT_FOUND);
             }
             let value = unsafe { res.unwrap_unchecked() };
             connection.write_message_and_status(value.deref(), actions::DONE)
         }
         None => {
-           connection.write_message(&[actions::TABLE_NOT_FOUND])
+            connection.write_error(errors::TABLE_IS_NOT_FOUND)
         }
     };
 }
@@ -36,19 +49,31 @@ pub fn get_field<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'str
     storage: &'static Storage,
     message: &[u8]
 ) -> Status {
+    if message.len() < 5 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let tables = storage.tables.get();
     return match tables.get(uint::u16(&message[1..3]) as usize) {
         Some(table) => {
-            let field = uint::u16(&message[3..5]);
-            let res = table.get_field(&BinKey::new(&message[5..]), field as usize);
+            let field = uint::u16(&message[3..5]) as usize;
+            if field >= table.scheme().len() {
+                return connection.write_error(errors::FIELD_IS_NOT_FOUND);
+            }
+            if message.len() - 5 > MAX_KEY_LEN {
+                return connection.write_error(errors::KEY_IS_TOO_LONG);
+            }
+            let res = match table.get_field(&BinKey::new(&message[5..]), field) {
+                Ok(res) => res,
+                Err(error) => return connection.write_error(error)
+            };
             if res.is_none() {
-                return connection.write_message(&[actions::NOT_FOUND]);
+                return connection.write_error(errors::KEY_IS_NOT_FOUND);
             }
             let value = unsafe { res.unwrap_unchecked() };
             connection.write_message_and_status(&value, actions::DONE)
         }
         None => {
-           connection.write_message(&[actions::TABLE_NOT_FOUND])
+            connection.write_error(errors::TABLE_IS_NOT_FOUND)
         }
     };
 }
@@ -59,23 +84,41 @@ pub fn get_fields<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'st
     storage: &'static Storage,
     message: &[u8]
 ) -> Status {
+    if message.len() < 5 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let tables = storage.tables.get();
     return match tables.get(uint::u16(&message[1..3]) as usize) {
         Some(table) => {
             let number_of_fields = uint::u16(&message[3..5]) as usize;
+            let key_offset = 5 + number_of_fields * 2;
+            if message.len() < key_offset {
+                return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+            }
+            let number_of_fields_in_scheme = table.scheme().len();
             let mut fields = Vec::with_capacity(number_of_fields);
             for i in 0..number_of_fields {
-                fields.push(uint::u16(&message[5+i*2..5+i*2+2]) as usize);
+                let field = uint::u16(&message[5+i*2..5+i*2+2]) as usize;
+                if field >= number_of_fields_in_scheme {
+                    return connection.write_error(errors::FIELD_IS_NOT_FOUND);
+                }
+                fields.push(field);
+            }
+            if message.len() - key_offset > MAX_KEY_LEN {
+                return connection.write_error(errors::KEY_IS_TOO_LONG);
             }
-            let res = table.get_fields(&BinKey::new(&message[5..]), &fields);
+            let res = match table.get_fields(&BinKey::new(&message[key_offset..]), &fields) {
+                Ok(res) => res,
+                Err(error) => return connection.write_error(error)
+            };
             if res.is_none() {
-                return connection.write_message(&[actions::NOT_FOUND])
+                return connection.write_error(errors::KEY_IS_NOT_FOUND);
             }
             let value = unsafe { res.unwrap_unchecked() };
             connection.write_message_and_status(&value, actions::DONE)
         }
         None => {
-           connection.write_message(&[actions::TABLE_NOT_FOUND])
+            connection.write_error(errors::TABLE_IS_NOT_FOUND)
         }
     };
 }
@@ -87,17 +130,28 @@ pub fn insert<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream
     message: &[u8],
     log_writer: &mut LogWriter
 ) -> Status {
+    if message.len() < 5 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let tables = storage.tables.get();
     let key_size = uint::u16(&message[3..5]) as usize;
+    if message.len() < 5 + key_size {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let key = &message[5..5+key_size];
     let value = &message[5+key_size..];
     return match tables.get(uint::u16(&message[1..3]) as usize) {
         Some(table) => {
-            table.insert(BinKey::new(key), BinValue::new(value), log_writer);
+            if !is_value_valid(value, table.scheme()) {
+                return connection.write_error(errors::VALUE_DOES_NOT_MATCH_SCHEME);
+            }
+            if let Err(error) = table.insert(BinKey::new(key), BinValue::new(value), log_writer) {
+                return connection.write_error(error);
+            }
             connection.write_message(&[actions::DONE])
         }
         None => {
-           connection.write_message(&[actions::TABLE_NOT_FOUND])
+            connection.write_error(errors::TABLE_IS_NOT_FOUND)
         }
     };
 }
@@ -109,17 +163,28 @@ pub fn set<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S
     message: &[u8],
     log_writer: &mut LogWriter
 ) -> Status {
+    if message.len() < 5 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let tables = storage.tables.get();
     let key_size = uint::u16(&message[3..5]) as usize;
+    if message.len() < 5 + key_size {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let key = &message[5..5+key_size];
     let value = &message[5+key_size..];
     return match tables.get(uint::u16(&message[1..3]) as usize) {
         Some(table) => {
-            table.set(BinKey::new(key), BinValue::new(value), log_writer);
+            if !is_value_valid(value, table.scheme()) {
+                return connection.write_error(errors::VALUE_DOES_NOT_MATCH_SCHEME);
+            }
+            if let Err(error) = table.set(BinKey::new(key), BinValue::new(value), log_writer) {
+                return connection.write_error(error);
+            }
             connection.write_message(&[actions::DONE])
         }
         None => {
-           connection.write_message(&[actions::TABLE_NOT_FOUND])
+            connection.write_error(errors::TABLE_IS_NOT_FOUND)
         }
     };
 }
@@ -131,15 +196,23 @@ pub fn delete<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream
     message: &[u8],
     log_writer: &mut LogWriter
 ) -> Status {
+    if message.len() < 3 {
+        return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+    }
     let tables = storage.tables.get();
     let key = &message[3..];
+    if key.len() > MAX_KEY_LEN {
+        return connection.write_error(errors::KEY_IS_TOO_LONG);
+    }
     return match tables.get(uint::u16(&message[1..3]) as usize) {
         Some(table) => {
-            table.delete(&BinKey::new(key), log_writer);
+            if let Err(error) = table.delete(&BinKey::new(key), log_writer) {
+                return connection.write_error(error);
+            }
             connection.write_message(&[actions::DONE])
         }
         None => {
-           connection.write_message(&[actions::TABLE_NOT_FOUND])
+            connection.write_error(errors::TABLE_IS_NOT_FOUND)
         }
     };
-}
\ No newline at end of file
+}
@@ -0,0 +1,123 @@
+//! Applies reloadable settings at runtime: by `CONFIG_SET` and when the config file is changed.
+//!
+//! When the file is changed, it is read again with the same environment variables and flags, so changes made by `CONFIG_SET`
+//! are replaced by the file. Changes of settings, that aren't reloadable, are ignored until the restart.
+use std::{
+    fs,
+    sync::{Arc, atomic::Ordering::{Relaxed, SeqCst}},
+    thread,
+    time::{Duration, SystemTime}
+};
+use serde_json::Value;
+use crate::{
+    console::logger::{self, Filter, Format},
+    constants::errors::{self, Error},
+    error,
+    http::MAX_BODY_SIZE,
+    info,
+    lsm::bloom,
+    server::{cfg::{find_setting, Config, SETTINGS}, server::Server},
+    success, warn
+};
+
+/// How often the modification time of the config file is checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Applies reloadable settings of the config. Other settings are used only on the start.
+pub fn apply(server: &Server, config: &Config) {
+    server.storage.dump_interval.store(config.dump_interval, SeqCst);
+    server.storage.retained_logs.store(config.retained_logs, SeqCst);
+    logger::set_filter(Filter::parse(&config.log_level));
+    if let Some(format) = Format::parse(&config.log_format) {
+        logger::set_format(format);
+    }
+    server.slowlog.set_threshold(Duration::from_micros(config.slowlog_threshold_micros));
+    server.slowlog.set_max_len(config.slowlog_max_len);
+    MAX_BODY_SIZE.store(config.http_max_body_bytes, Relaxed);
+    server.storage.log_file.backlog.set_max_len(config.replication_backlog_bytes);
+    server.failover.set_timeouts(Duration::from_millis(config.heartbeat_interval_millis), Duration::from_millis(config.failover_timeout_millis));
+    server.pubsub.set_max_pending_bytes(config.pubsub_max_pending_bytes);
+    server.storage.disk_cache.set_capacity(config.disk_cache_bytes);
+    bloom::set_false_positive_rate(config.bloom_false_positive_rate);
+}
+
+fn value_to_string(value: Value) -> String {
+    match value {
+        Value::String(value) => value,
+        value => value.to_string()
+    }
+}
+
+/// Sets the reloadable setting of the running server. It is used by `CONFIG_SET`.
+pub fn set(server: &Server, name: &str, value: &str) -> Result<(), Error> {
+    let setting = find_setting(name).ok_or(errors::UNKNOWN_SETTING)?;
+    if !setting.is_reloadable {
+        return Err(errors::SETTING_IS_NOT_RELOADABLE);
+    }
+    let mut current = server.config.write().map_err(|_| errors::CONFIG_LOCK_IS_POISONED)?;
+    let mut config = current.clone();
+    if let Err(e) = config.set(setting.name, value) {
+        warn!("CONFIG_SET failed: {}", e);
+        return Err(errors::SETTING_VALUE_IS_NOT_VALID);
+    }
+    apply(server, &config);
+    *current = config;
+    info!("The setting \"{}\" was set to: {} using CONFIG_SET", setting.name, value);
+    Ok(())
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reads the changed file and applies its reloadable settings.
+fn reload(server: &Server) {
+    let mut current = match server.config.write() {
+        Ok(current) => current,
+        Err(_) => return
+    };
+    let reloaded = match current.reload() {
+        Ok(reloaded) => reloaded,
+        Err(e) => {
+            error!("The config file was changed, but it is wrong: {}. The old config is used.", e);
+            return;
+        }
+    };
+
+    let mut config = current.clone();
+    for setting in SETTINGS.iter() {
+        let value = reloaded.get(setting.name);
+        if value == config.get(setting.name) {
+            continue;
+        }
+        if !setting.is_reloadable {
+            warn!("The setting \"{}\" was changed in the config file, but it is used only after the restart", setting.name);
+            continue;
+        }
+        let value = value_to_string(value.unwrap_or(Value::Null));
+        // The value is already validated by `Config::reload`.
+        if config.set(setting.name, &value).is_ok() {
+            info!("The setting \"{}\" was set to: {} using the config file", setting.name, value);
+        }
+    }
+    apply(server, &config);
+    *current = config;
+    success!("The config file was reloaded");
+}
+
+/// Watches the config file and reloads it, when it is changed.
+pub fn run(server: Arc<Server>) {
+    let path = match server.config.read() {
+        Ok(config) => config.config_file.clone(),
+        Err(_) => return
+    };
+    let mut last_modified = modified(&path);
+    loop {
+        thread::sleep(CHECK_INTERVAL);
+        let now_modified = modified(&path);
+        if now_modified != last_modified {
+            last_modified = now_modified;
+            reload(&server);
+        }
+    }
+}
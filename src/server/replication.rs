@@ -0,0 +1,497 @@
+//! Leader-follower replication by shipping the log.
+//!
+//! A follower is a server with the `leader_addr` setting. It connects to the leader, sends the `leader_password`
+//! and the request `[REPLICATE]`. The leader answers with frames, that are messages of the binary protocol:
+//!
+//! - `[DONE, SNAPSHOT, offset (8 bytes)]`: the snapshot starts, the tail will start at `offset` of the
+//!   [`ReplicationBacklog`](crate::writers::ReplicationBacklog);
+//! - `[DONE, RECORDS, end offset (8 bytes), records]`: records of the log (see [`crate::storage::log_record`]).
+//!   Records of the snapshot create all tables and insert all keys. Records of the tail can be split between frames;
+//! - `[DONE, SNAPSHOT_END]`;
+//! - `[DONE, HEARTBEAT, offset (8 bytes)]`: is sent every [`HEARTBEAT_INTERVAL`], if there are no new records;
+//! - the error [`FOLLOWER_IS_TOO_FAR_BEHIND`](errors::FOLLOWER_IS_TOO_FAR_BEHIND), then the leader closes the connection.
+//!
+//! The follower applies records with `*_without_log` methods of tables and rejects writes of clients.
+//! It gets a new snapshot after every reconnection. Machines of a node, that aren't its leader, follow the leader,
+//! and [`crate::server::failover`] changes the leader address, when the leader fails.
+//!
+//! Tables without logging and on-disk tables don't write the log, but their records are sent to followers too
+//! (see [`LogWriter::replicate_key_and_value`](crate::writers::LogWriter::replicate_key_and_value)), so followers have the same data.
+//! These records are only in the backlog, not in log files, so a follower, that is too far behind, needs a new snapshot as usual.
+use std::{
+    collections::HashMap,
+    io::{self, BufReader as IoBufReader, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicBool, AtomicU64, Ordering::SeqCst}},
+    thread,
+    time::{Duration, Instant}
+};
+use serde_json::{json, Value};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors::{self, Error}},
+    console::logger,
+    index::HashInMemoryIndex,
+    metrics::{GaugeGuard, METRICS},
+    scheme::scheme::{empty_scheme, scheme_from_bytes},
+    server::server::Server,
+    storage::{log_record::{self, LogRecord}, storage::{Storage, CANT_CREATE_TABLE_NUMBER}},
+    stream::Stream,
+    table::table::TableEngine,
+    utils::bytes::uint,
+    error, info, success, warn
+};
+
+pub const SNAPSHOT: u8 = 0;
+pub const RECORDS: u8 = 1;
+pub const SNAPSHOT_END: u8 = 2;
+pub const HEARTBEAT: u8 = 3;
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// The follower reconnects, if the leader sends nothing for this time.
+const LEADER_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+/// The leader sends records in frames of this size at most.
+const MAX_FRAME_BYTES: usize = 256 * 1024;
+
+/// State of the follower and of the leader's followers for `INFO`.
+pub struct Replication {
+    /// Empty address means, that the server is a leader.
+    leader_addr: RwLock<String>,
+    pub(crate) leader_password: String,
+
+    is_connected: AtomicBool,
+    is_synced: AtomicBool,
+    applied_offset: AtomicU64,
+    leader_offset: AtomicU64,
+    /// When the follower had applied everything, that the leader had written.
+    caught_up_at: Mutex<Instant>,
+
+    /// Connected followers of the leader by the id: the address and the offset, that was sent.
+    followers: Mutex<HashMap<u64, (String, u64)>>,
+    next_follower_id: AtomicU64,
+}
+
+impl Replication {
+    pub fn new(leader_addr: &str, leader_password: &str) -> Self {
+        Self {
+            leader_addr: RwLock::new(leader_addr.to_string()),
+            leader_password: leader_password.to_string(),
+            is_connected: AtomicBool::new(false),
+            is_synced: AtomicBool::new(false),
+            applied_offset: AtomicU64::new(0),
+            leader_offset: AtomicU64::new(0),
+            caught_up_at: Mutex::new(Instant::now()),
+            followers: Mutex::new(HashMap::new()),
+            next_follower_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Followers reject writes of clients.
+    #[inline(always)]
+    pub fn is_follower(&self) -> bool {
+        self.leader_addr.read().map(|addr| !addr.is_empty()).unwrap_or(false)
+    }
+
+    pub fn leader_addr(&self) -> String {
+        self.leader_addr.read().map(|addr| addr.clone()).unwrap_or_default()
+    }
+
+    /// Makes the server a follower of `addr` or a leader, if `addr` is empty. The follower reconnects to the new leader.
+    pub(crate) fn set_leader_addr(&self, addr: &str) {
+        if let Ok(mut leader_addr) = self.leader_addr.write() {
+            *leader_addr = addr.to_string();
+        }
+    }
+
+    /// Bytes of the leader's log, that the follower hasn't applied yet.
+    pub fn lag_bytes(&self) -> u64 {
+        self.leader_offset.load(SeqCst).saturating_sub(self.applied_offset.load(SeqCst))
+    }
+
+    /// Time since the follower was in sync with the leader. It is zero, if the follower is in sync now.
+    pub fn lag(&self) -> Duration {
+        if self.is_connected.load(SeqCst) && self.is_synced.load(SeqCst) && self.lag_bytes() == 0 {
+            return Duration::ZERO;
+        }
+        self.caught_up_at.lock().map(|caught_up_at| caught_up_at.elapsed()).unwrap_or_default()
+    }
+
+    fn set_offsets(&self, applied_offset: u64, leader_offset: u64) {
+        self.applied_offset.store(applied_offset, SeqCst);
+        self.leader_offset.store(leader_offset, SeqCst);
+        let lag_bytes = leader_offset.saturating_sub(applied_offset);
+        METRICS.replication_lag_bytes.set(lag_bytes as i64);
+        if lag_bytes == 0 && self.is_synced.load(SeqCst) {
+            if let Ok(mut caught_up_at) = self.caught_up_at.lock() {
+                *caught_up_at = Instant::now();
+            }
+        }
+    }
+
+    pub fn report(&self, server: &Server) -> Value {
+        if self.is_follower() {
+            return json!({
+                "role": "follower",
+                "leader_addr": self.leader_addr(),
+                "is_connected": self.is_connected.load(SeqCst),
+                "is_synced": self.is_synced.load(SeqCst),
+                "applied_offset": self.applied_offset.load(SeqCst),
+                "leader_offset": self.leader_offset.load(SeqCst),
+                "lag_bytes": self.lag_bytes(),
+                "lag_millis": self.lag().as_millis() as u64,
+            });
+        }
+        let backlog = &server.storage.log_file.backlog;
+        let offset = backlog.end_offset();
+        let followers: Vec<Value> = self.followers.lock().map(|followers| followers.values().map(|(addr, sent_offset)| json!({
+            "addr": addr,
+            "sent_offset": sent_offset,
+            "lag_bytes": offset.saturating_sub(*sent_offset),
+        })).collect()).unwrap_or_default();
+        json!({
+            "role": "leader",
+            "offset": offset,
+            "is_backlog_enabled": backlog.is_enabled(),
+            "followers": followers,
+        })
+    }
+}
+
+/// Removes the follower from the list of the leader, when the stream ends.
+struct FollowerGuard<'replication> {
+    replication: &'replication Replication,
+    id: u64,
+    _connected: GaugeGuard,
+}
+
+impl Drop for FollowerGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut followers) = self.replication.followers.lock() {
+            followers.remove(&self.id);
+        }
+    }
+}
+
+impl FollowerGuard<'_> {
+    fn sent(&self, offset: u64) {
+        if let Ok(mut followers) = self.replication.followers.lock() {
+            if let Some((_, sent_offset)) = followers.get_mut(&self.id) {
+                *sent_offset = offset;
+            }
+        }
+    }
+}
+
+pub(crate) fn write_frame<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    frame: &[u8]
+) -> bool {
+    connection.write_message(frame) == Status::Ok && connection.flush().is_ok()
+}
+
+/// Sends all tables as records. Writes, that happen now, can be in the snapshot and in the tail too,
+/// but the follower applies the tail after the snapshot, so it gets the same state.
+fn send_snapshot<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    storage: &'static Storage,
+    offset: u64
+) -> bool {
+    let new_frame = || {
+        let mut frame = Vec::with_capacity(MAX_FRAME_BYTES + 1024);
+        frame.extend_from_slice(&[actions::DONE, RECORDS]);
+        frame.extend_from_slice(&uint::u64tob(offset));
+        frame
+    };
+    let names = storage.tables_names.read().map(|names| names.clone()).unwrap_or_default();
+    let mut frame = new_frame();
+    for (number, (table, name)) in storage.tables.get().iter().zip(names.iter()).enumerate() {
+        let engine = table.engine();
+        // On-disk tables don't log, and only cache tables have the duration.
+        let is_it_logging = engine != TableEngine::OnDisk && table.is_it_logging();
+        let cache_duration = if engine == TableEngine::CACHE { table.cache_duration() } else { 0 };
+        log_record::encode_create_table(&mut frame, engine, name.as_bytes(), is_it_logging, cache_duration, &table.user_scheme());
+        let keys = match table.keys() {
+            Ok(keys) => keys,
+            Err(error) => {
+                error!("Can't send the snapshot of the table {}: {}", name, error.message);
+                return false;
+            }
+        };
+        for key in keys {
+            match table.get(&BinKey::new(&key)) {
+                Ok(Some(value)) => log_record::encode_key_and_value(&mut frame, actions::INSERT, number as u16, &key, value.deref()),
+                Ok(None) => {}
+                Err(error) => {
+                    error!("Can't send the snapshot of the table {}: {}", name, error.message);
+                    return false;
+                }
+            }
+            if frame.len() >= MAX_FRAME_BYTES {
+                if !write_frame(connection, &frame) {
+                    return false;
+                }
+                frame = new_frame();
+            }
+        }
+    }
+    write_frame(connection, &frame) && write_frame(connection, &[actions::DONE, SNAPSHOT_END])
+}
+
+/// Streams the snapshot and the log to the follower, until it disconnects or the shutdown is requested.
+pub fn serve<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Arc<Server>,
+    client: &str
+) -> Status {
+    let replication = &server.replication;
+    let backlog = &server.storage.log_file.backlog;
+    let mut offset = backlog.enable();
+
+    let id = replication.next_follower_id.fetch_add(1, SeqCst);
+    if let Ok(mut followers) = replication.followers.lock() {
+        followers.insert(id, (client.to_string(), offset));
+    }
+    let follower = FollowerGuard { replication, id, _connected: METRICS.replication_followers.track() };
+    info!("The follower {} is connected, sending the snapshot", client);
+
+    let start = Instant::now();
+    let mut frame = vec![actions::DONE, SNAPSHOT];
+    frame.extend_from_slice(&uint::u64tob(offset));
+    if !write_frame(connection, &frame) || !send_snapshot(connection, server.storage, offset) {
+        warn!("The follower {} has disconnected during the snapshot", client);
+        return Status::Closed;
+    }
+    success!("The snapshot was sent to the follower {} in {:?}", client, start.elapsed());
+
+    while !server.shutdown.is_requested() {
+        let (records, end) = match backlog.read(offset, MAX_FRAME_BYTES, HEARTBEAT_INTERVAL) {
+            Ok(read) => read,
+            Err(oldest) => {
+                warn!("The follower {} is too far behind: it needs the offset {}, but the oldest one is {}", client, offset, oldest);
+                let _ = connection.write_error(errors::FOLLOWER_IS_TOO_FAR_BEHIND);
+                return Status::Closed;
+            }
+        };
+        frame.clear();
+        if records.is_empty() {
+            frame.extend_from_slice(&[actions::DONE, HEARTBEAT]);
+            frame.extend_from_slice(&uint::u64tob(end));
+        } else {
+            frame.extend_from_slice(&[actions::DONE, RECORDS]);
+            frame.extend_from_slice(&uint::u64tob(end));
+            frame.extend_from_slice(&records);
+        }
+        if !write_frame(connection, &frame) {
+            info!("The follower {} has disconnected", client);
+            return Status::Closed;
+        }
+        offset = end;
+        follower.sent(offset);
+    }
+    Status::Closed
+}
+
+/// Follows the leader until the shutdown is requested. Reconnects, if the connection is lost or the leader is changed.
+pub fn run(server: Arc<Server>) {
+    let replication = &server.replication;
+    while !server.shutdown.is_requested() {
+        let leader_addr = replication.leader_addr();
+        if leader_addr.is_empty() {
+            thread::sleep(RECONNECT_INTERVAL);
+            continue;
+        }
+        let _leader = logger::field("leader", leader_addr.as_str());
+        match follow(&server, &leader_addr) {
+            Ok(()) => {
+                replication.is_connected.store(false, SeqCst);
+                continue;
+            }
+            Err(e) => error!("Replication has stopped: {}. Reconnecting in {:?}", e, RECONNECT_INTERVAL)
+        }
+        replication.is_connected.store(false, SeqCst);
+        thread::sleep(RECONNECT_INTERVAL);
+    }
+}
+
+/// Connects to another server and sends the password, if it is not empty. Reading from the stream times out after `timeout`.
+pub(crate) fn connect(addr: &str, password: &str, timeout: Duration) -> Result<TcpStream, String> {
+    let socket_addr = addr.to_socket_addrs().ok().and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("can't resolve the address {}", addr))?;
+    let mut stream = TcpStream::connect_timeout(&socket_addr, timeout).map_err(|e| format!("can't connect to {}: {}", addr, e))?;
+    stream.set_read_timeout(Some(timeout)).map_err(|e| e.to_string())?;
+    if !password.is_empty() {
+        Write::write_all(&mut stream, password.as_bytes()).map_err(|e| e.to_string())?;
+        let mut answer = [0u8; 1];
+        if stream.read_exact(&mut answer).is_err() || answer[0] != actions::DONE {
+            return Err(format!("{} has rejected the password", addr));
+        }
+    }
+    Ok(stream)
+}
+
+/// Sends the request with one message to another server and returns the answer.
+pub(crate) fn request(stream: &mut TcpStream, message: &[u8]) -> Result<Vec<u8>, String> {
+    let mut request = Vec::with_capacity(message.len() + 11);
+    // The request: [size (4 bytes), is reading, message length (2 bytes or 65535 and 4 bytes), message].
+    request.extend_from_slice(&[0, 0, 0, 0, 0]);
+    if message.len() < u16::MAX as usize {
+        request.extend_from_slice(&uint::u16tob(message.len() as u16));
+    } else {
+        request.extend_from_slice(&[255, 255]);
+        request.extend_from_slice(&uint::u32tob(message.len() as u32));
+    }
+    request.extend_from_slice(message);
+    let size = request.len() as u32 - 5;
+    request[..4].copy_from_slice(&uint::u32tob(size));
+    Write::write_all(stream, &request).map_err(|e| e.to_string())?;
+    read_frame(stream).map_err(|e| e.to_string())
+}
+
+pub(crate) fn read_frame(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    reader.read_exact(&mut len[..2])?;
+    let mut frame_len = uint::u16(&len[..2]) as usize;
+    if frame_len == u16::MAX as usize {
+        reader.read_exact(&mut len)?;
+        frame_len = uint::u32(&len) as usize;
+    }
+    let mut frame = vec![0; frame_len];
+    reader.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+fn offset_of(frame: &[u8]) -> Result<u64, String> {
+    match frame.get(2..10) {
+        Some(offset) => Ok(uint::u64(offset)),
+        None => Err("the frame has no offset".to_string())
+    }
+}
+
+/// Connects to the leader once, applies the snapshot and then the log, until an error or until the leader is changed.
+fn follow(server: &Server, leader_addr: &str) -> Result<(), String> {
+    let replication = &server.replication;
+    let mut stream = connect(leader_addr, &replication.leader_password, LEADER_TIMEOUT)?;
+    // The request: [size (4 bytes), is reading, message length (2 bytes), REPLICATE].
+    Write::write_all(&mut stream, &[3, 0, 0, 0, 0, 1, 0, actions::REPLICATE]).map_err(|e| e.to_string())?;
+    let mut reader = IoBufReader::with_capacity(MAX_FRAME_BYTES, stream);
+
+    let storage = server.storage;
+    let mut pending: Vec<u8> = Vec::new();
+    let mut is_snapshot = false;
+    let mut tables_in_snapshot = 0usize;
+    replication.is_synced.store(false, SeqCst);
+    while !server.shutdown.is_requested() {
+        if replication.leader_addr() != leader_addr {
+            info!("The leader is changed, disconnecting from {}", leader_addr);
+            return Ok(());
+        }
+        let frame = read_frame(&mut reader).map_err(|e| format!("can't read from the leader: {}", e))?;
+        if frame.len() < 2 {
+            return Err("the leader has sent an empty frame".to_string());
+        }
+        if frame[0] != actions::DONE {
+            let message = String::from_utf8_lossy(frame.get(3..).unwrap_or(&[]));
+            let code = if frame.len() >= 3 { uint::u16(&frame[1..3]) } else { 0 };
+            return Err(format!("the leader has answered with the error {}: {}", code, message));
+        }
+        match frame[1] {
+            SNAPSHOT => {
+                let offset = offset_of(&frame)?;
+                replication.is_connected.store(true, SeqCst);
+                is_snapshot = true;
+                tables_in_snapshot = 0;
+                pending.clear();
+                replication.set_offsets(offset, offset);
+                info!("Receiving the snapshot from the leader {}", leader_addr);
+            }
+            RECORDS => {
+                let end = offset_of(&frame)?;
+                pending.extend_from_slice(&frame[10..]);
+                let mut applied = 0;
+                loop {
+                    match log_record::decode(&pending[applied..]) {
+                        Ok(Some((record, len))) => {
+                            apply(storage, record, is_snapshot, &mut tables_in_snapshot)?;
+                            applied += len;
+                        }
+                        Ok(None) => break,
+                        Err(action) => return Err(format!("the leader has sent an unknown record {}", action))
+                    }
+                }
+                pending.drain(..applied);
+                if !is_snapshot {
+                    replication.set_offsets(end - pending.len() as u64, end);
+                }
+            }
+            SNAPSHOT_END => {
+                is_snapshot = false;
+                replication.is_synced.store(true, SeqCst);
+                let offset = replication.applied_offset.load(SeqCst);
+                replication.set_offsets(offset, offset);
+                success!("The snapshot of {} tables is applied, following the log of the leader {}", tables_in_snapshot, leader_addr);
+            }
+            HEARTBEAT => {
+                let applied_offset = replication.applied_offset.load(SeqCst);
+                replication.set_offsets(applied_offset, offset_of(&frame)?);
+            }
+            kind => return Err(format!("the leader has sent an unknown frame {}", kind))
+        }
+    }
+    Ok(())
+}
+
+/// Applies the record of the leader. Tables of the snapshot have the same numbers as on the leader,
+/// because they are created in the order of numbers, so the follower must have the same tables or no tables.
+fn apply(storage: &'static Storage, record: LogRecord, is_snapshot: bool, tables_in_snapshot: &mut usize) -> Result<(), String> {
+    let table_by_number = |number: u16| storage.tables.get().get(number as usize).map(|table| table.as_ref())
+        .ok_or_else(|| format!("the table {} is not found", number));
+    let table_error = |error: Error| error.message.to_string();
+    match record {
+        LogRecord::Insert { table, key, value } => {
+            table_by_number(table)?.insert_without_log(BinKey::new(key), BinValue::new(value)).map_err(table_error)?;
+        }
+        LogRecord::Set { table, key, value } => {
+            table_by_number(table)?.set_without_log(BinKey::new(key), BinValue::new(value)).map_err(table_error)?;
+        }
+        LogRecord::Delete { table, key } => {
+            table_by_number(table)?.delete_without_log(&BinKey::new(key)).map_err(table_error)?;
+        }
+        LogRecord::CreateTable { engine, name, is_it_logging, cache_duration, user_scheme } => {
+            let name = String::from_utf8(name.to_vec()).map_err(|_| "the table name is not valid UTF-8".to_string())?;
+            let scheme = if user_scheme.is_empty() {
+                empty_scheme()
+            } else {
+                scheme_from_bytes(user_scheme).map_err(|e| format!("the scheme of the table \"{}\" is not valid: {}", name, e.message))?
+            };
+            let existing = storage.table_by_name(name.as_bytes()).map(|(number, _)| number);
+            let number = match engine {
+                TableEngine::InMemory => Storage::create_in_memory_table(storage, name.clone(), HashInMemoryIndex::new(), is_it_logging, scheme, user_scheme),
+                TableEngine::OnDisk => Storage::create_on_disk_table(storage, name.clone(), HashInMemoryIndex::new(), scheme, user_scheme),
+                TableEngine::CACHE => Storage::create_cache_table(storage, name.clone(), HashInMemoryIndex::new(), cache_duration, is_it_logging, scheme, user_scheme),
+                TableEngine::Lsm => Storage::create_lsm_table(storage, name.clone(), scheme, user_scheme),
+            };
+            if number == CANT_CREATE_TABLE_NUMBER {
+                return Err(format!("can't create the table \"{}\"", name));
+            }
+            if is_snapshot {
+                if number != *tables_in_snapshot {
+                    return Err(format!(
+                        "the table \"{}\" has the number {} on the leader and {} here, start the follower with an empty persistence directory",
+                        name, tables_in_snapshot, number
+                    ));
+                }
+                *tables_in_snapshot += 1;
+                // The follower had the table before the reconnection, so it can have keys, that the leader has deleted.
+                if existing.is_some() {
+                    let table = table_by_number(number as u16)?;
+                    for key in table.keys().map_err(table_error)? {
+                        table.delete_without_log(&BinKey::new(&key)).map_err(table_error)?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
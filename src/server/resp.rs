@@ -0,0 +1,509 @@
+//! RESP2/RESP3 listener, so Redis clients and tools can work with the storage.
+//!
+//! Keys like `table:key` address the table `table`, if it exists. Other keys are in the table from [`Config::resp_table`],
+//! it is created as an in-memory logging table on the first write. Commands use the same [`Table`] methods
+//! and [`LogWriter`] as the binary protocol, so the log is replayed the same way.
+//!
+//! Keys of other nodes of the cluster are answered with `-MOVED shard address`, like in Redis Cluster, and so are writes
+//! to machines, that aren't the leader of the node: the address is the leader then.
+//!
+//! We have no per-key expiration: keys of cache tables expire after the cache duration of the table, not after a TTL of the key.
+//! So `EXPIRE` and `SET .. EX` are rejected for all tables, only `EXPIRE` with a non-positive time is accepted and deletes the key.
+//!
+//! [`Config::resp_table`]: crate::server::cfg::Config::resp_table
+use std::{
+    io::{BufReader, BufWriter, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, atomic::Ordering::Relaxed},
+    thread
+};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    console::logger,
+    constants::errors::{self, Error},
+    error,
+    metrics::METRICS,
+    resp::{read_command, Reply, RespError},
+    scheme::scheme::is_value_valid,
+    server::{reactions::table::create_table, server::{NEXT_CONNECTION_ID, Server}, sharding::{self, shard_of}},
+    success,
+    table::table::{Table, TableEngine},
+    writers::LogWriter
+};
+
+/// [`BinKey`] stores the length of the key in 2 bytes.
+const MAX_KEY_LEN: usize = u16::MAX as usize;
+/// Longer patterns of `KEYS` are rejected.
+pub(crate) const MAX_PATTERN_LEN: usize = 256;
+/// Cache tables have only one expiration time for all keys, so a TTL of one key can't be set for any table.
+const PER_KEY_EXPIRATION_IS_NOT_SUPPORTED: &str = "per-key expiration is not supported";
+
+struct Session {
+    resp3: bool,
+    is_authorized: bool,
+    log_writer: LogWriter,
+}
+
+pub fn run(server: Arc<Server>) {
+    let listener = match TcpListener::bind(&server.resp_addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            panic!("Can't bind RESP listener to address: {}, the error is: {:?}", server.resp_addr, e);
+        }
+    };
+    success!("RESP listening on address {}", server.resp_addr);
+    for stream in listener.incoming() {
+        let server = server.clone();
+        match stream {
+            Ok(stream) => {
+                thread::spawn(move || handle_connection(&server, stream));
+            }
+            Err(e) => {
+                error!("Error: {}", e);
+            }
+        }
+    }
+}
+
+fn handle_connection(server: &Server, stream: TcpStream) {
+    let _connection = METRICS.resp_connections.track();
+    let _connection_id = logger::field("connection", NEXT_CONNECTION_ID.fetch_add(1, Relaxed));
+    let reader_stream = match stream.try_clone() {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(reader_stream);
+    let mut writer = BufWriter::new(stream);
+    let mut session = Session {
+        resp3: false,
+        is_authorized: server.password.is_empty(),
+        log_writer: LogWriter::new(server.storage.log_file.clone()),
+    };
+
+    // Is held from the first command of the batch until the log writer is flushed, so the shutdown waits for it.
+    let mut request = None;
+    loop {
+        let (reply, is_quit) = match read_command(&mut reader) {
+            Ok(Some(_)) if server.shutdown.is_requested() => break,
+            Ok(Some(command)) => {
+                if request.is_none() {
+                    request = Some(server.shutdown.track_request());
+                }
+                let is_quit = command[0].eq_ignore_ascii_case(b"QUIT");
+                (handle_command(server, &mut session, &command), is_quit)
+            }
+            Ok(None) | Err(RespError::Io(_)) => break,
+            Err(RespError::Protocol(message)) => (Reply::Error(format!("ERR Protocol error: {}", message)), true),
+        };
+        if reply.write_to(&mut writer, session.resp3).is_err() {
+            break;
+        }
+        // Pipelined commands are answered together, like requests of the binary protocol.
+        if is_quit || reader.buffer().is_empty() {
+            session.log_writer.flush();
+            request = None;
+            if writer.flush().is_err() || is_quit {
+                break;
+            }
+        }
+    }
+    session.log_writer.flush();
+}
+
+fn wrong_arguments(command: &str) -> Reply {
+    Reply::Error(format!("ERR wrong number of arguments for '{}' command", command.to_lowercase()))
+}
+
+fn storage_error(error: Error) -> Reply {
+    Reply::Error(format!("ERR {} (code {})", error.message, error.code))
+}
+
+fn parse_i64(bytes: &[u8]) -> Option<i64> {
+    std::str::from_utf8(bytes).ok()?.parse().ok()
+}
+
+fn handle_command(server: &Server, session: &mut Session, command: &[Vec<u8>]) -> Reply {
+    let name = String::from_utf8_lossy(&command[0]).to_uppercase();
+    let arguments = &command[1..];
+
+    match name.as_str() {
+        "AUTH" => return auth(server, session, arguments),
+        "HELLO" => return hello(server, session, arguments),
+        "QUIT" => return Reply::ok(),
+        _ => {}
+    }
+    if !session.is_authorized {
+        return Reply::Error("NOAUTH Authentication required.".to_string());
+    }
+    let is_write = matches!(name.as_str(), "SET" | "DEL" | "EXPIRE");
+    let keys_of_command = match name.as_str() {
+        "GET" | "SET" | "EXPIRE" => arguments.get(..1).unwrap_or_default(),
+        "MGET" | "DEL" | "EXISTS" => arguments,
+        _ => &[],
+    };
+    if let Some(reply) = redirect(server, is_write, keys_of_command) {
+        return reply;
+    }
+    if is_write && server.replication.is_follower() {
+        return Reply::Error("READONLY You can't write against a read only replica.".to_string());
+    }
+
+    let result = match name.as_str() {
+        "PING" => match arguments {
+            [] => Ok(Reply::Simple("PONG")),
+            [message] => Ok(Reply::Bulk(message.clone())),
+            _ => return wrong_arguments(&name),
+        },
+        "ECHO" => match arguments {
+            [message] => Ok(Reply::Bulk(message.clone())),
+            _ => return wrong_arguments(&name),
+        },
+        "SELECT" => match arguments {
+            [db] if db.as_slice() == b"0" => Ok(Reply::ok()),
+            [_] => Ok(Reply::error("only DB 0 is supported")),
+            _ => return wrong_arguments(&name),
+        },
+        "COMMAND" => Ok(Reply::Array(Vec::new())),
+        "GET" => match arguments {
+            [key] => get(server, key),
+            _ => return wrong_arguments(&name),
+        },
+        "MGET" if !arguments.is_empty() => arguments.iter().map(|key| get(server, key)).collect::<Result<Vec<Reply>, Error>>().map(Reply::Array),
+        "SET" if arguments.len() >= 2 => set(server, session, arguments),
+        "DEL" if !arguments.is_empty() => del(server, session, arguments),
+        "EXISTS" if !arguments.is_empty() => exists(server, arguments),
+        "EXPIRE" => match arguments {
+            [key, seconds] => expire(server, session, key, seconds),
+            _ => return wrong_arguments(&name),
+        },
+        "KEYS" => match arguments {
+            [pattern] if pattern.len() > MAX_PATTERN_LEN => return Reply::Error("ERR pattern is too long".to_string()),
+            [pattern] => Ok(keys(server, pattern)),
+            _ => return wrong_arguments(&name),
+        },
+        "INFO" => Ok(info(server)),
+        "MGET" | "SET" | "DEL" | "EXISTS" => return wrong_arguments(&name),
+        _ => return Reply::Error(format!("ERR unknown command '{}'", String::from_utf8_lossy(&command[0]))),
+    };
+    match result {
+        Ok(reply) => reply,
+        Err(error) => storage_error(error),
+    }
+}
+
+fn check_password(server: &Server, session: &mut Session, password: &[u8]) -> bool {
+    session.is_authorized = server.password.is_empty() || password == server.password.as_bytes();
+    session.is_authorized
+}
+
+fn wrong_password() -> Reply {
+    Reply::Error("WRONGPASS invalid username-password pair or user is disabled.".to_string())
+}
+
+/// `AUTH password` or `AUTH user password`. The user is ignored.
+fn auth(server: &Server, session: &mut Session, arguments: &[Vec<u8>]) -> Reply {
+    let password = match arguments {
+        [password] | [_, password] => password,
+        _ => return wrong_arguments("AUTH"),
+    };
+    if check_password(server, session, password) {
+        Reply::ok()
+    } else {
+        wrong_password()
+    }
+}
+
+/// `HELLO [protover [AUTH user password] [SETNAME name]]`.
+fn hello(server: &Server, session: &mut Session, arguments: &[Vec<u8>]) -> Reply {
+    let mut resp3 = session.resp3;
+    if let Some(version) = arguments.first() {
+        match parse_i64(version) {
+            Some(2) => resp3 = false,
+            Some(3) => resp3 = true,
+            _ => return Reply::Error("NOPROTO unsupported protocol version".to_string()),
+        }
+    }
+    let mut i = 1;
+    while i < arguments.len() {
+        if arguments[i].eq_ignore_ascii_case(b"AUTH") && i + 2 < arguments.len() {
+            if !check_password(server, session, &arguments[i + 2]) {
+                return wrong_password();
+            }
+            i += 3;
+        } else if arguments[i].eq_ignore_ascii_case(b"SETNAME") && i + 1 < arguments.len() {
+            i += 2;
+        } else {
+            return Reply::error("syntax error in HELLO option");
+        }
+    }
+    if !session.is_authorized {
+        return Reply::Error("NOAUTH HELLO must be called with the client already authenticated, \
+            otherwise the HELLO <proto> AUTH <user> <pass> option can be used".to_string());
+    }
+
+    session.resp3 = resp3;
+    let bulk = |s: &str| Reply::Bulk(s.as_bytes().to_vec());
+    Reply::Map(vec![
+        (bulk("server"), bulk("dbms")),
+        (bulk("version"), bulk(env!("CARGO_PKG_VERSION"))),
+        (bulk("proto"), Reply::Integer(if resp3 { 3 } else { 2 })),
+        (bulk("mode"), bulk("standalone")),
+        (bulk("role"), bulk("master")),
+        (bulk("modules"), Reply::Array(Vec::new())),
+    ])
+}
+
+/// Keys of other nodes are redirected to their nodes, and writes are redirected to the leader of the node,
+/// like the binary protocol does. The shard is of the key without the table prefix.
+fn redirect(server: &Server, is_write: bool, keys: &[Vec<u8>]) -> Option<Reply> {
+    for key in keys.iter() {
+        if let Some((shard, node)) = sharding::foreign_key(server, split_key(server, key).1) {
+            return Some(Reply::Error(format!("MOVED {} {}", shard, sharding::addr_of(server, node))));
+        }
+    }
+    if is_write && !server.failover.is_leader() {
+        let shard = keys.first().map(|key| shard_of(split_key(server, key).1)).unwrap_or(0);
+        return Some(Reply::Error(format!("MOVED {} {}", shard, server.failover.leader())));
+    }
+    None
+}
+
+/// Returns the table of the key and the key without the table prefix. The table is `None`, if the default table doesn't exist yet.
+fn split_key<'key>(server: &Server, key: &'key [u8]) -> (Option<&'static dyn Table>, &'key [u8]) {
+    let storage = server.storage;
+    if let Some(colon) = key.iter().position(|byte| *byte == b':') {
+        if let Some((_, table)) = storage.table_by_name(&key[..colon]) {
+            return (Some(table), &key[colon + 1..]);
+        }
+    }
+    (storage.table_by_name(server.resp_table.as_bytes()).map(|(_, table)| table), key)
+}
+
+/// Like [`split_key`], but checks the length of the key.
+fn resolve<'key>(server: &Server, key: &'key [u8]) -> Result<(Option<&'static dyn Table>, &'key [u8]), Error> {
+    let (table, key) = split_key(server, key);
+    check_key(table, key)
+}
+
+fn check_key<'key>(table: Option<&'static dyn Table>, key: &'key [u8]) -> Result<(Option<&'static dyn Table>, &'key [u8]), Error> {
+    if key.len() > MAX_KEY_LEN {
+        return Err(errors::KEY_IS_TOO_LONG);
+    }
+    Ok((table, key))
+}
+
+/// Like [`resolve`], but creates the default table, if it doesn't exist.
+fn resolve_for_write<'key>(server: &Server, session: &mut Session, key: &'key [u8]) -> Result<(&'static dyn Table, &'key [u8]), Error> {
+    let (table, key) = resolve(server, key)?;
+    if let Some(table) = table {
+        return Ok((table, key));
+    }
+    let storage = server.storage;
+    match create_table(storage, TableEngine::InMemory, server.resp_table.as_bytes(), true, 0, &[], &mut session.log_writer) {
+        // Another connection has created it.
+        Ok(_) | Err(errors::TABLE_ALREADY_EXISTS) => {}
+        Err(error) => return Err(error),
+    }
+    match storage.table_by_name(server.resp_table.as_bytes()) {
+        Some((_, table)) => Ok((table, key)),
+        None => Err(errors::TABLE_IS_NOT_FOUND),
+    }
+}
+
+fn get(server: &Server, key: &[u8]) -> Result<Reply, Error> {
+    let (table, key) = resolve(server, key)?;
+    let value = match table {
+        Some(table) => table.get(&BinKey::new(key))?,
+        None => None,
+    };
+    Ok(match value {
+        Some(value) => Reply::Bulk(value.deref().to_vec()),
+        None => Reply::Null,
+    })
+}
+
+/// `SET key value [NX | XX] [EX seconds | PX milliseconds]`.
+fn set(server: &Server, session: &mut Session, arguments: &[Vec<u8>]) -> Result<Reply, Error> {
+    let (mut nx, mut xx, mut expires) = (false, false, false);
+    let mut i = 2;
+    while i < arguments.len() {
+        let option = arguments[i].to_ascii_uppercase();
+        match option.as_slice() {
+            b"NX" => nx = true,
+            b"XX" => xx = true,
+            b"EX" | b"PX" if i + 1 < arguments.len() => {
+                if parse_i64(&arguments[i + 1]).is_none_or(|time| time <= 0) {
+                    return Ok(Reply::error("invalid expire time in 'set' command"));
+                }
+                expires = true;
+                i += 1;
+            }
+            _ => return Ok(Reply::error("syntax error")),
+        }
+        i += 1;
+    }
+    if nx && xx {
+        return Ok(Reply::error("syntax error"));
+    }
+    if expires {
+        return Ok(Reply::error(PER_KEY_EXPIRATION_IS_NOT_SUPPORTED));
+    }
+
+    let (table, key) = resolve_for_write(server, session, &arguments[0])?;
+    let value = &arguments[1];
+    if !is_value_valid(value, table.scheme()) {
+        return Err(errors::VALUE_DOES_NOT_MATCH_SCHEME);
+    }
+    let key = BinKey::new(key);
+    if nx {
+        return Ok(if table.insert(key, BinValue::new(value), &mut session.log_writer)? { Reply::ok() } else { Reply::Null });
+    }
+    if xx && table.get(&key)?.is_none() {
+        return Ok(Reply::Null);
+    }
+    table.set(key, BinValue::new(value), &mut session.log_writer)?;
+    Ok(Reply::ok())
+}
+
+fn del(server: &Server, session: &mut Session, keys: &[Vec<u8>]) -> Result<Reply, Error> {
+    let mut deleted = 0;
+    for key in keys.iter() {
+        if let (Some(table), key) = resolve(server, key)? {
+            let key = BinKey::new(key);
+            if table.get(&key)?.is_some() {
+                table.delete(&key, &mut session.log_writer)?;
+                deleted += 1;
+            }
+        }
+    }
+    Ok(Reply::Integer(deleted))
+}
+
+fn exists(server: &Server, keys: &[Vec<u8>]) -> Result<Reply, Error> {
+    let mut found = 0;
+    for key in keys.iter() {
+        if let (Some(table), key) = resolve(server, key)? {
+            if table.get(&BinKey::new(key))?.is_some() {
+                found += 1;
+            }
+        }
+    }
+    Ok(Reply::Integer(found))
+}
+
+fn expire(server: &Server, session: &mut Session, key: &[u8], seconds: &[u8]) -> Result<Reply, Error> {
+    let seconds = match parse_i64(seconds) {
+        Some(seconds) => seconds,
+        None => return Ok(Reply::error("value is not an integer or out of range")),
+    };
+    let (table, key) = match resolve(server, key)? {
+        (Some(table), key) => (table, BinKey::new(key)),
+        (None, _) => return Ok(Reply::Integer(0)),
+    };
+    if table.get(&key)?.is_none() {
+        return Ok(Reply::Integer(0));
+    }
+    if seconds <= 0 {
+        table.delete(&key, &mut session.log_writer)?;
+        return Ok(Reply::Integer(1));
+    }
+    Ok(Reply::error(PER_KEY_EXPIRATION_IS_NOT_SUPPORTED))
+}
+
+/// Keys of the default table are returned as is, keys of other tables are returned as `table:key`.
+fn keys(server: &Server, pattern: &[u8]) -> Reply {
+    let storage = server.storage;
+    let names = match storage.tables_names.read() {
+        Ok(names) => names.clone(),
+        Err(_) => return storage_error(errors::TABLES_LOCK_IS_POISONED),
+    };
+    let mut found = Vec::new();
+    for (table, name) in storage.tables.get().iter().zip(names.iter()) {
+        let prefix = if *name == server.resp_table { Vec::new() } else { format!("{}:", name).into_bytes() };
+        let table_keys = match table.keys() {
+            Ok(table_keys) => table_keys,
+            Err(error) => return storage_error(error),
+        };
+        for key in table_keys {
+            let mut full_key = prefix.clone();
+            full_key.extend_from_slice(&key);
+            if glob_match(pattern, &full_key) {
+                found.push(Reply::Bulk(full_key));
+            }
+        }
+    }
+    Reply::Array(found)
+}
+
+/// Redis glob: `*`, `?`, `[abc]`, `[^a-z]` and `\` for escaping.
+///
+/// Only the last `*` is remembered for backtracking, so matching takes `O(pattern * s)` steps and no stack.
+pub(crate) fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    let (mut p, mut i) = (0, 0);
+    // The position in the pattern after the last `*` and the position in `s`, that the `*` matches up to.
+    let mut star: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            star = Some((p, i));
+            continue;
+        }
+        if let Some(next) = match_one(pattern, p, s[i]) {
+            p = next;
+            i += 1;
+            continue;
+        }
+        let Some((after_star, matched_to)) = star else { return false };
+        p = after_star;
+        i = matched_to + 1;
+        star = Some((after_star, i));
+    }
+    pattern[p.min(pattern.len())..].iter().all(|&c| c == b'*')
+}
+
+/// Matches `c` with the one-byte token of the pattern at `p` and returns the position of the next token.
+fn match_one(pattern: &[u8], p: usize, c: u8) -> Option<usize> {
+    match *pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => {
+            let mut i = p + 1;
+            let negate = pattern.get(i) == Some(&b'^');
+            if negate {
+                i += 1;
+            }
+            let mut matched = false;
+            while i < pattern.len() && pattern[i] != b']' {
+                if pattern[i] == b'\\' && i + 1 < pattern.len() {
+                    i += 1;
+                    matched |= pattern[i] == c;
+                } else if i + 2 < pattern.len() && pattern[i + 1] == b'-' && pattern[i + 2] != b']' {
+                    let (low, high) = (pattern[i].min(pattern[i + 2]), pattern[i].max(pattern[i + 2]));
+                    matched |= low <= c && c <= high;
+                    i += 2;
+                } else {
+                    matched |= pattern[i] == c;
+                }
+                i += 1;
+            }
+            // Without the closing bracket the class ends at the end of the pattern.
+            (matched != negate).then_some((i + 1).min(pattern.len()))
+        }
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        token => (token == c).then_some(p + 1),
+    }
+}
+
+fn info(server: &Server) -> Reply {
+    let storage = server.storage;
+    let tables = storage.tables.get();
+    let default_keys = storage.table_by_name(server.resp_table.as_bytes()).map(|(_, table)| table.count()).unwrap_or(0);
+    let mut info = String::new();
+    info.push_str("# Server\r\n");
+    info.push_str("redis_version:7.0.0\r\n");
+    info.push_str(&format!("dbms_version:{}\r\n", env!("CARGO_PKG_VERSION")));
+    info.push_str("redis_mode:standalone\r\n");
+    info.push_str(&format!("dbms_tables:{}\r\n", tables.len()));
+    info.push_str("\r\n# Keyspace\r\n");
+    info.push_str(&format!("db0:keys={},expires=0,avg_ttl=0\r\n", default_keys));
+    Reply::Bulk(info.into_bytes())
+}
@@ -1,71 +1,113 @@
 use std::{
-    fs::OpenOptions,
-    io::{Seek, SeekFrom, Write},
+    fs,
+    io::{ErrorKind, Write},
     net::{TcpListener},
     path::PathBuf,
-    sync::{Arc},
+    sync::{Arc, RwLock, atomic::{AtomicU64, Ordering::Relaxed}},
+    time::{Duration, Instant},
+    panic::{self, AssertUnwindSafe},
     {mem, thread}
 };
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::net::UnixListener;
 use crate::{
-    connection::{BufConnection, buffered, BufReader, BufWriter, Status}
-    constants::actions,
+    connection::{BufConnection, buffered, BufReader, BufWriter, Status},
+    constants::{actions, errors},
     constants::actions::DONE,
-    {error, success, warn},
+    {error, info, success, warn},
+    console::logger,
+    metrics::METRICS,
     node::Node,
-    server::cfg::Config,
+    server::{cfg::Config, changes, failover::{self, Failover}, gateway, hierarchy, metrics, migration::{self, Migration}, pubsub::{self, PubSub}, reload, replication::{self, Replication}, resp, sharding::{self, ShardMap}, shutdown::Shutdown, slowlog::SlowLog},
     storage::storage::Storage,
     server::reactions::{
-        status::{get_hierarchy, get_shard_metadata, ping},
-        table::{create_table_cache, create_table_in_memory, create_table_on_disk, get_tables_names},
+        config::{config_get, config_set},
+        notifications::{set_notifications, watch},
+        status::{get_hierarchy, get_shard_metadata, info, ping, shutdown, slowlog_get, slowlog_reset},
+        table::{create_table_cache, create_table_in_memory, create_table_lsm, create_table_on_disk, get_table_scheme, get_tables_names},
         work_with_tables::{delete, get, get_field, get_fields, insert, set},
     },
     stream::Stream,
-    utils::{
-        bytes::uint,
-        read_more
-    },
+    utils::bytes::uint,
     writers::LogWriter
 };
 
+/// Ids of connections of all protocols for logs. They are unique until the restart.
+pub(crate) static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(0);
+
 pub struct Server {
-    storage: &'static Storage,
+    pub(crate) storage: &'static Storage,
     is_running: bool,
 
-    password: String,
-    tcp_addr: String,
+    pub(crate) password: String,
+    pub(crate) tcp_addr: String,
     unix_addr: String,
-    node_addr: String,
+    pub(crate) node_addr: String,
+    pub(crate) http_addr: String,
+    pub(crate) resp_addr: String,
+    pub(crate) resp_table: String,
+    pub(crate) metrics_addr: String,
 
-    pub hierarchy: Vec<Vec<String>>,
-    hierarchy_file_path: PathBuf,
+    /// Addresses of machines of every node, see [`crate::server::hierarchy`].
+    pub(crate) hierarchy: RwLock<Vec<Vec<String>>>,
+    pub(crate) hierarchy_file_path: PathBuf,
 
     // Shard metadata is array with 65536 length, where every item is 16-bit number of node, that contains this shard.
     pub shard_metadata_file_path: PathBuf,
-
-    node: Node
+    pub(crate) shard_map: ShardMap,
+
+    pub(crate) node: Node,
+    pub(crate) started_at: Instant,
+    pub(crate) slowlog: SlowLog,
+    /// The config, that the server was started with, and changes of reloadable settings.
+    pub(crate) config: RwLock<Config>,
+    pub(crate) shutdown: Arc<Shutdown>,
+    pub(crate) replication: Replication,
+    pub(crate) failover: Failover,
+    pub(crate) migration: Migration,
+    pub(crate) pubsub: Arc<PubSub>
 }
 
 impl Server {
     pub fn new(storage: &'static Storage) -> Self {
-        let config = Config::new();
+        Self::with_config(storage, Config::new())
+    }
+
+    /// Creates a server with the given config instead of reading it from the environment.
+    pub fn with_config(storage: &'static Storage, config: Config) -> Self {
         let hierarchy_file_path: PathBuf = storage.persistence_dir_path.join("hierarchy.bin");
         let shard_metadata_file_path: PathBuf = storage.persistence_dir_path.join("shard metadata.bin");
 
         let mut server = Self {
             storage: storage,
-            tcp_addr: config.tcp_addr,
-            unix_addr: config.unix_addr,
-            node_addr: config.node_addr,
-            password: config.password,
+            tcp_addr: config.tcp_addr.clone(),
+            unix_addr: config.unix_addr.clone(),
+            node_addr: config.node_addr.clone(),
+            http_addr: config.http_addr.clone(),
+            resp_addr: config.resp_addr.clone(),
+            resp_table: config.resp_table.clone(),
+            metrics_addr: config.metrics_addr.clone(),
+            password: config.password.clone(),
             is_running: false,
-            hierarchy: Vec::with_capacity(0),
+            hierarchy: RwLock::new(Vec::new()),
             hierarchy_file_path,
+            shard_map: ShardMap::empty(&shard_metadata_file_path),
             shard_metadata_file_path,
-            node: Node::new()
+            node: Node::new(),
+            started_at: Instant::now(),
+            slowlog: SlowLog::new(Duration::from_micros(config.slowlog_threshold_micros), config.slowlog_max_len, &config.slowlog_file),
+            config: RwLock::new(Config::default()),
+            shutdown: Arc::new(Shutdown::new()),
+            replication: Replication::new(&config.leader_addr, &config.leader_password),
+            failover: Failover::new(),
+            migration: Migration::new(),
+            pubsub: Arc::new(PubSub::default())
         };
 
+        reload::apply(&server, &config);
+        storage.notifications.set_pubsub(server.pubsub.clone());
+        server.config = RwLock::new(config);
+
         server.rise_hierarchy_and_lookup_node();
 
         server.set_up_shard_metadata_file();
@@ -76,127 +118,67 @@ impl Server {
     }
 
     fn rise_hierarchy_and_lookup_node(&mut self) {
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .read(true)
-            .open(&self.hierarchy_file_path);
-        let mut hierarchy;
-        if file.is_err() {
-            panic!("Can't open hierarchy file {}", self.hierarchy_file_path.display());
-        } else {
-            let mut file = file.unwrap();
-            if file.metadata().unwrap().len() < 3 {
-                hierarchy = vec![vec![self.tcp_addr.clone()]];
-                let l = self.tcp_addr.len();
-                file.seek(SeekFrom::Start(0)).expect("Can't seek hierarchy file");
-                // Format is: 1 byte for count of machines in the node and next 2 bytes for a name length and name.
-                Stream::write_all(&mut file, &[1, l as u8, (l >> 8) as u8]).expect("Can't write to hierarchy file");
-                Stream::write_all(&mut file, hierarchy[0][0].as_bytes()).expect("Can't write to hierarchy file");
-            } else {
-                let mut l;
-                let mut read;
-                let mut offset;
-                let mut number_of_machines = 0;
-                let mut buf = vec![0u8; 4096];
-                let mut offset_last_record = 0;
-                let mut node: Vec<String> = vec![];
-                let mut this_node = false;
-
-                hierarchy = Vec::with_capacity(1);
-                'read: loop {
-                    read = file.read(&mut buf).expect("Can't read from hierarchy file");
-                    if read == 0 {
-                        break;
-                    }
-
-                    offset = 0;
-                    read += offset_last_record;
-
-                    loop {
-                        if number_of_machines == 0 {
-                            if read < 1 + offset {
-                                read_more(&mut buf, offset, read, &mut offset_last_record);
-                                continue 'read;
-                            }
-                            number_of_machines = buf[offset];
-                            offset += 1;
-                            if node.len() > 0 {
-                                if this_node {
-                                    this_node = false;
-                                    let mut list = Vec::with_capacity(node.len() - 1);
-                                    for addr in &node {
-                                        if addr != &self.tcp_addr {
-                                            list.push(addr.to_string());
-                                        }
-                                    }
-                                    self.node.set(&list);
-                                }
-                                hierarchy.push(node);
-                            }
-                            node = Vec::with_capacity(number_of_machines as usize);
-                        }
-
-                        if read < 2 + offset {
-                            read_more(&mut buf, offset, read, &mut offset_last_record);
-                            continue 'read;
-                        }
-                        l = buf[offset] as usize + (buf[offset + 1] as usize) << 8;
-                        offset += 2;
-                        if read < l + offset {
-                            read_more(&mut buf, offset, read, &mut offset_last_record);
-                            continue 'read;
-                        }
-                        let name = String::from_utf8_lossy(&buf[offset..l + offset]).to_string();
-                        if self.tcp_addr == name {
-                            this_node = true;
-                        }
-                        node.push(name);
-                        offset += l;
-                        number_of_machines -= 1;
-                    }
-                }
-                if node.len() == 0 {
-                    error!("Incorrect hierarchy file!");
-                    self.hierarchy = vec![vec![self.tcp_addr.clone()]];
-                    return;
+        let hierarchy = match fs::read(&self.hierarchy_file_path) {
+            Ok(buf) if buf.len() >= 3 => match hierarchy::decode(&buf) {
+                Ok(hierarchy) => hierarchy,
+                Err(e) => {
+                    error!("Incorrect hierarchy file {}: {}", self.hierarchy_file_path.display(), e);
+                    vec![vec![self.tcp_addr.clone()]]
                 }
-                hierarchy.push(node);
+            },
+            Ok(_) => {
+                let hierarchy = vec![vec![self.tcp_addr.clone()]];
+                hierarchy::save(&self.hierarchy_file_path, &hierarchy);
+                hierarchy
             }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                let hierarchy = vec![vec![self.tcp_addr.clone()]];
+                hierarchy::save(&self.hierarchy_file_path, &hierarchy);
+                hierarchy
+            }
+            Err(e) => panic!("Can't read hierarchy file {}: {}", self.hierarchy_file_path.display(), e)
+        };
+        if let Some(machines) = hierarchy.iter().find(|machines| machines.contains(&self.tcp_addr)) {
+            let other_machines: Vec<String> = machines.iter().filter(|addr| **addr != self.tcp_addr).cloned().collect();
+            if !other_machines.is_empty() {
+                self.node.set(&other_machines);
+            }
+        }
+        self.set_hierarchy(hierarchy);
+    }
+
+    /// Returns addresses of machines of every node.
+    pub fn hierarchy(&self) -> Vec<Vec<String>> {
+        self.hierarchy.read().map(|hierarchy| hierarchy.clone()).unwrap_or_default()
+    }
+
+    /// Replaces the hierarchy in memory. [`crate::server::hierarchy`] changes it on all machines.
+    pub fn set_hierarchy(&self, hierarchy: Vec<Vec<String>>) {
+        if let Ok(mut current) = self.hierarchy.write() {
+            *current = hierarchy;
         }
+    }
 
-        self.hierarchy = hierarchy
+    #[inline(always)]
+    pub(crate) fn number_of_nodes(&self) -> usize {
+        self.hierarchy.read().map(|hierarchy| hierarchy.len()).unwrap_or(1)
     }
-    
+
+    /// Returns the leftmost machine of the node.
+    pub(crate) fn first_machine_of(&self, node: usize) -> Option<String> {
+        self.hierarchy.read().ok()?.get(node)?.first().cloned()
+    }
+
     fn set_up_shard_metadata_file(&mut self) {
         // We split all data in shards. We have 65,536 shards, and we distribute shards into different nodes.
-        // We store shard metadata as [`number of machine addresses`, [`address length`, `machine address`]; 65,536].
+        // We store shard metadata as [`number of node` (2 bytes); 65,536], see `sharding`.
         // We always think that the leftmost alive machine is the master.
+        self.shard_map = ShardMap::open(&self.shard_metadata_file_path, self.number_of_nodes());
+    }
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .read(true)
-            .open(&self.shard_metadata_file_path);
-        if file.is_err() {
-            panic!("Can't open shard metadata file {}", self.shard_metadata_file_path.display());
-        }
-        
-        let mut file = file.unwrap();
-        if file.metadata().unwrap().len() == 0 {
-            if self.hierarchy.len() == 1 {
-                let mut buf = Vec::with_capacity(65536 * 2);
-                for _i in 0..65_536 {
-                    buf.extend_from_slice(&uint::u16tob(0));
-                }
-
-                Stream::write_all(&mut file, &buf).expect("Can't write to shard metadata file");
-            } else {
-                // TODO: set up with cluster
-            }
-        } else {
-            // TODO: set up with cluster
-        }
+    /// Returns the number of the node of this machine in the hierarchy.
+    pub(crate) fn this_node(&self) -> Option<usize> {
+        self.hierarchy.read().ok()?.iter().position(|node| node.contains(&self.tcp_addr))
     }
 
     fn connect_to_node(&mut self) {
@@ -207,17 +189,71 @@ impl Server {
 
     }
 
-    pub fn run(mut self) {
+    /// Returns machines of the node of this machine in the order of the hierarchy.
+    fn node_machines(&self) -> Vec<String> {
+        match self.hierarchy().into_iter().find(|node| node.contains(&self.tcp_addr)) {
+            Some(node) => node,
+            None => vec![self.tcp_addr.clone()]
+        }
+    }
+
+    /// Returns the handle, that requests the graceful shutdown of [`Server::run`].
+    pub fn shutdown_handle(&self) -> Arc<Shutdown> {
+        self.shutdown.clone()
+    }
+
+    /// Serves clients until the shutdown is requested. Returns `true`, if the shutdown was clean:
+    /// all requests were finished, the final dump was done, if it was needed, and the log file was synced.
+    pub fn run(mut self) -> bool {
         if self.is_running {
-            return;
+            return true;
         }
         self.is_running = true;
 
         self.connect_to_cluster();
 
+        // Shards of nodes, that were removed from the hierarchy, get other nodes.
+        self.shard_map.fit(self.number_of_nodes());
+
+        let has_other_machines = self.failover.start(&self.tcp_addr, self.node_machines());
+        if has_other_machines && !self.failover.is_leader() {
+            self.replication.set_leader_addr(&self.failover.leader());
+        }
+
         let server = Arc::new(self);
 
-        #[cfg(not(target_os = "windows"))] {
+        if !server.http_addr.is_empty() {
+            let server = server.clone();
+            thread::spawn(move || gateway::run(server));
+        }
+
+        if !server.resp_addr.is_empty() {
+            let server = server.clone();
+            thread::spawn(move || resp::run(server));
+        }
+
+        if !server.metrics_addr.is_empty() {
+            let server = server.clone();
+            thread::spawn(move || metrics::run(server));
+        }
+
+        if server.config.read().map(|config| !config.config_file.is_empty()).unwrap_or(false) {
+            let server = server.clone();
+            thread::spawn(move || reload::run(server));
+        }
+
+        // Machines can be added to the node at any time, so replication and failover wait for them.
+        {
+            let server = server.clone();
+            thread::spawn(move || replication::run(server));
+        }
+        {
+            let server = server.clone();
+            thread::spawn(move || failover::run(server));
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        let unix_listener = {
             let storage = server.storage.clone();
             let unix_port = server.unix_addr.clone();
             let server = server.clone();
@@ -229,14 +265,18 @@ impl Server {
                         panic!("Can't bind to address: {}, the error is: {:?}", unix_port, e);
                     }
                 };
+                server.shutdown.add_listener(&unix_port, true);
                 success!("Server unix listening on address {}", unix_port);
                 for stream in listener.incoming() {
+                    if server.shutdown.is_requested() {
+                        break;
+                    }
                     let storage = storage.clone();
                     let server = server.clone();
                     thread::spawn(move || {
                         match stream {
                             Ok(stream) => {
-                                Self::handle_client(server, storage, buffered(stream));
+                                Self::handle_client(server, storage, buffered(stream), "unix".to_string());
                             }
                             Err(e) => {
                                 error!("Error: {}", e);
@@ -244,8 +284,8 @@ impl Server {
                         }
                     });
                 }
-            });
-        }
+            })
+        };
         let listener_ = TcpListener::bind(format!("{}", server.tcp_addr.clone()));
         let listener = match listener_ {
             Ok(listener) => listener,
@@ -253,14 +293,19 @@ impl Server {
                 panic!("Can't bind to address: {}, the error is: {:?}", server.tcp_addr.clone(), e);
             }
         };
+        server.shutdown.add_listener(&server.tcp_addr, false);
         success!("Server tcp listening on address {}", server.tcp_addr.clone());
         for stream in listener.incoming() {
+            if server.shutdown.is_requested() {
+                break;
+            }
             let server = server.clone();
             let storage= server.storage;
             thread::spawn(move || {
                 match stream {
                     Ok(stream) => {
-                        Self::handle_client(server, storage, buffered(stream));
+                        let client = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+                        Self::handle_client(server, storage, buffered(stream), client);
                     }
                     Err(e) => {
                         error!("Error: {}", e);
@@ -268,58 +313,147 @@ impl Server {
                 }
             });
         }
+        drop(listener);
+        #[cfg(not(target_os = "windows"))]
+        let _ = unix_listener.join();
+
+        Self::finish_shutdown(&server)
+    }
+
+    /// Waits for requests, dumps the storage, if it is needed, syncs the log file and removes the Unix socket file.
+    fn finish_shutdown(server: &Server) -> bool {
+        let (dump_on_shutdown, timeout) = match server.config.read() {
+            Ok(config) => (config.dump_on_shutdown, Duration::from_secs(config.shutdown_timeout_secs)),
+            Err(_) => (true, Duration::from_secs(30))
+        };
+        let mut is_clean = server.shutdown.wait_for_requests(timeout);
+
+        let sync_log = || match server.storage.log_file.file.lock() {
+            Ok(file) => file.sync_all().map_err(|e| e.to_string()),
+            Err(_) => Err("the log file lock is poisoned".to_string())
+        };
+        if let Err(e) = sync_log() {
+            error!("Can't sync the log file: {}", e);
+            is_clean = false;
+        }
+
+        if server.shutdown.is_dump_needed(dump_on_shutdown) {
+            info!("Starting the final dump");
+            let storage = server.storage;
+            if panic::catch_unwind(AssertUnwindSafe(|| storage.dump())).is_err() {
+                error!("The final dump failed, the log file will be replayed on the start");
+                is_clean = false;
+            } else if let Err(e) = sync_log() {
+                error!("Can't sync the log file: {}", e);
+                is_clean = false;
+            }
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        match fs::remove_file(&server.unix_addr) {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => {
+                error!("Can't remove the Unix socket file {}: {}", server.unix_addr, e);
+                is_clean = false;
+            }
+        }
+
+        if is_clean {
+            success!("The server was shut down cleanly");
+        } else {
+            warn!("The server was shut down, but not cleanly");
+        }
+        is_clean
     }
 
     #[inline(always)]
-    fn handle_client<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    fn handle_client<'stream, S: Stream + Send + 'static, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
         server: Arc<Server>,
         storage: &'static Storage,
-        mut connection: BufConnection<'stream, S, R, W>
+        mut connection: BufConnection<'stream, S, R, W>,
+        client: String
     ) {
+        let _connection = METRICS.binary_connections.track();
+        let _connection_id = logger::field("connection", NEXT_CONNECTION_ID.fetch_add(1, Relaxed));
+        let _client = logger::field("client", client.as_str());
         let mut status;
         let mut is_reading;
         if server.password.len() > 0 {
             let mut buf = vec![0;server.password.len()];
             let stream = connection.writer.stream();
-            stream.read_exact(&mut buf).expect("Failed to read password");
+            if let Err(e) = stream.read_exact(&mut buf) {
+                warn!("Failed to read password: {}. Disconnected.", e);
+                let _ = connection.close();
+                return;
+            }
             if buf != server.password.as_bytes() {
                 warn!("Wrong password. Disconnected.");
-                connection.close().expect("Failed to close connection");
+                let _ = connection.close();
+                return;
+            }
+            if connection.writer.write_all(&[DONE]).is_err() || connection.writer.flush().is_err() {
+                let _ = connection.close();
                 return;
             }
-            connection.writer.write_all(&[DONE]).expect("Failed to write DONE");
-            connection.writer.flush().expect("Failed to flush connection");
         }
         success!("Connection accepted");
 
         let mut log_writer = LogWriter::new(storage.log_file.clone());
 
         let mut message;
+        let mut subscription: Option<Vec<u8>> = None;
         loop {
             (status, is_reading) = connection.read_request();
             if status != Status::Ok {
-                connection.close().expect("Failed to close connection");
+                let _ = connection.close();
                 return;
             }
+            {
+                // The shutdown waits for the request, until it is answered and its log writer is flushed.
+                let _request = server.shutdown.track_request();
+                if server.shutdown.is_requested() {
+                    let _ = connection.close();
+                    return;
+                }
 
-            loop {
-                (message, status) = connection.read_message();
-                if status != Status::Ok {
-                    if status == Status::All {
+                loop {
+                    (message, status) = connection.read_message();
+                    if status != Status::Ok {
+                        if status == Status::All {
+                            log_writer.flush();
+                            if connection.flush().is_err() || server.shutdown.is_requested() {
+                                let _ = connection.close();
+                                return;
+                            }
+                            break;
+                        }
+                        let _ = connection.close();
+                        return;
+                    }
+
+                    // The connection switches into the push mode. The rest of the request is read in it.
+                    if matches!(message.first(), Some(&actions::SUBSCRIBE) | Some(&actions::PSUBSCRIBE)) {
                         log_writer.flush();
-                        connection.flush().expect("Failed to flush connection");
+                        subscription = Some(message.to_vec());
                         break;
                     }
-                    connection.close().expect("Failed to close connection");
-                    return;
+
+                    // copy the reference to ignore error below and do not clone the message.
+                    // It is always safe.
+                    message = unsafe { mem::transmute::<&[u8], &[u8]>(message) };
+                    status = Self::handle_message(&mut connection, &server, storage, message, &mut log_writer, &client);
+                    if status != Status::Ok {
+                        let _ = connection.close();
+                        return;
+                    }
                 }
+            }
 
-                // copy the reference to ignore error below and do not clone the message.
-                // It is always safe.
-                message = unsafe { mem::transmute::<&[u8], &[u8]>(message) };
-                status = Self::handle_message(&mut connection, &server, storage, message, &mut log_writer);
-                if status != Status::Ok {
-                    connection.close().expect("Failed to close connection");
+            // The shutdown doesn't wait for subscribed connections, they can stay for hours.
+            if let Some(message) = subscription.take() {
+                if pubsub::serve(&mut connection, &server, &message) != Status::Ok {
+                    let _ = connection.close();
                     return;
                 }
             }
@@ -327,22 +461,73 @@ impl Server {
     }
 
     #[inline(always)]
-    fn handle_message<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
+    pub(crate) fn handle_message<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>> (
         connection: &mut BufConnection<'stream, S, R, W>,
         server: &Arc<Server>,
         storage: &'static Storage,
         message: &[u8],
-        log_writer: &mut LogWriter
+        log_writer: &mut LogWriter,
+        client: &str
     ) -> Status {
-        return match message[0] {
+        if message.is_empty() {
+            return connection.write_error(errors::MESSAGE_IS_TOO_SHORT);
+        }
+        let _table = match message[0] {
+            actions::GET..=actions::GET_TABLE_SCHEME if message.len() >= 3 => Some(logger::field("table", uint::u16(&message[1..3]))),
+            _ => None
+        };
+        let start = Instant::now();
+        let status = match message[0] {
+            actions::GET..=actions::DELETE | actions::WATCH if let Some((shard, node)) = sharding::foreign_shard(server, message) => {
+                sharding::moved(connection, server, shard, node)
+            }
+            actions::CREATE_TABLE_IN_MEMORY | actions::CREATE_TABLE_CACHE | actions::CREATE_TABLE_ON_DISK | actions::CREATE_TABLE_LSM
+            | actions::INSERT | actions::SET | actions::DELETE | actions::REPLICATE | actions::SUBSCRIBE_CHANGES
+            | actions::MIGRATE_SHARDS..=actions::REBALANCE if !server.failover.is_leader() => {
+                failover::redirect(connection, server)
+            }
+            actions::CREATE_TABLE_IN_MEMORY | actions::CREATE_TABLE_CACHE | actions::CREATE_TABLE_ON_DISK | actions::CREATE_TABLE_LSM
+            | actions::INSERT | actions::SET | actions::DELETE | actions::REPLICATE | actions::SUBSCRIBE_CHANGES
+            | actions::MIGRATE_SHARDS..=actions::REBALANCE if server.replication.is_follower() => {
+                connection.write_error(errors::SERVER_IS_READ_ONLY_FOLLOWER)
+            }
+            actions::INSERT | actions::SET | actions::DELETE if server.migration.is_running() => {
+                migration::write(connection, server, storage, message, log_writer)
+            }
+            actions::REPLICATE => replication::serve(connection, server, client),
+            actions::SUBSCRIBE_CHANGES => changes::subscribe_changes(connection, server, message),
+            actions::PUBLISH => pubsub::publish(connection, server, message),
+            // `handle_client` switches binary connections into the push mode before this.
+            actions::SUBSCRIBE | actions::PSUBSCRIBE => connection.write_error(errors::PUSH_MODE_IS_NOT_AVAILABLE),
+            actions::UNSUBSCRIBE => pubsub::unsubscribe(connection, message),
+            actions::SET_NOTIFICATIONS => set_notifications(connection, storage, message),
+            actions::WATCH => watch(connection, server, message),
+            actions::HEARTBEAT => failover::heartbeat(connection, server, message),
+            actions::ADD_NODE => hierarchy::add_node(connection, server, message),
+            actions::REMOVE_NODE => hierarchy::remove_node(connection, server, message),
+            actions::ADD_MACHINE => hierarchy::add_machine(connection, server, message),
+            actions::REMOVE_MACHINE => hierarchy::remove_machine(connection, server, message),
+            actions::SET_HIERARCHY => hierarchy::set_hierarchy(connection, server, message),
+            actions::MIGRATE_SHARDS => migration::migrate_shards(connection, server, message),
+            actions::IMPORT_RECORDS => migration::import_records(connection, storage, message, log_writer),
+            actions::SET_SHARDS_NODE => migration::set_shards_node(connection, server, message),
+            actions::REBALANCE => migration::rebalance(connection, server, message),
             actions::PING => ping(connection),
             actions::GET_SHARD_METADATA => get_shard_metadata(connection, server),
             actions::GET_HIERARCHY => get_hierarchy(connection, server),
+            actions::INFO => info(connection, server),
+            actions::SLOWLOG_GET => slowlog_get(connection, server, message),
+            actions::SLOWLOG_RESET => slowlog_reset(connection, server),
+            actions::CONFIG_GET => config_get(connection, server, message),
+            actions::CONFIG_SET => config_set(connection, server, message),
+            actions::SHUTDOWN => shutdown(connection, server, message),
 
             actions::CREATE_TABLE_IN_MEMORY => create_table_in_memory(connection, storage, message, log_writer),
             actions::CREATE_TABLE_CACHE => create_table_cache(connection, storage, message, log_writer),
             actions::CREATE_TABLE_ON_DISK => create_table_on_disk(connection, storage, message, log_writer),
+            actions::CREATE_TABLE_LSM => create_table_lsm(connection, storage, message, log_writer),
             actions::GET_TABLES_NAMES => get_tables_names(connection, storage),
+            actions::GET_TABLE_SCHEME => get_table_scheme(connection, storage, message),
 
             actions::GET => get(connection, storage, message),
             actions::GET_FIELD => get_field(connection, storage, message),
@@ -352,8 +537,12 @@ impl Server {
             actions::SET => set(connection, storage, message, log_writer),
             actions::DELETE => delete(connection, storage, message, log_writer),
             _ => {
-                connection.write_message(&[actions::BAD_REQUEST])
+                connection.write_error(errors::UNKNOWN_ACTION)
             }
-        }
+        };
+        let elapsed = start.elapsed();
+        METRICS.actions[message[0] as usize].observe(elapsed);
+        server.slowlog.record(message, elapsed, client);
+        status
     }
 }
\ No newline at end of file
@@ -0,0 +1,229 @@
+//! Routing of keys to nodes of the cluster.
+//!
+//! Every key belongs to one of 65,536 shards: the shard is CRC-16/XMODEM of the key (the polynomial `0x1021`,
+//! the initial value 0, like in Redis Cluster, but without the modulo). The hash never changes, so clients can compute it too.
+//! The table isn't hashed, so a key is in the same shard in all tables.
+//!
+//! The shard metadata maps every shard to the number of a node in the hierarchy. It is stored in the file
+//! `shard metadata.bin` as 65,536 numbers of 2 bytes, and `GET_SHARD_METADATA` returns it as is.
+//! A machine answers actions with keys of other nodes with [`KEY_IS_MOVED`](errors::KEY_IS_MOVED), its message
+//! is `MOVED shard node address`, where the address is the leftmost machine of the node. The HTTP gateway and the RESP listener
+//! check keys too: the gateway answers `421` with the shard, the node and the address, and the RESP listener answers `-MOVED shard address`.
+//! Shards are moved between nodes by [`crate::server::migration`].
+use std::{
+    fs::{self, File},
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+    sync::RwLock
+};
+use crate::{
+    connection::{BufConnection, BufReader, BufWriter, Status},
+    constants::{actions, errors},
+    server::server::Server,
+    stream::Stream,
+    utils::bytes::uint,
+    error, warn
+};
+
+pub const NUMBER_OF_SHARDS: usize = 65_536;
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static CRC16_TABLE: [u16; 256] = crc16_table();
+
+/// Returns the shard of the key.
+#[inline(always)]
+pub fn shard_of(key: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in key {
+        crc = (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ *byte) as usize];
+    }
+    crc
+}
+
+/// Returns the key of a message of `GET`, `GET_FIELD`, `GET_FIELDS`, `INSERT`, `SET` or `DELETE`.
+/// Returns `None`, if the message is too short, so the action answers with the error itself.
+pub fn key_of(message: &[u8]) -> Option<&[u8]> {
+    match *message.first()? {
+        actions::GET | actions::DELETE => message.get(3..),
+        actions::GET_FIELD => message.get(5..),
+        actions::WATCH => message.get(7..),
+        actions::GET_FIELDS => {
+            let number_of_fields = uint::u16(message.get(3..5)?) as usize;
+            message.get(5 + number_of_fields * 2..)
+        }
+        actions::INSERT | actions::SET => {
+            let key_len = uint::u16(message.get(3..5)?) as usize;
+            message.get(5..5 + key_len)
+        }
+        _ => None
+    }
+}
+
+/// Numbers of nodes of all shards. Changes are written to the file at once.
+pub struct ShardMap {
+    path: PathBuf,
+    /// The content of the file, so `GET_SHARD_METADATA` only copies it.
+    bytes: RwLock<Vec<u8>>,
+}
+
+impl ShardMap {
+    /// Returns the map without shards. [`ShardMap::open`] replaces it, when the hierarchy is read.
+    pub fn empty(path: &Path) -> Self {
+        Self { path: path.to_path_buf(), bytes: RwLock::new(Vec::new()) }
+    }
+
+    /// Reads the file. If there is no file, shards are split between `number_of_nodes` nodes in equal ranges.
+    pub fn open(path: &Path, number_of_nodes: usize) -> Self {
+        let map = Self::empty(path);
+        let mut buf = Vec::with_capacity(NUMBER_OF_SHARDS * 2);
+        let is_read = match File::open(path).and_then(|mut file| file.read_to_end(&mut buf)) {
+            Ok(len) if len == NUMBER_OF_SHARDS * 2 => true,
+            Ok(0) => false,
+            Err(e) if e.kind() == ErrorKind::NotFound => false,
+            Ok(len) => {
+                error!("The shard metadata file {} has {} bytes instead of {}, shards are split again", path.display(), len, NUMBER_OF_SHARDS * 2);
+                false
+            }
+            Err(e) => panic!("Can't read shard metadata file {}: {}", path.display(), e)
+        };
+        if is_read {
+            if let Ok(mut bytes) = map.bytes.write() {
+                *bytes = buf;
+            }
+        } else {
+            let number_of_nodes = number_of_nodes.max(1);
+            map.replace((0..NUMBER_OF_SHARDS).map(|shard| (shard * number_of_nodes / NUMBER_OF_SHARDS) as u16).collect());
+        }
+        map
+    }
+
+    #[inline(always)]
+    pub fn node_of(&self, shard: u16) -> u16 {
+        let offset = shard as usize * 2;
+        self.bytes.read().ok().and_then(|bytes| bytes.get(offset..offset + 2).map(uint::u16)).unwrap_or(0)
+    }
+
+    /// Returns the file content.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.bytes.read().map(|bytes| bytes.clone()).unwrap_or_default()
+    }
+
+    /// Returns nodes of all shards.
+    pub fn nodes(&self) -> Vec<u16> {
+        self.bytes.read().map(|bytes| bytes.chunks_exact(2).map(uint::u16).collect()).unwrap_or_default()
+    }
+
+    /// Sets the node of shards from `from` to `to` inclusive and writes the file.
+    pub fn set_node(&self, from: u16, to: u16, node: u16) {
+        if let Ok(mut bytes) = self.bytes.write() {
+            for shard in bytes.chunks_exact_mut(2).skip(from as usize).take((to as usize + 1).saturating_sub(from as usize)) {
+                shard.copy_from_slice(&uint::u16tob(node));
+            }
+        }
+        self.save();
+    }
+
+    /// Gives numbers one less to nodes after the removed node and writes the file. The removed node must have no shards.
+    pub fn remove_node(&self, removed: u16) {
+        if let Ok(mut bytes) = self.bytes.write() {
+            for node in bytes.chunks_exact_mut(2).filter(|node| uint::u16(node) > removed) {
+                let number = uint::u16(node) - 1;
+                node.copy_from_slice(&uint::u16tob(number));
+            }
+        }
+        self.save();
+    }
+
+    /// Sets nodes of all shards and writes them to the file.
+    pub fn replace(&self, nodes: Vec<u16>) {
+        assert_eq!(nodes.len(), NUMBER_OF_SHARDS);
+        let mut bytes = Vec::with_capacity(NUMBER_OF_SHARDS * 2);
+        for node in nodes {
+            bytes.extend_from_slice(&uint::u16tob(node));
+        }
+        if let Ok(mut current) = self.bytes.write() {
+            *current = bytes;
+        }
+        self.save();
+    }
+
+    /// Moves shards of nodes, that aren't in the hierarchy anymore, to other nodes in turn.
+    /// Returns the number of moved shards.
+    pub fn fit(&self, number_of_nodes: usize) -> usize {
+        let number_of_nodes = number_of_nodes.max(1);
+        let mut moved = 0;
+        if let Ok(mut bytes) = self.bytes.write() {
+            for node in bytes.chunks_exact_mut(2).filter(|node| uint::u16(node) as usize >= number_of_nodes) {
+                node.copy_from_slice(&uint::u16tob((moved % number_of_nodes) as u16));
+                moved += 1;
+            }
+        }
+        if moved > 0 {
+            warn!("{} shards of removed nodes were moved to {} nodes", moved, number_of_nodes);
+            self.save();
+        }
+        moved
+    }
+
+    /// Writes a new file and renames it, so the file is never half-written.
+    fn save(&self) {
+        let tmp_path = self.path.with_extension("bin.tmp");
+        let result = fs::write(&tmp_path, self.to_bytes()).and_then(|_| fs::rename(&tmp_path, &self.path));
+        if let Err(e) = result {
+            error!("Can't write the shard metadata file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Returns the shard and the node of the key of the message, if the key belongs to another node.
+pub fn foreign_shard(server: &Server, message: &[u8]) -> Option<(u16, u16)> {
+    foreign_key(server, key_of(message)?)
+}
+
+/// Returns the shard and the node of the key, if the key belongs to another node.
+/// The HTTP gateway and the RESP listener check keys with it, like [`foreign_shard`] checks messages.
+pub fn foreign_key(server: &Server, key: &[u8]) -> Option<(u16, u16)> {
+    if server.number_of_nodes() <= 1 {
+        return None;
+    }
+    let this_node = server.this_node()?;
+    let shard = shard_of(key);
+    let node = server.shard_map.node_of(shard);
+    if node as usize == this_node {
+        return None;
+    }
+    Some((shard, node))
+}
+
+/// Returns the address of the node, that is the leftmost machine of it.
+pub fn addr_of(server: &Server, node: u16) -> String {
+    server.first_machine_of(node as usize).unwrap_or_default()
+}
+
+/// Answers with the node of the key.
+pub fn moved<'stream, S: Stream, R: BufReader<'stream, S>, W: BufWriter<'stream, S>>(
+    connection: &mut BufConnection<'stream, S, R, W>,
+    server: &Server,
+    shard: u16,
+    node: u16
+) -> Status {
+    let error = errors::KEY_IS_MOVED;
+    let mut answer = vec![error.status, error.code as u8, (error.code >> 8) as u8];
+    answer.extend_from_slice(format!("MOVED {} {} {}", shard, node, addr_of(server, node)).as_bytes());
+    connection.write_message(&answer)
+}
@@ -0,0 +1,143 @@
+//! Graceful shutdown. It is requested by SIGTERM, SIGINT or the `SHUTDOWN` action.
+//!
+//! Listeners stop accepting connections, connections finish requests, that are handled now, and close.
+//! Every request flushes its [`LogWriter`](crate::writers::LogWriter) when it ends,
+//! so after all requests are finished, the log file has all changes. Then [`Server::run`](crate::server::server::Server::run)
+//! optionally dumps the storage, syncs the log file, removes the Unix socket file and returns.
+use std::{
+    net::TcpStream,
+    sync::{Mutex, atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering::SeqCst}},
+    thread,
+    time::{Duration, Instant}
+};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::net::UnixStream;
+use crate::{error, warn};
+
+/// How often the number of requests in flight is checked, while we wait for them.
+const WAIT_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct Shutdown {
+    is_requested: AtomicBool,
+    /// 0 means, that the `dump_on_shutdown` setting is used, 1 is a dump and 2 is no dump.
+    dump: AtomicU8,
+    in_flight: AtomicUsize,
+    /// Addresses of listeners, that block in `accept`. We connect to them, so they see the request.
+    listeners: Mutex<Vec<String>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            is_requested: AtomicBool::new(false),
+            dump: AtomicU8::new(0),
+            in_flight: AtomicUsize::new(0),
+            listeners: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds the address of a listener. `is_unix` means, that it is the path of a Unix socket.
+    pub fn add_listener(&self, addr: &str, is_unix: bool) {
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.push(format!("{}{}", if is_unix { "unix:" } else { "" }, addr));
+        }
+    }
+
+    /// Requests the shutdown. `dump` overrides the `dump_on_shutdown` setting. The second request is ignored.
+    pub fn request(&self, dump: Option<bool>) {
+        if self.is_requested.swap(true, SeqCst) {
+            return;
+        }
+        self.dump.store(match dump { None => 0, Some(true) => 1, Some(false) => 2 }, SeqCst);
+        warn!("Shutdown is requested");
+
+        // Wakes up listeners, they check the request after every accepted connection.
+        let listeners = self.listeners.lock().map(|listeners| listeners.clone()).unwrap_or_default();
+        for addr in listeners {
+            match addr.strip_prefix("unix:") {
+                #[cfg(not(target_os = "windows"))]
+                Some(path) => {
+                    let _ = UnixStream::connect(path);
+                }
+                #[cfg(target_os = "windows")]
+                Some(_) => {}
+                None => {
+                    let _ = TcpStream::connect(&addr);
+                }
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_requested(&self) -> bool {
+        self.is_requested.load(SeqCst)
+    }
+
+    /// Returns `dump_on_shutdown`, if the request hasn't overridden it.
+    pub fn is_dump_needed(&self, dump_on_shutdown: bool) -> bool {
+        match self.dump.load(SeqCst) {
+            1 => true,
+            2 => false,
+            _ => dump_on_shutdown
+        }
+    }
+
+    /// The shutdown waits for the request until the guard is dropped. Check [`Shutdown::is_requested`] after this call,
+    /// because the shutdown doesn't wait for requests, that are started after the shutdown is requested.
+    #[inline(always)]
+    pub fn track_request(&self) -> RequestGuard<'_> {
+        self.in_flight.fetch_add(1, SeqCst);
+        RequestGuard(self)
+    }
+
+    /// Returns `false`, if some requests weren't finished in time.
+    pub fn wait_for_requests(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while self.in_flight.load(SeqCst) > 0 {
+            if start.elapsed() >= timeout {
+                warn!("{} requests weren't finished in {:?}", self.in_flight.load(SeqCst), timeout);
+                return false;
+            }
+            thread::sleep(WAIT_INTERVAL);
+        }
+        true
+    }
+}
+
+pub struct RequestGuard<'shutdown>(&'shutdown Shutdown);
+
+impl Drop for RequestGuard<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, SeqCst);
+    }
+}
+
+/// Waits for SIGTERM or SIGINT. Waits forever, if the handlers can't be installed.
+pub async fn wait_for_signal() {
+    #[cfg(not(target_os = "windows"))]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let (mut terminate, mut interrupt) = match (signal(SignalKind::terminate()), signal(SignalKind::interrupt())) {
+            (Ok(terminate), Ok(interrupt)) => (terminate, interrupt),
+            (Err(e), _) | (_, Err(e)) => {
+                error!("Can't handle signals: {}. Use the SHUTDOWN action to stop the server gracefully.", e);
+                return std::future::pending().await;
+            }
+        };
+        tokio::select! {
+            _ = terminate.recv() => warn!("SIGTERM is received"),
+            _ = interrupt.recv() => warn!("SIGINT is received"),
+        }
+    }
+    #[cfg(target_os = "windows")]
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Can't handle Ctrl-C: {}. Use the SHUTDOWN action to stop the server gracefully.", e);
+        std::future::pending::<()>().await;
+    }
+}
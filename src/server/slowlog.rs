@@ -0,0 +1,164 @@
+//! Slow-query log. Messages handled longer than the threshold are kept in a bounded ring and, optionally, appended to a file.
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{Mutex, atomic::{AtomicU64, AtomicUsize, Ordering::{Relaxed, SeqCst}}},
+    time::{Duration, SystemTime, UNIX_EPOCH}
+};
+use serde_json::{json, Value};
+use crate::{
+    constants::actions,
+    error,
+    metrics::action_name,
+    utils::bytes::uint
+};
+
+/// We keep only the beginning of the key, because keys can be large and the log is in memory.
+pub const KEY_PREFIX_LEN: usize = 32;
+
+pub struct SlowLogEntry {
+    pub id: u64,
+    /// Unix time in microseconds.
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub action: u8,
+    pub table: Option<u16>,
+    pub key_prefix: Vec<u8>,
+    pub client: String,
+}
+
+impl SlowLogEntry {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "id": self.id,
+            "timestamp_micros": self.timestamp,
+            "duration_micros": self.duration.as_micros() as u64,
+            "action": action_name(self.action),
+            "action_code": self.action,
+            "table": self.table,
+            "key_prefix": String::from_utf8_lossy(&self.key_prefix),
+            "client": self.client,
+        })
+    }
+}
+
+pub struct SlowLog {
+    /// In microseconds.
+    threshold: AtomicU64,
+    max_len: AtomicUsize,
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+    next_id: AtomicU64,
+    file: Option<Mutex<File>>,
+}
+
+/// Returns the table number and the key of the message, if the action has them.
+fn table_and_key(message: &[u8]) -> (Option<u16>, &[u8]) {
+    let table = if message.len() >= 3 { Some(uint::u16(&message[1..3])) } else { None };
+    let key_offset = match message[0] {
+        actions::GET | actions::DELETE => 3,
+        actions::GET_FIELD => 5,
+        actions::GET_FIELDS if message.len() >= 5 => 5 + uint::u16(&message[3..5]) as usize * 2,
+        actions::INSERT | actions::SET if message.len() >= 5 => {
+            let key_size = uint::u16(&message[3..5]) as usize;
+            return (table, message.get(5..5 + key_size).unwrap_or(&[]));
+        }
+        actions::GET_TABLE_SCHEME => return (table, &[]),
+        _ => return (None, &[]),
+    };
+    (table, message.get(key_offset..).unwrap_or(&[]))
+}
+
+impl SlowLog {
+    /// `file_path` is the file sink, empty path means no file.
+    pub fn new(threshold: Duration, max_len: usize, file_path: &str) -> Self {
+        let file = if file_path.is_empty() {
+            None
+        } else {
+            match OpenOptions::new().create(true).append(true).open(file_path) {
+                Ok(file) => Some(Mutex::new(file)),
+                Err(e) => {
+                    error!("Can't open the slow log file {}: {}. Slow queries are kept only in memory.", file_path, e);
+                    None
+                }
+            }
+        };
+        Self {
+            threshold: AtomicU64::new(threshold.as_micros() as u64),
+            max_len: AtomicUsize::new(max_len),
+            entries: Mutex::new(VecDeque::with_capacity(max_len)),
+            next_id: AtomicU64::new(0),
+            file,
+        }
+    }
+
+    /// Records the message, if it took longer than the threshold. `message` is not empty.
+    #[inline(always)]
+    pub fn record(&self, message: &[u8], duration: Duration, client: &str) {
+        if (duration.as_micros() as u64) < self.threshold.load(Relaxed) {
+            return;
+        }
+        let (table, key) = table_and_key(message);
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, SeqCst),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_micros() as u64).unwrap_or(0),
+            duration,
+            action: message[0],
+            table,
+            key_prefix: key[..key.len().min(KEY_PREFIX_LEN)].to_vec(),
+            client: client.to_string(),
+        };
+
+        if let Some(file) = &self.file {
+            if let Ok(mut file) = file.lock() {
+                let _ = writeln!(file, "{}", entry.to_json());
+            }
+        }
+        let max_len = self.max_len.load(Relaxed);
+        if max_len == 0 {
+            return;
+        }
+        if let Ok(mut entries) = self.entries.lock() {
+            while entries.len() >= max_len {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+
+    /// Returns at most `count` entries, the newest first.
+    pub fn get(&self, count: usize) -> Value {
+        match self.entries.lock() {
+            Ok(entries) => Value::Array(entries.iter().rev().take(count).map(SlowLogEntry::to_json).collect()),
+            Err(_) => Value::Array(Vec::new()),
+        }
+    }
+
+    pub fn set_threshold(&self, threshold: Duration) {
+        self.threshold.store(threshold.as_micros() as u64, Relaxed);
+    }
+
+    /// Drops the oldest entries, if there are more than `max_len`.
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, Relaxed);
+        if let Ok(mut entries) = self.entries.lock() {
+            while entries.len() > max_len {
+                entries.pop_front();
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|entries| entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
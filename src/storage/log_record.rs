@@ -0,0 +1,159 @@
+//! Records of the log, like [`LogWriter`](crate::writers::LogWriter) and tables creation write them.
+//!
+//! - `INSERT` and `SET`: [`action`, `table` (2 bytes), `key` (1 byte length or 255 and 2 bytes), `value` (2 bytes length or 65535 and 4 bytes)];
+//! - `DELETE`: [`action`, `table` (2 bytes), `key`];
+//! - `CREATE_TABLE_IN_MEMORY`: [`action`, `name length` (2 bytes), `is it logging`, `name`, `scheme length` (2 bytes), `scheme`];
+//! - `CREATE_TABLE_ON_DISK` and `CREATE_TABLE_LSM`: [`action`, `name length` (2 bytes), `name`, `scheme length` (2 bytes), `scheme`];
+//! - `CREATE_TABLE_CACHE`: [`action`, `name length` (2 bytes), `is it logging`, `cache duration` (8 bytes, big endian), `name`, `scheme length` (2 bytes), `scheme`].
+//!
+//! Followers get the records of the leader and apply them, see [`crate::server::replication`].
+use crate::{
+    constants::actions::{CREATE_TABLE_CACHE, CREATE_TABLE_IN_MEMORY, CREATE_TABLE_LSM, CREATE_TABLE_ON_DISK, DELETE, INSERT, SET},
+    table::table::TableEngine,
+    utils::bytes::uint
+};
+
+#[derive(Debug, PartialEq)]
+pub enum LogRecord<'a> {
+    Insert { table: u16, key: &'a [u8], value: &'a [u8] },
+    Set { table: u16, key: &'a [u8], value: &'a [u8] },
+    Delete { table: u16, key: &'a [u8] },
+    CreateTable { engine: TableEngine, name: &'a [u8], is_it_logging: bool, cache_duration: u64, user_scheme: &'a [u8] },
+}
+
+/// Reads bytes of a record. Every function returns `None`, if the buffer ends before the record.
+struct Reader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.buf.get(self.offset..self.offset.checked_add(len)?)?;
+        self.offset += len;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(uint::u16(self.bytes(2)?))
+    }
+
+    fn key(&mut self) -> Option<&'a [u8]> {
+        let len = match self.u8()? {
+            255 => self.u16()? as usize,
+            len => len as usize
+        };
+        self.bytes(len)
+    }
+
+    fn value(&mut self) -> Option<&'a [u8]> {
+        let len = match self.u16()? {
+            65535 => uint::u32(self.bytes(4)?) as usize,
+            len => len as usize
+        };
+        self.bytes(len)
+    }
+}
+
+/// Decodes the first record of `buf`. Returns the record and its length, or `Ok(None)`, if `buf` has only a part of it.
+/// Returns the action as an error, if the record is unknown.
+pub fn decode(buf: &[u8]) -> Result<Option<(LogRecord<'_>, usize)>, u8> {
+    let action = match buf.first() {
+        Some(action) => *action,
+        None => return Ok(None)
+    };
+    let mut reader = Reader { buf, offset: 1 };
+    let record = match action {
+        INSERT | SET | DELETE => (|| {
+            let table = reader.u16()?;
+            let key = reader.key()?;
+            Some(match action {
+                INSERT => LogRecord::Insert { table, key, value: reader.value()? },
+                SET => LogRecord::Set { table, key, value: reader.value()? },
+                _ => LogRecord::Delete { table, key }
+            })
+        })(),
+        CREATE_TABLE_IN_MEMORY | CREATE_TABLE_ON_DISK | CREATE_TABLE_CACHE | CREATE_TABLE_LSM => (|| {
+            let name_len = reader.u16()? as usize;
+            let engine = match action {
+                CREATE_TABLE_IN_MEMORY => TableEngine::InMemory,
+                CREATE_TABLE_ON_DISK => TableEngine::OnDisk,
+                CREATE_TABLE_LSM => TableEngine::Lsm,
+                _ => TableEngine::CACHE
+            };
+            // LSM tables always log.
+            let is_it_logging = match engine {
+                TableEngine::OnDisk => false,
+                TableEngine::Lsm => true,
+                _ => reader.u8()? != 0
+            };
+            let cache_duration = if engine == TableEngine::CACHE {
+                u64::from_be_bytes(reader.bytes(8)?.try_into().ok()?)
+            } else {
+                0
+            };
+            let name = reader.bytes(name_len)?;
+            let scheme_len = reader.u16()? as usize;
+            let user_scheme = reader.bytes(scheme_len)?;
+            Some(LogRecord::CreateTable { engine, name, is_it_logging, cache_duration, user_scheme })
+        })(),
+        _ => return Err(action)
+    };
+    Ok(record.map(|record| (record, reader.offset)))
+}
+
+/// Appends the record of a new table.
+pub fn encode_create_table(buf: &mut Vec<u8>, engine: TableEngine, name: &[u8], is_it_logging: bool, cache_duration: u64, user_scheme: &[u8]) {
+    let name_len = name.len();
+    let scheme_len = user_scheme.len();
+    match engine {
+        TableEngine::InMemory => {
+            buf.extend_from_slice(&[CREATE_TABLE_IN_MEMORY, name_len as u8, (name_len >> 8) as u8]);
+            buf.push(if is_it_logging { 1 } else { 0 });
+        }
+        TableEngine::OnDisk => {
+            buf.extend_from_slice(&[CREATE_TABLE_ON_DISK, name_len as u8, (name_len >> 8) as u8]);
+        }
+        TableEngine::Lsm => {
+            buf.extend_from_slice(&[CREATE_TABLE_LSM, name_len as u8, (name_len >> 8) as u8]);
+        }
+        TableEngine::CACHE => {
+            // TODO: maybe extra two bytes?
+            buf.extend_from_slice(&[CREATE_TABLE_CACHE, name_len as u8, (name_len >> 8) as u8]);
+            buf.push(if is_it_logging { 1 } else { 0 });
+            buf.extend_from_slice(&cache_duration.to_be_bytes());
+        }
+    }
+    buf.extend_from_slice(name);
+    buf.extend_from_slice(&[scheme_len as u8, (scheme_len >> 8) as u8]);
+    buf.extend_from_slice(user_scheme);
+}
+
+/// Appends a `DELETE` record.
+pub fn encode_key(buf: &mut Vec<u8>, action: u8, table: u16, key: &[u8]) {
+    buf.extend_from_slice(&[action, table as u8, (table >> 8) as u8]);
+    let key_len = key.len();
+    if key_len < 255 {
+        buf.push(key_len as u8);
+    } else {
+        buf.extend_from_slice(&[255, key_len as u8, (key_len >> 8) as u8]);
+    }
+    buf.extend_from_slice(key);
+}
+
+/// Appends an `INSERT` or `SET` record.
+pub fn encode_key_and_value(buf: &mut Vec<u8>, action: u8, table: u16, key: &[u8], value: &[u8]) {
+    encode_key(buf, action, table, key);
+    let value_len = value.len();
+    if value_len < 65535 {
+        buf.extend_from_slice(&[value_len as u8, (value_len >> 8) as u8]);
+    } else {
+        buf.extend_from_slice(&[255, 255]);
+        buf.extend_from_slice(&uint::u32tob(value_len as u32));
+    }
+    buf.extend_from_slice(value);
+}
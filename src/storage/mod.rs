@@ -1,3 +1,4 @@
 pub mod storage;
+pub mod log_record;
 
 pub use storage::Storage;
\ No newline at end of file
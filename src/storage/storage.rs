@@ -1,11 +1,15 @@
 use crate::{
     bin_types::{BinKey, BinValue},
     constants::actions::*,
+    disk_storage::value_cache::ValueCache,
     index::{HashInMemoryIndex, Index},
+    metrics::METRICS,
     scheme::scheme::{empty_scheme, scheme_from_bytes, Scheme},
     table::{
         cache::CacheTable,
         in_memory::InMemoryTable,
+        lsm::LsmTable,
+        notifications::Notifications,
         on_disk::OnDiskTable,
         table::{Table, TableEngine},
     },
@@ -26,11 +30,16 @@ use std::{
     Arc, RwLock, RwLockWriteGuard,
     },
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
-    env, thread,
+    thread,
 };
 
 pub static NOW_MINUTES: AtomicU64 = AtomicU64::new(0);
 
+/// We allocate the tables vector once, so it can't contain more tables (see [`Storage::tables`]).
+pub const MAX_NUMBER_OF_TABLES: usize = 4096;
+/// create_*_table functions return it, if the table can't be created.
+pub const CANT_CREATE_TABLE_NUMBER: usize = (u16::MAX - 1u16) as usize;
+
 pub struct Storage {
     /// SAFETY:
     ///
@@ -48,30 +57,30 @@ pub struct Storage {
 
     pub persistence_dir_path: PathBuf,
     pub log_file: LogFile,
+    /// The number of the log file, that is written now. It is changed with the log file locked.
+    pub log_number: AtomicU32,
     pub table_configs_file_path: PathBuf,
     pub number_of_dumps_file_path: PathBuf,
 
     pub cache_tables_indexes: RwLock<Vec<usize>>,
-    pub dump_interval: u32,
+    /// In minutes. It is the `dump_interval` setting, so it can be changed at runtime.
+    pub dump_interval: AtomicU32,
+    /// How many log files before the current one the dump keeps. It is the `retained_logs` setting.
+    pub retained_logs: AtomicU32,
+    pub notifications: Arc<Notifications>,
+    /// Values of on-disk tables. Its capacity is the `disk_cache_bytes` setting.
+    pub disk_cache: Arc<ValueCache>,
 }
 
+/// In minutes. Use the `dump_interval` setting to change it.
+pub const DEFAULT_DUMP_INTERVAL: u32 = 60;
+/// Use the `retained_logs` setting to change it.
+pub const DEFAULT_RETAINED_LOGS: u32 = 0;
+
 impl Storage {
     pub fn new(persistence_dir_path: PathBuf) -> Self {
         std::fs::create_dir_all(&persistence_dir_path)
             .expect("[Error] Failed to create the persistence directory");
-        let dump_interval = match env::var("DUMP_INTERVAL") {
-            Ok(value) => {
-                info!("The dump interval was set to: {} minutes using the environment variable \"DUMP_INTERVAL\"", value);
-                value
-                    .parse()
-                    .expect("[Panic] The dump interval must be a number!")
-            }
-            Err(_) => {
-                info!("The dump interval was not set using the environment variable \"DUMP_INTERVAL\", setting it to 60 minutes");
-                60
-            }
-        };
-
         let number_of_dumps_file_path: PathBuf = persistence_dir_path.join("number_of_dumps.bin");
         let file = OpenOptions::new()
             .read(true)
@@ -101,29 +110,65 @@ impl Storage {
         let log_number = Self::get_log_file_number(number_of_dumps_file_path.clone());
         let file_name = format!("log{log_number}.bin",);
         let path: PathBuf = persistence_dir_path.join(file_name);
+        // Records, that were written before the restart, stay in the file, so sequence numbers of changes stay valid.
         let log_file = OpenOptions::new()
             .read(true)
-            .write(true)
+            .append(true)
             .create(true)
             .open(path)
             .unwrap();
         let log_file = LogFile::new(log_file);
 
         Self {
-            tables: UnsafeCell::new(Vec::with_capacity(4096)),
+            tables: UnsafeCell::new(Vec::with_capacity(MAX_NUMBER_OF_TABLES)),
             tables_names: RwLock::new(Vec::with_capacity(1)),
             cache_tables_indexes: RwLock::new(Vec::with_capacity(1)),
             number_of_dumps: Arc::new(AtomicU32::new(number_of_dumps)),
             last_tables_count: AtomicU32::new(0),
             persistence_dir_path,
             log_file,
+            log_number: AtomicU32::new(log_number as u32),
             table_configs_file_path,
             number_of_dumps_file_path,
-            dump_interval,
+            dump_interval: AtomicU32::new(DEFAULT_DUMP_INTERVAL),
+            retained_logs: AtomicU32::new(DEFAULT_RETAINED_LOGS),
+            notifications: Arc::new(Notifications::default()),
+            disk_cache: Arc::new(ValueCache::default()),
+        }
+    }
+
+    pub fn log_file_path(&self, number: u32) -> PathBuf {
+        self.persistence_dir_path.join(format!("log{}.bin", number))
+    }
+
+    /// Rise replays log files from this number. It is the number of the last successful dump, so if a dump fails,
+    /// changes, that the failed tables have in memory only, are replayed from older log files.
+    /// Storages without the `first_log.bin` file replay the log of the last dump.
+    pub fn first_log_number(&self) -> u32 {
+        let mut buf = [0u8; 4];
+        match File::open(self.persistence_dir_path.join("first_log.bin")).and_then(|mut file| file.read_exact(&mut buf)) {
+            Ok(()) => uint::u32(&buf),
+            Err(_) => Self::get_log_file_number(self.number_of_dumps_file_path.clone()) as u32,
         }
     }
 
+    /// Writes a new file and renames it, so the file is never half-written.
+    fn write_first_log_number(&self, number: u32) {
+        let path = self.persistence_dir_path.join("first_log.bin");
+        let tmp_path = path.with_extension("bin.tmp");
+        let result = std::fs::write(&tmp_path, uint::u32tob(number)).and_then(|_| std::fs::rename(&tmp_path, &path));
+        if let Err(e) = result {
+            error!("Can't write the first log number to {}: {}", path.display(), e);
+        }
+    }
+
+    /// Dumps all tables and starts a new log file. Old log files are removed, only if all tables are dumped,
+    /// otherwise rise replays them (see [`Storage::first_log_number`]).
     pub fn dump(&'static self) {
+        let start = Instant::now();
+        // Is written before the new log is started, so changes are replayed from it, if the dump fails or the server stops now.
+        let first_log_number = self.first_log_number();
+        self.write_first_log_number(first_log_number);
         let old_number_of_dumps = self.number_of_dumps.fetch_add(1, SeqCst);
         let number_of_dumps = old_number_of_dumps + 1;
         let mut file = OpenOptions::new()
@@ -141,14 +186,15 @@ impl Storage {
         .unwrap();
 
         {
-            let file_name = format!("log{}.bin", number_of_dumps);
-            let path: PathBuf = self.persistence_dir_path.join(file_name);
-            let file = File::create(path).unwrap();
-            *self.log_file.file.lock().unwrap() = file;
+            let file = File::create(self.log_file_path(number_of_dumps)).unwrap();
+            let mut log_file = self.log_file.file.lock().unwrap();
+            *log_file = file;
+            self.log_number.store(number_of_dumps, SeqCst);
         }
 
         let last_tables_count = self.last_tables_count.load(SeqCst);
         let join = thread::spawn(move || {
+            let mut is_dumped = true;
             let tables = self.tables.get_mut();
             for (number, table) in tables.iter().enumerate() {
                 if number as u32 >= last_tables_count {
@@ -164,6 +210,15 @@ impl Storage {
                         }
                         TableEngine::OnDisk => {
                             self.write_on_disk_table_on_disk(
+                                CREATE_TABLE_ON_DISK,
+                                &table.name(),
+                                number,
+                                &table.user_scheme(),
+                            );
+                        }
+                        TableEngine::Lsm => {
+                            self.write_on_disk_table_on_disk(
+                                CREATE_TABLE_LSM,
                                 &table.name(),
                                 number,
                                 &table.user_scheme(),
@@ -180,7 +235,10 @@ impl Storage {
                         }
                     }
                 }
-                table.dump();
+                if let Err(e) = table.dump() {
+                    error!("Failed to dump the table {}: {}", table.name(), e);
+                    is_dumped = false;
+                }
                 file.write_all(&[
                     number as u8,
                     (number >> 8) as u8,
@@ -189,15 +247,31 @@ impl Storage {
                 ])
                 .unwrap();
             }
+            is_dumped
         });
-        join.join().unwrap();
+        let is_dumped = join.join().unwrap();
 
         self.last_tables_count
             .store(self.tables.get().len() as u32, SeqCst);
 
-        let file_name = format!("log{}.bin", old_number_of_dumps);
-        let path: PathBuf = self.persistence_dir_path.join(file_name);
-        let _ = std::fs::remove_file(path);
+        // Changes of tables, that weren't dumped, are only in the log, so rise replays it from the first log number as before.
+        let first_log_number = if is_dumped {
+            self.write_first_log_number(number_of_dumps);
+            number_of_dumps
+        } else {
+            warn!("Not all tables are dumped, so log files from log{}.bin are kept", first_log_number);
+            first_log_number
+        };
+        // Old log files are kept for subscribers of changes, see [`crate::server::changes`].
+        let retained_logs = self.retained_logs.load(SeqCst);
+        let last_removed = old_number_of_dumps.checked_sub(retained_logs)
+            .and_then(|number| Some(number.min(first_log_number.checked_sub(1)?)));
+        if let Some(mut number) = last_removed {
+            while std::fs::remove_file(self.log_file_path(number)).is_ok() && number > 0 {
+                number -= 1;
+            }
+        }
+        METRICS.dumped(start.elapsed());
     }
 
     pub fn init(&'static self) {
@@ -211,14 +285,14 @@ impl Storage {
 
         NOW_MINUTES.store(since_the_epoch, SeqCst);
 
-        let dump_interval = self.dump_interval;
         tokio::spawn(async move {
-            let mut dump_after = dump_interval;
+            let mut minutes_since_dump = 0;
             loop {
                 tokio::time::sleep(Duration::from_secs(60)).await;
 
-                dump_after -= 1;
-                if dump_after == 0 {
+                // The interval is read every minute, because it can be changed at runtime.
+                minutes_since_dump += 1;
+                if minutes_since_dump >= self.dump_interval.load(SeqCst) {
                     tokio::spawn(async move {
                         info!("Starting dump");
                         let start = Instant::now();
@@ -226,7 +300,7 @@ impl Storage {
                         let elapsed = start.elapsed();
                         success!("Dump took {:?} seconds", elapsed);
                     });
-                    dump_after = dump_interval;
+                    minutes_since_dump = 0;
                 }
 
                 let since_the_epoch = SystemTime::now()
@@ -266,18 +340,38 @@ impl Storage {
         file.write_all(bin_config).unwrap();
     }
 
+    pub fn table_exists(&self, name: &str) -> bool {
+        match self.tables_names.read() {
+            Ok(tables_names) => tables_names.iter().any(|table_name| table_name == name),
+            Err(_) => false,
+        }
+    }
+
+    /// Returns the table with this name and its number.
+    pub fn table_by_name(&self, name: &[u8]) -> Option<(usize, &(dyn Table + 'static))> {
+        let number = match self.tables_names.read() {
+            Ok(tables_names) => tables_names.iter().position(|table_name| table_name.as_bytes() == name)?,
+            Err(_) => return None,
+        };
+        self.tables.get().get(number).map(|table| (number, table.as_ref()))
+    }
+
+    /// Returns None, if the table doesn't exist and we can't create more tables.
     fn insert_table_name_and_get_number(
         tables_names: &mut RwLockWriteGuard<Vec<String>>,
         name: &str,
-    ) -> (usize, bool) {
+    ) -> Option<(usize, bool)> {
         let len = tables_names.len();
         for i in 0..len {
             if tables_names[i] == name {
-                return (i, true);
+                return Some((i, true));
             }
         }
+        if len >= MAX_NUMBER_OF_TABLES {
+            return None;
+        }
         tables_names.push(name.to_string());
-        (len, false)
+        Some((len, false))
     }
 
     pub fn create_in_memory_table<I: Index<BinKey, BinValue> + 'static>(
@@ -289,7 +383,10 @@ impl Storage {
         user_scheme: &[u8],
     ) -> usize {
         let mut lock = self.tables_names.write().unwrap();
-        let (number, is_exist) = Self::insert_table_name_and_get_number(&mut lock, &name);
+        let (number, is_exist) = match Self::insert_table_name_and_get_number(&mut lock, &name) {
+            Some(res) => res,
+            None => return CANT_CREATE_TABLE_NUMBER,
+        };
         if is_exist {
             return number;
         }
@@ -302,6 +399,7 @@ impl Storage {
             self.number_of_dumps.clone(),
             scheme,
             Box::from(user_scheme),
+            self.notifications.table(number as u16),
         );
         self.tables.get_mut().push(Box::new(table));
 
@@ -343,30 +441,80 @@ impl Storage {
         user_scheme: &[u8],
     ) -> usize {
         let mut lock = self.tables_names.write().unwrap();
-        let (number, is_exist) = Self::insert_table_name_and_get_number(&mut lock, &name);
+        let (number, is_exist) = match Self::insert_table_name_and_get_number(&mut lock, &name) {
+            Some(res) => res,
+            None => return CANT_CREATE_TABLE_NUMBER,
+        };
         if is_exist {
             return number;
         }
-        let table = OnDiskTable::new(
+        let table = match OnDiskTable::new(
             self.persistence_dir_path.clone(),
+            number as u16,
             name.clone(),
             512,
             index,
             scheme,
             Box::from(user_scheme),
-        );
+            self.notifications.table(number as u16),
+            self.disk_cache.clone(),
+        ) {
+            Ok(table) => table,
+            Err(e) => {
+                error!("Can't open files of the on-disk table {}: {}", name, e);
+                lock.pop();
+                return CANT_CREATE_TABLE_NUMBER;
+            }
+        };
         self.tables.get_mut().push(Box::new(table));
 
         drop(lock);
 
-        return number;
+        number
     }
 
-    fn write_on_disk_table_on_disk(&'static self, name: &str, number: usize, user_scheme: &[u8]) {
+    pub fn create_lsm_table(
+        &'static self,
+        name: String,
+        scheme: Scheme,
+        user_scheme: &[u8],
+    ) -> usize {
+        let mut lock = self.tables_names.write().unwrap();
+        let (number, is_exist) = match Self::insert_table_name_and_get_number(&mut lock, &name) {
+            Some(res) => res,
+            None => return CANT_CREATE_TABLE_NUMBER,
+        };
+        if is_exist {
+            return number;
+        }
+        let table = match LsmTable::new(
+            self.persistence_dir_path.clone(),
+            number as u16,
+            name.clone(),
+            scheme,
+            Box::from(user_scheme),
+            self.notifications.table(number as u16),
+        ) {
+            Ok(table) => table,
+            Err(e) => {
+                error!("Can't open the LSM tree of the table {}: {}", name, e);
+                lock.pop();
+                return CANT_CREATE_TABLE_NUMBER;
+            }
+        };
+        self.tables.get_mut().push(Box::new(table));
+
+        drop(lock);
+
+        number
+    }
+
+    /// `action` is [`CREATE_TABLE_ON_DISK`] or [`CREATE_TABLE_LSM`], their configs are the same.
+    fn write_on_disk_table_on_disk(&'static self, action: u8, name: &str, number: usize, user_scheme: &[u8]) {
         let name_len = name.len();
         let mut buf = Vec::with_capacity(7 + name_len + user_scheme.len());
         buf.extend_from_slice(&[
-            CREATE_TABLE_ON_DISK,
+            action,
             number as u8,
             (number >> 8) as u8,
             name_len as u8,
@@ -389,7 +537,10 @@ impl Storage {
         user_scheme: &[u8],
     ) -> usize {
         let mut lock = self.tables_names.write().unwrap();
-        let (number, is_exist) = Self::insert_table_name_and_get_number(&mut lock, &name);
+        let (number, is_exist) = match Self::insert_table_name_and_get_number(&mut lock, &name) {
+            Some(res) => res,
+            None => return CANT_CREATE_TABLE_NUMBER,
+        };
         if is_exist {
             return number;
         }
@@ -403,6 +554,7 @@ impl Storage {
             self.number_of_dumps.clone(),
             scheme,
             Box::from(user_scheme),
+            self.notifications.table(number as u16),
         );
         self.tables.get_mut().push(Box::new(table));
         self.cache_tables_indexes.write().unwrap().push(number);
@@ -601,7 +753,7 @@ impl Storage {
                                 user_scheme,
                             );
                         }
-                        CREATE_TABLE_ON_DISK => {
+                        CREATE_TABLE_ON_DISK | CREATE_TABLE_LSM => {
                             if offset + 2 > bytes_read {
                                 read_more(
                                     &mut buf,
@@ -683,13 +835,17 @@ impl Storage {
                                 }
                             }
 
-                            Self::create_on_disk_table(
-                                self,
-                                name,
-                                HashInMemoryIndex::new(),
-                                scheme.unwrap(),
-                                user_scheme,
-                            );
+                            if table_engine == CREATE_TABLE_LSM {
+                                Self::create_lsm_table(self, name, scheme.unwrap(), user_scheme);
+                            } else {
+                                Self::create_on_disk_table(
+                                    self,
+                                    name,
+                                    HashInMemoryIndex::new(),
+                                    scheme.unwrap(),
+                                    user_scheme,
+                                );
+                            }
                         }
                         CREATE_TABLE_CACHE => {
                             if offset + 2 > bytes_read {
@@ -845,7 +1001,7 @@ impl Storage {
     }
 
     pub fn read_log(&'static self) {
-        let mut log_number = Self::get_log_file_number(self.number_of_dumps_file_path.clone());
+        let mut log_number = self.first_log_number() as usize;
 
         loop {
             let file_name = format!("log{}.bin", log_number);
@@ -995,7 +1151,8 @@ impl Storage {
                             value_offset = offset;
                             offset += vl as usize;
 
-                            tables[number as usize].insert_without_log(
+                            // Tables log their own errors, the rest of the log is replayed anyway.
+                            let _ = tables[number as usize].insert_without_log(
                                 BinKey::new(&chunk[key_offset..key_offset + kl as usize]),
                                 BinValue::new(&chunk[value_offset..offset]),
                             );
@@ -1081,7 +1238,8 @@ impl Storage {
                             value_offset = offset;
                             offset += vl as usize;
 
-                            tables[number as usize].set_without_log(
+                            // Tables log their own errors, the rest of the log is replayed anyway.
+                            let _ = tables[number as usize].set_without_log(
                                 BinKey::new(&chunk[key_offset..key_offset + kl as usize]),
                                 BinValue::new(&chunk[value_offset..offset]),
                             );
@@ -1136,7 +1294,8 @@ impl Storage {
                             key_offset = offset;
                             offset += kl as usize;
 
-                            tables[number as usize].delete_without_log(&BinKey::new(
+                            // Tables log their own errors, the rest of the log is replayed anyway.
+                            let _ = tables[number as usize].delete_without_log(&BinKey::new(
                                 &chunk[key_offset..key_offset + kl as usize],
                             ));
                         }
@@ -1242,7 +1401,7 @@ impl Storage {
                                 user_scheme,
                             );
                         }
-                        CREATE_TABLE_ON_DISK => {
+                        CREATE_TABLE_ON_DISK | CREATE_TABLE_LSM => {
                             if offset + 2 > bytes_read {
                                 read_more(
                                     &mut chunk,
@@ -1321,13 +1480,17 @@ impl Storage {
                                 }
                             }
 
-                            Self::create_on_disk_table(
-                                self,
-                                name,
-                                HashInMemoryIndex::new(),
-                                scheme.unwrap(),
-                                user_scheme,
-                            );
+                            if action == CREATE_TABLE_LSM {
+                                Self::create_lsm_table(self, name, scheme.unwrap(), user_scheme);
+                            } else {
+                                Self::create_on_disk_table(
+                                    self,
+                                    name,
+                                    HashInMemoryIndex::new(),
+                                    scheme.unwrap(),
+                                    user_scheme,
+                                );
+                            }
                         }
                         CREATE_TABLE_CACHE => {
                             if offset + 2 > bytes_read {
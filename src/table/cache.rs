@@ -1,19 +1,22 @@
 use std::{
-    fs::{DirBuilder, File},
-    io::{Read, Seek, SeekFrom, Write},
+    cell::{Cell, RefCell},
+    mem::size_of,
+    fs::DirBuilder,
+    io::{self, Read},
     path::PathBuf,
-    sync::{Arc, atomic::{AtomicBool, AtomicU32, Ordering::SeqCst}},
+    sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering::SeqCst}},
 };
 use crate::{
     bin_types::{BinKey, BinValue},
-    constants::actions,
+    constants::{actions, errors::Error},
     error,
-    table::table::{Table, TableEngine},
+    table::{dump::{DumpReader, DumpWriter}, notifications::{self, TableNotifications}, table::{Table, TableEngine}},
     storage::storage::NOW_MINUTES,
     index::Index,
+    metrics::METRICS,
     scheme::scheme,
-    utils::{bytes::uint, read_more},
-    writers::{LogWriter, SizedWriter},
+    utils::{compression::Compression, read_more},
+    writers::LogWriter,
 };
 
 pub struct CacheTable<I: Index<BinKey, (u64, BinValue)>> {
@@ -27,7 +30,9 @@ pub struct CacheTable<I: Index<BinKey, (u64, BinValue)>> {
     is_it_logging: bool,
     scheme: scheme::Scheme,
     user_scheme: Box<[u8]>,
-    persistence_dir_path: PathBuf
+    compression: Compression,
+    persistence_dir_path: PathBuf,
+    notifications: TableNotifications
 }
 
 impl<I: Index<BinKey, (u64, BinValue)>> CacheTable<I> {
@@ -41,6 +46,7 @@ impl<I: Index<BinKey, (u64, BinValue)>> CacheTable<I> {
         number_of_dumps: Arc<AtomicU32>,
         scheme: scheme::Scheme,
         user_scheme: Box<[u8]>,
+        notifications: TableNotifications,
     ) -> CacheTable<I> {
         CacheTable {
             persistence_dir_path,
@@ -52,7 +58,9 @@ impl<I: Index<BinKey, (u64, BinValue)>> CacheTable<I> {
             name,
             is_it_logging,
             scheme,
-            user_scheme
+            compression: Compression::from_user_scheme(&user_scheme).unwrap_or(Compression::NONE),
+            user_scheme,
+            notifications
         }
     }
 }
@@ -79,65 +87,79 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
     }
 
     #[inline(always)]
-    fn get(&self, key: &BinKey) -> Option<BinValue> {
+    fn get(&self, key: &BinKey) -> Result<Option<BinValue>, Error> {
         let res = self.index.get_and_modify(key, |value| {
             value.0 = NOW_MINUTES.load(SeqCst);
         });
 
         if res.is_none() {
-            return None;
+            return Ok(None);
         }
-        Some(res.unwrap().1)
+        self.compression.unpack(res.unwrap().1).map(Some)
     }
 
     #[inline(always)]
-    fn set(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Option<BinValue> {
+    fn set(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<Option<BinValue>, Error> {
         if self.is_it_logging {
             log_writer.write_key_and_value(actions::SET, self.number, &key, &value);
+        } else {
+            log_writer.replicate_key_and_value(actions::SET, self.number, &key, &value);
         }
 
-        let res = self.index.set(key, (NOW_MINUTES.load(SeqCst), value));
-        if res.is_none() {
-            return None;
-        }
-        Some(res.unwrap().1)
+        self.set_without_log(key, value)
     }
 
     #[inline(always)]
-    fn set_without_log(&self, key: BinKey, value: BinValue) -> Option<BinValue> {
-        let res = self.index.set(key, (NOW_MINUTES.load(SeqCst), value));
+    fn set_without_log(&self, key: BinKey, value: BinValue) -> Result<Option<BinValue>, Error> {
+        let notified_key = if self.notifications.is_wanted(notifications::SET) { Some(key.clone()) } else { None };
+        let res = self.index.set(key, (NOW_MINUTES.load(SeqCst), self.compression.pack(value)));
+        if let Some(key) = notified_key {
+            self.notifications.notify(notifications::SET, key.deref());
+        }
         if res.is_none() {
-            return None;
+            return Ok(None);
         }
-        Some(res.unwrap().1)
+        self.compression.unpack(res.unwrap().1).map(Some)
     }
 
     #[inline(always)]
-    fn insert(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> bool {
+    fn insert(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<bool, Error> {
         if self.is_it_logging {
             log_writer.write_key_and_value(actions::INSERT, self.number, &key, &value);
+        } else {
+            log_writer.replicate_key_and_value(actions::INSERT, self.number, &key, &value);
         }
 
-        self.index.insert(key, (NOW_MINUTES.load(SeqCst), value))
+        self.insert_without_log(key, value)
     }
 
     #[inline(always)]
-    fn insert_without_log(&self, key: BinKey, value: BinValue) -> bool {
-        self.index.insert(key, (NOW_MINUTES.load(SeqCst), value))
+    fn insert_without_log(&self, key: BinKey, value: BinValue) -> Result<bool, Error> {
+        let notified_key = if self.notifications.is_wanted(notifications::INSERT) { Some(key.clone()) } else { None };
+        let is_inserted = self.index.insert(key, (NOW_MINUTES.load(SeqCst), self.compression.pack(value)));
+        if let Some(key) = notified_key.filter(|_| is_inserted) {
+            self.notifications.notify(notifications::INSERT, key.deref());
+        }
+        Ok(is_inserted)
     }
 
     #[inline(always)]
-    fn delete(&self, key: &BinKey, log_writer: &mut LogWriter) {
+    fn delete(&self, key: &BinKey, log_writer: &mut LogWriter) -> Result<(), Error> {
         if self.is_it_logging {
             log_writer.write_key(actions::DELETE, self.number, key);
+        } else {
+            log_writer.replicate_key(actions::DELETE, self.number, key);
         }
 
-        self.index.remove(key);
+        self.delete_without_log(key)
     }
 
     #[inline(always)]
-    fn delete_without_log(&self, key: &BinKey) {
-        self.index.remove(key);
+    fn delete_without_log(&self, key: &BinKey) -> Result<(), Error> {
+        if self.index.remove(key).is_some() {
+            self.notifications.notify(notifications::DELETE, key.deref());
+        }
+        Ok(())
     }
 
     #[inline(always)]
@@ -145,14 +167,43 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
         self.index.count() as u64
     }
 
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let keys = RefCell::new(Vec::with_capacity(self.index.count()));
+        self.index.for_each(|key, _| keys.borrow_mut().push(key.deref().to_vec()));
+        Ok(keys.into_inner())
+    }
+
+    fn memory_usage(&self) -> u64 {
+        let usage = Cell::new(0);
+        self.index.for_each(|key, value| {
+            usage.set(usage.get() + (key.deref_all().len() + value.1.deref_all().len() + size_of::<(BinKey, (u64, BinValue))>()) as u64);
+        });
+        usage.get()
+    }
+
     #[inline(always)]
     fn invalid_cache(&self) {
         let now = NOW_MINUTES.load(SeqCst);
         let duration = self.cache_duration;
 
-        self.index.retain(|_, value| {
-            return value.0 + duration > now;
+        let expired = AtomicU64::new(0);
+        // Keys are notified after the walk, so subscribers and watches don't wait for the index.
+        let expired_keys = Mutex::new(Vec::new());
+        let is_notified = self.notifications.is_wanted(notifications::EXPIRE);
+        self.index.retain(|key, value| {
+            let is_alive = value.0 + duration > now;
+            if !is_alive {
+                expired.fetch_add(1, SeqCst);
+                if is_notified {
+                    expired_keys.lock().unwrap().push(key.deref().to_vec());
+                }
+            }
+            is_alive
         });
+        METRICS.cache_expirations.add(expired.into_inner());
+        for key in expired_keys.into_inner().unwrap() {
+            self.notifications.notify(notifications::EXPIRE, &key);
+        }
     }
 
     fn user_scheme(&self) -> Box<[u8]> {
@@ -163,10 +214,11 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
         &self.scheme
     }
 
-    fn dump(&self) {
-        const BUF_SIZE: usize = 64 * 1024;
-        const COUNT_OF_ELEMS_SIZE: usize = 8;
+    fn notifications(&self) -> &TableNotifications {
+        &self.notifications
+    }
 
+    fn dump(&self) -> io::Result<()> {
         let number = self.number_of_dumps.load(SeqCst);
         if self.was_dumped.load(SeqCst) == false {
             let dir_path: PathBuf = self.persistence_dir_path.join(self.name.clone());
@@ -176,26 +228,15 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
         let file_name = format!("{}{number}.dump", self.name);
         let path: PathBuf = self.persistence_dir_path.join(self.name.clone()).join(file_name);
         // TODO: maybe remove old dumps?
-        let output = File::create(path.clone()).expect(&*format!("failed to create file with path {}", path.to_string_lossy()));
-        let mut writer = SizedWriter::new_with_capacity(output, BUF_SIZE);
-        let mut count = 0;
-
-        // COUNT_OF_ELEMS_SIZE bytes for number of elements and one byte for a flag.
-        // We will set the flag to one, when we finish dumping
-        writer.write(&[0u8;COUNT_OF_ELEMS_SIZE + 1]).expect("failed to write");
+        let mut writer = DumpWriter::create(&path, self.compression.codec)?;
+        let mut result = Ok(());
         self.index.for_each_mut(|key, value| {
-            count += 1;
-            writer.write_key(key).expect("failed to write");
-            writer.write_value(&value.1).expect("failed to write");
+            if result.is_ok() {
+                result = writer.write_record(key, &value.1);
+            }
         });
-        // Write number of elements and change the flag
-        let mut buf = [1u8;COUNT_OF_ELEMS_SIZE+1];
-        buf[0] = count as u8;
-        buf[1..COUNT_OF_ELEMS_SIZE + 1].copy_from_slice(&uint::u64tob(count as u64));
-        // Seek will flush the buffer before seek.
-        writer.inner.seek(SeekFrom::Start(0)).expect("failed to seek");
-        writer.inner.write_all(&buf).expect("failed to write");
-        writer.inner.flush().expect("failed to flush");
+        result?;
+        writer.finish()
     }
 
     fn rise(&mut self) {
@@ -207,16 +248,10 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
         let file_name = format!("{}{}.dump", self.name, number_of_dumps);
         let path: PathBuf = self.persistence_dir_path.join(self.name.clone()).join(file_name.clone());
 
-        let mut input = File::open(path.clone()).expect(&*format!("Failed to open file with path: {}", path.to_string_lossy()));
-        let file_len = input.metadata().unwrap().len();
-        if file_len < 8 {
-            panic!("file len is less than 8!");
-        }
+        let mut input = DumpReader::open(&path).expect(&*format!("Failed to open file with path: {}", path.to_string_lossy()));
         let mut chunk = [0u8; 64 * 1024];
-        input.read(&mut chunk[..8]).expect("can't get count from buf!");
-        let all_count = uint::u64(&chunk[..8]);
+        let all_count = input.count;
         self.index.resize(((all_count as f64) * 1.2) as usize);
-        let mut total_read = 8;
         let mut total_records_read = 0;
 
         let mut offset_last_record = 0;
@@ -228,9 +263,6 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
         let mut vl;
 
         'read: loop {
-            if total_read == file_len {
-                break;
-            }
             let mut bytes_read = input.read(&mut chunk[offset_last_record..]).expect("Failed to read");
             if bytes_read == 0 {
                 break;
@@ -239,11 +271,11 @@ impl<I: Index<BinKey, (u64, BinValue)>> Table for CacheTable<I> {
 
             bytes_read += offset_last_record;
             offset = 0;
-            total_read += bytes_read as u64;
 
             loop {
                 if offset + 1 > bytes_read {
-                    read_more(&mut chunk, start_offset, bytes_read, &mut offset_last_record);
+                    // The last record is whole, so nothing is left for the next read.
+                    read_more(&mut chunk, offset, bytes_read, &mut offset_last_record);
                     continue 'read;
                 }
                 start_offset = offset;
@@ -0,0 +1,143 @@
+//! Dump files of in-memory and cache tables. Records are [`key` with its size, `value` with its size], see [`BinKey`] and [`BinValue`].
+//!
+//! - Tables without compression write [`count as u8` (1 byte), `count` (8 bytes)] and raw records.
+//! - Tables with compression write [[`MAGIC`] (4 bytes), `codec` (1 byte), `count` (8 bytes)] and blocks of records:
+//!   [`raw length` (4 bytes), `compressed length` (4 bytes), compressed records].
+//!
+//! The first two bytes of the old header are equal, so they never look like [`MAGIC`].
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::Path
+};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    utils::compression::Codec
+};
+
+pub const MAGIC: [u8; 4] = *b"DBZD";
+/// Records are compressed by blocks of this size.
+const BLOCK_SIZE: usize = 64 * 1024;
+const RAW_HEADER_SIZE: usize = 9;
+const COMPRESSED_HEADER_SIZE: usize = 13;
+
+pub struct DumpWriter {
+    file: File,
+    codec: Codec,
+    buf: Vec<u8>,
+    count: u64,
+}
+
+impl DumpWriter {
+    pub fn create(path: &Path, codec: Codec) -> io::Result<DumpWriter> {
+        let mut file = File::create(path)?;
+        // The header is written again with the count, when the dump is finished.
+        let header_size = if codec == Codec::None { RAW_HEADER_SIZE } else { COMPRESSED_HEADER_SIZE };
+        file.write_all(&[0u8; COMPRESSED_HEADER_SIZE][..header_size])?;
+        Ok(DumpWriter { file, codec, buf: Vec::with_capacity(BLOCK_SIZE * 2), count: 0 })
+    }
+
+    #[inline(always)]
+    pub fn write_record(&mut self, key: &BinKey, value: &BinValue) -> io::Result<()> {
+        self.buf.extend_from_slice(key.deref_all_with_len(key.len()));
+        self.buf.extend_from_slice(value.deref_all_with_len(value.len()));
+        self.count += 1;
+        if self.buf.len() >= BLOCK_SIZE {
+            self.write_block()?;
+        }
+        Ok(())
+    }
+
+    fn write_block(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        if self.codec == Codec::None {
+            self.file.write_all(&self.buf)?;
+        } else {
+            let compressed = self.codec.compress(&self.buf);
+            let mut header = [0u8; 8];
+            header[..4].copy_from_slice(&(self.buf.len() as u32).to_le_bytes());
+            header[4..].copy_from_slice(&(compressed.len() as u32).to_le_bytes());
+            self.file.write_all(&header)?;
+            self.file.write_all(&compressed)?;
+        }
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Writes the rest of records and the header with the count.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write_block()?;
+        let mut header = Vec::with_capacity(COMPRESSED_HEADER_SIZE);
+        if self.codec == Codec::None {
+            header.push(self.count as u8);
+        } else {
+            header.extend_from_slice(&MAGIC);
+            header.push(self.codec as u8);
+        }
+        header.extend_from_slice(&self.count.to_le_bytes());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.flush()
+    }
+}
+
+/// Reads raw records of the dump of any format.
+pub struct DumpReader {
+    file: File,
+    codec: Codec,
+    buf: Vec<u8>,
+    pos: usize,
+    /// The number of records, that the dump has.
+    pub count: u64,
+}
+
+impl DumpReader {
+    pub fn open(path: &Path) -> io::Result<DumpReader> {
+        let mut file = File::open(path)?;
+        let mut header = [0u8; COMPRESSED_HEADER_SIZE];
+        file.read_exact(&mut header[..RAW_HEADER_SIZE])?;
+        if header[..4] != MAGIC {
+            let count = u64::from_le_bytes(header[1..RAW_HEADER_SIZE].try_into().unwrap());
+            return Ok(DumpReader { file, codec: Codec::None, buf: Vec::new(), pos: 0, count });
+        }
+        file.read_exact(&mut header[RAW_HEADER_SIZE..])?;
+        let codec = Codec::from_u8(header[4]).ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "unknown codec of the dump"))?;
+        let count = u64::from_le_bytes(header[5..COMPRESSED_HEADER_SIZE].try_into().unwrap());
+        Ok(DumpReader { file, codec, buf: Vec::new(), pos: 0, count })
+    }
+
+    /// Returns false at the end of the file.
+    fn read_block(&mut self) -> io::Result<bool> {
+        let mut header = [0u8; 8];
+        match self.file.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let raw_len = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+        let mut compressed = vec![0u8; u32::from_le_bytes(header[4..].try_into().unwrap()) as usize];
+        self.file.read_exact(&mut compressed)?;
+        self.buf = self.codec.decompress(&compressed, raw_len)?;
+        self.pos = 0;
+        Ok(true)
+    }
+}
+
+impl Read for DumpReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.codec == Codec::None {
+            return self.file.read(out);
+        }
+        while self.pos == self.buf.len() {
+            if out.is_empty() || !self.read_block()? {
+                return Ok(0);
+            }
+        }
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
@@ -1,18 +1,20 @@
 use std::{
-    fs::{DirBuilder, File},
-    io::{Read, Seek, SeekFrom, Write},
+    cell::{Cell, RefCell},
+    mem::size_of,
+    fs::DirBuilder,
+    io::{self, Read},
     path::PathBuf,
     sync::{Arc, atomic::{AtomicBool, AtomicU32, Ordering::SeqCst}},
 };
 use crate::{
     bin_types::{BinKey, BinValue},
-    constants::actions,
+    constants::{actions, errors::Error},
     error,
     index::Index,
     scheme::scheme,
-    writers::{LogWriter, SizedWriter},
-    table::table::{Table, TableEngine},
-    utils::{bytes::uint, read_more},
+    writers::LogWriter,
+    table::{dump::{DumpReader, DumpWriter}, notifications::{self, TableNotifications}, table::{Table, TableEngine}},
+    utils::{compression::Compression, read_more},
 };
 
 pub struct InMemoryTable<I: Index<BinKey, BinValue>> {
@@ -26,6 +28,8 @@ pub struct InMemoryTable<I: Index<BinKey, BinValue>> {
     is_it_logging: bool,
     scheme: scheme::Scheme,
     user_scheme: Box<[u8]>,
+    compression: Compression,
+    notifications: TableNotifications,
 }
 
 impl<I: Index<BinKey, BinValue>> InMemoryTable<I> {
@@ -38,6 +42,7 @@ impl<I: Index<BinKey, BinValue>> InMemoryTable<I> {
         number_of_dumps: Arc<AtomicU32>,
         scheme: scheme::Scheme,
         user_scheme: Box<[u8]>,
+        notifications: TableNotifications,
     ) -> InMemoryTable<I> {
 
         InMemoryTable {
@@ -49,7 +54,9 @@ impl<I: Index<BinKey, BinValue>> InMemoryTable<I> {
             name,
             is_it_logging,
             scheme,
+            compression: Compression::from_user_scheme(&user_scheme).unwrap_or(Compression::NONE),
             user_scheme,
+            notifications,
         }
     }
 }
@@ -76,50 +83,69 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
     }
 
     #[inline(always)]
-    fn get(&self, key: &BinKey) -> Option<BinValue> {
-        self.index.get(key)
+    fn get(&self, key: &BinKey) -> Result<Option<BinValue>, Error> {
+        self.index.get(key).map(|value| self.compression.unpack(value)).transpose()
     }
 
     #[inline(always)]
-    fn set(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Option<BinValue> {
+    fn set(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<Option<BinValue>, Error> {
         if self.is_it_logging {
             log_writer.write_key_and_value(actions::SET, self.number, &key, &value);
+        } else {
+            log_writer.replicate_key_and_value(actions::SET, self.number, &key, &value);
         }
 
-        self.index.set(key, value)
+        self.set_without_log(key, value)
     }
 
     #[inline(always)]
-    fn set_without_log(&self, key: BinKey, value: BinValue) -> Option<BinValue> {
-        self.index.set(key, value)
+    fn set_without_log(&self, key: BinKey, value: BinValue) -> Result<Option<BinValue>, Error> {
+        let notified_key = if self.notifications.is_wanted(notifications::SET) { Some(key.clone()) } else { None };
+        let res = self.index.set(key, self.compression.pack(value));
+        if let Some(key) = notified_key {
+            self.notifications.notify(notifications::SET, key.deref());
+        }
+        res.map(|value| self.compression.unpack(value)).transpose()
     }
 
     #[inline(always)]
-    fn insert(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> bool {
+    fn insert(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<bool, Error> {
         if self.is_it_logging {
             log_writer.write_key_and_value(actions::INSERT, self.number, &key, &value);
+        } else {
+            log_writer.replicate_key_and_value(actions::INSERT, self.number, &key, &value);
         }
 
-        self.index.insert(key, value)
+        self.insert_without_log(key, value)
     }
 
     #[inline(always)]
-    fn insert_without_log(&self, key: BinKey, value: BinValue) -> bool {
-        self.index.insert(key, value)
+    fn insert_without_log(&self, key: BinKey, value: BinValue) -> Result<bool, Error> {
+        let notified_key = if self.notifications.is_wanted(notifications::INSERT) { Some(key.clone()) } else { None };
+        let is_inserted = self.index.insert(key, self.compression.pack(value));
+        if let Some(key) = notified_key.filter(|_| is_inserted) {
+            self.notifications.notify(notifications::INSERT, key.deref());
+        }
+        Ok(is_inserted)
     }
 
     #[inline(always)]
-    fn delete(&self, key: &BinKey, log_writer: &mut LogWriter) {
+    fn delete(&self, key: &BinKey, log_writer: &mut LogWriter) -> Result<(), Error> {
         if self.is_it_logging {
             log_writer.write_key(actions::DELETE, self.number, key);
+        } else {
+            log_writer.replicate_key(actions::DELETE, self.number, key);
         }
 
-        self.index.remove(key);
+        self.delete_without_log(key)
     }
 
     #[inline(always)]
-    fn delete_without_log(&self, key: &BinKey) {
-        self.index.remove(key);
+    fn delete_without_log(&self, key: &BinKey) -> Result<(), Error> {
+        if self.index.remove(key).is_some() {
+            self.notifications.notify(notifications::DELETE, key.deref());
+        }
+        Ok(())
     }
 
     #[inline(always)]
@@ -127,6 +153,20 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
         self.index.count() as u64
     }
 
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let keys = RefCell::new(Vec::with_capacity(self.index.count()));
+        self.index.for_each(|key, _| keys.borrow_mut().push(key.deref().to_vec()));
+        Ok(keys.into_inner())
+    }
+
+    fn memory_usage(&self) -> u64 {
+        let usage = Cell::new(0);
+        self.index.for_each(|key, value| {
+            usage.set(usage.get() + (key.deref_all().len() + value.deref_all().len() + size_of::<(BinKey, BinValue)>()) as u64);
+        });
+        usage.get()
+    }
+
     #[inline(always)]
     fn user_scheme(&self) -> Box<[u8]> {
         self.user_scheme.clone()
@@ -137,10 +177,7 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
         &self.scheme
     }
 
-    fn dump(&self) {
-        const BUF_SIZE: usize = 64 * 1024;
-        const COUNT_OF_ELEMS_SIZE: usize = 8;
-
+    fn dump(&self) -> io::Result<()> {
         let number = self.number_of_dumps.load(SeqCst);
         if self.was_dumped.load(SeqCst) == false {
             let dir_path: PathBuf = self.persistence_dir_path.join(self.name.clone());
@@ -151,26 +188,15 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
         let path: PathBuf = self.persistence_dir_path.join(self.name.clone()).join(file_name);
 
         // TODO: maybe remove old dumps?
-        let output = File::create(path.clone()).expect(&*format!("failed to create file with path {}", path.to_string_lossy()));
-        let mut writer = SizedWriter::new_with_capacity(output, BUF_SIZE);
-        let mut count = 0;
-
-        // COUNT_OF_ELEMS_SIZE bytes for number of elements and one byte for a flag.
-        // We will set the flag to one, when we finish dumping
-        writer.write(&[0u8;COUNT_OF_ELEMS_SIZE + 1]).expect("failed to write");
+        let mut writer = DumpWriter::create(&path, self.compression.codec)?;
+        let mut result = Ok(());
         self.index.for_each_mut(|key, value| {
-            count += 1;
-            writer.write_key(key).expect("failed to write");
-            writer.write_value(value).expect("failed to write");
+            if result.is_ok() {
+                result = writer.write_record(key, value);
+            }
         });
-        // Write number of elements and change the flag
-        let mut buf = [1u8;COUNT_OF_ELEMS_SIZE+1];
-        buf[0] = count as u8;
-        buf[1..COUNT_OF_ELEMS_SIZE + 1].copy_from_slice(&uint::u64tob(count as u64));
-        // Seek will flush the buffer before seek.
-        writer.inner.seek(SeekFrom::Start(0)).expect("failed to seek");
-        writer.inner.write_all(&buf).expect("failed to write");
-        writer.inner.flush().expect("failed to flush");
+        result?;
+        writer.finish()
     }
 
     fn rise(&mut self) {
@@ -182,16 +208,10 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
         let file_name = format!("{}{}.dump", self.name, number_of_dumps);
         let path: PathBuf = self.persistence_dir_path.join(self.name.clone()).join(file_name.clone());
 
-        let mut input = File::open(path.clone()).expect(&*format!("Failed to open file with path: {}", path.to_string_lossy()));
-        let file_len = input.metadata().unwrap().len();
-        if file_len < 8 {
-            panic!("file len is less than 8!");
-        }
+        let mut input = DumpReader::open(&path).expect(&*format!("Failed to open file with path: {}", path.to_string_lossy()));
         let mut chunk = [0u8; 64 * 1024];
-        input.read(&mut chunk[..9]).expect("can't get count from buf!");
-        let all_count = uint::u64(&chunk[1..9]);
+        let all_count = input.count;
         self.index.resize(((all_count as f64) * 1.2) as usize);
-        let mut total_read = 9;
         let mut total_records_read = 0;
 
         let mut offset_last_record = 0;
@@ -203,9 +223,6 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
         let mut vl;
 
         'read: loop {
-            if total_read == file_len {
-                break;
-            }
             let mut bytes_read = input.read(&mut chunk[offset_last_record..]).expect("Failed to read");
             if bytes_read == 0 {
                 break;
@@ -214,11 +231,11 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
 
             bytes_read += offset_last_record;
             offset = 0;
-            total_read += bytes_read as u64;
 
             loop {
                 if offset + 1 > bytes_read {
-                    read_more(&mut chunk, start_offset, bytes_read, &mut offset_last_record);
+                    // The last record is whole, so nothing is left for the next read.
+                    read_more(&mut chunk, offset, bytes_read, &mut offset_last_record);
                     continue 'read;
                 }
                 start_offset = offset;
@@ -274,6 +291,10 @@ impl<I: Index<BinKey, BinValue>> Table for InMemoryTable<I> {
     fn invalid_cache(&self) {
         unreachable!()
     }
+
+    fn notifications(&self) -> &TableNotifications {
+        &self.notifications
+    }
 }
 
 unsafe impl<I: Index<BinKey, BinValue>> Send for InMemoryTable<I> {}
@@ -0,0 +1,171 @@
+use std::{io, path::PathBuf};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    constants::{actions, errors::{self, Error}},
+    error,
+    lsm::tree::{Lsm, Options},
+    scheme::scheme::Scheme,
+    utils::compression::Compression,
+    table::{notifications::{self, TableNotifications}, table::{Table, TableEngine}},
+    writers::LogWriter,
+};
+
+/// The table on the LSM tree (see [`crate::lsm::tree`]). Only sparse indexes and bloom filters of its files are in memory,
+/// so the table can be much bigger than the memory.
+///
+/// The memtable isn't written on the disk until it is full or the storage is dumped, so changes are always logged.
+pub struct LsmTable {
+    tree: Lsm,
+    number: u16,
+    name: String,
+    scheme: Scheme,
+    user_scheme: Box<[u8]>,
+    compression: Compression,
+    notifications: TableNotifications,
+}
+
+impl LsmTable {
+    /// Fails, if the tree can't be opened, like when its manifest or files are broken.
+    pub fn new(
+        persistence_dir_path: PathBuf,
+        number: u16,
+        name: String,
+        scheme: Scheme,
+        user_scheme: Box<[u8]>,
+        notifications: TableNotifications,
+    ) -> io::Result<LsmTable> {
+        let tree = Lsm::open(persistence_dir_path.join(name.clone()), Options::default())?;
+        Ok(LsmTable {
+            tree,
+            number,
+            name,
+            scheme,
+            compression: Compression::from_user_scheme(&user_scheme).unwrap_or(Compression::NONE),
+            user_scheme,
+            notifications,
+        })
+    }
+
+    /// Numbers of files of levels of the tree.
+    pub fn tables_per_level(&self) -> Vec<usize> {
+        self.tree.tables_per_level()
+    }
+
+    /// Logs the error of the tree and returns the error for the client.
+    fn read_error(&self, e: io::Error) -> Error {
+        error!("Failed to read the LSM tree of the table {}: {}", self.name, e);
+        errors::VALUE_CANT_BE_READ
+    }
+}
+
+impl Table for LsmTable {
+    #[inline(always)]
+    fn engine(&self) -> TableEngine {
+        TableEngine::Lsm
+    }
+
+    #[inline(always)]
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[inline(always)]
+    fn is_it_logging(&self) -> bool {
+        true
+    }
+
+    #[inline(always)]
+    fn cache_duration(&self) -> u64 {
+        unreachable!()
+    }
+
+    #[inline(always)]
+    fn get(&self, key: &BinKey) -> Result<Option<BinValue>, Error> {
+        let value = self.tree.get(key.deref()).map_err(|e| self.read_error(e))?;
+        value.map(|value| self.compression.unpack(BinValue::new(&value))).transpose()
+    }
+
+    #[inline(always)]
+    fn set(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<Option<BinValue>, Error> {
+        log_writer.write_key_and_value(actions::SET, self.number, &key, &value);
+        self.set_without_log(key, value)
+    }
+
+    #[inline(always)]
+    fn set_without_log(&self, key: BinKey, value: BinValue) -> Result<Option<BinValue>, Error> {
+        let old = self.tree.set(key.deref(), self.compression.pack(value).deref().to_vec()).map_err(|e| self.read_error(e))?;
+        if self.notifications.is_wanted(notifications::SET) {
+            self.notifications.notify(notifications::SET, key.deref());
+        }
+        old.map(|value| self.compression.unpack(BinValue::new(&value))).transpose()
+    }
+
+    #[inline(always)]
+    fn insert(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<bool, Error> {
+        log_writer.write_key_and_value(actions::INSERT, self.number, &key, &value);
+        self.insert_without_log(key, value)
+    }
+
+    #[inline(always)]
+    fn insert_without_log(&self, key: BinKey, value: BinValue) -> Result<bool, Error> {
+        let is_inserted = self.tree.insert(key.deref(), self.compression.pack(value).deref().to_vec()).map_err(|e| self.read_error(e))?;
+        if is_inserted && self.notifications.is_wanted(notifications::INSERT) {
+            self.notifications.notify(notifications::INSERT, key.deref());
+        }
+        Ok(is_inserted)
+    }
+
+    #[inline(always)]
+    fn delete(&self, key: &BinKey, log_writer: &mut LogWriter) -> Result<(), Error> {
+        log_writer.write_key(actions::DELETE, self.number, key);
+        self.delete_without_log(key)
+    }
+
+    #[inline(always)]
+    fn delete_without_log(&self, key: &BinKey) -> Result<(), Error> {
+        if self.tree.delete(key.deref()).map_err(|e| self.read_error(e))? {
+            self.notifications.notify(notifications::DELETE, key.deref());
+        }
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn count(&self) -> u64 {
+        self.tree.count()
+    }
+
+    /// Keys can't be listed, if a file of the tree can't be read.
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Error> {
+        self.tree.keys().map_err(|e| self.read_error(e))
+    }
+
+    /// Memtables, sparse indexes and bloom filters. Values of files aren't counted.
+    fn memory_usage(&self) -> u64 {
+        self.tree.memory_usage()
+    }
+
+    fn user_scheme(&self) -> Box<[u8]> {
+        self.user_scheme.clone()
+    }
+
+    fn scheme(&self) -> &Scheme {
+        &self.scheme
+    }
+
+    fn notifications(&self) -> &TableNotifications {
+        &self.notifications
+    }
+
+    /// The memtable is flushed, so the log before the dump isn't needed for the table.
+    /// If the flush fails, the memtable is kept and flushed by the next dump.
+    fn dump(&self) -> io::Result<()> {
+        self.tree.flush()
+    }
+
+    /// [`Lsm::open`] has already read the manifest, and the memtable is restored from the log.
+    fn rise(&mut self) {}
+
+    fn invalid_cache(&self) {
+        unreachable!()
+    }
+}
@@ -1,4 +1,6 @@
 pub mod table;
 pub mod in_memory;
 pub mod cache;
-pub mod on_disk;
\ No newline at end of file
+pub mod on_disk;
+pub mod notifications;pub mod lsm;
+pub mod dump;
\ No newline at end of file
@@ -0,0 +1,173 @@
+//! Keyspace notifications and watches of keys.
+//!
+//! Tables call [`TableNotifications::notify`] on every set, insert, delete and expiration of a key. If the table has opted in
+//! for the event with `SET_NOTIFICATIONS`, two messages are published to pub/sub channels (see [`crate::server::pubsub`]):
+//!
+//! - the name of the event to `__keyspace@{table}__:{key}`;
+//! - the key to `__keyevent@{table}__:{event name}`.
+//!
+//! Events of all tables wake clients, that wait for their keys with `WATCH`. Events aren't persisted and are fired on the machine,
+//! that has changed the key, so followers fire events of replicated changes too. Opt-ins are reset on the restart.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex, RwLock, atomic::{AtomicU8, AtomicUsize, Ordering::Relaxed}},
+    time::{Duration, Instant}
+};
+use crate::server::pubsub::PubSub;
+
+pub const SET: u8 = 1;
+pub const INSERT: u8 = 2;
+pub const DELETE: u8 = 4;
+pub const EXPIRE: u8 = 8;
+pub const ALL: u8 = SET | INSERT | DELETE | EXPIRE;
+/// Is returned by [`Notifications::watch`], if the key hasn't changed in time.
+pub const TIMEOUT: u8 = 0;
+
+/// How often a watch checks, that it must stop.
+const WATCH_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn event_name(event: u8) -> &'static str {
+    match event {
+        SET => "set",
+        INSERT => "insert",
+        DELETE => "delete",
+        EXPIRE => "expire",
+        _ => "unknown"
+    }
+}
+
+pub fn keyspace_channel(table: u16, key: &[u8]) -> Vec<u8> {
+    let mut channel = format!("__keyspace@{}__:", table).into_bytes();
+    channel.extend_from_slice(key);
+    channel
+}
+
+pub fn keyevent_channel(table: u16, event: u8) -> Vec<u8> {
+    format!("__keyevent@{}__:{}", table, event_name(event)).into_bytes()
+}
+
+/// A client, that waits for the change of a key.
+#[derive(Default)]
+struct Watch {
+    /// The event, that has woken the watch, or [`TIMEOUT`].
+    event: Mutex<u8>,
+    changed: Condvar,
+}
+
+/// Watches of keys by the table number and the key.
+type Watches = Mutex<HashMap<(u16, Vec<u8>), Vec<Arc<Watch>>>>;
+
+/// Notifications of all tables of the storage.
+#[derive(Default)]
+pub struct Notifications {
+    /// Is set by the server. Events aren't published without it.
+    pubsub: RwLock<Option<Arc<PubSub>>>,
+    watches: Watches,
+    /// The number of watched keys, so tables don't lock `watches`, when nobody waits.
+    watched_keys: AtomicUsize,
+}
+
+impl Notifications {
+    pub fn set_pubsub(&self, pubsub: Arc<PubSub>) {
+        *self.pubsub.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(pubsub);
+    }
+
+    pub fn watched_keys(&self) -> usize {
+        self.watched_keys.load(Relaxed)
+    }
+
+    /// Returns notifications of the table with the number.
+    pub fn table(self: &Arc<Self>, table: u16) -> TableNotifications {
+        TableNotifications { table, events: AtomicU8::new(0), shared: self.clone() }
+    }
+
+    fn notify(&self, table: u16, event: u8, key: &[u8], is_published: bool) {
+        if self.watched_keys.load(Relaxed) > 0 {
+            let mut watches = self.watches.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(woken) = watches.remove(&(table, key.to_vec())) {
+                self.watched_keys.store(watches.len(), Relaxed);
+                drop(watches);
+                for watch in woken {
+                    *watch.event.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = event;
+                    watch.changed.notify_all();
+                }
+            }
+        }
+        if !is_published {
+            return;
+        }
+        let pubsub = self.pubsub.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(pubsub) = pubsub.as_ref() {
+            pubsub.publish(&keyspace_channel(table, key), event_name(event).as_bytes());
+            pubsub.publish(&keyevent_channel(table, event), key);
+        }
+    }
+
+    /// Waits, until the key is changed, the timeout passes or `is_stopped` returns true. Returns the event or [`TIMEOUT`].
+    /// `None` timeout means no timeout.
+    pub fn watch(&self, table: u16, key: &[u8], timeout: Option<Duration>, is_stopped: impl Fn() -> bool) -> u8 {
+        let watch = Arc::new(Watch::default());
+        {
+            let mut watches = self.watches.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            watches.entry((table, key.to_vec())).or_default().push(watch.clone());
+            self.watched_keys.store(watches.len(), Relaxed);
+        }
+
+        let start = Instant::now();
+        let mut event = watch.event.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        while *event == TIMEOUT && !is_stopped() {
+            let mut wait = WATCH_CHECK_INTERVAL;
+            if let Some(timeout) = timeout {
+                match timeout.checked_sub(start.elapsed()) {
+                    Some(left) if !left.is_zero() => wait = wait.min(left),
+                    _ => break
+                }
+            }
+            event = watch.changed.wait_timeout(event, wait).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+        }
+        let event = *event;
+
+        if event == TIMEOUT {
+            let mut watches = self.watches.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(of_key) = watches.get_mut(&(table, key.to_vec())) {
+                of_key.retain(|other| !Arc::ptr_eq(other, &watch));
+                if of_key.is_empty() {
+                    watches.remove(&(table, key.to_vec()));
+                }
+            }
+            self.watched_keys.store(watches.len(), Relaxed);
+        }
+        event
+    }
+}
+
+/// Notifications of one table. Every [`crate::table::table::Table`] has them.
+pub struct TableNotifications {
+    table: u16,
+    /// Events, that are published.
+    events: AtomicU8,
+    shared: Arc<Notifications>,
+}
+
+impl TableNotifications {
+    pub fn events(&self) -> u8 {
+        self.events.load(Relaxed)
+    }
+
+    pub fn set_events(&self, events: u8) {
+        self.events.store(events & ALL, Relaxed);
+    }
+
+    /// Returns true, if somebody gets the event: it is published or the table has watched keys.
+    #[inline(always)]
+    pub fn is_wanted(&self, event: u8) -> bool {
+        self.events.load(Relaxed) & event != 0 || self.shared.watched_keys.load(Relaxed) > 0
+    }
+
+    #[inline(always)]
+    pub fn notify(&self, event: u8, key: &[u8]) {
+        if self.is_wanted(event) {
+            self.shared.notify(self.table, event, key, self.events.load(Relaxed) & event != 0);
+        }
+    }
+}
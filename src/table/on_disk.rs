@@ -1,35 +1,48 @@
-use std::path::PathBuf;
+use std::{cell::{Cell, RefCell}, io, mem::size_of, path::PathBuf, sync::Arc};
 use crate::{
     bin_types::{BinKey, BinValue},
-    table::table::{Table, TableEngine},
-    disk_storage::storage::DiskStorage,
+    constants::{actions, errors::Error},
+    table::{notifications::{self, TableNotifications}, table::{Table, TableEngine}},
+    disk_storage::{storage::DiskStorage, value_cache::ValueCache},
     index::Index,
     scheme::scheme::Scheme,
+    utils::compression::Compression,
     writers::LogWriter,
 };
 
 pub struct OnDiskTable<I: Index<BinKey, (u64, u64)>> {
     core: DiskStorage<I>,
+    number: u16,
     name: String,
     scheme: Scheme,
     user_scheme: Box<[u8]>,
+    compression: Compression,
+    notifications: TableNotifications,
 }
 
 impl<I: Index<BinKey, (u64, u64)>> OnDiskTable<I> {
+    /// Fails, if files of the table can't be opened or read.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         persistence_dir_path: PathBuf,
+        number: u16,
         name: String,
         size: usize,
         index: I,
         scheme: Scheme,
         user_scheme: Box<[u8]>,
-    ) -> OnDiskTable<I> {
-        OnDiskTable {
-            core: DiskStorage::new(persistence_dir_path.join(name.clone()), size, index),
+        notifications: TableNotifications,
+        cache: Arc<ValueCache>,
+    ) -> io::Result<OnDiskTable<I>> {
+        Ok(OnDiskTable {
+            core: DiskStorage::new(persistence_dir_path.join(name.clone()), size, index, cache)?,
+            number,
             name,
             scheme,
+            compression: Compression::from_user_scheme(&user_scheme).unwrap_or(Compression::NONE),
             user_scheme,
-        }
+            notifications,
+        })
     }
 }
 
@@ -55,44 +68,75 @@ impl<I: Index<BinKey, (u64, u64)>> Table for OnDiskTable<I> {
     }
 
     #[inline(always)]
-    fn get(&self, key: &BinKey) -> Option<BinValue> {
-        self.core.get(key)
+    fn get(&self, key: &BinKey) -> Result<Option<BinValue>, Error> {
+        self.core.get(key).map(|value| self.compression.unpack(value)).transpose()
     }
 
     #[inline(always)]
-    fn set(&self, key: BinKey, value: BinValue, _: &mut LogWriter) -> Option<BinValue> {
-        self.core.set(key, value)
+    fn set(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<Option<BinValue>, Error> {
+        log_writer.replicate_key_and_value(actions::SET, self.number, &key, &value);
+        self.set_without_log(key, value)
     }
 
     #[inline(always)]
-    fn set_without_log(&self, key: BinKey, value: BinValue) -> Option<BinValue> {
-        self.core.set(key, value)
+    fn set_without_log(&self, key: BinKey, value: BinValue) -> Result<Option<BinValue>, Error> {
+        let notified_key = if self.notifications.is_wanted(notifications::SET) { Some(key.clone()) } else { None };
+        let res = self.core.set(key, self.compression.pack(value));
+        if let Some(key) = notified_key {
+            self.notifications.notify(notifications::SET, key.deref());
+        }
+        res.map(|value| self.compression.unpack(value)).transpose()
     }
 
     #[inline(always)]
-    fn insert(&self, key: BinKey, value: BinValue, _: &mut LogWriter) -> bool {
-        self.core.insert(key, value)
+    fn insert(&self, key: BinKey, value: BinValue, log_writer: &mut LogWriter) -> Result<bool, Error> {
+        log_writer.replicate_key_and_value(actions::INSERT, self.number, &key, &value);
+        self.insert_without_log(key, value)
     }
 
     #[inline(always)]
-    fn insert_without_log(&self, key: BinKey, value: BinValue) -> bool {
-        self.core.insert(key, value)
+    fn insert_without_log(&self, key: BinKey, value: BinValue) -> Result<bool, Error> {
+        let notified_key = if self.notifications.is_wanted(notifications::INSERT) { Some(key.clone()) } else { None };
+        let is_inserted = self.core.insert(key, self.compression.pack(value));
+        if let Some(key) = notified_key.filter(|_| is_inserted) {
+            self.notifications.notify(notifications::INSERT, key.deref());
+        }
+        Ok(is_inserted)
     }
 
     #[inline(always)]
-    fn delete(&self, key: &BinKey, _: &mut LogWriter) {
-        self.core.delete(key);
+    fn delete(&self, key: &BinKey, log_writer: &mut LogWriter) -> Result<(), Error> {
+        log_writer.replicate_key(actions::DELETE, self.number, key);
+        self.delete_without_log(key)
     }
 
     #[inline(always)]
-    fn delete_without_log(&self, key: &BinKey) {
-        self.core.delete(key);
+    fn delete_without_log(&self, key: &BinKey) -> Result<(), Error> {
+        if self.core.delete(key) {
+            self.notifications.notify(notifications::DELETE, key.deref());
+        }
+        Ok(())
     }
 
     fn count(&self) -> u64 {
         self.core.infos.count() as u64
     }
 
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let keys = RefCell::new(Vec::with_capacity(self.core.infos.count()));
+        self.core.infos.for_each(|key, _| keys.borrow_mut().push(key.deref().to_vec()));
+        Ok(keys.into_inner())
+    }
+
+    /// Values are on the disk, so only the index and bloom filters are counted.
+    fn memory_usage(&self) -> u64 {
+        let usage = Cell::new(self.core.filters_memory_usage() as u64);
+        self.core.infos.for_each(|key, _| {
+            usage.set(usage.get() + (key.deref_all().len() + size_of::<(BinKey, (u64, u64))>()) as u64);
+        });
+        usage.get()
+    }
+
     fn user_scheme(&self) -> Box<[u8]> {
         self.user_scheme.clone()
     }
@@ -101,9 +145,12 @@ impl<I: Index<BinKey, (u64, u64)>> Table for OnDiskTable<I> {
         &self.scheme
     }
 
-    fn rise(&mut self) {
-        self.core.rise();
+    fn notifications(&self) -> &TableNotifications {
+        &self.notifications
     }
+
+    /// [`DiskStorage::new`] has already risen.
+    fn rise(&mut self) {}
     
     // NOT EXISTS!
 
@@ -111,8 +158,9 @@ impl<I: Index<BinKey, (u64, u64)>> Table for OnDiskTable<I> {
         unreachable!()
     }
 
-    fn dump(&self) {
-        return;
+    /// Values are already on the disk, so only the hint of the index is written.
+    fn dump(&self) -> io::Result<()> {
+        self.core.write_hint()
     }
 }
 
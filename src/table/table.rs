@@ -1,6 +1,9 @@
+use std::io;
 use crate::{
     bin_types::{BinKey, BinValue},
+    constants::errors::Error,
     scheme::scheme::{get_field, get_fields, Scheme},
+    table::notifications::TableNotifications,
     writers::LogWriter
 };
 
@@ -10,52 +13,64 @@ pub trait Table: Sync + Send {
     fn is_it_logging(&self) -> bool;
     fn cache_duration(&self) -> u64;
 
-    fn get(&self, key: &BinKey) -> Option<BinValue>;
+    /// Values of tables on the disk can fail to be read. Then an error is returned, and it is sent to the client.
+    fn get(&self, key: &BinKey) -> Result<Option<BinValue>, Error>;
 
     #[inline(always)]
-    fn get_field(&self, key: &BinKey, field: usize) -> Option<Vec<u8>> {
-        let res = self.get(key);
-        if res.is_none() {
-            return None;
-        }
-        let res = res.unwrap();
-        Some(get_field(&res, self.scheme(), field))
+    fn get_field(&self, key: &BinKey, field: usize) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get(key)?.map(|res| get_field(&res, self.scheme(), field)))
     }
 
     #[inline(always)]
-    fn get_fields(&self, key: &BinKey, fields: &[usize]) -> Option<Vec<u8>> {
-        let res = self.get(key);
-        if res.is_none() {
-            return None;
-        }
-        let res = res.unwrap();
-        Some(get_fields(&res, self.scheme(), fields))
+    fn get_fields(&self, key: &BinKey, fields: &[usize]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get(key)?.map(|res| get_fields(&res, self.scheme(), fields)))
     }
 
 
 
-    fn set(&self, key: BinKey, value: BinValue,  log_writer: &mut LogWriter) -> Option<BinValue>;
-    fn set_without_log(&self, key: BinKey, value: BinValue) -> Option<BinValue>;
+    fn set(&self, key: BinKey, value: BinValue,  log_writer: &mut LogWriter) -> Result<Option<BinValue>, Error>;
+    fn set_without_log(&self, key: BinKey, value: BinValue) -> Result<Option<BinValue>, Error>;
     /// Inserts a key-value pair into the index. Do nothing if the key already exists.
     ///
     /// Returns `true` if inserted, `false` otherwise.
-    fn insert(&self, key: BinKey, value: BinValue,  log_writer: &mut LogWriter) -> bool;
-    fn insert_without_log(&self, key: BinKey, value: BinValue) -> bool;
-    fn delete(&self, key: &BinKey,  log_writer: &mut LogWriter);
-    fn delete_without_log(&self, key: &BinKey);
+    fn insert(&self, key: BinKey, value: BinValue,  log_writer: &mut LogWriter) -> Result<bool, Error>;
+    fn insert_without_log(&self, key: BinKey, value: BinValue) -> Result<bool, Error>;
+    fn delete(&self, key: &BinKey,  log_writer: &mut LogWriter) -> Result<(), Error>;
+    fn delete_without_log(&self, key: &BinKey) -> Result<(), Error>;
     fn count(&self) -> u64;
+    /// Returns a copy of all keys. It is slow, use it only for administration.
+    fn keys(&self) -> Result<Vec<Vec<u8>>, Error>;
+    /// Returns the approximate number of bytes, that keys and values take in memory. It walks the whole index.
+    fn memory_usage(&self) -> u64;
 
     /// user_scheme is a scheme, that we get from user. We will not send `scheme::Scheme` to user.
     fn user_scheme(&self) -> Box<[u8]>;
     fn scheme(&self) -> &Scheme;
-    fn dump(&self);
+    /// Fails, if the table can't be written on the disk. Then [`Storage::dump`](crate::storage::Storage::dump) keeps the log,
+    /// so changes of the table are replayed after the restart.
+    fn dump(&self) -> io::Result<()>;
     fn rise(&mut self);
     fn invalid_cache(&self);
+    /// Sets, inserts, deletes and expirations fire them. See [`crate::table::notifications`].
+    fn notifications(&self) -> &TableNotifications;
 }
 
 #[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum TableEngine {
     InMemory = 0,
     OnDisk = 1,
-    CACHE = 2
+    CACHE = 2,
+    Lsm = 3
+}
+
+impl TableEngine {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TableEngine::InMemory => "in_memory",
+            TableEngine::OnDisk => "on_disk",
+            TableEngine::CACHE => "cache",
+            TableEngine::Lsm => "lsm",
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,85 @@
+#![cfg(test)]
+use std::{fs, path::PathBuf, sync::Arc};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    disk_storage::{storage::DiskStorage, value_cache::ValueCache},
+    index::{HashInMemoryIndex, Index},
+    lsm::{bloom::{self, GrowingBloomFilter}, tree::{Lsm, Options}},
+    metrics::METRICS
+};
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key{:06}", i).into_bytes()
+}
+
+#[test]
+fn growing_bloom_filter() {
+    assert_eq!(bloom::bits_per_key_for(0.01), 10);
+    assert_eq!(bloom::bits_per_key_for(0.001), 15);
+
+    let mut filter = GrowingBloomFilter::new(100, 10);
+    let memory_usage = filter.memory_usage();
+    for i in 0..10_000 {
+        filter.insert_hash(bloom::hash(&key(i)));
+    }
+    assert!(filter.memory_usage() > memory_usage * 50);
+    assert!((0..10_000).all(|i| filter.may_contain_hash(bloom::hash(&key(i)))));
+    // Parts with more bits keep the rate below twice the rate of the first one.
+    let false_positives = (10_000..110_000).filter(|i| filter.may_contain_hash(bloom::hash(&key(*i)))).count();
+    assert!(false_positives < 2_000, "{}", false_positives);
+}
+
+#[test]
+fn on_disk_bloom_filters() {
+    let dir: PathBuf = "test_data_on_disk_bloom".into();
+    let _ = fs::remove_dir_all(&dir);
+    let open = || DiskStorage::new(dir.clone(), 4, HashInMemoryIndex::new(), Arc::new(ValueCache::new(0))).unwrap();
+    {
+        let storage = open();
+        for i in 0..5000u32 {
+            assert!(storage.insert(BinKey::new(&key(i)), BinValue::new(&i.to_le_bytes())));
+        }
+        for i in 0..2500u32 {
+            assert!(storage.delete(&BinKey::new(&key(i))));
+        }
+        assert!(storage.set(BinKey::new(&key(1)), BinValue::new(b"again")).is_none());
+
+        let skips = METRICS.bloom_filter_skips.get();
+        assert!((10_000..20_000).all(|i| storage.get(&BinKey::new(&key(i))).is_none()));
+        assert!(METRICS.bloom_filter_skips.get() - skips > 9_000);
+        assert!(!storage.delete(&BinKey::new(&key(10_000))));
+        assert!(storage.filters_memory_usage() > 0);
+        // Deleted keys stay in filters, until the rise.
+        assert!((2..2500).all(|i| storage.get(&BinKey::new(&key(i))).is_none()));
+    }
+
+    let storage = open();
+    assert_eq!(storage.infos.count(), 2501);
+    assert_eq!(storage.get(&BinKey::new(&key(1))).unwrap().deref(), b"again");
+    assert!((2500..5000u32).all(|i| storage.get(&BinKey::new(&key(i))).unwrap().deref() == i.to_le_bytes()));
+    let skips = METRICS.bloom_filter_skips.get();
+    assert!((2..2500).all(|i| storage.get(&BinKey::new(&key(i))).is_none()));
+    assert!(METRICS.bloom_filter_skips.get() - skips > 2_000);
+    drop(storage);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lsm_bloom_filters() {
+    let dir: PathBuf = "test_data_lsm_bloom".into();
+    let _ = fs::remove_dir_all(&dir);
+    let tree = Lsm::open(dir.clone(), Options { memtable_bytes: 16 * 1024, ..Options::default() }).unwrap();
+    for i in 0..2000u32 {
+        assert!(tree.insert(&key(i), i.to_le_bytes().to_vec()).unwrap());
+    }
+    tree.flush().unwrap();
+    assert!(tree.tables_per_level().iter().sum::<usize>() > 1);
+
+    let skips = METRICS.bloom_filter_skips.get();
+    assert!((10_000..12_000).all(|i| tree.get(&key(i)).unwrap().is_none()));
+    assert!(METRICS.bloom_filter_skips.get() - skips > 2_000);
+    assert!((0..2000u32).all(|i| tree.get(&key(i)).unwrap() == Some(i.to_le_bytes().to_vec())));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,168 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::Write,
+    net::TcpStream,
+    path::PathBuf,
+    sync::atomic::Ordering::SeqCst,
+    thread,
+    time::Duration
+};
+use crate::{
+    constants::{actions, errors},
+    server::{
+        cfg::Config,
+        changes::{sequence_number, split_sequence_number, CHANGES, FROM_OLDEST, HEARTBEAT, NO_VALUE, WITH_OLD_VALUES},
+        replication::read_frame,
+        server::Server
+    },
+    storage::Storage,
+    tests::{failover::request, http_gateway::free_addr},
+    utils::bytes::uint
+};
+
+/// [`action`, `sequence number`, `table`, `key`, `value`, `old value`]
+type Change = (u8, u64, u16, Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>);
+
+fn subscribe(addr: &str, flags: u8, from: u64, tables: &[u16]) -> TcpStream {
+    let mut message = vec![actions::SUBSCRIBE_CHANGES, flags];
+    message.extend_from_slice(&uint::u64tob(from));
+    for table in tables {
+        message.extend_from_slice(&uint::u16tob(*table));
+    }
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let len = message.len() + 2;
+    let mut request = vec![len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8, 1];
+    request.extend_from_slice(&uint::u16tob(message.len() as u16));
+    request.extend_from_slice(&message);
+    stream.write_all(&request).unwrap();
+    stream
+}
+
+/// Reads the first heartbeat and returns its sequence number.
+fn started(stream: &mut TcpStream) -> u64 {
+    let frame = read_frame(stream).unwrap();
+    assert_eq!(&frame[..2], &[actions::DONE, HEARTBEAT], "{}", String::from_utf8_lossy(&frame));
+    uint::u64(&frame[2..10])
+}
+
+fn value(frame: &[u8], offset: &mut usize) -> Option<Vec<u8>> {
+    let len = uint::u32(&frame[*offset..*offset + 4]);
+    *offset += 4;
+    if len == NO_VALUE {
+        return None;
+    }
+    let value = frame[*offset..*offset + len as usize].to_vec();
+    *offset += len as usize;
+    Some(value)
+}
+
+/// Reads frames, until there are `count` changes. Heartbeats are skipped.
+fn changes(stream: &mut TcpStream, count: usize) -> Vec<Change> {
+    let mut changes = Vec::new();
+    while changes.len() < count {
+        let frame = read_frame(stream).unwrap();
+        assert_eq!(frame[0], actions::DONE);
+        if frame[1] == HEARTBEAT {
+            continue;
+        }
+        assert_eq!(frame[1], CHANGES);
+        let mut offset = 2;
+        while offset < frame.len() {
+            let action = frame[offset];
+            let sequence_number = uint::u64(&frame[offset + 1..offset + 9]);
+            let table = uint::u16(&frame[offset + 9..offset + 11]);
+            let key_len = uint::u16(&frame[offset + 11..offset + 13]) as usize;
+            let key = frame[offset + 13..offset + 13 + key_len].to_vec();
+            offset += 13 + key_len;
+            let new_value = value(&frame, &mut offset);
+            changes.push((action, sequence_number, table, key, new_value, value(&frame, &mut offset)));
+        }
+    }
+    assert_eq!(changes.len(), count);
+    changes
+}
+
+fn set(addr: &str, table: u8, key: &[u8], value: &[u8]) {
+    let mut message = vec![actions::SET, table, 0, key.len() as u8, 0];
+    message.extend_from_slice(key);
+    message.extend_from_slice(value);
+    assert_eq!(request(addr, &message), vec![actions::DONE]);
+}
+
+fn code_of(stream: &mut TcpStream) -> u16 {
+    let frame = read_frame(stream).unwrap();
+    assert_ne!(frame[0], actions::DONE);
+    uint::u16(&frame[1..3])
+}
+
+#[test]
+fn subscribe_changes() {
+    let addr = free_addr();
+    let dir: PathBuf = "test_data_changes".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let config = Config {
+        tcp_addr: addr.clone(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        retained_logs: 1,
+        ..Config::default()
+    };
+    let server = Server::with_config(storage, config);
+    thread::spawn(move || server.run());
+    for _ in 0..500 {
+        if TcpStream::connect(&addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let addr = addr.as_str();
+
+    assert_eq!(request(addr, &[actions::CREATE_TABLE_IN_MEMORY, 1, 0, 0, b'k', b'v', b's']), vec![actions::DONE, 0, 0]);
+    assert_eq!(request(addr, &[actions::CREATE_TABLE_IN_MEMORY, 1, 0, 0, b'o', b't', b'h', b'e', b'r']), vec![actions::DONE, 1, 0]);
+    set(addr, 0, b"a", b"1");
+    set(addr, 0, b"a", b"2");
+    set(addr, 1, b"b", b"x");
+    assert_eq!(request(addr, &[actions::DELETE, 0, 0, b'a']), vec![actions::DONE]);
+
+    // Old values come from the changes, that the stream has seen.
+    let mut stream = subscribe(addr, WITH_OLD_VALUES, FROM_OLDEST, &[0]);
+    assert_eq!(started(&mut stream), sequence_number(0, 0));
+    let seen = changes(&mut stream, 3);
+    assert_eq!(seen.iter().map(|change| (change.0, change.3.clone(), change.4.clone(), change.5.clone())).collect::<Vec<_>>(), vec![
+        (actions::SET, b"a".to_vec(), Some(b"1".to_vec()), None),
+        (actions::SET, b"a".to_vec(), Some(b"2".to_vec()), Some(b"1".to_vec())),
+        (actions::DELETE, b"a".to_vec(), None, Some(b"2".to_vec())),
+    ]);
+    assert!(seen.windows(2).all(|pair| pair[0].1 < pair[1].1));
+
+    // The stream resumes after the sequence number.
+    let mut resumed = subscribe(addr, 0, seen[0].1, &[]);
+    assert_eq!(started(&mut resumed), seen[0].1);
+    let tail = changes(&mut resumed, 3);
+    assert_eq!(tail.iter().map(|change| (change.0, change.2, change.5.clone())).collect::<Vec<_>>(), vec![
+        (actions::SET, 0, None), (actions::SET, 1, None), (actions::DELETE, 0, None)
+    ]);
+    assert_eq!(tail[2].1, seen[2].1);
+
+    // The dump starts the next log file, and the stream goes on from it.
+    storage.dump();
+    set(addr, 0, b"c", b"3");
+    let after_dump = changes(&mut stream, 1);
+    assert_eq!(after_dump[0].3, b"c".to_vec());
+    assert_eq!(split_sequence_number(after_dump[0].1).0, 1);
+
+    // The first log file is retained after one dump, but not after the next one without retained files.
+    assert_eq!(started(&mut subscribe(addr, 0, seen[0].1, &[0])), seen[0].1);
+    storage.retained_logs.store(0, SeqCst);
+    storage.dump();
+    assert_eq!(code_of(&mut subscribe(addr, 0, seen[0].1, &[0])), errors::SEQUENCE_NUMBER_IS_NOT_RETAINED.code);
+    assert_eq!(started(&mut subscribe(addr, 0, FROM_OLDEST, &[])), sequence_number(2, 0));
+    assert_eq!(code_of(&mut subscribe(addr, 0, sequence_number(9, 0), &[])), errors::SEQUENCE_NUMBER_IS_NOT_RETAINED.code);
+    let answer = request(addr, &[actions::SUBSCRIBE_CHANGES, 0, 0]);
+    assert_eq!(uint::u16(&answer[1..3]), errors::MESSAGE_IS_TOO_SHORT.code);
+
+    let _ = fs::remove_dir_all(&dir);
+}
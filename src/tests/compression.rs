@@ -0,0 +1,181 @@
+#![cfg(test)]
+use std::{fs, io::Read, path::PathBuf};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    constants::errors,
+    index::HashInMemoryIndex,
+    scheme::scheme::{get_field, get_fields, scheme_from_bytes},
+    storage::storage::Storage,
+    table::dump::{self, DumpReader, DumpWriter},
+    utils::compression::{Codec, Compression},
+    writers::LogWriter
+};
+
+const SCHEME: &[u8] = br#"{"sized_fields":{"age":"Uint32"},"unsized_fields":{"bio":"String"},"compression":"zstd","compression_threshold":64}"#;
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key{:06}", i).into_bytes()
+}
+
+/// [`age` (4 bytes), `bio` length (2 bytes), `bio`]. Long bios repeat, so they are compressed well.
+fn value(i: u32) -> Vec<u8> {
+    let bio = format!("user number {} likes compression. ", i).repeat(1 + (i % 10) as usize);
+    let mut value = i.to_le_bytes().to_vec();
+    value.extend_from_slice(&(bio.len() as u16).to_le_bytes());
+    value.extend_from_slice(bio.as_bytes());
+    value
+}
+
+#[test]
+fn codecs_and_values() {
+    let data = b"compressible data ".repeat(100);
+    for codec in [Codec::None, Codec::Lz4, Codec::Zstd] {
+        let compressed = codec.compress(&data);
+        if codec != Codec::None {
+            assert!(compressed.len() < data.len() / 4, "{:?}", codec);
+        }
+        assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+        assert!(codec.decompress(&compressed, data.len() + 1).is_err());
+        assert_eq!(Codec::from_name(codec.name()), Some(codec));
+        assert_eq!(Codec::from_u8(codec as u8), Some(codec));
+    }
+
+    let compression = Compression::from_user_scheme(br#"{"sized_fields":{},"unsized_fields":{},"compression":"lz4"}"#).unwrap();
+    assert_eq!(compression, Compression { codec: Codec::Lz4, threshold: 128 });
+    assert_eq!(Compression::from_user_scheme(b"").unwrap(), Compression::NONE);
+    assert_eq!(scheme_from_bytes(br#"{"sized_fields":{},"unsized_fields":{},"compression":"gzip"}"#).unwrap_err().code, errors::UNKNOWN_COMPRESSION.code);
+
+    let packed = compression.pack(BinValue::new(&data));
+    assert_eq!(packed.deref()[0], Codec::Lz4 as u8);
+    assert!(packed.len() < data.len() / 4);
+    assert_eq!(compression.unpack(packed).unwrap().deref(), data.as_slice());
+    // Short values and values, that don't become shorter, are stored as is.
+    let short = compression.pack(BinValue::new(b"short"));
+    assert_eq!(short.deref(), b"\0short");
+    assert_eq!(compression.unpack(short).unwrap().deref(), b"short");
+    let mut state = 0x2545f4914f6cdd1du64;
+    let random: Vec<u8> = (0..1000).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect();
+    let packed = compression.pack(BinValue::new(&random));
+    assert_eq!(packed.deref()[0], Codec::None as u8);
+    assert_eq!(compression.unpack(packed).unwrap().deref(), random.as_slice());
+    // Damaged values are errors, not panics.
+    assert_eq!(compression.unpack(BinValue::new(&[Codec::Lz4 as u8, 100, 0, 0, 0, 1, 2, 3])).err(), Some(errors::VALUE_IS_BROKEN));
+    assert_eq!(compression.unpack(BinValue::new(&[Codec::Zstd as u8, 1])).err(), Some(errors::VALUE_IS_BROKEN));
+    assert_eq!(compression.unpack(BinValue::new(&[7, 1])).err(), Some(errors::VALUE_IS_BROKEN));
+    // Tables without compression store values as is.
+    assert_eq!(Compression::NONE.pack(BinValue::new(&data)).deref(), data.as_slice());
+}
+
+#[test]
+fn dump_formats() {
+    let dir: PathBuf = "test_data_dump_formats".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let records: Vec<(BinKey, BinValue)> = (0..5000).map(|i| (BinKey::new(&key(i)), BinValue::new(&value(i)))).collect();
+    let mut raw = Vec::new();
+    for (key, value) in records.iter() {
+        raw.extend_from_slice(key.deref_all_with_len(key.len()));
+        raw.extend_from_slice(value.deref_all_with_len(value.len()));
+    }
+
+    for codec in [Codec::None, Codec::Lz4, Codec::Zstd] {
+        let path = dir.join(format!("{}.dump", codec.name()));
+        let mut writer = DumpWriter::create(&path, codec).unwrap();
+        for (key, value) in records.iter() {
+            writer.write_record(key, value).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let file = fs::read(&path).unwrap();
+        if codec == Codec::None {
+            // The old format: [count as u8, count].
+            assert_eq!(file[0], 5000u64 as u8);
+            assert_eq!(&file[1..9], 5000u64.to_le_bytes());
+            assert_eq!(&file[9..], raw.as_slice());
+        } else {
+            assert_eq!(file[..4], dump::MAGIC);
+            assert_eq!(file[4], codec as u8);
+            assert!(file.len() < raw.len() / 4, "{:?}", codec);
+        }
+
+        let mut reader = DumpReader::open(&path).unwrap();
+        assert_eq!(reader.count, 5000);
+        let mut read = Vec::new();
+        let mut chunk = [0u8; 1000];
+        loop {
+            let bytes_read = reader.read(&mut chunk).unwrap();
+            if bytes_read == 0 {
+                break;
+            }
+            read.extend_from_slice(&chunk[..bytes_read]);
+        }
+        assert!(read == raw, "{:?}", codec);
+    }
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compressed_tables() {
+    let dir: PathBuf = "test_data_compressed_tables".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let names = ["memory", "cache", "disk", "lsm", "raw"];
+    let check = |storage: &'static Storage| {
+        for name in names {
+            let (_, table) = storage.table_by_name(name.as_bytes()).unwrap();
+            assert_eq!(table.count(), 1000, "{}", name);
+            for i in (0..1000).step_by(7) {
+                let expected = if i == 0 { value(1000) } else { value(i) };
+                assert_eq!(table.get(&BinKey::new(&key(i))).unwrap().unwrap().deref(), expected.as_slice(), "{} {}", name, i);
+                let expected_fields = get_fields(&BinValue::new(&expected), table.scheme(), &[0, 1]);
+                assert_eq!(table.get_field(&BinKey::new(&key(i)), 0).unwrap().unwrap(), get_field(&BinValue::new(&expected), table.scheme(), 0), "{} {}", name, i);
+                assert_eq!(table.get_fields(&BinKey::new(&key(i)), &[0, 1]).unwrap().unwrap(), expected_fields, "{} {}", name, i);
+            }
+        }
+    };
+    let memory_usage;
+    {
+        let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+        storage.rise();
+        let mut log_writer = LogWriter::new(storage.log_file.clone());
+        let raw_scheme = br#"{"sized_fields":{"age":"Uint32"},"unsized_fields":{"bio":"String"}}"#;
+        Storage::create_in_memory_table(storage, "memory".to_string(), HashInMemoryIndex::new(), true, scheme_from_bytes(SCHEME).unwrap(), SCHEME);
+        Storage::create_cache_table(storage, "cache".to_string(), HashInMemoryIndex::new(), 1000, true, scheme_from_bytes(SCHEME).unwrap(), SCHEME);
+        Storage::create_on_disk_table(storage, "disk".to_string(), HashInMemoryIndex::new(), scheme_from_bytes(SCHEME).unwrap(), SCHEME);
+        Storage::create_lsm_table(storage, "lsm".to_string(), scheme_from_bytes(SCHEME).unwrap(), SCHEME);
+        Storage::create_in_memory_table(storage, "raw".to_string(), HashInMemoryIndex::new(), true, scheme_from_bytes(raw_scheme).unwrap(), raw_scheme);
+        for name in names {
+            let (_, table) = storage.table_by_name(name.as_bytes()).unwrap();
+            for i in 0..1000 {
+                assert!(table.insert(BinKey::new(&key(i)), BinValue::new(&value(i)), &mut log_writer).unwrap());
+            }
+            let old = table.set(BinKey::new(&key(0)), BinValue::new(&value(1000)), &mut log_writer).unwrap().unwrap();
+            assert_eq!(old.deref(), value(0).as_slice(), "{}", name);
+        }
+        log_writer.flush();
+        check(storage);
+        let usage = |name: &str| storage.table_by_name(name.as_bytes()).unwrap().1.memory_usage();
+        assert!(usage("memory") < usage("raw") / 2, "{} {}", usage("memory"), usage("raw"));
+        memory_usage = usage("memory");
+        storage.dump();
+    }
+
+    let dump = fs::read(dir.join("memory").join("memory1.dump")).unwrap();
+    assert_eq!(dump[..4], dump::MAGIC);
+    assert_eq!(dump[4], Codec::Zstd as u8);
+    let raw_dump = fs::read(dir.join("raw").join("raw1.dump")).unwrap();
+    assert!(dump.len() < raw_dump.len() / 4, "{} {}", dump.len(), raw_dump.len());
+
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    storage.rise();
+    check(storage);
+    assert_eq!(storage.table_by_name(b"memory").unwrap().1.memory_usage(), memory_usage);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
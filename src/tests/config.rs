@@ -0,0 +1,73 @@
+#![cfg(test)]
+use std::fs;
+use crate::server::cfg::Config;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn config() {
+    let file_path = "test_data_config.toml";
+    fs::write(file_path, "tcp_addr = \"0.0.0.0:11000\"\ndump_interval = 5\nlog_level = \"warn\"\npassword = \"file secret\"\n").unwrap();
+    let env = |name: &str| match name {
+        "CONFIG_FILE" => Some(file_path.to_string()),
+        "DUMP_INTERVAL" => Some("10".to_string()),
+        _ => None
+    };
+
+    // Flags override environment variables, that override the file.
+    let config = Config::load(args(&["--dump-interval", "15", "--slowlog_max_len=7"]), env).unwrap();
+    assert_eq!(config.tcp_addr, "0.0.0.0:11000");
+    assert_eq!(config.dump_interval, 15);
+    assert_eq!(config.slowlog_max_len, 7);
+    assert_eq!(config.log_level, "warn");
+    assert_eq!(config.password, "file secret");
+    assert_eq!(config.unix_addr, Config::default().unix_addr);
+    assert!(config.to_json().get("password").is_none());
+    assert_eq!(config.to_json()["dump_interval"], 15);
+
+    let config = Config::load(args(&[]), env).unwrap();
+    assert_eq!(config.dump_interval, 10);
+
+    // Values are validated instead of silent fallbacks.
+    let error = Config::load(args(&["--tcp-addr", "localhost"]), |_: &str| None).unwrap_err();
+    assert!(error.0.contains("tcp_addr") && error.0.contains("--tcp-addr"), "{}", error);
+    assert!(Config::load(args(&["--dump-interval", "0"]), |_: &str| None).is_err());
+    assert!(Config::load(args(&["--log-level", "info,dbms=loud"]), |_: &str| None).is_err());
+    assert!(Config::load(args(&["--nothing", "1"]), |_: &str| None).is_err());
+    assert!(Config::load(args(&["--port"]), |_: &str| None).is_err());
+    assert!(Config::load(args(&["positional"]), |_: &str| None).is_err());
+    let error = Config::load(args(&[]), |name: &str| if name == "SLOWLOG_MAX_LEN" { Some("many".to_string()) } else { None }).unwrap_err();
+    assert!(error.0.contains("SLOWLOG_MAX_LEN"), "{}", error);
+
+    let mut config = Config::load(args(&["--config", file_path]), |_: &str| None).unwrap();
+    assert_eq!(config.dump_interval, 5);
+    assert!(config.set("log_format", "xml").is_err());
+    assert_eq!(config.log_format, "text");
+    config.set("log-format", "JSON").unwrap();
+    assert_eq!(config.log_format, "json");
+    config.set("bloom_false_positive_rate", "0.001").unwrap();
+    assert_eq!(config.to_json()["bloom_false_positive_rate"], 0.001);
+    assert!(config.set("bloom_false_positive_rate", "1").is_err());
+
+    // The reloaded file keeps flags.
+    let config = Config::load(args(&["--config", file_path, "--log-level", "debug"]), |_: &str| None).unwrap();
+    fs::write(file_path, "dump_interval = 30\nlog_level = \"error\"\nbloom_false_positive_rate = 0.01\n").unwrap();
+    let reloaded = config.reload().unwrap();
+    assert_eq!(reloaded.dump_interval, 30);
+    assert_eq!(reloaded.bloom_false_positive_rate, 0.01);
+    assert_eq!(reloaded.log_level, "debug");
+    assert_eq!(reloaded.tcp_addr, Config::default().tcp_addr);
+
+    fs::write(file_path, "unknown = 1\n").unwrap();
+    assert!(config.reload().is_err());
+    fs::write(file_path, "bloom_false_positive_rate = 0.9\n").unwrap();
+    assert!(config.reload().is_err());
+    fs::write(file_path, "dump_interval = [1]\n").unwrap();
+    assert!(config.reload().is_err());
+    fs::write(file_path, "dump_interval = \n").unwrap();
+    assert!(config.reload().is_err());
+
+    fs::remove_file(file_path).unwrap();
+}
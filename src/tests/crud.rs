@@ -36,7 +36,7 @@ pub fn crud(storage: &'static Storage) {
         joins.push(thread::spawn(move || {
             for j in 0..COUNT {
                 unsafe {
-                    (*storage.tables.get())[number].insert(keys[i * COUNT + j].clone(), values[i * COUNT + j].clone(), &mut log_writer);
+                    (*storage.tables.get())[number].insert(keys[i * COUNT + j].clone(), values[i * COUNT + j].clone(), &mut log_writer).unwrap();
                 }
             }
         }));
@@ -56,7 +56,7 @@ pub fn crud(storage: &'static Storage) {
         joins.push(thread::spawn(move || {
             for j in 0..COUNT {
                 unsafe {
-                    assert_eq!(values[i * COUNT + j].clone(), (*storage.tables.get())[number].get(&keys[i * COUNT + j]).unwrap());
+                    assert_eq!(values[i * COUNT + j].clone(), (*storage.tables.get())[number].get(&keys[i * COUNT + j]).unwrap().unwrap());
                 }
             }
         }));
@@ -78,7 +78,7 @@ pub fn crud(storage: &'static Storage) {
         joins.push(thread::spawn(move || {
             for j in 0..COUNT {
                 unsafe {
-                    (*storage.tables.get())[number].delete(&keys[i * COUNT + j].clone(), &mut log_writer);
+                    (*storage.tables.get())[number].delete(&keys[i * COUNT + j].clone(), &mut log_writer).unwrap();
                 }
             }
         }));
@@ -97,7 +97,7 @@ pub fn crud(storage: &'static Storage) {
         joins.push(thread::spawn(move || {
             for j in 0..COUNT {
                 unsafe {
-                    assert_eq!(None, (*storage.tables.get())[number].get(&keys[i * COUNT + j]));
+                    assert_eq!(None, (*storage.tables.get())[number].get(&keys[i * COUNT + j]).unwrap());
                 }
             }
         }));
@@ -119,7 +119,7 @@ pub fn crud(storage: &'static Storage) {
         joins.push(thread::spawn(move || {
             for j in 0..COUNT {
                 unsafe {
-                    (*storage.tables.get())[number].set(keys[i * COUNT + j].clone(), values[i * COUNT + j].clone(), &mut log_writer);
+                    (*storage.tables.get())[number].set(keys[i * COUNT + j].clone(), values[i * COUNT + j].clone(), &mut log_writer).unwrap();
                 }
             }
         }));
@@ -138,7 +138,7 @@ pub fn crud(storage: &'static Storage) {
         joins.push(thread::spawn(move || {
             for j in 0..COUNT {
                 unsafe {
-                    assert_eq!(values[i * COUNT + j].clone(), (*storage.tables.get())[number].get(&keys[i * COUNT + j]).unwrap());
+                    assert_eq!(values[i * COUNT + j].clone(), (*storage.tables.get())[number].get(&keys[i * COUNT + j]).unwrap().unwrap());
                 }
             }
         }));
@@ -35,7 +35,7 @@ pub fn crud_bench(storage: &'static Storage) {
             joins.push(std::thread::spawn(move || unsafe {
                 let mut log_writer = LogWriter::new(storage.log_file.clone());
                 for j in i * COUNT..(i + 1) * COUNT {
-                    (*storage.tables.get())[number].set(keys[j].clone(), values[j].clone(), &mut log_writer);
+                    (*storage.tables.get())[number].set(keys[j].clone(), values[j].clone(), &mut log_writer).unwrap();
                 }
             }));
         }
@@ -49,7 +49,7 @@ pub fn crud_bench(storage: &'static Storage) {
             joins.push(std::thread::spawn(move || unsafe {
                 let mut log_writer = LogWriter::new(storage.log_file.clone());
                 for j in i * COUNT..(i + 1) * COUNT {
-                    (*storage.tables.get())[number].delete(&keys[j], &mut log_writer);
+                    (*storage.tables.get())[number].delete(&keys[j], &mut log_writer).unwrap();
                 }
             }));
         }
@@ -66,7 +66,7 @@ pub fn crud_bench(storage: &'static Storage) {
         joins.push(std::thread::spawn(move || unsafe {
             let mut log_writer = LogWriter::new(storage.log_file.clone());
             for j in i * COUNT..(i + 1) * COUNT {
-                (*storage.tables.get())[number].insert(keys[j].clone(), values[j].clone(), &mut log_writer);
+                (*storage.tables.get())[number].insert(keys[j].clone(), values[j].clone(), &mut log_writer).unwrap();
             }
         }));
     }
@@ -82,7 +82,7 @@ pub fn crud_bench(storage: &'static Storage) {
         let keys = keys.clone();
         joins.push(std::thread::spawn(move || unsafe {
             for j in i * COUNT..(i + 1) * COUNT {
-                (*storage.tables.get())[number].get(&keys[j]);
+                (*storage.tables.get())[number].get(&keys[j]).unwrap();
             }
         }));
     }
@@ -99,7 +99,7 @@ pub fn crud_bench(storage: &'static Storage) {
         joins.push(std::thread::spawn(move || unsafe {
             let mut log_writer = LogWriter::new(storage.log_file.clone());
             for j in i * COUNT..(i + 1) * COUNT {
-                (*storage.tables.get())[number].set(keys[j].clone(), values[j].clone(), &mut log_writer);
+                (*storage.tables.get())[number].set(keys[j].clone(), values[j].clone(), &mut log_writer).unwrap();
             }
         }));
     }
@@ -116,7 +116,7 @@ pub fn crud_bench(storage: &'static Storage) {
         joins.push(std::thread::spawn(move || unsafe {
             let mut log_writer = LogWriter::new(storage.log_file.clone());
             for j in i * COUNT..(i + 1) * COUNT {
-                (*storage.tables.get())[number].delete(&keys[j], &mut log_writer);
+                (*storage.tables.get())[number].delete(&keys[j], &mut log_writer).unwrap();
             }
         }));
     }
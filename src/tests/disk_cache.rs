@@ -0,0 +1,93 @@
+#![cfg(test)]
+use std::{fs, path::PathBuf, sync::Arc};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    disk_storage::{storage::DiskStorage, value_cache::{Location, ValueCache}},
+    index::HashInMemoryIndex,
+    metrics::METRICS
+};
+
+fn location(offset: u64) -> Location {
+    Location { table: 0, file: 0, offset }
+}
+
+#[test]
+fn value_cache() {
+    let cache = ValueCache::new(1024 * 1024);
+    assert_eq!(cache.get(&location(0), |value| value.to_vec()), None);
+    cache.insert(location(0), b"hello");
+    assert_eq!(cache.get(&location(0), |value| value.to_vec()), Some(b"hello".to_vec()));
+    assert_eq!(cache.remove(&location(0)).as_deref(), Some(&b"hello"[..]));
+    assert_eq!(cache.get(&location(0), |value| value.to_vec()), None);
+    assert_eq!(cache.used_bytes(), 0);
+
+    // The cache is bounded, and values, that are read, survive the eviction.
+    cache.insert(location(1), &[1; 100]);
+    for offset in 2..20_000 {
+        cache.get(&location(1), |_| ());
+        cache.insert(location(offset), &[2; 100]);
+        assert!(cache.used_bytes() <= cache.capacity());
+    }
+    assert!(cache.used_bytes() > cache.capacity() / 2);
+    assert_eq!(cache.get(&location(1), |value| value.to_vec()), Some(vec![1; 100]));
+    assert_eq!(cache.get(&location(2), |_| ()), None);
+
+    // Big values aren't cached.
+    cache.insert(location(0), &vec![3; 64 * 1024]);
+    assert_eq!(cache.get(&location(0), |_| ()), None);
+
+    cache.set_capacity(0);
+    assert_eq!(cache.used_bytes(), 0);
+    cache.insert(location(0), b"hello");
+    assert_eq!(cache.get(&location(0), |_| ()), None);
+}
+
+#[test]
+fn disk_storage_with_value_cache() {
+    let dir: PathBuf = "test_data_disk_cache".into();
+    let _ = fs::remove_dir_all(&dir);
+    let cache = Arc::new(ValueCache::new(1024 * 1024));
+    let storage = DiskStorage::new(dir.clone(), 4, HashInMemoryIndex::new(), cache.clone()).unwrap();
+    let key = BinKey::new(b"key");
+
+    assert!(storage.insert(key.clone(), BinValue::new(b"first")));
+    let misses = METRICS.disk_cache_misses.get();
+    let from_file = storage.get(&key).unwrap().deref().to_vec();
+    assert_eq!(from_file, b"first");
+    assert!(METRICS.disk_cache_misses.get() > misses);
+    assert!(cache.used_bytes() > 0);
+    let hits = METRICS.disk_cache_hits.get();
+    assert_eq!(storage.get(&key).unwrap().deref(), from_file.as_slice());
+    assert!(METRICS.disk_cache_hits.get() > hits);
+
+    // `set` returns the cached old value and frees it.
+    assert_eq!(storage.set(key.clone(), BinValue::new(b"second")).unwrap().deref(), from_file.as_slice());
+    assert_eq!(cache.used_bytes(), 0);
+    let from_file = storage.get(&key).unwrap().deref().to_vec();
+    assert_eq!(from_file, b"second");
+    assert_eq!(storage.get(&key).unwrap().deref(), from_file.as_slice());
+    cache.set_capacity(0);
+    assert_eq!(storage.get(&key).unwrap().deref(), from_file.as_slice());
+    cache.set_capacity(1024 * 1024);
+    storage.get(&key);
+
+    assert!(storage.delete(&key));
+    assert_eq!(cache.used_bytes(), 0);
+    assert!(storage.get(&key).is_none());
+    assert!(!storage.delete(&key));
+
+    // Other storages with the same cache don't see values of this one.
+    let other_dir: PathBuf = "test_data_disk_cache_other".into();
+    let _ = fs::remove_dir_all(&other_dir);
+    let other = DiskStorage::new(other_dir.clone(), 4, HashInMemoryIndex::new(), cache.clone()).unwrap();
+    assert!(storage.insert(key.clone(), BinValue::new(b"this")));
+    assert!(other.insert(key.clone(), BinValue::new(b"other")));
+    let this_value = storage.get(&key).unwrap().deref().to_vec();
+    let other_value = other.get(&key).unwrap().deref().to_vec();
+    assert_eq!((this_value.as_slice(), other_value.as_slice()), (&b"this"[..], &b"other"[..]));
+    assert_eq!(storage.get(&key).unwrap().deref(), this_value.as_slice());
+    assert_eq!(other.get(&key).unwrap().deref(), other_value.as_slice());
+
+    let _ = fs::remove_dir_all(&dir);
+    let _ = fs::remove_dir_all(&other_dir);
+}
@@ -0,0 +1,164 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::Write,
+    net::TcpStream,
+    path::PathBuf,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration
+};
+use serde_json::Value;
+use crate::{
+    constants::{actions, errors},
+    server::{cfg::Config, replication::read_frame, server::Server, sharding::shard_of, shutdown::Shutdown},
+    storage::Storage,
+    tests::{http_gateway::{self, free_addr}, resp_listener::RespClient},
+    utils::bytes::uint
+};
+
+/// Sends one message and returns the answer.
+pub fn request(addr: &str, message: &[u8]) -> Vec<u8> {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let len = message.len() + 2;
+    let mut request = vec![len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8, 0];
+    request.extend_from_slice(&[message.len() as u8, (message.len() >> 8) as u8]);
+    request.extend_from_slice(message);
+    stream.write_all(&request).unwrap();
+    read_frame(&mut stream).unwrap()
+}
+
+pub fn info(addr: &str) -> Value {
+    let answer = request(addr, &[actions::INFO]);
+    assert_eq!(answer[0], actions::DONE);
+    serde_json::from_slice(&answer[1..]).unwrap()
+}
+
+fn insert(addr: &str, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut message = vec![actions::INSERT, 0, 0, key.len() as u8, (key.len() >> 8) as u8];
+    message.extend_from_slice(key);
+    message.extend_from_slice(value);
+    request(addr, &message)
+}
+
+fn get(addr: &str, key: &[u8]) -> Option<Vec<u8>> {
+    let mut message = vec![actions::GET, 0, 0];
+    message.extend_from_slice(key);
+    let answer = request(addr, &message);
+    if answer[0] == actions::DONE { Some(answer[1..].to_vec()) } else { None }
+}
+
+fn redirect(leader: &str) -> Vec<u8> {
+    let error = errors::NOT_THE_LEADER;
+    let mut answer = vec![error.status, error.code as u8, (error.code >> 8) as u8];
+    answer.extend_from_slice(leader.as_bytes());
+    answer
+}
+
+pub fn eventually(what: &str, condition: impl Fn() -> bool) {
+    for _ in 0..1000 {
+        if condition() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("{} hasn't happened", what);
+}
+
+/// Starts a machine of the node `machines`. Empty `http_addr` and `resp_addr` mean, that the gateway and the RESP listener are disabled.
+fn start_machine(addr: &str, machines: &[String], http_addr: &str, resp_addr: &str) -> (Arc<Shutdown>, JoinHandle<bool>) {
+    let dir: PathBuf = format!("test_data_failover_{}", addr.replace(':', "_")).into();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let config = Config {
+        tcp_addr: addr.to_string(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        heartbeat_interval_millis: 20,
+        failover_timeout_millis: 300,
+        dump_on_shutdown: false,
+        http_addr: http_addr.to_string(),
+        resp_addr: resp_addr.to_string(),
+        ..Config::default()
+    };
+    let mut server = Server::with_config(storage, config);
+    server.set_hierarchy(vec![machines.to_vec()]);
+    let other_machines: Vec<String> = machines.iter().filter(|machine| *machine != addr).cloned().collect();
+    server.node.set(&other_machines);
+    let shutdown = server.shutdown_handle();
+    let handle = thread::spawn(move || server.run());
+    for addr in [addr, http_addr, resp_addr].into_iter().filter(|addr| !addr.is_empty()) {
+        eventually("the start of the machine", || TcpStream::connect(addr).is_ok());
+    }
+    (shutdown, handle)
+}
+
+#[test]
+fn failover() {
+    let machines: Vec<String> = (0..3).map(|_| free_addr()).collect();
+    for addr in machines.iter() {
+        let _ = fs::remove_dir_all(format!("test_data_failover_{}", addr.replace(':', "_")));
+    }
+    let (a, b, c) = (machines[0].as_str(), machines[1].as_str(), machines[2].as_str());
+    let (http_addr, resp_addr) = (free_addr(), free_addr());
+    let (shutdown_a, handle_a) = start_machine(a, &machines, "", "");
+    let (shutdown_b, handle_b) = start_machine(b, &machines, &http_addr, &resp_addr);
+    let (shutdown_c, handle_c) = start_machine(c, &machines, "", "");
+
+    // The leftmost machine is the leader, others follow it and redirect writes.
+    let mut create = vec![actions::CREATE_TABLE_IN_MEMORY, 1, 0, 0];
+    create.extend_from_slice(b"users");
+    assert_eq!(request(a, &create), vec![actions::DONE, 0, 0]);
+    assert_eq!(insert(a, b"alice", b"1"), vec![actions::DONE]);
+    assert_eq!(insert(b, b"bob", b"2"), redirect(a));
+    assert_eq!(request(c, &create), redirect(a));
+    for addr in [b, c] {
+        eventually("the replication of the leader", || get(addr, b"alice") == Some(b"1".to_vec()));
+        let info = info(addr);
+        assert_eq!(info["failover"]["leader"], a);
+        assert_eq!(info["failover"]["is_leader"], false);
+        assert_eq!(info["failover"]["epoch"], 1);
+        assert_eq!(info["replication"]["leader_addr"], a);
+    }
+    // The HTTP gateway and the RESP listener of a follower redirect writes to the leader too.
+    let (status, body) = http_gateway::request(&http_addr, "PUT", "/tables/users/keys/bob", None, b"2");
+    assert_eq!(status, 421);
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!((body["code"].as_u64(), body["addr"].as_str()), (Some(errors::NOT_THE_LEADER.code as u64), Some(a)));
+    assert_eq!(http_gateway::request(&http_addr, "GET", "/tables/users/keys/alice", None, b""), (200, b"1".to_vec()));
+    let mut client = RespClient::connect(&resp_addr);
+    assert_eq!(client.call(&[b"SET", b"users:bob", b"2"]), format!("-MOVED {} {}", shard_of(b"bob"), a));
+    assert_eq!(client.call(&[b"GET", b"users:alice"]), "$1");
+    eventually("heartbeats of all machines", || {
+        info(a)["failover"]["machines"].as_array().unwrap().iter().all(|machine| machine["is_alive"] == true)
+    });
+
+    // The next machine replaces the dead leader in the next epoch.
+    shutdown_a.request(Some(false));
+    assert!(handle_a.join().unwrap());
+    eventually("the promotion of the next machine", || info(b)["failover"]["is_leader"] == true);
+    eventually("the new leader on other machines", || info(c)["failover"]["leader"] == b);
+    assert_eq!(info(b)["failover"]["epoch"], 2);
+    assert_eq!(info(c)["failover"]["epoch"], 2);
+    assert_eq!(info(b)["replication"]["role"], "leader");
+    assert_eq!(insert(b, b"bob", b"2"), vec![actions::DONE]);
+    assert_eq!(insert(c, b"carol", b"3"), redirect(b));
+    eventually("the replication of the new leader", || get(c, b"bob") == Some(b"2".to_vec()));
+    assert_eq!(get(c, b"alice"), Some(b"1".to_vec()));
+
+    // The old leader is back with the old epoch, it steps down and follows the new leader.
+    let (shutdown_a, handle_a) = start_machine(a, &machines, "", "");
+    eventually("the step down of the stale leader", || insert(a, b"dave", b"4") == redirect(b));
+    let info_a = info(a);
+    assert_eq!(info_a["failover"]["epoch"], 2);
+    assert_eq!(info_a["failover"]["is_leader"], false);
+    eventually("the replication of the new leader", || get(a, b"bob") == Some(b"2".to_vec()));
+    assert_eq!(uint::u16(&insert(a, b"dave", b"4")[1..3]), errors::NOT_THE_LEADER.code);
+
+    for (shutdown, handle) in [(shutdown_a, handle_a), (shutdown_b, handle_b), (shutdown_c, handle_c)] {
+        shutdown.request(Some(false));
+        handle.join().unwrap();
+    }
+    for addr in machines.iter() {
+        fs::remove_dir_all(format!("test_data_failover_{}", addr.replace(':', "_"))).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+#![cfg(test)]
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::Duration
+};
+use serde_json::{json, Value};
+use crate::{
+    constants::{actions, errors},
+    server::{cfg::Config, hierarchy::{decode, encode}, server::Server},
+    storage::Storage,
+    tests::{failover::request, http_gateway::free_addr},
+    utils::bytes::uint
+};
+
+fn config(addr: &str, dir: &PathBuf) -> Config {
+    Config {
+        tcp_addr: addr.to_string(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        ..Config::default()
+    }
+}
+
+#[test]
+fn hierarchy_file() {
+    // Addresses longer than 255 bytes need the second byte of the length.
+    let long_addr = format!("{}.example.com:2345", "a".repeat(300));
+    let hierarchy = vec![vec!["127.0.0.1:2345".to_string(), long_addr.clone()], vec!["10.0.0.2:2345".to_string()]];
+    let buf = encode(&hierarchy);
+    assert_eq!(decode(&buf).unwrap(), hierarchy);
+    assert!(decode(&buf[..buf.len() - 1]).is_err());
+    assert!(decode(&encode(&[vec!["a:1".to_string()], vec!["a:1".to_string()]])).is_err());
+    assert!(decode(&[0]).is_err());
+
+    let dir: PathBuf = "test_data_hierarchy_file".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("hierarchy.bin"), &buf).unwrap();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let server = Server::with_config(storage, config(&long_addr, &dir));
+    assert_eq!(server.hierarchy(), hierarchy);
+    assert_eq!(server.this_node(), Some(0));
+    assert_eq!(&*server.node.get_other_machines_addr(), &["127.0.0.1:2345".to_string()]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+fn change(addr: &str, message: &[u8]) -> Value {
+    let answer = request(addr, message);
+    assert_eq!(answer[0], actions::DONE, "{}", String::from_utf8_lossy(&answer));
+    serde_json::from_slice(&answer[1..]).unwrap()
+}
+
+fn code_of(answer: &[u8]) -> u16 {
+    uint::u16(&answer[1..3])
+}
+
+fn with_addr(action: u8, prefix: &[u8], addr: &str) -> Vec<u8> {
+    let mut message = vec![action];
+    message.extend_from_slice(prefix);
+    message.extend_from_slice(addr.as_bytes());
+    message
+}
+
+fn hierarchy_of(addr: &str) -> Vec<Vec<String>> {
+    let answer = request(addr, &[actions::GET_HIERARCHY]);
+    assert_eq!(answer[0], actions::DONE);
+    decode(&answer[1..]).unwrap()
+}
+
+fn node_of_first_shard(addr: &str) -> u16 {
+    let answer = request(addr, &[actions::GET_SHARD_METADATA]);
+    uint::u16(&answer[1..3])
+}
+
+#[test]
+fn membership() {
+    let (a, b, c, d) = (free_addr(), free_addr(), free_addr(), free_addr());
+    for (number, addr) in [&a, &b].into_iter().enumerate() {
+        let dir: PathBuf = format!("test_data_membership_{}", number).into();
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+        let server = Server::with_config(storage, config(addr, &dir));
+        thread::spawn(move || server.run());
+        for _ in 0..500 {
+            if std::net::TcpStream::connect(addr).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    let (a, b, c, d) = (a.as_str(), b.as_str(), c.as_str(), d.as_str());
+    assert_eq!(hierarchy_of(a), vec![vec![a.to_string()]]);
+
+    // The second server becomes the second node, and both of them have the new hierarchy.
+    let mut add_node = vec![actions::ADD_NODE, b.len() as u8, 0];
+    add_node.extend_from_slice(b.as_bytes());
+    let answer = change(a, &add_node);
+    assert_eq!(answer["hierarchy"], json!([[a], [b]]));
+    assert_eq!(answer["unreachable"], json!([]));
+    let mut epoch = answer["epoch"].as_u64().unwrap();
+    for (number, addr) in [a, b].into_iter().enumerate() {
+        assert_eq!(hierarchy_of(addr), vec![vec![a.to_string()], vec![b.to_string()]]);
+        let file = fs::read(format!("test_data_membership_{}/hierarchy.bin", number)).unwrap();
+        assert_eq!(decode(&file).unwrap(), hierarchy_of(addr));
+    }
+
+    // Machines, that are down, are reported.
+    let answer = change(a, &with_addr(actions::ADD_MACHINE, &[1, 0], c));
+    assert_eq!(answer["hierarchy"], json!([[a], [b, c]]));
+    assert_eq!(answer["unreachable"], json!([c]));
+    assert!(answer["epoch"].as_u64().unwrap() > epoch);
+    epoch = answer["epoch"].as_u64().unwrap();
+    assert_eq!(hierarchy_of(b)[1], vec![b.to_string(), c.to_string()]);
+
+    // Changes are validated.
+    assert_eq!(code_of(&request(a, &with_addr(actions::ADD_MACHINE, &[0, 0], b))), errors::MACHINE_ALREADY_EXISTS.code);
+    assert_eq!(code_of(&request(a, &with_addr(actions::ADD_MACHINE, &[7, 0], d))), errors::NODE_IS_NOT_FOUND.code);
+    assert_eq!(code_of(&request(a, &with_addr(actions::REMOVE_MACHINE, &[], d))), errors::MACHINE_IS_NOT_FOUND.code);
+    assert_eq!(code_of(&request(a, &[actions::REMOVE_NODE, 0, 0])), errors::NODE_OWNS_SHARDS.code);
+    assert_eq!(code_of(&request(b, &[actions::ADD_NODE])), errors::NODE_NEEDS_A_MACHINE.code);
+
+    assert_eq!(change(b, &with_addr(actions::REMOVE_MACHINE, &[], c))["hierarchy"], json!([[a], [b]]));
+    assert_eq!(code_of(&request(a, &with_addr(actions::REMOVE_MACHINE, &[], b))), errors::NODE_NEEDS_A_MACHINE.code);
+    assert_eq!(hierarchy_of(a), vec![vec![a.to_string()], vec![b.to_string()]]);
+
+    // Nodes after the removed one get numbers one less, in the shard metadata too.
+    let mut add_node = vec![actions::ADD_NODE, d.len() as u8, 0];
+    add_node.extend_from_slice(d.as_bytes());
+    assert_eq!(change(a, &add_node)["unreachable"], json!([d]));
+    for addr in [a, b] {
+        assert_eq!(request(addr, &[actions::SET_SHARDS_NODE, 0, 0, 9, 0, 2, 0]), vec![actions::DONE]);
+        assert_eq!(node_of_first_shard(addr), 2);
+    }
+    let answer = change(a, &[actions::REMOVE_NODE, 1, 0]);
+    assert_eq!(answer["hierarchy"], json!([[a], [d]]));
+    assert!(answer["epoch"].as_u64().unwrap() > epoch);
+    for addr in [a, b] {
+        assert_eq!(hierarchy_of(addr), vec![vec![a.to_string()], vec![d.to_string()]]);
+        assert_eq!(node_of_first_shard(addr), 1);
+    }
+
+    for number in 0..2 {
+        let _ = fs::remove_dir_all(format!("test_data_membership_{}", number));
+    }
+}
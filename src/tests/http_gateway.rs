@@ -0,0 +1,138 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    thread,
+    time::Duration
+};
+use serde_json::{json, Value};
+use crate::{
+    server::{cfg::Config, server::Server},
+    storage::Storage
+};
+
+pub fn free_addr() -> String {
+    format!("127.0.0.1:{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port())
+}
+
+/// Sends one request with `Connection: close` and returns the status and the body.
+pub fn request(addr: &str, method: &str, path: &str, authorization: Option<&str>, body: &[u8]) -> (u16, Vec<u8>) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n", method, path, addr, body.len());
+    if let Some(authorization) = authorization {
+        head.push_str(&format!("Authorization: {}\r\n", authorization));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let status = line.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    (status, body)
+}
+
+fn json_of(body: &[u8]) -> Value {
+    serde_json::from_slice(body).unwrap()
+}
+
+#[test]
+fn http_gateway() {
+    let dir: PathBuf = "test_data_http_gateway".into();
+    let _ = fs::remove_dir_all(&dir);
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let http_addr = free_addr();
+    let config = Config {
+        tcp_addr: free_addr(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        password: "secret".to_string(),
+        http_addr: http_addr.clone(),
+        ..Config::default()
+    };
+    thread::spawn(move || Server::with_config(storage, config).run());
+    for _ in 0..500 {
+        if TcpStream::connect(&http_addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let addr = http_addr.as_str();
+    let auth = Some("Bearer secret");
+
+    let (status, body) = request(addr, "GET", "/ping", None, b"");
+    assert_eq!(status, 401);
+    assert_eq!(json_of(&body)["code"], 600);
+    // "user:secret"
+    assert_eq!(request(addr, "GET", "/ping", Some("Basic dXNlcjpzZWNyZXQ="), b"").0, 200);
+    assert_eq!(request(addr, "GET", "/nothing", auth, b"").0, 404);
+
+    let create = json!({
+        "name": "users",
+        "scheme": {
+            "sized_fields": { "age": "Uint32", "score": "Int128" },
+            "unsized_fields": { "name": "String" }
+        }
+    });
+    let (status, body) = request(addr, "POST", "/tables", auth, create.to_string().as_bytes());
+    assert_eq!(status, 201);
+    assert_eq!(json_of(&body)["number"], 0);
+    assert_eq!(request(addr, "POST", "/tables", auth, create.to_string().as_bytes()).0, 409);
+    assert_eq!(request(addr, "POST", "/tables", auth, br#"{"name": "raw", "engine": "cache", "cache_duration": 10}"#).0, 201);
+    assert_eq!(request(addr, "POST", "/tables", auth, br#"{"name": "bad", "engine": "columnar"}"#).0, 400);
+    assert_eq!(request(addr, "POST", "/tables", auth, b"not json").0, 400);
+
+    let (status, body) = request(addr, "GET", "/tables", auth, b"");
+    assert_eq!(status, 200);
+    let tables = json_of(&body);
+    assert_eq!(tables[0]["name"], "users");
+    assert_eq!(tables[1]["engine"], "cache");
+    assert_eq!(tables[1]["cache_duration"], 10);
+
+    let user = json!({ "age": 42, "score": "-170141183460469231731687303715884105728", "name": "Alice" });
+    assert_eq!(request(addr, "POST", "/tables/users/keys/alice", auth, user.to_string().as_bytes()).0, 201);
+    let (status, body) = request(addr, "POST", "/tables/users/keys/alice", auth, user.to_string().as_bytes());
+    assert_eq!(status, 409);
+    assert_eq!(json_of(&body)["code"], 404);
+    let (status, body) = request(addr, "GET", "/tables/users/keys/alice", auth, b"");
+    assert_eq!(status, 200);
+    assert_eq!(json_of(&body), user);
+    assert_eq!(request(addr, "PUT", "/tables/0/keys/alice", auth, br#"{"age": "old", "score": 1, "name": "A"}"#).0, 400);
+    assert_eq!(request(addr, "PUT", "/tables/0/keys/alice", auth, br#"{"age": 43, "score": 1, "name": "Alice B"}"#).0, 204);
+
+    let (status, body) = request(addr, "GET", "/tables/users/keys/alice/fields/name", auth, b"");
+    assert_eq!(status, 200);
+    assert_eq!(json_of(&body), json!("Alice B"));
+    let (status, body) = request(addr, "GET", "/tables/users/keys/alice/fields?names=name,age", auth, b"");
+    assert_eq!(status, 200);
+    assert_eq!(json_of(&body), json!({ "name": "Alice B", "age": 43 }));
+    assert_eq!(request(addr, "GET", "/tables/users/keys/alice/fields/nothing", auth, b"").0, 400);
+
+    assert_eq!(request(addr, "PUT", "/tables/raw/keys/a%20b", auth, b"\x00\x01raw").0, 204);
+    assert_eq!(request(addr, "GET", "/tables/raw/keys/a%20b", auth, b""), (200, b"\x00\x01raw".to_vec()));
+
+    assert_eq!(request(addr, "DELETE", "/tables/users/keys/alice", auth, b"").0, 204);
+    let (status, body) = request(addr, "GET", "/tables/users/keys/alice", auth, b"");
+    assert_eq!(status, 404);
+    assert_eq!(json_of(&body)["code"], 400);
+    assert_eq!(request(addr, "GET", "/tables/nothing/keys/alice", auth, b"").0, 404);
+    assert_eq!(request(addr, "PATCH", "/tables/users/keys/alice", auth, b"").0, 405);
+
+    fs::remove_dir_all(dir).unwrap();
+}
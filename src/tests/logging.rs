@@ -0,0 +1,39 @@
+#![cfg(test)]
+use std::time::{Duration, UNIX_EPOCH};
+use serde_json::{json, Value};
+use crate::console::logger::{field, format_record, Filter, Format, Level};
+
+#[test]
+fn logging() {
+    let filter = Filter::parse("warn, dbms::server=debug ,dbms::server::resp=off,wrong,dbms::storage=loud");
+    assert_eq!(filter.level("dbms::storage::storage"), Level::Warn);
+    assert_eq!(filter.level("dbms::server::server"), Level::Debug);
+    assert_eq!(filter.level("dbms::server::resp"), Level::Off);
+    assert_eq!(Filter::parse(""), Filter::parse("info"));
+
+    let time = UNIX_EPOCH + Duration::from_millis(1709211909123);
+    let record = {
+        let _connection = field("connection", 7u64);
+        let _table = field("table", 2u16);
+        format_record(Format::Json, time, Level::Warn, false, "dbms::server", format_args!("slow {}", "disk"))
+    };
+    let record: Value = serde_json::from_str(&record).unwrap();
+    assert_eq!(record, json!({
+        "ts": "2024-02-29T13:05:09.123Z",
+        "level": "warn",
+        "target": "dbms::server",
+        "msg": "slow disk",
+        "connection": 7,
+        "table": 2
+    }));
+
+    colored::control::set_override(false);
+    let line = {
+        let _client = field("client", "127.0.0.1:1");
+        format_record(Format::Text, time, Level::Info, true, "dbms", format_args!("Connection accepted"))
+    };
+    assert_eq!(line, "2024-02-29T13:05:09.123Z INFO  dbms: Connection accepted client=\"127.0.0.1:1\"");
+    // Guards are dropped, so the fields are removed.
+    let line = format_record(Format::Text, time, Level::Error, false, "dbms", format_args!("x"));
+    assert_eq!(line, "2024-02-29T13:05:09.123Z ERROR dbms: x");
+}
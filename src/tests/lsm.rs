@@ -0,0 +1,324 @@
+#![cfg(test)]
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    net::TcpStream,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::Duration
+};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    constants::{actions, errors},
+    lsm::{
+        bloom::{self, BloomFilter},
+        sstable::{SsTable, SsTableWriter},
+        tree::{Lsm, Options}
+    },
+    server::{cfg::Config, server::Server},
+    storage::storage::{Storage, CANT_CREATE_TABLE_NUMBER},
+    scheme::scheme::empty_scheme,
+    table::table::TableEngine,
+    tests::{failover::request, http_gateway::free_addr},
+    utils::bytes::uint,
+    writers::LogWriter
+};
+
+/// Small limits, so a few thousands of keys make many flushes and compactions.
+fn small_options() -> Options {
+    Options {
+        memtable_bytes: 16 * 1024,
+        table_bytes: 8 * 1024,
+        l0_compaction_trigger: 3,
+        level_base_bytes: 32 * 1024,
+        ..Options::default()
+    }
+}
+
+fn key(i: u32) -> Vec<u8> {
+    format!("key{:06}", i).into_bytes()
+}
+
+fn check(tree: &Lsm, expected: &BTreeMap<Vec<u8>, Vec<u8>>, range: u32) {
+    assert_eq!(tree.count(), expected.len() as u64);
+    assert_eq!(tree.keys().unwrap(), expected.keys().cloned().collect::<Vec<_>>());
+    for i in 0..range {
+        assert_eq!(tree.get(&key(i)).unwrap().as_ref(), expected.get(&key(i)), "{}", i);
+    }
+}
+
+#[test]
+fn bloom_filter_and_sstable() {
+    let dir: PathBuf = "test_data_lsm_sstable".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let hashes: Vec<u64> = (0..1000).map(|i| bloom::hash(&key(i))).collect();
+    let filter = BloomFilter::new(&hashes, 10);
+    assert!((0..1000).all(|i| filter.may_contain(&key(i))));
+    let false_positives = (1000..11000).filter(|i| filter.may_contain(&key(*i))).count();
+    assert!(false_positives < 300, "{}", false_positives);
+    let mut buf = Vec::new();
+    filter.encode(&mut buf);
+    let decoded = BloomFilter::decode(&buf).unwrap();
+    assert!((0..1000).all(|i| decoded.may_contain(&key(i))));
+    assert!(BloomFilter::decode(&buf[..buf.len() - 1]).is_none());
+
+    let mut writer = SsTableWriter::create(&dir, 1, 10).unwrap();
+    for i in 0..1000 {
+        let value = if i % 10 == 0 { None } else { Some(vec![b'v'; i as usize % 100]) };
+        writer.add(&key(i * 2), value.as_deref()).unwrap();
+    }
+    writer.finish().unwrap();
+    let table = SsTable::open(&dir, 1).unwrap();
+    assert_eq!(table.records, 1000);
+    assert_eq!(table.smallest, key(0));
+    assert_eq!(table.largest, key(1998));
+    for i in 0..1000 {
+        let expected = if i % 10 == 0 { None } else { Some(vec![b'v'; i as usize % 100]) };
+        assert_eq!(table.get(&key(i * 2)).unwrap(), Some(expected));
+        assert_eq!(table.get(&key(i * 2 + 1)).unwrap(), None);
+    }
+    assert_eq!(table.iter().filter(Result::is_ok).count(), 1000);
+    assert!(table.is_overlapping(&key(1998), &key(5000)));
+    assert!(!table.is_overlapping(&key(1999), &key(5000)));
+
+    fs::write(dir.join("2.sst"), b"short").unwrap();
+    assert!(SsTable::open(&dir, 2).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lsm_tree() {
+    let dir: PathBuf = "test_data_lsm_tree".into();
+    let _ = fs::remove_dir_all(&dir);
+    let mut expected = BTreeMap::new();
+    {
+        let tree = Lsm::open(dir.clone(), small_options()).unwrap();
+        for i in 0..3000u32 {
+            assert!(tree.insert(&key(i), i.to_le_bytes().repeat(4)).unwrap());
+            expected.insert(key(i), i.to_le_bytes().repeat(4));
+        }
+        assert!(!tree.insert(&key(0), b"again".to_vec()).unwrap());
+        for i in (0..3000u32).step_by(3) {
+            assert_eq!(tree.set(&key(i), b"new".to_vec()).unwrap(), Some(i.to_le_bytes().repeat(4)));
+            expected.insert(key(i), b"new".to_vec());
+        }
+        for i in (0..3000u32).step_by(5) {
+            assert!(tree.delete(&key(i)).unwrap());
+            expected.remove(&key(i));
+        }
+        assert!(!tree.delete(&key(0)).unwrap());
+        assert_eq!(tree.set(&key(5), b"back".to_vec()).unwrap(), None);
+        expected.insert(key(5), b"back".to_vec());
+
+        let levels = tree.tables_per_level();
+        assert!(levels[0] < small_options().l0_compaction_trigger);
+        assert!(levels[1..].iter().sum::<usize>() > 0, "{:?}", levels);
+        check(&tree, &expected, 3100);
+        tree.flush().unwrap();
+        check(&tree, &expected, 3100);
+    }
+
+    // The memtable is lost without the flush, [`crate::table::lsm::LsmTable`] restores it from the log.
+    let tree = Lsm::open(dir.clone(), small_options()).unwrap();
+    check(&tree, &expected, 3100);
+    assert!(tree.insert(&key(5000), b"lost".to_vec()).unwrap());
+    drop(tree);
+    let tree = Lsm::open(dir.clone(), small_options()).unwrap();
+    check(&tree, &expected, 5001);
+
+    // Files, that aren't in the manifest, are removed.
+    fs::write(dir.join("100000.sst"), b"orphan").unwrap();
+    drop(tree);
+    let tree = Lsm::open(dir.clone(), small_options()).unwrap();
+    assert!(!dir.join("100000.sst").exists());
+    check(&tree, &expected, 3100);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lsm_concurrent_writes() {
+    let dir: PathBuf = "test_data_lsm_concurrent".into();
+    let _ = fs::remove_dir_all(&dir);
+    let tree = Arc::new(Lsm::open(dir.clone(), small_options()).unwrap());
+    let writers: Vec<_> = (0..4u32).map(|thread| {
+        let tree = tree.clone();
+        thread::spawn(move || {
+            for i in 0..2000u32 {
+                let i = i * 4 + thread;
+                assert!(tree.insert(&key(i), i.to_le_bytes().to_vec()).unwrap());
+                assert_eq!(tree.get(&key(i)).unwrap(), Some(i.to_le_bytes().to_vec()));
+                if i % 2 == 0 {
+                    assert!(tree.delete(&key(i)).unwrap());
+                }
+            }
+        })
+    }).collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+    let expected: BTreeMap<Vec<u8>, Vec<u8>> = (0..8000u32).filter(|i| i % 2 == 1).map(|i| (key(i), i.to_le_bytes().to_vec())).collect();
+    check(&tree, &expected, 8000);
+    tree.flush().unwrap();
+    drop(tree);
+    let tree = Lsm::open(dir.clone(), small_options()).unwrap();
+    check(&tree, &expected, 8000);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lsm_table_restart() {
+    let dir: PathBuf = "test_data_lsm_table".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    {
+        let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+        storage.rise();
+        let mut log_writer = LogWriter::new(storage.log_file.clone());
+        let number = Storage::create_lsm_table(storage, "lsm".to_string(), empty_scheme(), &[]);
+        let (_, table) = storage.table_by_name(b"lsm").unwrap();
+        assert_eq!(table.engine(), TableEngine::Lsm);
+        assert!(table.is_it_logging());
+        for i in 0..100u32 {
+            assert!(table.insert(BinKey::new(&key(i)), BinValue::new(&i.to_le_bytes()), &mut log_writer).unwrap());
+        }
+        log_writer.flush();
+        storage.dump();
+
+        // Changes after the dump are only in the log.
+        let old = table.set(BinKey::new(&key(0)), BinValue::new(b"new"), &mut log_writer).unwrap().unwrap();
+        assert_eq!(old.deref(), 0u32.to_le_bytes());
+        table.delete(&BinKey::new(&key(1)), &mut log_writer).unwrap();
+        assert!(table.insert(BinKey::new(&key(100)), BinValue::new(b"after"), &mut log_writer).unwrap());
+        assert_eq!(table.count(), 100);
+        log_writer.flush();
+        assert_eq!(number, 0);
+    }
+
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    storage.rise();
+    let (_, table) = storage.table_by_name(b"lsm").unwrap();
+    assert_eq!(table.engine(), TableEngine::Lsm);
+    assert_eq!(table.count(), 100);
+    assert_eq!(table.get(&BinKey::new(&key(0))).unwrap().unwrap().deref(), b"new");
+    assert!(table.get(&BinKey::new(&key(1))).unwrap().is_none());
+    assert_eq!(table.get(&BinKey::new(&key(2))).unwrap().unwrap().deref(), 2u32.to_le_bytes());
+    assert_eq!(table.get(&BinKey::new(&key(100))).unwrap().unwrap().deref(), b"after");
+    assert_eq!(table.keys().unwrap().len(), 100);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The log isn't removed, if the memtable can't be flushed, so changes are replayed after the restart.
+#[test]
+fn lsm_failed_flush() {
+    let dir: PathBuf = "test_data_lsm_failed_flush".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    {
+        let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+        storage.rise();
+        let mut log_writer = LogWriter::new(storage.log_file.clone());
+        Storage::create_lsm_table(storage, "lsm".to_string(), empty_scheme(), &[]);
+        let (_, table) = storage.table_by_name(b"lsm").unwrap();
+        for i in 0..100u32 {
+            assert!(table.insert(BinKey::new(&key(i)), BinValue::new(&i.to_le_bytes()), &mut log_writer).unwrap());
+        }
+        log_writer.flush();
+
+        // Files of the tree can't be created, while its directory is replaced with a file.
+        fs::rename(dir.join("lsm"), dir.join("lsm moved")).unwrap();
+        fs::write(dir.join("lsm"), b"not a directory").unwrap();
+        storage.dump();
+        assert!(dir.join("log0.bin").exists());
+        assert_eq!(storage.first_log_number(), 0);
+        fs::remove_file(dir.join("lsm")).unwrap();
+        fs::rename(dir.join("lsm moved"), dir.join("lsm")).unwrap();
+
+        assert!(table.insert(BinKey::new(&key(100)), BinValue::new(b"after"), &mut log_writer).unwrap());
+        log_writer.flush();
+    }
+
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    storage.rise();
+    let (_, table) = storage.table_by_name(b"lsm").unwrap();
+    assert_eq!(table.count(), 101);
+    assert_eq!(table.get(&BinKey::new(&key(0))).unwrap().unwrap().deref(), 0u32.to_le_bytes());
+    assert_eq!(table.get(&BinKey::new(&key(100))).unwrap().unwrap().deref(), b"after");
+
+    // The next dump flushes the memtable, so old log files aren't needed anymore.
+    storage.dump();
+    assert_eq!(storage.first_log_number(), 2);
+    assert!(!dir.join("log0.bin").exists() && !dir.join("log1.bin").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// The table isn't created, if its tree can't be opened, and the number is given to the next table.
+#[test]
+fn lsm_open_error() {
+    let dir: PathBuf = "test_data_lsm_open_error".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lsm"), b"not a directory").unwrap();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    assert_eq!(Storage::create_lsm_table(storage, "lsm".to_string(), empty_scheme(), &[]), CANT_CREATE_TABLE_NUMBER);
+    assert!(storage.table_by_name(b"lsm").is_none());
+    assert_eq!(Storage::create_lsm_table(storage, "other".to_string(), empty_scheme(), &[]), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A broken file is answered with an error, and the server goes on serving.
+#[test]
+fn lsm_truncated_table() {
+    let addr = free_addr();
+    let dir: PathBuf = "test_data_lsm_truncated".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    storage.rise();
+    let number = Storage::create_lsm_table(storage, "lsm".to_string(), empty_scheme(), &[]);
+    let (_, table) = storage.table_by_name(b"lsm").unwrap();
+    let mut log_writer = LogWriter::new(storage.log_file.clone());
+    for i in 0..1000u32 {
+        assert!(table.insert(BinKey::new(&key(i)), BinValue::new(&[7; 100]), &mut log_writer).unwrap());
+    }
+    log_writer.flush();
+    storage.dump();
+
+    let sst = fs::read_dir(dir.join("lsm")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| path.extension().is_some_and(|extension| extension == "sst"))
+        .unwrap();
+    let file = OpenOptions::new().write(true).open(&sst).unwrap();
+    file.set_len(file.metadata().unwrap().len() / 2).unwrap();
+    assert_eq!(table.get(&BinKey::new(&key(999))).err(), Some(errors::VALUE_CANT_BE_READ));
+    assert_eq!(table.set(BinKey::new(&key(999)), BinValue::new(b"new"), &mut log_writer).err(), Some(errors::VALUE_CANT_BE_READ));
+    assert_eq!(table.keys().err(), Some(errors::VALUE_CANT_BE_READ));
+
+    let config = Config {
+        tcp_addr: addr.clone(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        ..Config::default()
+    };
+    thread::spawn(move || Server::with_config(storage, config).run());
+    for _ in 0..500 {
+        if TcpStream::connect(&addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let get = [&[actions::GET][..], &uint::u16tob(number as u16), &key(999)].concat();
+    let answer = request(&addr, &get);
+    assert_eq!(answer[0], errors::VALUE_CANT_BE_READ.status);
+    assert_eq!(uint::u16(&answer[1..3]), errors::VALUE_CANT_BE_READ.code);
+    assert_eq!(request(&addr, &[actions::PING]), vec![actions::DONE, actions::PING]);
+
+    let _ = fs::remove_dir_all(&dir);
+}
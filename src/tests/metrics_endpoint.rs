@@ -0,0 +1,64 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    thread,
+    time::Duration
+};
+use crate::{
+    constants::actions,
+    index::HashInMemoryIndex,
+    scheme::scheme::empty_scheme,
+    server::{cfg::Config, server::Server},
+    storage::Storage,
+    tests::http_gateway::{free_addr, request}
+};
+
+#[test]
+fn metrics_endpoint() {
+    let dir: PathBuf = "test_data_metrics_endpoint".into();
+    let _ = fs::remove_dir_all(&dir);
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    Storage::create_in_memory_table(storage, "metrics \"table\"".to_string(), HashInMemoryIndex::new(), false, empty_scheme(), &[]);
+    let tcp_addr = free_addr();
+    let metrics_addr = free_addr();
+    let config = Config {
+        tcp_addr: tcp_addr.clone(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        metrics_addr: metrics_addr.clone(),
+        ..Config::default()
+    };
+    thread::spawn(move || Server::with_config(storage, config).run());
+    let mut stream = None;
+    for _ in 0..500 {
+        if let Ok(connected) = TcpStream::connect(&tcp_addr) {
+            stream = Some(connected);
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let mut stream = stream.unwrap();
+
+    // One request with one PING message.
+    stream.write_all(&[3, 0, 0, 0, 1, 1, 0, actions::PING]).unwrap();
+    let mut answer = [0; 4];
+    stream.read_exact(&mut answer).unwrap();
+    assert_eq!(answer, [2, 0, actions::DONE, actions::PING]);
+
+    let (status, body) = request(&metrics_addr, "GET", "/metrics", None, b"");
+    assert_eq!(status, 200);
+    let body = String::from_utf8(body).unwrap();
+    assert!(body.contains("# TYPE dbms_request_duration_seconds histogram"));
+    assert!(body.contains("dbms_requests_total{action=\"ping\",code=\"9\"}"));
+    assert!(body.contains("dbms_request_duration_seconds_bucket{action=\"ping\",code=\"9\",le=\"+Inf\"}"));
+    assert!(body.contains("dbms_table_keys{table=\"metrics \\\"table\\\"\"} 0"));
+    assert!(body.contains("dbms_active_connections{protocol=\"binary\"}"));
+    assert!(body.contains("dbms_log_written_bytes_total"));
+    assert!(body.contains("dbms_cache_expirations_total"));
+
+    assert_eq!(request(&metrics_addr, "GET", "/", None, b"").0, 404);
+
+    fs::remove_dir_all(dir).unwrap();
+}
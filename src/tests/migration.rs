@@ -0,0 +1,160 @@
+#![cfg(test)]
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, atomic::{AtomicBool, Ordering::SeqCst}},
+    thread,
+    time::Duration
+};
+use serde_json::{json, Value};
+use crate::{
+    constants::{actions, errors},
+    server::{cfg::Config, migration::{plan, Move}, server::Server, sharding::{shard_of, NUMBER_OF_SHARDS}},
+    storage::Storage,
+    tests::{failover::request, http_gateway::free_addr},
+    utils::bytes::uint
+};
+
+#[test]
+fn rebalance_plan() {
+    // The new node gets the first half of shards of the only node.
+    let nodes = vec![0u16; NUMBER_OF_SHARDS];
+    assert_eq!(plan(&nodes, 1), vec![]);
+    assert_eq!(plan(&nodes, 2), vec![Move { from: 0, to: 32767, source: 0, target: 1 }]);
+
+    // Three even nodes and a new one: every node gives a quarter of its shards.
+    let nodes: Vec<u16> = (0..NUMBER_OF_SHARDS).map(|shard| (shard * 3 / NUMBER_OF_SHARDS) as u16).collect();
+    let moves = plan(&nodes, 4);
+    assert_eq!(moves.len(), 3);
+    assert!(moves.iter().all(|step| step.target == 3));
+    let mut counts = [0usize; 4];
+    for node in nodes.iter() {
+        counts[*node as usize] += 1;
+    }
+    for step in moves.iter() {
+        let len = (step.to - step.from) as usize + 1;
+        counts[step.source as usize] -= len;
+        counts[step.target as usize] += len;
+    }
+    assert_eq!(counts, [16384; 4]);
+}
+
+fn set(addr: &str, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut message = vec![actions::SET, 0, 0, key.len() as u8, 0];
+    message.extend_from_slice(key);
+    message.extend_from_slice(value);
+    request(addr, &message)
+}
+
+fn get(addr: &str, key: &[u8]) -> Vec<u8> {
+    let mut message = vec![actions::GET, 0, 0];
+    message.extend_from_slice(key);
+    request(addr, &message)
+}
+
+fn json_of(answer: &[u8]) -> Value {
+    assert_eq!(answer[0], actions::DONE, "{}", String::from_utf8_lossy(answer));
+    serde_json::from_slice(&answer[1..]).unwrap()
+}
+
+fn code_of(answer: &[u8]) -> u16 {
+    uint::u16(&answer[1..3])
+}
+
+#[test]
+fn migrate_shards() {
+    let nodes: Vec<String> = (0..2).map(|_| free_addr()).collect();
+    let hierarchy: Vec<Vec<String>> = nodes.iter().map(|addr| vec![addr.clone()]).collect();
+    // All shards are in the first node, like before the second node was added.
+    let shard_metadata = vec![0u8; NUMBER_OF_SHARDS * 2];
+    for (number, addr) in nodes.iter().enumerate() {
+        let dir: PathBuf = format!("test_data_migration_{}", number).into();
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("shard metadata.bin"), &shard_metadata).unwrap();
+        let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+        let config = Config {
+            tcp_addr: addr.clone(),
+            unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+            ..Config::default()
+        };
+        let mut server = Server::with_config(storage, config);
+        server.set_hierarchy(hierarchy.clone());
+        thread::spawn(move || server.run());
+    }
+    for addr in nodes.iter() {
+        for _ in 0..500 {
+            if std::net::TcpStream::connect(addr).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    let (a, b) = (nodes[0].as_str(), nodes[1].as_str());
+
+    // Only the first node has the table, the migration creates it on the second one.
+    assert_eq!(request(a, &[actions::CREATE_TABLE_IN_MEMORY, 1, 0, 0, b'k', b'v', b's']), vec![actions::DONE, 0, 0]);
+    let keys: Vec<Vec<u8>> = (0..2000).map(|i| format!("key{}", i).into_bytes()).collect();
+    for key in keys.iter() {
+        assert_eq!(set(a, key, key), vec![actions::DONE]);
+    }
+    let is_moved = |key: &[u8]| (shard_of(key) as usize) < NUMBER_OF_SHARDS / 2;
+
+    assert_eq!(json_of(&request(a, &[actions::REBALANCE, 1])), json!([{ "from": 0, "to": 32767, "source": 0, "target": 1 }]));
+    assert_eq!(code_of(&request(b, &[actions::MIGRATE_SHARDS, 0, 0, 0, 1, 0, 0])), errors::SHARDS_ARE_NOT_OWNED.code);
+    assert_eq!(code_of(&request(a, &[actions::MIGRATE_SHARDS, 0, 0, 0, 1, 5, 0])), errors::NODE_IS_NOT_FOUND.code);
+
+    // Writes to moving shards go on during the migration and move to the second node after the flip.
+    let hot_keys: Vec<Vec<u8>> = keys.iter().filter(|key| is_moved(key)).take(16).cloned().collect();
+    let is_done = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (a, b, hot_keys, is_done) = (a.to_string(), b.to_string(), hot_keys.clone(), is_done.clone());
+        thread::spawn(move || {
+            let mut last_values = HashMap::new();
+            let mut round = 0;
+            while !is_done.load(SeqCst) {
+                for key in hot_keys.iter() {
+                    let value = format!("round{}", round).into_bytes();
+                    let mut answer = set(&a, key, &value);
+                    if answer[0] != actions::DONE {
+                        assert_eq!(code_of(&answer), errors::KEY_IS_MOVED.code);
+                        answer = set(&b, key, &value);
+                    }
+                    assert_eq!(answer, vec![actions::DONE]);
+                    last_values.insert(key.clone(), value);
+                }
+                round += 1;
+            }
+            last_values
+        })
+    };
+    thread::sleep(Duration::from_millis(20));
+    let moves = json_of(&request(a, &[actions::REBALANCE, 0]));
+    let moved_keys = keys.iter().filter(|key| is_moved(key)).count();
+    assert_eq!(moves[0]["keys"], moved_keys);
+    thread::sleep(Duration::from_millis(20));
+    is_done.store(true, SeqCst);
+    let last_values = writer.join().unwrap();
+
+    for addr in nodes.iter() {
+        let answer = request(addr, &[actions::GET_SHARD_METADATA]);
+        assert_eq!(answer[0], actions::DONE);
+        assert!((0..NUMBER_OF_SHARDS).all(|shard| uint::u16(&answer[1 + shard * 2..3 + shard * 2]) == (shard < NUMBER_OF_SHARDS / 2) as u16));
+    }
+    for key in keys.iter() {
+        let (owner, other) = if is_moved(key) { (b, a) } else { (a, b) };
+        let mut value = vec![actions::DONE];
+        value.extend_from_slice(last_values.get(key).unwrap_or(key));
+        assert_eq!(get(owner, key), value);
+        assert_eq!(code_of(&get(other, key)), errors::KEY_IS_MOVED.code);
+    }
+    // The source has deleted its copy.
+    let info = json_of(&request(a, &[actions::INFO]));
+    assert_eq!(info["tables"][0]["count"], keys.len() - moved_keys);
+    assert_eq!(json_of(&request(a, &[actions::REBALANCE, 1])), json!([]));
+
+    for number in 0..2 {
+        let _ = fs::remove_dir_all(format!("test_data_migration_{}", number));
+    }
+}
@@ -1,6 +1,26 @@
 pub mod crud;
 pub mod persistence;
 pub mod crud_bench;
+pub mod protocol_fuzz;
+pub mod http_gateway;
+pub mod resp_listener;
+pub mod metrics_endpoint;
+pub mod slowlog;
+pub mod logging;
+pub mod config;
+pub mod replication;
+pub mod failover;
+pub mod sharding;
+pub mod migration;
+pub mod hierarchy;
+pub mod changes;
+pub mod pubsub;
+pub mod notifications;
+pub mod disk_cache;
+pub mod on_disk_persistence;
+pub mod lsm;
+pub mod bloom_filters;
+pub mod compression;
 
 #[cfg(test)]
 pub use crate::tests::crud::*;
@@ -0,0 +1,162 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::Write,
+    net::TcpStream,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant}
+};
+use crate::{
+    constants::{actions, errors},
+    server::{
+        cfg::Config,
+        pubsub::{PATTERN_MESSAGE, SUBSCRIPTIONS},
+        replication::read_frame,
+        server::Server
+    },
+    storage::Storage,
+    table::notifications::{self, keyevent_channel, keyspace_channel},
+    tests::{failover::request, http_gateway::free_addr},
+    utils::bytes::uint
+};
+
+fn set(addr: &str, action: u8, table: u8, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut message = vec![action, table, 0, key.len() as u8, 0];
+    message.extend_from_slice(key);
+    message.extend_from_slice(value);
+    request(addr, &message)
+}
+
+fn delete(addr: &str, table: u8, key: &[u8]) {
+    let mut message = vec![actions::DELETE, table, 0];
+    message.extend_from_slice(key);
+    assert_eq!(request(addr, &message), vec![actions::DONE]);
+}
+
+fn set_notifications(addr: &str, table: u8, events: u8) -> Vec<u8> {
+    request(addr, &[actions::SET_NOTIFICATIONS, table, 0, events])
+}
+
+fn watch(addr: &str, table: u8, timeout_millis: u32, key: &[u8]) -> Vec<u8> {
+    let mut message = vec![actions::WATCH, table, 0];
+    message.extend_from_slice(&uint::u32tob(timeout_millis));
+    message.extend_from_slice(key);
+    request(addr, &message)
+}
+
+/// Reads the next pattern message and returns its channel and message.
+fn next_message(stream: &mut TcpStream) -> (Vec<u8>, Vec<u8>) {
+    let frame = read_frame(stream).unwrap();
+    assert_eq!(&frame[..2], &[actions::DONE, PATTERN_MESSAGE], "{:?}", frame);
+    let pattern_len = uint::u16(&frame[2..4]) as usize;
+    let offset = 4 + pattern_len;
+    let channel_len = uint::u16(&frame[offset..offset + 2]) as usize;
+    let channel = frame[offset + 2..offset + 2 + channel_len].to_vec();
+    (channel, frame[offset + 2 + channel_len..].to_vec())
+}
+
+/// Reads both messages of the event: to the keyspace channel and to the keyevent channel.
+fn next_event(stream: &mut TcpStream) -> (u16, Vec<u8>, u8) {
+    let (channel, event_name) = next_message(stream);
+    let prefix = b"__keyspace@";
+    assert!(channel.starts_with(prefix), "{}", String::from_utf8_lossy(&channel));
+    let text = String::from_utf8(channel[prefix.len()..].to_vec()).unwrap();
+    let (table, key) = text.split_once("__:").unwrap();
+    let table: u16 = table.parse().unwrap();
+    let event = [notifications::SET, notifications::INSERT, notifications::DELETE, notifications::EXPIRE].into_iter()
+        .find(|&event| notifications::event_name(event).as_bytes() == event_name.as_slice())
+        .unwrap();
+    assert_eq!(channel, keyspace_channel(table, key.as_bytes()));
+    assert_eq!(next_message(stream), (keyevent_channel(table, event), key.as_bytes().to_vec()));
+    (table, key.as_bytes().to_vec(), event)
+}
+
+#[test]
+fn keyspace_notifications() {
+    let addr = free_addr();
+    let dir: PathBuf = "test_data_notifications".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let config = Config {
+        tcp_addr: addr.clone(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        ..Config::default()
+    };
+    let server = Server::with_config(storage, config);
+    thread::spawn(move || server.run());
+    for _ in 0..500 {
+        if TcpStream::connect(&addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let addr = addr.as_str();
+
+    assert_eq!(request(addr, &[actions::CREATE_TABLE_IN_MEMORY, 1, 0, 0, b'k', b'v', b's']), vec![actions::DONE, 0, 0]);
+    // A cache table without the duration, so every key expires on the next check.
+    let mut cache = vec![actions::CREATE_TABLE_CACHE, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    cache.extend_from_slice(b"sessions");
+    assert_eq!(request(addr, &cache), vec![actions::DONE, 1, 0]);
+    assert_eq!(request(addr, &[actions::CREATE_TABLE_ON_DISK, 0, 0, b'd', b'i', b's', b'k']), vec![actions::DONE, 2, 0]);
+
+    let mut subscriber = TcpStream::connect(addr).unwrap();
+    subscriber.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let pattern = b"__key*";
+    let mut subscribe = vec![pattern.len() as u8 + 5, 0, 0, 0, 0, pattern.len() as u8 + 3, 0, actions::PSUBSCRIBE, pattern.len() as u8, 0];
+    subscribe.extend_from_slice(pattern);
+    subscriber.write_all(&subscribe).unwrap();
+    assert_eq!(read_frame(&mut subscriber).unwrap(), vec![actions::DONE, SUBSCRIPTIONS, 1, 0, 0, 0]);
+
+    // Tables are silent without the opt-in.
+    assert_eq!(set(addr, actions::SET, 0, b"quiet", b"1"), vec![actions::DONE]);
+    assert_eq!(set_notifications(addr, 0, notifications::SET | notifications::DELETE), vec![actions::DONE]);
+    assert_eq!(set(addr, actions::SET, 0, b"a", b"1"), vec![actions::DONE]);
+    assert_eq!(next_event(&mut subscriber), (0, b"a".to_vec(), notifications::SET));
+    assert_eq!(set(addr, actions::INSERT, 0, b"b", b"1"), vec![actions::DONE]);
+    delete(addr, 0, b"missing");
+    delete(addr, 0, b"a");
+    assert_eq!(next_event(&mut subscriber), (0, b"a".to_vec(), notifications::DELETE));
+
+    assert_eq!(set_notifications(addr, 2, notifications::ALL), vec![actions::DONE]);
+    assert_eq!(set(addr, actions::INSERT, 2, b"c", b"1")[0], actions::DONE);
+    set(addr, actions::INSERT, 2, b"c", b"2");
+    delete(addr, 2, b"c");
+    assert_eq!(next_event(&mut subscriber), (2, b"c".to_vec(), notifications::INSERT));
+    assert_eq!(next_event(&mut subscriber), (2, b"c".to_vec(), notifications::DELETE));
+
+    assert_eq!(set_notifications(addr, 1, notifications::EXPIRE), vec![actions::DONE]);
+    assert_eq!(set(addr, actions::SET, 1, b"token", b"1"), vec![actions::DONE]);
+    storage.tables.get()[1].invalid_cache();
+    assert_eq!(next_event(&mut subscriber), (1, b"token".to_vec(), notifications::EXPIRE));
+
+    let answer = set_notifications(addr, 0, 16);
+    assert_eq!(uint::u16(&answer[1..3]), errors::EVENTS_ARE_NOT_VALID.code);
+    let answer = set_notifications(addr, 9, notifications::ALL);
+    assert_eq!(uint::u16(&answer[1..3]), errors::TABLE_IS_NOT_FOUND.code);
+    let answer = request(addr, &[actions::INFO]);
+    let info: serde_json::Value = serde_json::from_slice(&answer[1..]).unwrap();
+    assert_eq!(info["tables"][0]["notifications"], (notifications::SET | notifications::DELETE) as u64);
+
+    // WATCH wakes on the change of the key in any table, and the timeout ends it without the change.
+    let watcher = {
+        let addr = addr.to_string();
+        thread::spawn(move || watch(&addr, 1, 5000, b"w"))
+    };
+    let start = Instant::now();
+    while storage.notifications.watched_keys() == 0 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(set(addr, actions::SET, 1, b"other", b"1"), vec![actions::DONE]);
+    assert_eq!(set(addr, actions::SET, 1, b"w", b"1"), vec![actions::DONE]);
+    assert_eq!(watcher.join().unwrap(), vec![actions::DONE, notifications::SET]);
+    assert_eq!(storage.notifications.watched_keys(), 0);
+    assert_eq!(watch(addr, 0, 50, b"b"), vec![actions::DONE, notifications::TIMEOUT]);
+    assert_eq!(storage.notifications.watched_keys(), 0);
+    let answer = watch(addr, 9, 50, b"b");
+    assert_eq!(uint::u16(&answer[1..3]), errors::TABLE_IS_NOT_FOUND.code);
+
+    let _ = fs::remove_dir_all(&dir);
+}
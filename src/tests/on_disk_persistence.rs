@@ -0,0 +1,170 @@
+#![cfg(test)]
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread
+};
+use crate::{
+    bin_types::{BinKey, BinValue},
+    disk_storage::{storage::DiskStorage, value_cache::ValueCache},
+    index::{HashInMemoryIndex, Index}
+};
+
+fn open(dir: &Path) -> DiskStorage<HashInMemoryIndex<BinKey, (u64, u64)>> {
+    DiskStorage::new(dir.to_path_buf(), 4, HashInMemoryIndex::new(), Arc::new(ValueCache::new(0))).unwrap()
+}
+
+fn check(storage: &DiskStorage<HashInMemoryIndex<BinKey, (u64, u64)>>, expected: &HashMap<Vec<u8>, Vec<u8>>) {
+    assert_eq!(storage.infos.count(), expected.len());
+    for (key, value) in expected {
+        assert_eq!(storage.get(&BinKey::new(key)).map(|value| value.deref().to_vec()).as_ref(), Some(value), "{:?}", key);
+    }
+}
+
+#[test]
+fn on_disk_rise() {
+    let dir: PathBuf = "test_data_on_disk_rise".into();
+    let _ = fs::remove_dir_all(&dir);
+    let long_key = vec![b'k'; 300];
+    let long_value = vec![b'v'; 70_000];
+    let mut expected = HashMap::new();
+    {
+        let storage = open(&dir);
+        for i in 0..100u32 {
+            assert!(storage.insert(BinKey::new(&i.to_le_bytes()), BinValue::new(&i.to_be_bytes())));
+        }
+        for i in 0..50u32 {
+            let old = storage.set(BinKey::new(&i.to_le_bytes()), BinValue::new(b"new")).unwrap();
+            assert_eq!(old.deref(), i.to_be_bytes());
+        }
+        for i in 25..75u32 {
+            assert!(storage.delete(&BinKey::new(&i.to_le_bytes())));
+        }
+        assert!(storage.insert(BinKey::new(&30u32.to_le_bytes()), BinValue::new(b"again")));
+        assert!(storage.insert(BinKey::new(&long_key), BinValue::new(&long_value)));
+        assert!(storage.set(BinKey::new(b"set"), BinValue::new(b"1")).is_none());
+        for i in 0..25u32 {
+            expected.insert(i.to_le_bytes().to_vec(), b"new".to_vec());
+        }
+        for i in 75..100u32 {
+            expected.insert(i.to_le_bytes().to_vec(), i.to_be_bytes().to_vec());
+        }
+        expected.insert(30u32.to_le_bytes().to_vec(), b"again".to_vec());
+        expected.insert(long_key.clone(), long_value.clone());
+        expected.insert(b"set".to_vec(), b"1".to_vec());
+        check(&storage, &expected);
+    }
+
+    // Without the hint all files are read.
+    let storage = open(&dir);
+    check(&storage, &expected);
+
+    // With the hint only records after it are read.
+    storage.write_hint().unwrap();
+    assert!(dir.join("hint.bin").exists());
+    assert!(storage.set(BinKey::new(&0u32.to_le_bytes()), BinValue::new(b"after")).is_some());
+    assert!(storage.delete(&BinKey::new(&80u32.to_le_bytes())));
+    assert!(storage.insert(BinKey::new(b"after"), BinValue::new(b"hint")));
+    assert!(storage.delete(&BinKey::new(b"set")));
+    assert!(storage.insert(BinKey::new(b"set"), BinValue::new(b"2")));
+    expected.insert(0u32.to_le_bytes().to_vec(), b"after".to_vec());
+    expected.remove(&80u32.to_le_bytes().to_vec());
+    expected.insert(b"after".to_vec(), b"hint".to_vec());
+    expected.insert(b"set".to_vec(), b"2".to_vec());
+    drop(storage);
+    let storage = open(&dir);
+    check(&storage, &expected);
+
+    // The broken record at the end is cut off, and new records are written after the last whole one.
+    drop(storage);
+    let mut file = OpenOptions::new().append(true).open(dir.join("0.bin")).unwrap();
+    file.write_all(&[5, b'b', b'r']).unwrap();
+    drop(file);
+    let storage = open(&dir);
+    check(&storage, &expected);
+    for i in 200..220u32 {
+        assert!(storage.insert(BinKey::new(&i.to_le_bytes()), BinValue::new(b"x")));
+        expected.insert(i.to_le_bytes().to_vec(), b"x".to_vec());
+    }
+    drop(storage);
+    let storage = open(&dir);
+    check(&storage, &expected);
+
+    // The hint, that doesn't match files, is ignored.
+    storage.write_hint().unwrap();
+    drop(storage);
+    for number in 0..4 {
+        OpenOptions::new().write(true).open(dir.join(format!("{number}D.bin"))).unwrap().set_len(0).unwrap();
+    }
+    let storage = open(&dir);
+    assert!(storage.infos.count() > expected.len());
+    drop(storage);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn on_disk_hint_with_concurrent_writes() {
+    let dir: PathBuf = "test_data_on_disk_hint".into();
+    let _ = fs::remove_dir_all(&dir);
+    let storage = Arc::new(open(&dir));
+    for i in 0..2000u32 {
+        storage.insert(BinKey::new(&i.to_le_bytes()), BinValue::new(&i.to_le_bytes()));
+    }
+
+    let writer = {
+        let storage = storage.clone();
+        thread::spawn(move || {
+            for round in 0..5u32 {
+                for i in 0..2000u32 {
+                    let key = BinKey::new(&i.to_le_bytes());
+                    match (i + round) % 3 {
+                        0 => { storage.set(key, BinValue::new(&round.to_le_bytes())); }
+                        1 => { storage.delete(&key); }
+                        _ => { storage.insert(key, BinValue::new(b"inserted")); }
+                    }
+                }
+            }
+        })
+    };
+    // The hint, that was written during writes, is kept for the rise.
+    let mut is_written = false;
+    loop {
+        storage.write_hint().unwrap();
+        if writer.is_finished() {
+            break;
+        }
+        fs::copy(dir.join("hint.bin"), dir.join("hint.during_writes")).unwrap();
+        is_written = true;
+    }
+    writer.join().unwrap();
+    assert!(is_written);
+    fs::rename(dir.join("hint.during_writes"), dir.join("hint.bin")).unwrap();
+
+    let mut expected = HashMap::new();
+    for i in 0..2000u32 {
+        if let Some(value) = storage.get(&BinKey::new(&i.to_le_bytes())) {
+            expected.insert(i.to_le_bytes().to_vec(), value.deref().to_vec());
+        }
+    }
+    drop(storage);
+    let storage = open(&dir);
+    check(&storage, &expected);
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+/// Files, that can't be opened, are an error, not a panic.
+#[test]
+fn on_disk_open_error() {
+    let path: PathBuf = "test_data_on_disk_open_error".into();
+    let _ = fs::remove_dir_all(&path);
+    let _ = fs::remove_file(&path);
+    fs::write(&path, b"not a directory").unwrap();
+    assert!(DiskStorage::new(path.clone(), 4, HashInMemoryIndex::new(), Arc::new(ValueCache::new(0))).is_err());
+
+    fs::remove_file(&path).unwrap();
+}
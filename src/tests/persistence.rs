@@ -48,14 +48,14 @@ fn test_dump(storage: &'static Storage) {
     }
 
     for i in 0..10000 {
-        tables[number1].insert(keys[i].clone(), values[i].clone(), &mut log_writer);
-        tables[number2].insert(keys[i].clone(), values[i].clone(), &mut log_writer);
+        tables[number1].insert(keys[i].clone(), values[i].clone(), &mut log_writer).unwrap();
+        tables[number2].insert(keys[i].clone(), values[i].clone(), &mut log_writer).unwrap();
     }
     for i in 0..10000 {
         if i % 2 == 0 {
-            tables[number1].delete(&keys[i], &mut log_writer);
+            tables[number1].delete(&keys[i], &mut log_writer).unwrap();
         } else {
-            tables[number2].delete(&keys[i], &mut log_writer);
+            tables[number2].delete(&keys[i], &mut log_writer).unwrap();
         }
     }
 
@@ -82,11 +82,11 @@ fn test_dump(storage: &'static Storage) {
 
     for i in 0..10000 {
         if i % 2 == 0 {
-            assert_eq!(None, tables[number1].get(&keys[i]));
-            assert_eq!(values[i].clone(), tables[number2].get(&keys[i]).unwrap());
+            assert_eq!(None, tables[number1].get(&keys[i]).unwrap());
+            assert_eq!(values[i].clone(), tables[number2].get(&keys[i]).unwrap().unwrap());
         } else {
-            assert_eq!(values[i].clone(), tables[number1].get(&keys[i]).unwrap());
-            assert_eq!(None, tables[number2].get(&keys[i]));
+            assert_eq!(values[i].clone(), tables[number1].get(&keys[i]).unwrap().unwrap());
+            assert_eq!(None, tables[number2].get(&keys[i]).unwrap());
         }
     }
 
@@ -116,28 +116,28 @@ fn test_dump_and_log(storage: &'static Storage) {
     let mut log_writer = LogWriter::new(storage.log_file.clone());
 
     for i in 0..5000 {
-        tables[number1].insert(keys[i].clone(), values[i].clone(), &mut log_writer);
-        tables[number2].insert(keys[i].clone(), values[i].clone(), &mut log_writer);
+        tables[number1].insert(keys[i].clone(), values[i].clone(), &mut log_writer).unwrap();
+        tables[number2].insert(keys[i].clone(), values[i].clone(), &mut log_writer).unwrap();
     }
     for i in 0..5000 {
         if i % 2 == 0 {
-            tables[number1].delete(&keys[i], &mut log_writer);
+            tables[number1].delete(&keys[i], &mut log_writer).unwrap();
         } else {
-            tables[number2].delete(&keys[i], &mut log_writer);
+            tables[number2].delete(&keys[i], &mut log_writer).unwrap();
         }
     }
 
     Storage::dump(storage.clone());
 
     for i in 5000..10000 {
-        tables[number1].insert(keys[i].clone(), values[i].clone(), &mut log_writer);
-        tables[number2].insert(keys[i].clone(), values[i].clone(), &mut log_writer);
+        tables[number1].insert(keys[i].clone(), values[i].clone(), &mut log_writer).unwrap();
+        tables[number2].insert(keys[i].clone(), values[i].clone(), &mut log_writer).unwrap();
     }
     for i in 5000..10000 {
         if i % 2 == 0 {
-            tables[number1].delete(&keys[i], &mut log_writer);
+            tables[number1].delete(&keys[i], &mut log_writer).unwrap();
         } else {
-            tables[number2].delete(&keys[i], &mut log_writer);
+            tables[number2].delete(&keys[i], &mut log_writer).unwrap();
         }
     }
     log_writer.flush();
@@ -162,11 +162,11 @@ fn test_dump_and_log(storage: &'static Storage) {
 
     for i in 0..10000 {
         if i % 2 == 0 {
-            assert_eq!(None, tables[number1].get(&keys[i]));
-            assert_eq!(values[i].clone(), tables[number2].get(&keys[i]).unwrap());
+            assert_eq!(None, tables[number1].get(&keys[i]).unwrap());
+            assert_eq!(values[i].clone(), tables[number2].get(&keys[i]).unwrap().unwrap());
         } else {
-            assert_eq!(values[i].clone(), tables[number1].get(&keys[i]).unwrap());
-            assert_eq!(None, tables[number2].get(&keys[i]));
+            assert_eq!(values[i].clone(), tables[number1].get(&keys[i]).unwrap().unwrap());
+            assert_eq!(None, tables[number2].get(&keys[i]).unwrap());
         }
     }
 
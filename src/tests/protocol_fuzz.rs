@@ -0,0 +1,199 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::Read,
+    mem,
+    os::unix::net::UnixStream,
+    sync::Arc,
+    thread
+};
+use crate::{
+    connection::{buffered, Status},
+    constants::actions,
+    server::server::Server,
+    storage::Storage,
+    success,
+    writers::LogWriter
+};
+
+const ITERATIONS: usize = 200_000;
+
+static SCHEME: &'static [u8] = br#"{
+    "sized_fields": {
+        "age": "Uint32"
+    },
+    "unsized_fields": {
+        "name": "String",
+        "surname": "String"
+    }
+}"#;
+
+/// xorshift64*. We don't need a good generator, but we need the same frames on every run.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        (0..len).map(|_| self.next() as u8).collect()
+    }
+}
+
+fn create_table_frame(action: u8, scheme: &[u8], name: &str) -> Vec<u8> {
+    let mut frame = vec![action];
+    if action == actions::CREATE_TABLE_IN_MEMORY {
+        frame.push(1);
+    } else if action == actions::CREATE_TABLE_CACHE {
+        frame.push(1);
+        frame.extend_from_slice(&10u64.to_le_bytes());
+    }
+    frame.extend_from_slice(&[scheme.len() as u8, (scheme.len() >> 8) as u8]);
+    frame.extend_from_slice(scheme);
+    frame.extend_from_slice(name.as_bytes());
+    frame
+}
+
+/// Returns a frame, that looks like a real one, but can be broken anywhere.
+fn random_frame(random: &mut Random) -> Vec<u8> {
+    // Fully random frames.
+    if random.below(4) == 0 {
+        let len = random.below(64);
+        return random.bytes(len);
+    }
+
+    // We don't create on disk tables here: every one of them opens a thousand files.
+    const ACTIONS: [u8; 15] = [
+        actions::PING, actions::GET_SHARD_METADATA, actions::GET_HIERARCHY,
+        actions::CREATE_TABLE_IN_MEMORY, actions::CREATE_TABLE_CACHE, actions::GET_TABLES_NAMES, actions::GET_TABLE_SCHEME,
+        actions::GET, actions::GET_FIELD, actions::GET_FIELDS, actions::INSERT, actions::SET, actions::DELETE,
+        actions::BIG_ACTION, 200
+    ];
+    let action = ACTIONS[random.below(ACTIONS.len())];
+    let table = random.below(5) as u16;
+    let key_len = random.below(8);
+    let key = random.bytes(key_len);
+    let mut frame = match action {
+        actions::CREATE_TABLE_IN_MEMORY | actions::CREATE_TABLE_CACHE => {
+            let scheme = if random.below(2) == 0 {
+                SCHEME.to_vec()
+            } else {
+                let len = random.below(16);
+                random.bytes(len)
+            };
+            create_table_frame(action, &scheme, &format!("fuzz{}", random.below(8)))
+        }
+        actions::GET | actions::DELETE | actions::GET_TABLE_SCHEME => {
+            let mut frame = vec![action, table as u8, (table >> 8) as u8];
+            frame.extend_from_slice(&key);
+            frame
+        }
+        actions::GET_FIELD => {
+            let field = random.below(4) as u16;
+            let mut frame = vec![action, table as u8, (table >> 8) as u8, field as u8, (field >> 8) as u8];
+            frame.extend_from_slice(&key);
+            frame
+        }
+        actions::GET_FIELDS => {
+            let number_of_fields = random.below(4) as u16;
+            let mut frame = vec![action, table as u8, (table >> 8) as u8, number_of_fields as u8, (number_of_fields >> 8) as u8];
+            for _ in 0..number_of_fields {
+                frame.extend_from_slice(&[random.below(4) as u8, 0]);
+            }
+            frame.extend_from_slice(&key);
+            frame
+        }
+        actions::INSERT | actions::SET => {
+            let mut frame = vec![action, table as u8, (table >> 8) as u8, key.len() as u8, (key.len() >> 8) as u8];
+            frame.extend_from_slice(&key);
+            if random.below(2) == 0 {
+                // The value matches SCHEME.
+                frame.extend_from_slice(&[1, 0, 0, 0, 3, 0]);
+                frame.extend_from_slice(b"Bob");
+                frame.extend_from_slice(&[5, 0]);
+                frame.extend_from_slice(b"Smith");
+            } else {
+                let len = random.below(32);
+                frame.extend_from_slice(&random.bytes(len));
+            }
+            frame
+        }
+        _ => vec![action],
+    };
+
+    // Break the frame.
+    match random.below(4) {
+        0 => {
+            let len = random.below(frame.len() + 1);
+            frame.truncate(len);
+        }
+        1 => {
+            let i = random.below(frame.len());
+            frame[i] = random.next() as u8;
+        }
+        _ => {}
+    }
+    frame
+}
+
+#[test]
+/// protocol_fuzz sends random and broken frames to `Server::handle_message`.
+/// The server must answer every frame and never panic.
+fn protocol_fuzz() {
+    let storage = Storage::new(["test_data_fuzz"].iter().collect());
+    let storage_static = unsafe { mem::transmute::<&Storage, &'static Storage>(&storage) };
+    let server = Arc::new(Server::new(storage_static));
+
+    let (stream, mut peer) = UnixStream::pair().unwrap();
+    // We don't check responses, but we must read them, otherwise the writer will be blocked.
+    let drain = thread::spawn(move || {
+        let mut buf = [0u8; 64 * 1024];
+        while peer.read(&mut buf).unwrap_or(0) > 0 {}
+    });
+    let mut connection = buffered(stream);
+    let mut log_writer = LogWriter::new(storage_static.log_file.clone());
+
+    for (action, name) in [
+        (actions::CREATE_TABLE_IN_MEMORY, "fuzz with scheme"),
+        (actions::CREATE_TABLE_IN_MEMORY, "fuzz without scheme"),
+        (actions::CREATE_TABLE_CACHE, "fuzz cache")
+    ] {
+        let scheme = if name == "fuzz without scheme" { &[][..] } else { SCHEME };
+        let frame = create_table_frame(action, scheme, name);
+        let status = Server::handle_message(&mut connection, &server, storage_static, &frame, &mut log_writer, "fuzz");
+        assert!(status == Status::Ok);
+    }
+
+    let mut random = Random(0x9E3779B97F4A7C15);
+    for i in 0..ITERATIONS {
+        let frame = random_frame(&mut random);
+        // REPLICATE and SUBSCRIBE_CHANGES make the connection a stream, that ends only when the client disconnects.
+        // WATCH waits for the change of the key.
+        if matches!(frame.first(), Some(&actions::REPLICATE) | Some(&actions::SUBSCRIBE_CHANGES) | Some(&actions::WATCH)) {
+            continue;
+        }
+        let status = Server::handle_message(&mut connection, &server, storage_static, &frame, &mut log_writer, "fuzz");
+        if status != Status::Ok {
+            panic!("frame {} wasn't handled: {:?}", i, frame);
+        }
+        if i % 1024 == 0 {
+            log_writer.flush();
+            connection.flush().unwrap();
+        }
+    }
+    log_writer.flush();
+    connection.close().unwrap();
+    drop(connection);
+    drain.join().unwrap();
+
+    fs::remove_dir_all("test_data_fuzz").unwrap();
+    success!("protocol fuzz: {} frames were handled", ITERATIONS);
+}
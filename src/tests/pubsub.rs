@@ -0,0 +1,155 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::{Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    thread,
+    time::Duration
+};
+use crate::{
+    constants::{actions, errors},
+    server::{
+        cfg::Config,
+        pubsub::{MESSAGE, PATTERN_MESSAGE, SUBSCRIPTIONS},
+        replication::read_frame,
+        server::Server
+    },
+    storage::Storage,
+    tests::{failover::request, http_gateway::free_addr},
+    utils::bytes::uint
+};
+
+fn connect(addr: &str) -> TcpStream {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream
+}
+
+/// Sends messages in one request.
+fn send(stream: &mut TcpStream, messages: &[&[u8]]) {
+    let len: usize = messages.iter().map(|message| message.len() + 2).sum();
+    let mut request = vec![len as u8, (len >> 8) as u8, (len >> 16) as u8, (len >> 24) as u8, 0];
+    for message in messages {
+        request.extend_from_slice(&uint::u16tob(message.len() as u16));
+        request.extend_from_slice(message);
+    }
+    stream.write_all(&request).unwrap();
+}
+
+fn with_names(action: u8, names: &[&[u8]]) -> Vec<u8> {
+    let mut message = vec![action];
+    for name in names {
+        message.extend_from_slice(&uint::u16tob(name.len() as u16));
+        message.extend_from_slice(name);
+    }
+    message
+}
+
+fn publish_message(channel: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut buf = with_names(actions::PUBLISH, &[channel]);
+    buf.extend_from_slice(message);
+    buf
+}
+
+fn subscriptions(stream: &mut TcpStream) -> u32 {
+    let frame = read_frame(stream).unwrap();
+    assert_eq!(&frame[..2], &[actions::DONE, SUBSCRIPTIONS], "{:?}", frame);
+    uint::u32(&frame[2..6])
+}
+
+fn receivers(frame: &[u8]) -> u32 {
+    assert_eq!(frame[0], actions::DONE, "{:?}", frame);
+    uint::u32(&frame[1..5])
+}
+
+#[test]
+fn pubsub() {
+    let addr = free_addr();
+    let dir: PathBuf = "test_data_pubsub".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let config = Config {
+        tcp_addr: addr.clone(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        pubsub_max_pending_bytes: 64 * 1024,
+        ..Config::default()
+    };
+    let server = Server::with_config(storage, config);
+    thread::spawn(move || server.run());
+    for _ in 0..500 {
+        if TcpStream::connect(&addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let addr = addr.as_str();
+
+    // Answers of messages before the subscription come first, and the rest of the request is read in the push mode.
+    let mut channel = connect(addr);
+    send(&mut channel, &[&[actions::PING], &with_names(actions::SUBSCRIBE, &[b"news", b"sport"]), &[actions::PING]]);
+    assert_eq!(read_frame(&mut channel).unwrap(), vec![actions::DONE, actions::PING]);
+    assert_eq!(subscriptions(&mut channel), 2);
+    assert_eq!(read_frame(&mut channel).unwrap(), vec![actions::DONE, actions::PING]);
+    let mut pattern = connect(addr);
+    send(&mut pattern, &[&with_names(actions::PSUBSCRIBE, &[b"n[aeiou]w?", b"*"])]);
+    assert_eq!(subscriptions(&mut pattern), 2);
+
+    assert_eq!(receivers(&request(addr, &publish_message(b"news", b"hello"))), 3);
+    let mut expected = vec![actions::DONE, MESSAGE, 4, 0];
+    expected.extend_from_slice(b"newshello");
+    assert_eq!(read_frame(&mut channel).unwrap(), expected);
+    let mut frames = vec![read_frame(&mut pattern).unwrap(), read_frame(&mut pattern).unwrap()];
+    frames.sort();
+    assert_eq!(frames[0], [&[actions::DONE, PATTERN_MESSAGE, 1, 0][..], b"*", &[4, 0], b"newshello"].concat());
+    assert_eq!(frames[1], [&[actions::DONE, PATTERN_MESSAGE, 10, 0][..], b"n[aeiou]w?", &[4, 0], b"newshello"].concat());
+    assert_eq!(receivers(&request(addr, &publish_message(b"weather", b""))), 1);
+    assert_eq!(read_frame(&mut pattern).unwrap(), [&[actions::DONE, PATTERN_MESSAGE, 1, 0][..], b"*", &[7, 0], b"weather"].concat());
+
+    // Other actions aren't allowed in the push mode.
+    send(&mut channel, &[&[actions::GET_TABLES_NAMES]]);
+    let frame = read_frame(&mut channel).unwrap();
+    assert_eq!(uint::u16(&frame[1..3]), errors::CONNECTION_IS_SUBSCRIBED.code);
+
+    // Long patterns are rejected, because every `PUBLISH` matches them.
+    send(&mut channel, &[&with_names(actions::PSUBSCRIBE, &["*".repeat(257).as_bytes()])]);
+    let frame = read_frame(&mut channel).unwrap();
+    assert_eq!(uint::u16(&frame[1..3]), errors::PATTERN_IS_TOO_LONG.code);
+
+    // The connection returns to the usual mode without subscriptions.
+    send(&mut channel, &[&[actions::UNSUBSCRIBE, 0, 4, 0, b'n', b'e', b'w', b's']]);
+    assert_eq!(subscriptions(&mut channel), 1);
+    send(&mut channel, &[&[actions::UNSUBSCRIBE], &[actions::GET_TABLES_NAMES]]);
+    assert_eq!(subscriptions(&mut channel), 0);
+    assert_eq!(uint::u16(&read_frame(&mut channel).unwrap()[1..3]), errors::CONNECTION_IS_SUBSCRIBED.code);
+    send(&mut channel, &[&[actions::GET_TABLES_NAMES]]);
+    assert_eq!(read_frame(&mut channel).unwrap()[0], actions::DONE);
+    assert_eq!(receivers(&request(addr, &publish_message(b"sport", b"goal"))), 1);
+    assert_eq!(request(addr, &[actions::UNSUBSCRIBE]), vec![actions::DONE, SUBSCRIPTIONS, 0, 0, 0, 0]);
+    assert_eq!(uint::u16(&request(addr, &publish_message(b"news", b"")[..2])[1..3]), errors::MESSAGE_IS_TOO_SHORT.code);
+
+    // The subscriber, that doesn't read, is disconnected, and the publisher isn't blocked by it.
+    let mut publisher = connect(addr);
+    let message = publish_message(b"slow", &[7; 60 * 1024]);
+    let mut is_dropped = false;
+    for _ in 0..10_000 {
+        send(&mut publisher, &[&message]);
+        if receivers(&read_frame(&mut publisher).unwrap()) == 1 {
+            continue;
+        }
+        is_dropped = true;
+        break;
+    }
+    assert!(is_dropped);
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match pattern.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+    assert_eq!(receivers(&request(addr, &publish_message(b"slow", b""))), 0);
+
+    let _ = fs::remove_dir_all(&dir);
+}
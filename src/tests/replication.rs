@@ -0,0 +1,117 @@
+#![cfg(test)]
+use std::{fs, net::TcpStream, path::PathBuf, thread, time::Duration};
+use crate::{
+    constants::actions,
+    server::{cfg::Config, server::Server},
+    storage::{log_record::{decode, encode_create_table, encode_key_and_value, LogRecord}, Storage},
+    table::table::TableEngine,
+    tests::{failover::{eventually, info, request}, http_gateway::free_addr},
+    writers::ReplicationBacklog
+};
+
+#[test]
+fn log_records() {
+    let mut buf = Vec::new();
+    encode_create_table(&mut buf, TableEngine::CACHE, b"sessions", true, 90, b"{}");
+    let long_key = vec![b'k'; 300];
+    let long_value = vec![1u8; 70_000];
+    encode_key_and_value(&mut buf, actions::SET, 3, &long_key, &long_value);
+    encode_key_and_value(&mut buf, actions::INSERT, 1, b"key", b"value");
+    buf.extend_from_slice(&[actions::DELETE, 1, 0, 3]);
+    buf.extend_from_slice(b"key");
+
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while let Ok(Some((record, len))) = decode(&buf[offset..]) {
+        records.push(record);
+        offset += len;
+    }
+    assert_eq!(offset, buf.len());
+    assert_eq!(records, vec![
+        LogRecord::CreateTable { engine: TableEngine::CACHE, name: b"sessions", is_it_logging: true, cache_duration: 90, user_scheme: b"{}" },
+        LogRecord::Set { table: 3, key: &long_key, value: &long_value },
+        LogRecord::Insert { table: 1, key: b"key", value: b"value" },
+        LogRecord::Delete { table: 1, key: b"key" },
+    ]);
+
+    // A part of a record is not an error, the rest comes later.
+    assert_eq!(decode(&buf[..buf.len() - 1]).map(|record| record.is_some()), Ok(true));
+    let last = buf.len() - 7;
+    assert_eq!(decode(&buf[last..buf.len() - 1]), Ok(None));
+    assert_eq!(decode(&[actions::PING]), Err(actions::PING));
+}
+
+#[test]
+fn replication_backlog() {
+    let backlog = ReplicationBacklog::new(8);
+    // Nothing is kept before the first follower.
+    backlog.append(b"lost");
+    assert_eq!(backlog.enable(), 0);
+
+    backlog.append(b"abcdef");
+    assert_eq!(backlog.read(0, 4, Duration::ZERO), Ok((b"abcd".to_vec(), 4)));
+    assert_eq!(backlog.read(4, 100, Duration::ZERO), Ok((b"ef".to_vec(), 6)));
+    assert_eq!(backlog.read(6, 100, Duration::from_millis(10)), Ok((Vec::new(), 6)));
+
+    // The oldest bytes are dropped.
+    backlog.append(b"ghijk");
+    assert_eq!(backlog.end_offset(), 11);
+    assert_eq!(backlog.read(0, 100, Duration::ZERO), Err(3));
+    assert_eq!(backlog.read(3, 100, Duration::ZERO), Ok((b"defghijk".to_vec(), 11)));
+    backlog.set_max_len(2);
+    assert_eq!(backlog.read(6, 100, Duration::ZERO), Err(9));
+}
+
+fn start_server(name: &str, addr: &str, leader_addr: &str) {
+    let dir: PathBuf = format!("test_data_replication_{}", name).into();
+    let _ = fs::remove_dir_all(&dir);
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    let config = Config {
+        tcp_addr: addr.to_string(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        leader_addr: leader_addr.to_string(),
+        ..Config::default()
+    };
+    thread::spawn(move || Server::with_config(storage, config).run());
+    eventually("the start of the server", || TcpStream::connect(addr).is_ok());
+}
+
+fn message(action: u8, table: u16, key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut message = vec![action, table as u8, (table >> 8) as u8];
+    if action == actions::INSERT || action == actions::SET {
+        message.extend_from_slice(&[key.len() as u8, (key.len() >> 8) as u8]);
+    }
+    message.extend_from_slice(key);
+    message.extend_from_slice(value);
+    message
+}
+
+/// Tables without logging and on-disk tables don't write the log, but followers get their writes after the snapshot too.
+#[test]
+fn replication_of_tables_without_log() {
+    let (leader, follower) = (free_addr(), free_addr());
+    start_server("leader", &leader, "");
+    let mut create = vec![actions::CREATE_TABLE_IN_MEMORY, 0, 0, 0];
+    create.extend_from_slice(b"temp");
+    assert_eq!(request(&leader, &create), vec![actions::DONE, 0, 0]);
+    let mut create = vec![actions::CREATE_TABLE_ON_DISK, 0, 0];
+    create.extend_from_slice(b"disk");
+    assert_eq!(request(&leader, &create), vec![actions::DONE, 1, 0]);
+
+    start_server("follower", &follower, &leader);
+    eventually("the snapshot", || info(&follower)["replication"]["is_synced"] == true);
+    for table in [0, 1] {
+        assert_eq!(request(&leader, &message(actions::INSERT, table, b"key", b"inserted")), vec![actions::DONE]);
+        assert_eq!(request(&leader, &message(actions::SET, table, b"key", b"set")), vec![actions::DONE]);
+        assert_eq!(request(&leader, &message(actions::INSERT, table, b"deleted", b"value")), vec![actions::DONE]);
+        assert_eq!(request(&leader, &message(actions::DELETE, table, b"deleted", b"")), vec![actions::DONE]);
+    }
+    for table in [0, 1] {
+        eventually("the replication of the tail", || request(&follower, &message(actions::GET, table, b"key", b"")) == b"\x00set");
+        assert_ne!(request(&follower, &message(actions::GET, table, b"deleted", b""))[0], actions::DONE);
+    }
+
+    for name in ["leader", "follower"] {
+        let _ = fs::remove_dir_all(format!("test_data_replication_{}", name));
+    }
+}
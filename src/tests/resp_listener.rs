@@ -0,0 +1,169 @@
+#![cfg(test)]
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    thread,
+    time::Duration
+};
+use crate::{
+    index::HashInMemoryIndex,
+    scheme::scheme::empty_scheme,
+    server::{cfg::Config, resp::glob_match, server::Server},
+    storage::Storage
+};
+
+fn free_addr() -> String {
+    format!("127.0.0.1:{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port())
+}
+
+pub struct RespClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl RespClient {
+    pub fn connect(addr: &str) -> Self {
+        let writer = TcpStream::connect(addr).unwrap();
+        Self { reader: BufReader::new(writer.try_clone().unwrap()), writer }
+    }
+
+    pub fn send(&mut self, command: &[&[u8]]) {
+        let mut frame = format!("*{}\r\n", command.len()).into_bytes();
+        for argument in command {
+            frame.extend_from_slice(format!("${}\r\n", argument.len()).as_bytes());
+            frame.extend_from_slice(argument);
+            frame.extend_from_slice(b"\r\n");
+        }
+        self.writer.write_all(&frame).unwrap();
+    }
+
+    /// Reads one reply and returns it as the raw text, bulk strings are inlined.
+    pub fn read(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        let line = line.trim_end().to_string();
+        let len = line[1..].parse::<i64>().unwrap_or(-1);
+        match line.as_bytes()[0] {
+            b'$' if len >= 0 => {
+                let mut bulk = vec![0; len as usize + 2];
+                self.reader.read_exact(&mut bulk).unwrap();
+                format!("${}", String::from_utf8_lossy(&bulk[..len as usize]))
+            }
+            b'*' | b'%' => {
+                let items = if line.starts_with('%') { len * 2 } else { len };
+                let items: Vec<String> = (0..items).map(|_| self.read()).collect();
+                format!("{}[{}]", &line[..1], items.join(","))
+            }
+            _ => line,
+        }
+    }
+
+    pub fn call(&mut self, command: &[&[u8]]) -> String {
+        self.send(command);
+        self.read()
+    }
+}
+
+#[test]
+fn resp_listener() {
+    let dir: PathBuf = "test_data_resp_listener".into();
+    let _ = fs::remove_dir_all(&dir);
+    let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+    Storage::create_in_memory_table(storage, "other".to_string(), HashInMemoryIndex::new(), false, empty_scheme(), &[]);
+    Storage::create_cache_table(storage, "cache".to_string(), HashInMemoryIndex::new(), 60_000, false, empty_scheme(), &[]);
+    let resp_addr = free_addr();
+    let config = Config {
+        tcp_addr: free_addr(),
+        unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+        password: "secret".to_string(),
+        resp_addr: resp_addr.clone(),
+        ..Config::default()
+    };
+    thread::spawn(move || Server::with_config(storage, config).run());
+    for _ in 0..500 {
+        if TcpStream::connect(&resp_addr).is_ok() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut client = RespClient::connect(&resp_addr);
+    assert!(client.call(&[b"GET", b"a"]).starts_with("-NOAUTH"));
+    assert!(client.call(&[b"AUTH", b"wrong"]).starts_with("-WRONGPASS"));
+    assert_eq!(client.call(&[b"AUTH", b"default", b"secret"]), "+OK");
+    assert_eq!(client.call(&[b"PING"]), "+PONG");
+
+    assert_eq!(client.call(&[b"GET", b"a"]), "$-1");
+    assert_eq!(client.call(&[b"SET", b"a", b"1"]), "+OK");
+    assert_eq!(client.call(&[b"SET", b"a", b"2", b"NX"]), "$-1");
+    assert_eq!(client.call(&[b"SET", b"b", b"2", b"XX"]), "$-1");
+    assert_eq!(client.call(&[b"SET", b"b", b"2"]), "+OK");
+    assert_eq!(client.call(&[b"MGET", b"a", b"b", b"c"]), "*[$1,$2,$-1]");
+    assert_eq!(client.call(&[b"EXISTS", b"a", b"b", b"c"]), ":2");
+    assert!(client.call(&[b"EXPIRE", b"a", b"10"]).starts_with("-ERR per-key expiration"));
+    assert!(client.call(&[b"SET", b"a", b"1", b"EX", b"10"]).starts_with("-ERR per-key expiration"));
+    // Keys of cache tables have no TTL of their own either.
+    assert_eq!(client.call(&[b"SET", b"cache:a", b"1"]), "+OK");
+    assert!(client.call(&[b"EXPIRE", b"cache:a", b"10"]).starts_with("-ERR per-key expiration"));
+    assert!(client.call(&[b"SET", b"cache:a", b"1", b"PX", b"10"]).starts_with("-ERR per-key expiration"));
+    assert_eq!(client.call(&[b"EXPIRE", b"cache:a", b"0"]), ":1");
+    assert!(client.call(&[b"GET"]).starts_with("-ERR wrong number of arguments"));
+    assert!(client.call(&[b"NOPE"]).starts_with("-ERR unknown command"));
+
+    // `table:key` addresses other tables. `resp:key` is the default table too.
+    assert_eq!(client.call(&[b"GET", b"resp:a"]), "$1");
+    assert_eq!(client.call(&[b"SET", b"other:a", b"3"]), "+OK");
+    assert_eq!(client.call(&[b"KEYS", b"*"]).len(), "*[$a,$b,$other:a]".len());
+    assert_eq!(client.call(&[b"KEYS", b"[ab]"]).len(), "*[$a,$b]".len());
+    assert_eq!(client.call(&[b"KEYS", b"oth?r:*"]), "*[$other:a]");
+    assert!(client.call(&[b"KEYS", "*".repeat(257).as_bytes()]).starts_with("-ERR pattern is too long"));
+
+    // Pipelining.
+    client.send(&[b"DEL", b"a", b"b", b"c"]);
+    client.send(&[b"EXPIRE", b"other:a", b"0"]);
+    client.send(&[b"GET", b"other:a"]);
+    assert_eq!(client.read(), ":2");
+    assert_eq!(client.read(), ":1");
+    assert_eq!(client.read(), "$-1");
+
+    assert_eq!(client.call(&[b"HELLO", b"3"]), "%[$server,$dbms,$version,$0.1.0,$proto,:3,$mode,$standalone,$role,$master,$modules,*[]]"
+        .replace("0.1.0", env!("CARGO_PKG_VERSION")));
+    assert_eq!(client.call(&[b"GET", b"a"]), "_");
+    assert!(client.call(&[b"INFO"]).contains("db0:keys=0"));
+
+    let mut inline = RespClient::connect(&resp_addr);
+    inline.writer.write_all(b"HELLO 2 AUTH default secret\r\nPING hi\r\n").unwrap();
+    assert!(inline.read().starts_with("*[$server,$dbms"));
+    assert_eq!(inline.read(), "$hi");
+    assert_eq!(inline.call(&[b"QUIT"]), "+OK");
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn glob() {
+    assert!(glob_match(b"*", b""));
+    assert!(glob_match(b"a*c", b"abbbc"));
+    assert!(!glob_match(b"a*c", b"abbbd"));
+    assert!(glob_match(b"h?llo", b"hello"));
+    assert!(glob_match(b"h[ae]llo", b"hallo"));
+    assert!(!glob_match(b"h[^e]llo", b"hello"));
+    assert!(glob_match(b"h[a-b]llo", b"hbllo"));
+    assert!(glob_match(b"h\\*llo", b"h*llo"));
+    assert!(!glob_match(b"h\\*llo", b"hello"));
+    assert!(glob_match(b"*[ab]", b"xxb"));
+
+    // A long run of `*` doesn't use the stack.
+    let stars = vec![b'*'; u16::MAX as usize];
+    assert!(glob_match(&stars, b"key"));
+    let mut stars_and_b = stars.clone();
+    stars_and_b.push(b'b');
+    assert!(!glob_match(&stars_and_b, b"key"));
+
+    // Backtracking takes a product of lengths, not an exponent of them.
+    let key = vec![b'a'; 200];
+    assert!(!glob_match(b"*a*a*a*a*a*a*b", &key));
+    assert!(glob_match(b"*a*a*a*a*a*a*a", &key));
+}
@@ -0,0 +1,150 @@
+#![cfg(test)]
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::Duration
+};
+use crate::{
+    constants::{actions, errors},
+    server::{cfg::Config, server::Server, sharding::{key_of, shard_of, ShardMap, NUMBER_OF_SHARDS}},
+    storage::Storage,
+    tests::{failover::request, http_gateway::{self, free_addr}, resp_listener::RespClient},
+    utils::bytes::uint
+};
+
+#[test]
+fn shard_map() {
+    // The check value of CRC-16/XMODEM.
+    assert_eq!(shard_of(b"123456789"), 0x31C3);
+    assert_eq!(shard_of(b""), 0);
+
+    assert_eq!(key_of(&[actions::GET, 1, 0, b'k', b'e', b'y']), Some(&b"key"[..]));
+    assert_eq!(key_of(&[actions::GET_FIELD, 1, 0, 2, 0, b'k']), Some(&b"k"[..]));
+    assert_eq!(key_of(&[actions::GET_FIELDS, 1, 0, 2, 0, 1, 0, 3, 0, b'k']), Some(&b"k"[..]));
+    assert_eq!(key_of(&[actions::INSERT, 1, 0, 2, 0, b'k', b'e', b'v']), Some(&b"ke"[..]));
+    assert_eq!(key_of(&[actions::SET, 1, 0, 9, 0, b'k']), None);
+    assert_eq!(key_of(&[actions::PING]), None);
+
+    let dir: PathBuf = "test_data_shard_map".into();
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shard metadata.bin");
+
+    // Shards are split in equal ranges.
+    let map = ShardMap::open(&path, 3);
+    assert_eq!(map.node_of(0), 0);
+    assert_eq!(map.node_of(30000), 1);
+    assert_eq!(map.node_of(65535), 2);
+    assert_eq!(fs::read(&path).unwrap(), map.to_bytes());
+    assert_eq!(map.to_bytes().len(), NUMBER_OF_SHARDS * 2);
+
+    // Shards of the removed node are moved to other nodes, and the file is changed.
+    let moved = map.fit(2);
+    assert_eq!(moved, NUMBER_OF_SHARDS - NUMBER_OF_SHARDS * 2 / 3 - 1);
+    assert_eq!(map.fit(2), 0);
+    let map = ShardMap::open(&path, 5);
+    assert!((0..NUMBER_OF_SHARDS).all(|shard| map.node_of(shard as u16) < 2));
+    assert_eq!(map.node_of(65534), 1);
+    assert_eq!(map.node_of(65535), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn moved() {
+    let nodes: Vec<String> = (0..2).map(|_| free_addr()).collect();
+    let http_addrs: Vec<String> = (0..2).map(|_| free_addr()).collect();
+    let resp_addrs: Vec<String> = (0..2).map(|_| free_addr()).collect();
+    let hierarchy: Vec<Vec<String>> = nodes.iter().map(|addr| vec![addr.clone()]).collect();
+    // The first half of shards is in the first node.
+    let mut shard_metadata = Vec::with_capacity(NUMBER_OF_SHARDS * 2);
+    for shard in 0..NUMBER_OF_SHARDS {
+        shard_metadata.extend_from_slice(&uint::u16tob((shard >= NUMBER_OF_SHARDS / 2) as u16));
+    }
+    for (number, addr) in nodes.iter().enumerate() {
+        let dir: PathBuf = format!("test_data_moved_{}", number).into();
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("shard metadata.bin"), &shard_metadata).unwrap();
+        let storage: &'static Storage = Box::leak(Box::new(Storage::new(dir.clone())));
+        let config = Config {
+            tcp_addr: addr.clone(),
+            unix_addr: dir.join("dbms.sock").to_str().unwrap().to_string(),
+            http_addr: http_addrs[number].clone(),
+            resp_addr: resp_addrs[number].clone(),
+            resp_table: "kvs".to_string(),
+            ..Config::default()
+        };
+        let mut server = Server::with_config(storage, config);
+        server.set_hierarchy(hierarchy.clone());
+        thread::spawn(move || server.run());
+    }
+    for addr in nodes.iter().chain(http_addrs.iter()).chain(resp_addrs.iter()) {
+        for _ in 0..500 {
+            if std::net::TcpStream::connect(addr).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    for addr in nodes.iter() {
+        assert_eq!(request(addr, &[actions::CREATE_TABLE_IN_MEMORY, 0, 0, 0, b'k', b'v', b's']), vec![actions::DONE, 0, 0]);
+        let answer = request(addr, &[actions::GET_SHARD_METADATA]);
+        assert_eq!(answer[0], actions::DONE);
+        assert_eq!(&answer[1..], &shard_metadata[..]);
+    }
+
+    let key_of_node = |node: usize| (0..).map(|i: u32| format!("key{}", i).into_bytes())
+        .find(|key| (shard_of(key) as usize >= NUMBER_OF_SHARDS / 2) as usize == node).unwrap();
+    for node in 0..2 {
+        let key = key_of_node(node);
+        let other = &nodes[1 - node];
+        let mut insert = vec![actions::INSERT, 0, 0, key.len() as u8, 0];
+        insert.extend_from_slice(&key);
+        insert.extend_from_slice(b"value");
+        let mut get = vec![actions::GET, 0, 0];
+        get.extend_from_slice(&key);
+
+        // The other node redirects.
+        let error = errors::KEY_IS_MOVED;
+        let mut moved = vec![error.status, error.code as u8, (error.code >> 8) as u8];
+        moved.extend_from_slice(format!("MOVED {} {} {}", shard_of(&key), node, nodes[node]).as_bytes());
+        assert_eq!(request(other, &insert), moved);
+        assert_eq!(request(other, &get), moved);
+
+        assert_eq!(request(&nodes[node], &insert), vec![actions::DONE]);
+        assert_eq!(request(&nodes[node], &get), b"\0value".to_vec());
+        // Actions without keys are handled by every node.
+        assert_eq!(request(other, &[actions::PING]), vec![actions::DONE, actions::PING]);
+
+        // The HTTP gateway and the RESP listener redirect too.
+        let other = 1 - node;
+        let key_path = format!("/tables/kvs/keys/{}", String::from_utf8_lossy(&key));
+        for method in ["GET", "PUT", "DELETE"] {
+            let (status, body) = http_gateway::request(&http_addrs[other], method, &key_path, None, b"value");
+            assert_eq!(status, 421);
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["code"], error.code);
+            assert_eq!(body["shard"], shard_of(&key));
+            assert_eq!(body["node"], node);
+            assert_eq!(body["addr"], nodes[node].as_str());
+        }
+        let (status, body) = http_gateway::request(&http_addrs[node], "GET", &key_path, None, b"");
+        assert_eq!((status, body), (200, b"value".to_vec()));
+
+        let moved = format!("-MOVED {} {}", shard_of(&key), nodes[node]);
+        let mut client = RespClient::connect(&resp_addrs[other]);
+        assert_eq!(client.call(&[b"GET", &key]), moved);
+        assert_eq!(client.call(&[b"SET", &key, b"1"]), moved);
+        assert_eq!(client.call(&[b"EXISTS", &key_of_node(other), &key]), moved);
+        // The table prefix isn't hashed.
+        assert_eq!(client.call(&[b"DEL", &[b"kvs:", key.as_slice()].concat()]), moved);
+        let mut client = RespClient::connect(&resp_addrs[node]);
+        assert_eq!(client.call(&[b"GET", &[b"kvs:", key.as_slice()].concat()]), "$value");
+    }
+
+    for number in 0..2 {
+        let _ = fs::remove_dir_all(format!("test_data_moved_{}", number));
+    }
+}
@@ -0,0 +1,51 @@
+#![cfg(test)]
+use std::{fs, time::Duration};
+use serde_json::Value;
+use crate::{
+    constants::actions,
+    server::slowlog::{KEY_PREFIX_LEN, SlowLog}
+};
+
+#[test]
+fn slowlog() {
+    let file_path = "test_data_slowlog.jsonl";
+    let _ = fs::remove_file(file_path);
+    let log = SlowLog::new(Duration::from_millis(1), 3, file_path);
+
+    log.record(&[actions::GET, 1, 0, b'k'], Duration::from_micros(10), "fast");
+    assert!(log.is_empty());
+
+    let mut set = vec![actions::SET, 2, 0, 100, 0];
+    set.extend_from_slice(&[b'a'; 100]);
+    set.extend_from_slice(b"value");
+    log.record(&set, Duration::from_millis(5), "127.0.0.1:1");
+    log.record(&[actions::PING], Duration::from_millis(2), "unix");
+    log.record(&[actions::GET_FIELD, 3, 0, 1, 0, b'k', b'e', b'y'], Duration::from_millis(3), "unix");
+    log.record(&[actions::DELETE, 4, 0, b'd'], Duration::from_millis(4), "unix");
+    assert_eq!(log.len(), 3);
+
+    let entries = log.get(10);
+    let entries = entries.as_array().unwrap();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0]["action"], "delete");
+    assert_eq!(entries[0]["table"], 4);
+    assert_eq!(entries[0]["key_prefix"], "d");
+    assert_eq!(entries[1]["action"], "get_field");
+    assert_eq!(entries[1]["key_prefix"], "key");
+    assert_eq!(entries[2]["action"], "ping");
+    assert_eq!(entries[2]["table"], Value::Null);
+    assert_eq!(entries[2]["duration_micros"], 2000);
+    assert_eq!(log.get(1).as_array().unwrap().len(), 1);
+
+    log.reset();
+    assert!(log.is_empty());
+
+    // The file has all entries, even the dropped ones.
+    let lines: Vec<Value> = fs::read_to_string(file_path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[0]["action"], "set");
+    assert_eq!(lines[0]["client"], "127.0.0.1:1");
+    assert_eq!(lines[0]["key_prefix"].as_str().unwrap().len(), KEY_PREFIX_LEN);
+
+    fs::remove_file(file_path).unwrap();
+}
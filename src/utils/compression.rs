@@ -0,0 +1,159 @@
+//! Compression of values and dumps. The codec of the table is chosen, when the table is created, with the `compression`
+//! field of its scheme, and it can't be changed later.
+//!
+//! A value of the table with compression is stored as [`codec` (1 byte), `raw length` (4 bytes), compressed value] or,
+//! if it is shorter than the threshold or doesn't become shorter, as [[`Codec::None`], raw value].
+//! Tables without compression store raw values.
+use std::io::{self, ErrorKind};
+use crate::{
+    bin_types::BinValue,
+    constants::errors::{self, Error},
+    error,
+    scheme::scheme::SchemeJSON
+};
+
+/// Values shorter than it aren't compressed, if the scheme doesn't set `compression_threshold`.
+pub const DEFAULT_THRESHOLD: usize = 128;
+const ZSTD_LEVEL: i32 = 3;
+/// [`codec` (1 byte), `raw length` (4 bytes)]
+const VALUE_HEADER_SIZE: usize = 5;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    None = 0,
+    Lz4 = 1,
+    Zstd = 2
+}
+
+impl Codec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Lz4 => "lz4",
+            Codec::Zstd => "zstd",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Codec> {
+        match name {
+            "none" => Some(Codec::None),
+            "lz4" => Some(Codec::Lz4),
+            "zstd" => Some(Codec::Zstd),
+            _ => None
+        }
+    }
+
+    pub fn from_u8(byte: u8) -> Option<Codec> {
+        match byte {
+            0 => Some(Codec::None),
+            1 => Some(Codec::Lz4),
+            2 => Some(Codec::Zstd),
+            _ => None
+        }
+    }
+
+    pub fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Codec::None => data.to_vec(),
+            Codec::Lz4 => lz4_flex::block::compress(data),
+            Codec::Zstd => zstd::bulk::compress(data, ZSTD_LEVEL).expect("failed to compress"),
+        }
+    }
+
+    /// `raw_len` is the length of the data before [`Codec::compress`].
+    pub fn decompress(&self, data: &[u8], raw_len: usize) -> io::Result<Vec<u8>> {
+        let raw = match self {
+            Codec::None => data.to_vec(),
+            Codec::Lz4 => lz4_flex::block::decompress(data, raw_len).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?,
+            Codec::Zstd => zstd::bulk::decompress(data, raw_len)?,
+        };
+        if raw.len() != raw_len {
+            return Err(io::Error::new(ErrorKind::InvalidData, "the length of the decompressed data is wrong"));
+        }
+        Ok(raw)
+    }
+}
+
+/// The compression of values of the table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Compression {
+    pub codec: Codec,
+    pub threshold: usize
+}
+
+impl Compression {
+    pub const NONE: Compression = Compression { codec: Codec::None, threshold: DEFAULT_THRESHOLD };
+
+    /// Reads `compression` and `compression_threshold` of the scheme. Empty schemes have no compression.
+    pub fn from_user_scheme(user_scheme: &[u8]) -> Result<Compression, Error> {
+        if user_scheme.is_empty() {
+            return Ok(Compression::NONE);
+        }
+        let scheme_json: SchemeJSON = match serde_json::from_slice(user_scheme) {
+            Ok(scheme_json) => scheme_json,
+            Err(_) => return Err(errors::SCHEME_IS_NOT_VALID_JSON),
+        };
+        Self::from_scheme_json(&scheme_json)
+    }
+
+    pub fn from_scheme_json(scheme_json: &SchemeJSON) -> Result<Compression, Error> {
+        let codec = match &scheme_json.compression {
+            Some(name) => Codec::from_name(name).ok_or(errors::UNKNOWN_COMPRESSION)?,
+            None => Codec::None,
+        };
+        Ok(Compression { codec, threshold: scheme_json.compression_threshold.unwrap_or(DEFAULT_THRESHOLD) })
+    }
+
+    #[inline(always)]
+    pub fn is_enabled(&self) -> bool {
+        self.codec != Codec::None
+    }
+
+    /// Returns the value to store.
+    #[inline(always)]
+    pub fn pack(&self, value: BinValue) -> BinValue {
+        if !self.is_enabled() {
+            return value;
+        }
+        let raw = value.deref();
+        if raw.len() >= self.threshold && raw.len() <= u32::MAX as usize {
+            let compressed = self.codec.compress(raw);
+            if compressed.len() + VALUE_HEADER_SIZE < raw.len() {
+                let mut packed = Vec::with_capacity(compressed.len() + VALUE_HEADER_SIZE);
+                packed.push(self.codec as u8);
+                packed.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+                packed.extend_from_slice(&compressed);
+                return BinValue::new(&packed);
+            }
+        }
+        let mut packed = Vec::with_capacity(raw.len() + 1);
+        packed.push(Codec::None as u8);
+        packed.extend_from_slice(raw);
+        BinValue::new(&packed)
+    }
+
+    /// Returns the raw value of the stored one. A damaged value is logged and returned as [`errors::VALUE_IS_BROKEN`].
+    #[inline(always)]
+    pub fn unpack(&self, value: BinValue) -> Result<BinValue, Error> {
+        if !self.is_enabled() {
+            return Ok(value);
+        }
+        let stored = value.deref();
+        let res = match stored.first().and_then(|codec| Codec::from_u8(*codec)) {
+            Some(Codec::None) => return Ok(BinValue::new(&stored[1..])),
+            Some(codec) if stored.len() >= VALUE_HEADER_SIZE => {
+                let raw_len = u32::from_le_bytes(stored[1..VALUE_HEADER_SIZE].try_into().unwrap()) as usize;
+                codec.decompress(&stored[VALUE_HEADER_SIZE..], raw_len)
+            }
+            _ => Err(io::Error::new(ErrorKind::InvalidData, "the header is unknown")),
+        };
+        match res {
+            Ok(raw) => Ok(BinValue::new(&raw)),
+            Err(e) => {
+                error!("The compressed value is broken: {}", e);
+                Err(errors::VALUE_IS_BROKEN)
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 pub mod bytes;
 pub mod read_more;
 pub mod cells;
+pub mod compression;
 
 pub use read_more::read_more;
\ No newline at end of file
@@ -5,21 +5,27 @@
 use std::{
     fs::File,
     io::{BufWriter, Write},
-    sync::{Arc, Mutex}
+    sync::{Arc, Mutex},
+    time::Instant
 };
 use crate::{
     bin_types::{BinKey, BinValue},
-    writers::{get_size_for_key_len, get_size_for_value_len}
+    metrics::METRICS,
+    storage::log_record,
+    writers::{get_size_for_key_len, get_size_for_value_len, ReplicationBacklog}
 };
 
 pub struct LogFile {
     pub file: Arc<Mutex<File>>,
+    /// Gets everything, that is written to the file, for followers.
+    pub backlog: Arc<ReplicationBacklog>,
 }
 
 impl LogFile {
     pub fn new(file: File) -> Self {
         Self {
             file: Arc::new(Mutex::new(file)),
+            backlog: Arc::new(ReplicationBacklog::default()),
         }
     }
 }
@@ -32,7 +38,11 @@ impl Drop for LogFile {
 
 impl Write for LogFile {
     fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
-        let _ = self.file.lock().unwrap().write_all(data);
+        let mut file = self.file.lock().unwrap();
+        let _ = file.write_all(data);
+        self.backlog.append(data);
+        drop(file);
+        METRICS.log_bytes_written.add(data.len() as u64);
         Ok(data.len())
     }
     fn flush(&mut self) -> std::io::Result<()> {
@@ -40,7 +50,12 @@ impl Write for LogFile {
     }
 
     fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
-        self.file.lock().unwrap().write_all(data)
+        let mut file = self.file.lock().unwrap();
+        file.write_all(data)?;
+        self.backlog.append(data);
+        drop(file);
+        METRICS.log_bytes_written.add(data.len() as u64);
+        Ok(())
     }
 }
 
@@ -51,27 +66,72 @@ impl Clone for LogFile {
     fn clone(&self) -> Self {
         Self {
             file: Arc::clone(&self.file),
+            backlog: Arc::clone(&self.backlog),
         }
     }
 }
 
 pub struct LogWriter {
     writer: BufWriter<LogFile>,
+    /// Records of tables, that don't write the log, for followers only. They are appended to the backlog after the log is flushed.
+    replicated: Vec<u8>,
 }
 
 const SIZE: usize = 65356;
 
+impl Drop for LogWriter {
+    /// The log is flushed first, so followers get records of a table after the record, that creates it.
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+        self.flush_replicated();
+    }
+}
+
 impl LogWriter {
     pub fn new(log_file: LogFile) -> Self {
         Self {
             writer: BufWriter::with_capacity(SIZE, log_file),
+            replicated: Vec::new(),
         }
     }
     
     #[inline(always)]
     pub fn flush(&mut self) {
         if self.writer.buffer().len() > 0 {
+            let start = Instant::now();
             self.writer.flush().expect("Can't flush log writer!");
+            METRICS.log_flush.observe(start.elapsed());
+        }
+        self.flush_replicated();
+    }
+
+    fn flush_replicated(&mut self) {
+        if !self.replicated.is_empty() {
+            self.writer.get_ref().backlog.append(&self.replicated);
+            self.replicated.clear();
+        }
+    }
+
+    /// Sends the record to followers without writing it in the log. It is for tables without logging and on-disk tables,
+    /// their records are the same as records of the log, see [`crate::storage::log_record`].
+    #[inline(always)]
+    pub fn replicate_key_and_value(&mut self, action: u8, table_number: u16, key: &BinKey, value: &BinValue) {
+        if self.writer.get_ref().backlog.is_enabled() {
+            log_record::encode_key_and_value(&mut self.replicated, action, table_number, key.deref(), value.deref());
+            if self.replicated.len() >= SIZE {
+                self.flush_replicated();
+            }
+        }
+    }
+
+    /// Like [`LogWriter::replicate_key_and_value`], but for `DELETE`.
+    #[inline(always)]
+    pub fn replicate_key(&mut self, action: u8, table_number: u16, key: &BinKey) {
+        if self.writer.get_ref().backlog.is_enabled() {
+            log_record::encode_key(&mut self.replicated, action, table_number, key.deref());
+            if self.replicated.len() >= SIZE {
+                self.flush_replicated();
+            }
         }
     }
 
@@ -2,7 +2,9 @@ pub mod pipe_writer;
 pub mod sized_writer;
 pub mod utils;
 pub mod log_writer;
+pub mod replication_backlog;
 
 pub use sized_writer::*;
 pub use utils::*;
-pub use log_writer::*;
\ No newline at end of file
+pub use log_writer::*;
+pub use replication_backlog::*;
\ No newline at end of file
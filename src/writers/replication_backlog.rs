@@ -0,0 +1,112 @@
+//! The tail of the log in memory. Followers read log records from it, see [`crate::server::replication`].
+//!
+//! An offset is the number of bytes, that were written to the log after the backlog was enabled. Records of tables,
+//! that don't write the log, are appended only to the backlog, so offsets aren't positions in log files.
+//! The backlog is enabled by the first follower, so a server without followers doesn't copy the log.
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex, atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst}},
+    time::Duration
+};
+
+/// The default of the `replication_backlog_bytes` setting.
+pub const DEFAULT_BACKLOG_BYTES: usize = 16 * 1024 * 1024;
+
+struct State {
+    bytes: VecDeque<u8>,
+    /// The offset of `bytes[0]`.
+    start: u64,
+}
+
+pub struct ReplicationBacklog {
+    is_enabled: AtomicBool,
+    max_len: AtomicUsize,
+    state: Mutex<State>,
+    appended: Condvar,
+}
+
+impl Default for ReplicationBacklog {
+    fn default() -> Self {
+        Self::new(DEFAULT_BACKLOG_BYTES)
+    }
+}
+
+impl ReplicationBacklog {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            is_enabled: AtomicBool::new(false),
+            max_len: AtomicUsize::new(max_len),
+            state: Mutex::new(State { bytes: VecDeque::new(), start: 0 }),
+            appended: Condvar::new(),
+        }
+    }
+
+    /// Starts to keep the log. Returns the current offset.
+    pub fn enable(&self) -> u64 {
+        let state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.is_enabled.store(true, SeqCst);
+        state.start + state.bytes.len() as u64
+    }
+
+    #[inline(always)]
+    pub fn is_enabled(&self) -> bool {
+        self.is_enabled.load(SeqCst)
+    }
+
+    /// Is called by [`LogFile`](crate::writers::LogFile) with the log file locked, so records are in the order of the file.
+    #[inline(always)]
+    pub fn append(&self, data: &[u8]) {
+        if !self.is_enabled() || data.is_empty() {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.bytes.extend(data);
+        let max_len = self.max_len.load(SeqCst);
+        if state.bytes.len() > max_len {
+            let extra = state.bytes.len() - max_len;
+            state.bytes.drain(..extra);
+            state.start += extra as u64;
+        }
+        drop(state);
+        self.appended.notify_all();
+    }
+
+    /// Returns the offset after the last written byte.
+    pub fn end_offset(&self) -> u64 {
+        let state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        state.start + state.bytes.len() as u64
+    }
+
+    /// Drops the oldest bytes, if there are more than `max_len`.
+    pub fn set_max_len(&self, max_len: usize) {
+        self.max_len.store(max_len, SeqCst);
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if state.bytes.len() > max_len {
+            let extra = state.bytes.len() - max_len;
+            state.bytes.drain(..extra);
+            state.start += extra as u64;
+        }
+    }
+
+    /// Returns at most `max` bytes from `offset` and the offset after them. Waits for new bytes up to `timeout`,
+    /// so the result is empty, if nothing was written.
+    ///
+    /// Returns the oldest offset as an error, if the bytes from `offset` were already dropped. The read bytes
+    /// can end in the middle of a record.
+    pub fn read(&self, offset: u64, max: usize, timeout: Duration) -> Result<(Vec<u8>, u64), u64> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if state.start + state.bytes.len() as u64 <= offset {
+            state = self.appended.wait_timeout(state, timeout).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+        }
+        if offset < state.start {
+            return Err(state.start);
+        }
+        let from = (offset - state.start) as usize;
+        let to = state.bytes.len().min(from.saturating_add(max));
+        if from >= to {
+            return Ok((Vec::new(), offset));
+        }
+        let bytes: Vec<u8> = state.bytes.range(from..to).copied().collect();
+        Ok((bytes, offset + (to - from) as u64))
+    }
+}